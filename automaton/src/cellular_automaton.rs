@@ -0,0 +1,234 @@
+use std::fmt;
+
+// 256通りある基本セルオートマトンの規則
+// 近傍 (左, 中央, 右) を3ビットの数と見て、そのビットが次の世代のセルになる
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ElementaryRule(pub u8);
+
+impl ElementaryRule {
+    pub const RULE_30: ElementaryRule = ElementaryRule(30);
+    pub const RULE_90: ElementaryRule = ElementaryRule(90);
+    pub const RULE_110: ElementaryRule = ElementaryRule(110);
+
+    pub fn next_cell(&self, left: bool, center: bool, right: bool) -> bool {
+        let neighborhood = (left as u8) << 2 | (center as u8) << 1 | right as u8;
+        self.0 >> neighborhood & 1 == 1
+    }
+}
+
+impl fmt::Display for ElementaryRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Rule {}", self.0)
+    }
+}
+
+// 両端の外側をどう扱うか
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Boundary {
+    Fixed(bool),
+    Periodic,
+}
+
+pub struct CellularAutomaton {
+    rule: ElementaryRule,
+    boundary: Boundary,
+    cells: Vec<bool>,
+    history: Option<Vec<Vec<bool>>>,
+}
+
+impl CellularAutomaton {
+    pub fn new(rule: ElementaryRule, boundary: Boundary, cells: Vec<bool>) -> Self {
+        CellularAutomaton {
+            rule,
+            boundary,
+            cells,
+            history: None,
+        }
+    }
+    // '#' か '1' を生きているセルとして読む
+    pub fn from_pattern(rule: ElementaryRule, boundary: Boundary, pattern: &str) -> Self {
+        CellularAutomaton::new(
+            rule,
+            boundary,
+            pattern.chars().map(|c| c == '#' || c == '1').collect(),
+        )
+    }
+    // 以降の世代を記録して時空図を描けるようにする
+    pub fn with_history(mut self) -> Self {
+        self.history = Some(vec![self.cells.clone()]);
+        self
+    }
+    pub fn rule(&self) -> ElementaryRule {
+        self.rule
+    }
+    pub fn cells(&self) -> &Vec<bool> {
+        &self.cells
+    }
+    pub fn history(&self) -> Option<&Vec<Vec<bool>>> {
+        self.history.as_ref()
+    }
+    fn cell_at(&self, index: isize) -> bool {
+        let width = self.cells.len() as isize;
+        if 0 <= index && index < width {
+            self.cells[index as usize]
+        } else {
+            match self.boundary {
+                Boundary::Fixed(value) => value,
+                Boundary::Periodic => self.cells[index.rem_euclid(width) as usize],
+            }
+        }
+    }
+    pub fn step(&mut self) {
+        self.cells = (0..self.cells.len() as isize)
+            .map(|i| {
+                self.rule
+                    .next_cell(self.cell_at(i - 1), self.cell_at(i), self.cell_at(i + 1))
+            })
+            .collect();
+        if let Some(history) = self.history.as_mut() {
+            history.push(self.cells.clone());
+        }
+    }
+    pub fn run(&mut self, generations: usize) {
+        for _ in 0..generations {
+            self.step();
+        }
+    }
+    fn rows(&self) -> Vec<Vec<bool>> {
+        match &self.history {
+            Some(history) => history.clone(),
+            None => vec![self.cells.clone()],
+        }
+    }
+    pub fn to_ascii(&self) -> String {
+        self.rows()
+            .iter()
+            .map(|row| {
+                let mut line = row
+                    .iter()
+                    .map(|&c| if c { '#' } else { '.' })
+                    .collect::<String>();
+                line.push('\n');
+                line
+            })
+            .collect()
+    }
+    // プレーンなPBM (P1) 形式の画像
+    pub fn to_pbm(&self) -> String {
+        let rows = self.rows();
+        let mut pbm = format!("P1\n{} {}\n", self.cells.len(), rows.len());
+        for row in rows {
+            pbm.push_str(
+                &row.iter()
+                    .map(|&c| if c { "1" } else { "0" })
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            );
+            pbm.push('\n');
+        }
+        pbm
+    }
+}
+
+impl fmt::Display for CellularAutomaton {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.cells
+                .iter()
+                .map(|&c| if c { '#' } else { '.' })
+                .collect::<String>()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rule_test() {
+        let rule = ElementaryRule::RULE_110;
+        assert!(!rule.next_cell(true, true, true));
+        assert!(rule.next_cell(true, true, false));
+        assert!(rule.next_cell(true, false, true));
+        assert!(!rule.next_cell(true, false, false));
+        assert!(rule.next_cell(false, true, true));
+        assert!(rule.next_cell(false, true, false));
+        assert!(rule.next_cell(false, false, true));
+        assert!(!rule.next_cell(false, false, false));
+        assert_eq!(rule.to_string(), "Rule 110");
+    }
+
+    #[test]
+    fn rule_110_test() {
+        let mut ca = CellularAutomaton::from_pattern(
+            ElementaryRule::RULE_110,
+            Boundary::Fixed(false),
+            ".......#",
+        )
+        .with_history();
+        ca.run(4);
+        assert_eq!(
+            ca.to_ascii(),
+            ".......#\n\
+             ......##\n\
+             .....###\n\
+             ....##.#\n\
+             ...#####\n"
+        );
+    }
+
+    #[test]
+    fn rule_90_test() {
+        let mut ca = CellularAutomaton::from_pattern(
+            ElementaryRule::RULE_90,
+            Boundary::Fixed(false),
+            "...#...",
+        )
+        .with_history();
+        ca.run(3);
+        assert_eq!(
+            ca.to_ascii(),
+            "...#...\n\
+             ..#.#..\n\
+             .#...#.\n\
+             #.#.#.#\n"
+        );
+    }
+
+    #[test]
+    fn boundary_test() {
+        let mut fixed = CellularAutomaton::from_pattern(
+            ElementaryRule::RULE_110,
+            Boundary::Fixed(false),
+            "#...",
+        );
+        fixed.step();
+        assert_eq!(fixed.to_string(), "#...");
+
+        let mut periodic =
+            CellularAutomaton::from_pattern(ElementaryRule::RULE_110, Boundary::Periodic, "#...");
+        periodic.step();
+        assert_eq!(periodic.to_string(), "#..#");
+
+        let mut filled = CellularAutomaton::from_pattern(
+            ElementaryRule::RULE_110,
+            Boundary::Fixed(true),
+            "....",
+        );
+        filled.step();
+        assert_eq!(filled.to_string(), "...#");
+    }
+
+    #[test]
+    fn pbm_test() {
+        let mut ca =
+            CellularAutomaton::from_pattern(ElementaryRule::RULE_30, Boundary::Periodic, "..#..")
+                .with_history();
+        ca.run(2);
+        assert_eq!(ca.to_pbm(), "P1\n5 3\n0 0 1 0 0\n0 1 1 1 0\n1 1 0 0 1\n");
+        assert_eq!(ca.history().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,164 @@
+// 巡回タグシステム: 文字は0と1だけで、規則を順番に使い、1文字ずつ消す
+const FIRST_CHARACTER: char = '1';
+
+pub struct CyclicTagRulebook {
+    rules: Vec<String>,
+    next_rule: usize,
+}
+
+impl CyclicTagRulebook {
+    pub fn new(rules: Vec<String>) -> Self {
+        if rules.is_empty() {
+            panic!("rulebook has no rules");
+        }
+        CyclicTagRulebook {
+            rules,
+            next_rule: 0,
+        }
+    }
+    pub fn build(args: Vec<&str>) -> Self {
+        CyclicTagRulebook::new(args.iter().map(|s| s.to_string()).collect())
+    }
+    pub fn rules(&self) -> &Vec<String> {
+        &self.rules
+    }
+    pub fn is_applied_to(&self, string: &str) -> bool {
+        !string.is_empty()
+    }
+    pub fn next_string(&mut self, string: &str) -> String {
+        self.follow_next_rule(string).chars().skip(1).collect()
+    }
+    fn follow_next_rule(&mut self, string: &str) -> String {
+        let rule = &self.rules[self.next_rule];
+        self.next_rule = (self.next_rule + 1) % self.rules.len();
+        if string.starts_with(FIRST_CHARACTER) {
+            format!("{}{}", string, rule)
+        } else {
+            string.to_string()
+        }
+    }
+}
+
+pub struct CyclicTagSystem {
+    current_string: String,
+    rulebook: CyclicTagRulebook,
+}
+
+impl CyclicTagSystem {
+    pub fn new(current_string: &str, rulebook: CyclicTagRulebook) -> Self {
+        CyclicTagSystem {
+            current_string: current_string.to_string(),
+            rulebook,
+        }
+    }
+    pub fn current_string(&self) -> &str {
+        &self.current_string
+    }
+    pub fn rulebook(&self) -> &CyclicTagRulebook {
+        &self.rulebook
+    }
+    pub fn is_halted(&self) -> bool {
+        !self.rulebook.is_applied_to(&self.current_string)
+    }
+    pub fn step(&mut self) {
+        self.current_string = self.rulebook.next_string(&self.current_string);
+    }
+    // 文字列が空にならない限り止まらないので歩数を決めて動かす
+    pub fn run(&mut self, steps: usize) {
+        for _ in 0..steps {
+            if self.is_halted() {
+                break;
+            }
+            self.step();
+        }
+    }
+}
+
+// タグシステムの1文字を、アルファベットの位置だけが1のビット列にする
+pub struct CyclicTagEncoder {
+    alphabet: Vec<char>,
+}
+
+impl CyclicTagEncoder {
+    pub fn new(alphabet: Vec<char>) -> Self {
+        if alphabet.is_empty() {
+            panic!("alphabet is empty");
+        }
+        CyclicTagEncoder { alphabet }
+    }
+    pub fn alphabet(&self) -> &Vec<char> {
+        &self.alphabet
+    }
+    pub fn encode_string(&self, string: &str) -> String {
+        string.chars().map(|c| self.encode_character(c)).collect()
+    }
+    pub fn encode_character(&self, character: char) -> String {
+        let position = self.alphabet.iter().position(|&c| c == character);
+        (0..self.alphabet.len())
+            .map(|n| if Some(n) == position { '1' } else { '0' })
+            .collect()
+    }
+    // 1文字分のビット列が、ちょうど1つだけ1を含むものでなければNone
+    pub fn decode_string(&self, string: &str) -> Option<String> {
+        string
+            .as_bytes()
+            .chunks(self.alphabet.len())
+            .map(|chunk| {
+                if chunk.len() != self.alphabet.len()
+                    || chunk.iter().any(|&b| b != b'0' && b != b'1')
+                    || chunk.iter().filter(|&&b| b == b'1').count() != 1
+                {
+                    return None;
+                }
+                let position = chunk.iter().position(|&b| b == b'1')?;
+                Some(self.alphabet[position])
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cyclic_tag_system_test() {
+        let rulebook = CyclicTagRulebook::build(vec!["1", "0010", "10"]);
+        let mut system = CyclicTagSystem::new("11", rulebook);
+        let mut strings = vec![system.current_string().to_string()];
+        for _ in 0..6 {
+            system.step();
+            strings.push(system.current_string().to_string());
+        }
+        assert_eq!(
+            strings,
+            vec!["11", "11", "10010", "001010", "01010", "1010", "01010"]
+        );
+    }
+
+    #[test]
+    fn halt_test() {
+        let rulebook = CyclicTagRulebook::build(vec!["", "1"]);
+        let mut system = CyclicTagSystem::new("101", rulebook);
+        system.run(100);
+        assert!(system.is_halted());
+        assert_eq!(system.current_string(), "");
+    }
+
+    #[test]
+    fn encoder_test() {
+        let encoder = CyclicTagEncoder::new(vec!['a', 'b', 'c']);
+        assert_eq!(encoder.encode_character('c'), "001");
+        assert_eq!(encoder.encode_string("cab"), "001100010");
+        assert_eq!(encoder.decode_string("001100010"), Some("cab".to_string()));
+        assert_eq!(encoder.decode_string("000100"), None);
+        assert_eq!(encoder.decode_string("011"), None);
+        assert_eq!(encoder.decode_string("0011"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "rulebook has no rules")]
+    fn empty_rulebook_test() {
+        CyclicTagRulebook::build(vec![]);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::state::State;
+use crate::tape::Tape;
+use crate::tm_configuration::{Direction, TMConfiguration, TMRule};
+use std::collections::BTreeSet;
+
+#[derive(Clone)]
+pub struct DTMRulebook {
+    pub rules: Vec<TMRule>,
+}
+
+impl DTMRulebook {
+    pub fn new(rules: Vec<TMRule>) -> Self {
+        DTMRulebook { rules }
+    }
+    pub fn rules(&self) -> &Vec<TMRule> {
+        &self.rules
+    }
+    pub fn build(args: Vec<(State, char, State, char, Direction)>) -> Self {
+        DTMRulebook {
+            rules: args
+                .iter()
+                .map(|&t| TMRule::new(t.0, t.1, t.2, t.3, t.4))
+                .collect(),
+        }
+    }
+    pub fn next_configuration(&self, configuration: &TMConfiguration) -> TMConfiguration {
+        match self.rule_for(configuration) {
+            Some(rule) => rule.follow(configuration),
+            None => panic!("next_configuration is null"),
+        }
+    }
+    pub fn is_applied_to(&self, configuration: &TMConfiguration) -> bool {
+        self.rule_for(configuration).is_some()
+    }
+    fn rule_for(&self, configuration: &TMConfiguration) -> Option<&TMRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.is_applied_to(configuration))
+    }
+}
+
+pub struct DTM {
+    current_configuration: TMConfiguration,
+    accept_states: BTreeSet<State>,
+    rulebook: DTMRulebook,
+}
+
+impl DTM {
+    pub fn new(
+        current_configuration: TMConfiguration,
+        accept_states: BTreeSet<State>,
+        rulebook: DTMRulebook,
+    ) -> Self {
+        DTM {
+            current_configuration,
+            accept_states,
+            rulebook,
+        }
+    }
+    pub fn current_configuration(&self) -> &TMConfiguration {
+        &self.current_configuration
+    }
+    pub fn accept_states(&self) -> &BTreeSet<State> {
+        &self.accept_states
+    }
+    pub fn rulebook(&self) -> &DTMRulebook {
+        &self.rulebook
+    }
+    pub fn is_accept(&self) -> bool {
+        self.accept_states
+            .contains(&self.current_configuration.state())
+    }
+    // 受理状態でもないのに適用できる規則がない
+    pub fn is_stuck(&self) -> bool {
+        !self.is_accept() && !self.rulebook.is_applied_to(&self.current_configuration)
+    }
+    pub fn step(&mut self) {
+        self.current_configuration = self
+            .rulebook
+            .next_configuration(&self.current_configuration);
+    }
+    pub fn run(&mut self) {
+        while !self.is_accept() && !self.is_stuck() {
+            self.step();
+        }
+    }
+}
+
+pub struct DTMDesign {
+    start_state: State,
+    blank: char,
+    accept_states: BTreeSet<State>,
+    rulebook: DTMRulebook,
+}
+
+impl DTMDesign {
+    pub fn new(arg: (State, char, BTreeSet<State>, DTMRulebook)) -> DTMDesign {
+        DTMDesign {
+            start_state: arg.0,
+            blank: arg.1,
+            accept_states: arg.2,
+            rulebook: arg.3,
+        }
+    }
+    pub fn to_dtm(&self, string: &str) -> DTM {
+        DTM::new(
+            TMConfiguration::new(self.start_state, Tape::from_input(string, self.blank)),
+            self.accept_states.clone(),
+            self.rulebook.clone(),
+        )
+    }
+    pub fn is_accept(&self, string: &str) -> bool {
+        let mut dtm = self.to_dtm(string);
+        dtm.run();
+        dtm.is_accept()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{anbncn_rulebook, increment_rulebook};
+
+    #[test]
+    fn dtm_increment_test() {
+        let tape = Tape::new(vec!['1', '0', '1'], '1', vec![], '_');
+        let mut dtm = DTM::new(
+            TMConfiguration::new(1, tape),
+            vec![3].into_iter().collect(),
+            increment_rulebook(),
+        );
+        assert!(!dtm.is_accept());
+        dtm.step();
+        assert_eq!(
+            dtm.current_configuration().tape().to_string(),
+            "<Tape 10(1)0>"
+        );
+        dtm.run();
+        assert!(dtm.is_accept());
+        assert_eq!(dtm.current_configuration().state(), 3);
+        assert_eq!(
+            dtm.current_configuration().tape().to_string(),
+            "<Tape 110(0)_>"
+        );
+
+        let tape = Tape::new(vec!['1', '1'], '1', vec![], '_');
+        let mut dtm = DTM::new(
+            TMConfiguration::new(1, tape),
+            vec![3].into_iter().collect(),
+            increment_rulebook(),
+        );
+        dtm.run();
+        assert_eq!(dtm.current_configuration().tape().contents(), "1000");
+    }
+
+    #[test]
+    fn dtm_stuck_test() {
+        let tape = Tape::new(vec!['1', '2', '1'], '1', vec![], '_');
+        let mut dtm = DTM::new(
+            TMConfiguration::new(1, tape),
+            vec![3].into_iter().collect(),
+            increment_rulebook(),
+        );
+        dtm.run();
+        assert_eq!(dtm.current_configuration().state(), 1);
+        assert_eq!(
+            dtm.current_configuration().tape().to_string(),
+            "<Tape 1(2)00>"
+        );
+        assert!(!dtm.is_accept());
+        assert!(dtm.is_stuck());
+    }
+
+    #[test]
+    fn dtm_design_test() {
+        // a^n b^n c^n を受理する
+        let rulebook = anbncn_rulebook();
+        let dtm_design = DTMDesign::new((1, '_', vec![6].into_iter().collect(), rulebook));
+        assert!(dtm_design.is_accept(""));
+        assert!(dtm_design.is_accept("abc"));
+        assert!(dtm_design.is_accept("aaabbbccc"));
+        assert!(!dtm_design.is_accept("aabbbccc"));
+        assert!(!dtm_design.is_accept("abcabc"));
+        assert!(!dtm_design.is_accept("cba"));
+
+        let mut dtm = dtm_design.to_dtm("aabbcc");
+        dtm.run();
+        assert_eq!(dtm.current_configuration().tape().contents(), "XXXXXX");
+    }
+}
@@ -1,6 +1,9 @@
+pub mod cellular_automaton;
+pub mod cyclic_tag_system;
 pub mod dfa;
 pub mod dfa_from_nfa;
 pub mod dpda;
+pub mod dtm;
 pub mod fa_rule;
 pub mod nfa;
 pub mod pattern;
@@ -8,3 +11,10 @@ pub mod pattern_parser;
 pub mod pda_configuration;
 pub mod stack;
 pub mod state;
+pub mod tag_system;
+pub mod tape;
+#[cfg(test)]
+mod test_helpers;
+pub mod tm_configuration;
+pub mod tm_encoding;
+pub mod universal_tm;
@@ -0,0 +1,221 @@
+use crate::cyclic_tag_system::{CyclicTagEncoder, CyclicTagRulebook, CyclicTagSystem};
+use std::collections::BTreeSet;
+use std::fmt;
+
+#[derive(Clone, Debug)]
+pub struct TagRule {
+    first_character: char,
+    append_characters: String,
+}
+
+impl TagRule {
+    pub fn new(first_character: char, append_characters: &str) -> Self {
+        TagRule {
+            first_character,
+            append_characters: append_characters.to_string(),
+        }
+    }
+    pub fn append_characters(&self) -> &str {
+        &self.append_characters
+    }
+    pub fn is_applied_to(&self, string: &str) -> bool {
+        string.starts_with(self.first_character)
+    }
+    pub fn follow(&self, string: &str) -> String {
+        format!("{}{}", string, self.append_characters)
+    }
+    fn alphabet(&self) -> BTreeSet<char> {
+        let mut alphabet = self.append_characters.chars().collect::<BTreeSet<_>>();
+        alphabet.insert(self.first_character);
+        alphabet
+    }
+}
+
+impl fmt::Display for TagRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "<TagRule {} -> {}>",
+            self.first_character, self.append_characters
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct TagRulebook {
+    deletion_number: usize,
+    rules: Vec<TagRule>,
+}
+
+impl TagRulebook {
+    pub fn new(deletion_number: usize, rules: Vec<TagRule>) -> Self {
+        if deletion_number == 0 {
+            panic!("deletion number must be at least 1");
+        }
+        TagRulebook {
+            deletion_number,
+            rules,
+        }
+    }
+    pub fn build(deletion_number: usize, args: Vec<(char, &str)>) -> Self {
+        TagRulebook::new(
+            deletion_number,
+            args.iter().map(|&(c, s)| TagRule::new(c, s)).collect(),
+        )
+    }
+    pub fn deletion_number(&self) -> usize {
+        self.deletion_number
+    }
+    pub fn next_string(&self, string: &str) -> String {
+        match self.rule_for(string) {
+            Some(rule) => rule
+                .follow(string)
+                .chars()
+                .skip(self.deletion_number)
+                .collect(),
+            None => panic!("next_string is null"),
+        }
+    }
+    pub fn is_applied_to(&self, string: &str) -> bool {
+        self.rule_for(string).is_some() && string.chars().count() >= self.deletion_number
+    }
+    fn rule_for(&self, string: &str) -> Option<&TagRule> {
+        self.rules.iter().find(|r| r.is_applied_to(string))
+    }
+    fn alphabet(&self) -> BTreeSet<char> {
+        self.rules.iter().flat_map(|r| r.alphabet()).collect()
+    }
+    // 1文字ごとに規則を1つずつ並べ、削除する残りの文字の分だけ何もしない規則を足す
+    pub fn to_cyclic(&self, encoder: &CyclicTagEncoder) -> CyclicTagRulebook {
+        let mut rules = encoder
+            .alphabet()
+            .iter()
+            .map(|&c| match self.rule_for(&c.to_string()) {
+                Some(rule) => encoder.encode_string(rule.append_characters()),
+                None => String::new(),
+            })
+            .collect::<Vec<_>>();
+        for _ in 0..(self.deletion_number - 1) * encoder.alphabet().len() {
+            rules.push(String::new());
+        }
+        CyclicTagRulebook::new(rules)
+    }
+}
+
+pub struct TagSystem {
+    current_string: String,
+    rulebook: TagRulebook,
+}
+
+impl TagSystem {
+    pub fn new(current_string: &str, rulebook: TagRulebook) -> Self {
+        TagSystem {
+            current_string: current_string.to_string(),
+            rulebook,
+        }
+    }
+    pub fn current_string(&self) -> &str {
+        &self.current_string
+    }
+    pub fn is_halted(&self) -> bool {
+        !self.rulebook.is_applied_to(&self.current_string)
+    }
+    pub fn step(&mut self) {
+        self.current_string = self.rulebook.next_string(&self.current_string);
+    }
+    pub fn run(&mut self) {
+        while !self.is_halted() {
+            self.step();
+        }
+    }
+    pub fn alphabet(&self) -> Vec<char> {
+        let mut alphabet = self.rulebook.alphabet();
+        alphabet.extend(self.current_string.chars());
+        alphabet.into_iter().collect()
+    }
+    pub fn encoder(&self) -> CyclicTagEncoder {
+        CyclicTagEncoder::new(self.alphabet())
+    }
+    pub fn to_cyclic(&self) -> CyclicTagSystem {
+        let encoder = self.encoder();
+        CyclicTagSystem::new(
+            &encoder.encode_string(&self.current_string),
+            self.rulebook.to_cyclic(&encoder),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn double_rulebook() -> TagRulebook {
+        TagRulebook::build(2, vec![('a', "cc"), ('b', "dddd")])
+    }
+
+    #[test]
+    fn tag_system_test() {
+        let mut system = TagSystem::new("aabbbbbb", double_rulebook());
+        system.step();
+        assert_eq!(system.current_string(), "bbbbbbcc");
+        system.run();
+        assert!(system.is_halted());
+        assert_eq!(system.current_string(), "ccdddddddddddd");
+    }
+
+    #[test]
+    fn deletion_number_test() {
+        // 3文字ずつ消して数を半分にする
+        let rulebook = TagRulebook::build(3, vec![('a', "x"), ('b', "y")]);
+        let mut system = TagSystem::new("aaabbbbbb", rulebook);
+        system.run();
+        assert_eq!(system.current_string(), "xyy");
+    }
+
+    #[test]
+    fn to_cyclic_test() {
+        let system = TagSystem::new("aabbbbbb", double_rulebook());
+        let encoder = system.encoder();
+        assert_eq!(encoder.alphabet(), &vec!['a', 'b', 'c', 'd']);
+        assert_eq!(encoder.encode_string("ab"), "10000100");
+        let cyclic = system.to_cyclic();
+        assert_eq!(cyclic.current_string(), encoder.encode_string("aabbbbbb"));
+    }
+
+    #[test]
+    #[should_panic(expected = "deletion number must be at least 1")]
+    fn zero_deletion_number_test() {
+        TagRulebook::build(0, vec![('a', "b")]);
+    }
+
+    #[test]
+    fn same_results_test() {
+        for (rulebook, input) in [
+            (double_rulebook(), "aabbbbbb"),
+            (double_rulebook(), "aab"),
+            (
+                TagRulebook::build(2, vec![('a', "ccdd"), ('b', "dd")]),
+                "aabbbb",
+            ),
+            (
+                TagRulebook::build(3, vec![('a', "x"), ('b', "y")]),
+                "aaabbbbbb",
+            ),
+        ] {
+            let mut system = TagSystem::new(input, rulebook.clone());
+            let encoder = system.encoder();
+            let mut cyclic = system.to_cyclic();
+            let steps_per_tag_step = encoder.alphabet().len() * rulebook.deletion_number();
+            while !system.is_halted() {
+                system.step();
+                for _ in 0..steps_per_tag_step {
+                    cyclic.step();
+                }
+                assert_eq!(
+                    encoder.decode_string(cyclic.current_string()).unwrap(),
+                    system.current_string()
+                );
+            }
+        }
+    }
+}
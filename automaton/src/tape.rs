@@ -0,0 +1,118 @@
+use std::fmt;
+
+#[derive(Clone, PartialEq)]
+pub struct Tape {
+    left: Vec<char>,
+    middle: char,
+    right: Vec<char>,
+    blank: char,
+}
+
+impl Tape {
+    pub fn new(left: Vec<char>, middle: char, right: Vec<char>, blank: char) -> Self {
+        Tape {
+            left,
+            middle,
+            right,
+            blank,
+        }
+    }
+    // 先頭の文字の上にヘッドを置いたテープを作る
+    pub fn from_input(string: &str, blank: char) -> Self {
+        let mut chars = string.chars();
+        match chars.next() {
+            Some(c) => Tape::new(vec![], c, chars.collect(), blank),
+            None => Tape::new(vec![], blank, vec![], blank),
+        }
+    }
+    pub fn left(&self) -> &Vec<char> {
+        &self.left
+    }
+    pub fn middle(&self) -> char {
+        self.middle
+    }
+    pub fn right(&self) -> &Vec<char> {
+        &self.right
+    }
+    pub fn blank(&self) -> char {
+        self.blank
+    }
+    pub fn write(&self, character: char) -> Self {
+        Tape::new(self.left.clone(), character, self.right.clone(), self.blank)
+    }
+    pub fn move_head_left(&self) -> Self {
+        let mut left = self.left.clone();
+        let middle = left.pop().unwrap_or(self.blank);
+        let mut right = vec![self.middle];
+        right.extend(self.right.iter().cloned());
+        Tape::new(left, middle, right, self.blank)
+    }
+    pub fn move_head_right(&self) -> Self {
+        let mut left = self.left.clone();
+        left.push(self.middle);
+        let middle = self.right.first().cloned().unwrap_or(self.blank);
+        let right = self.right.iter().skip(1).cloned().collect();
+        Tape::new(left, middle, right, self.blank)
+    }
+    // 両端の空白を除いたテープの内容
+    pub fn contents(&self) -> String {
+        let mut chars = self.left.clone();
+        chars.push(self.middle);
+        chars.extend(self.right.iter().cloned());
+        chars
+            .iter()
+            .collect::<String>()
+            .trim_matches(self.blank)
+            .to_string()
+    }
+}
+
+impl fmt::Display for Tape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "<Tape {}({}){}>",
+            self.left.iter().collect::<String>(),
+            self.middle,
+            self.right.iter().collect::<String>()
+        )
+    }
+}
+impl fmt::Debug for Tape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tape_test() {
+        let tape = Tape::new(vec!['1', '0', '1'], '1', vec![], '_');
+        assert_eq!(tape.to_string(), "<Tape 101(1)>");
+        assert_eq!(tape.move_head_left().to_string(), "<Tape 10(1)1>");
+        assert_eq!(tape.write('0').to_string(), "<Tape 101(0)>");
+        assert_eq!(tape.move_head_right().to_string(), "<Tape 1011(_)>");
+        assert_eq!(
+            tape.move_head_right().write('0').to_string(),
+            "<Tape 1011(0)>"
+        );
+    }
+
+    #[test]
+    fn blank_test() {
+        let tape = Tape::from_input("", '_');
+        assert_eq!(tape.middle(), '_');
+        assert_eq!(tape.move_head_left().to_string(), "<Tape (_)_>");
+        assert_eq!(Tape::from_input("ab", '_').contents(), "ab");
+        assert_eq!(
+            Tape::from_input("ab", '_')
+                .move_head_right()
+                .move_head_right()
+                .contents(),
+            "ab"
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use crate::dtm::{DTMRulebook, DTM};
+use crate::tape::Tape;
+use crate::tm_configuration::Direction::{Left, Right};
+use crate::tm_configuration::TMConfiguration;
+
+// 複数のテストで使うチューリングマシン
+
+// 2進数に1を足す。状態3で受理
+pub fn increment_rulebook() -> DTMRulebook {
+    DTMRulebook::build(vec![
+        (1, '0', 2, '1', Right),
+        (1, '1', 1, '0', Left),
+        (1, '_', 2, '1', Right),
+        (2, '0', 2, '0', Right),
+        (2, '1', 2, '1', Right),
+        (2, '_', 3, '_', Left),
+    ])
+}
+
+pub fn increment_dtm(tape: Tape) -> DTM {
+    DTM::new(
+        TMConfiguration::new(1, tape),
+        vec![3].into_iter().collect(),
+        increment_rulebook(),
+    )
+}
+
+// a^n b^n c^n を受理する。状態1から始めて状態6で受理
+pub fn anbncn_rulebook() -> DTMRulebook {
+    DTMRulebook::build(vec![
+        // 状態1: aを探して右へ
+        (1, 'X', 1, 'X', Right),
+        (1, 'a', 2, 'X', Right),
+        (1, '_', 6, '_', Left),
+        // 状態2: bを探して右へ
+        (2, 'a', 2, 'a', Right),
+        (2, 'X', 2, 'X', Right),
+        (2, 'b', 3, 'X', Right),
+        // 状態3: cを探して右へ
+        (3, 'b', 3, 'b', Right),
+        (3, 'X', 3, 'X', Right),
+        (3, 'c', 4, 'X', Right),
+        // 状態4: 文字列の終端を探して右へ
+        (4, 'c', 4, 'c', Right),
+        (4, '_', 5, '_', Left),
+        // 状態5: 文字列の先頭を探して左へ
+        (5, 'a', 5, 'a', Left),
+        (5, 'b', 5, 'b', Left),
+        (5, 'c', 5, 'c', Left),
+        (5, 'X', 5, 'X', Left),
+        (5, '_', 1, '_', Right),
+    ])
+}
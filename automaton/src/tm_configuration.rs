@@ -0,0 +1,101 @@
+use crate::state::State;
+use crate::tape::Tape;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TMConfiguration {
+    state: State,
+    tape: Tape,
+}
+
+impl TMConfiguration {
+    pub fn new(state: State, tape: Tape) -> Self {
+        TMConfiguration { state, tape }
+    }
+    pub fn state(&self) -> State {
+        self.state
+    }
+    pub fn tape(&self) -> &Tape {
+        &self.tape
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TMRule {
+    state: State,
+    character: char,
+    next_state: State,
+    write_character: char,
+    direction: Direction,
+}
+
+impl TMRule {
+    pub fn new(
+        state: State,
+        character: char,
+        next_state: State,
+        write_character: char,
+        direction: Direction,
+    ) -> Self {
+        TMRule {
+            state,
+            character,
+            next_state,
+            write_character,
+            direction,
+        }
+    }
+    pub fn state(&self) -> State {
+        self.state
+    }
+    pub fn character(&self) -> char {
+        self.character
+    }
+    pub fn next_state(&self) -> State {
+        self.next_state
+    }
+    pub fn write_character(&self) -> char {
+        self.write_character
+    }
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+    pub fn is_applied_to(&self, configuration: &TMConfiguration) -> bool {
+        self.state == configuration.state && self.character == configuration.tape.middle()
+    }
+    pub fn follow(&self, configuration: &TMConfiguration) -> TMConfiguration {
+        TMConfiguration::new(self.next_state, self.next_tape(configuration))
+    }
+    fn next_tape(&self, configuration: &TMConfiguration) -> Tape {
+        let written_tape = configuration.tape.write(self.write_character);
+        match self.direction {
+            Direction::Left => written_tape.move_head_left(),
+            Direction::Right => written_tape.move_head_right(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tm_rule_test() {
+        let rule = TMRule::new(1, '0', 2, '1', Direction::Right);
+        let tape = Tape::new(vec![], '0', vec![], '_');
+        assert!(rule.is_applied_to(&TMConfiguration::new(1, tape.clone())));
+        assert!(!rule.is_applied_to(&TMConfiguration::new(1, tape.write('1'))));
+        assert!(!rule.is_applied_to(&TMConfiguration::new(2, tape.clone())));
+
+        let rule = TMRule::new(1, '1', 1, '0', Direction::Left);
+        let configuration =
+            TMConfiguration::new(1, Tape::new(vec!['1', '0', '1'], '1', vec![], '_'));
+        let expected = TMConfiguration::new(1, Tape::new(vec!['1', '0'], '1', vec!['0'], '_'));
+        assert_eq!(rule.follow(&configuration), expected);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::dtm::{DTMRulebook, DTM};
+use crate::state::State;
+use crate::tape::Tape;
+use crate::tm_configuration::{Direction, TMConfiguration, TMRule};
+use std::collections::BTreeSet;
+
+// 万能チューリングマシンのテープに載せるための符号化
+//   $;<q><c>><q'><c'><L|R>;...#<Q>#|<cell>|<cell>^<cell>...
+// 状態も文字も幅widthのビット列で、状態の先頭ビットは受理状態かどうか、
+// 文字は0番が空白になるように番号を振る
+pub const TEXT_SYMBOLS: [char; 10] = ['0', '1', '$', ';', '>', 'L', 'R', '#', '|', '^'];
+
+pub struct TMEncoding {
+    width: usize,
+    states: Vec<State>,
+    accept_states: BTreeSet<State>,
+    alphabet: Vec<char>,
+    text: String,
+}
+
+impl TMEncoding {
+    pub fn encode(dtm: &DTM) -> Self {
+        let configuration = dtm.current_configuration();
+        let tape = configuration.tape();
+        let rules = dtm.rulebook().rules();
+
+        let mut states = BTreeSet::new();
+        states.insert(configuration.state());
+        states.extend(dtm.accept_states().iter().cloned());
+        states.extend(rules.iter().flat_map(|r| vec![r.state(), r.next_state()]));
+
+        let mut characters = BTreeSet::new();
+        characters.extend(tape.left().iter().cloned());
+        characters.insert(tape.middle());
+        characters.extend(tape.right().iter().cloned());
+        characters.extend(
+            rules
+                .iter()
+                .flat_map(|r| vec![r.character(), r.write_character()]),
+        );
+        characters.remove(&tape.blank());
+        let mut alphabet = vec![tape.blank()];
+        alphabet.extend(characters);
+
+        let width = (bits_for(states.len()) + 1).max(bits_for(alphabet.len()));
+        let mut encoding = TMEncoding {
+            width,
+            states: states.into_iter().collect(),
+            accept_states: dtm.accept_states().clone(),
+            alphabet,
+            text: String::new(),
+        };
+        encoding.text = encoding.encode_machine(dtm);
+        encoding
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn alphabet(&self) -> &Vec<char> {
+        &self.alphabet
+    }
+    pub fn to_text(&self) -> &str {
+        &self.text
+    }
+    // 記号1つを4ビットにして0と1だけの列にする
+    pub fn to_binary(&self) -> String {
+        self.text
+            .chars()
+            .map(|c| {
+                let index = TEXT_SYMBOLS.iter().position(|&s| s == c).unwrap();
+                to_bits(index, 4)
+            })
+            .collect()
+    }
+    pub fn text_from_binary(binary: &str) -> String {
+        binary
+            .as_bytes()
+            .chunks(4)
+            .map(|chunk| TEXT_SYMBOLS[from_bits(std::str::from_utf8(chunk).unwrap())])
+            .collect()
+    }
+    pub fn decode(&self, text: &str) -> DTM {
+        let parts = text.split('#').collect::<Vec<_>>();
+        let rules = parts[0]
+            .trim_start_matches('$')
+            .split(';')
+            .filter(|r| !r.is_empty())
+            .map(|r| self.decode_rule(r))
+            .collect();
+        DTM::new(
+            self.decode_configuration(text),
+            self.accept_states.clone(),
+            DTMRulebook::new(rules),
+        )
+    }
+    pub fn decode_configuration(&self, text: &str) -> TMConfiguration {
+        let parts = text.split('#').collect::<Vec<_>>();
+        let state = self.decode_state(parts[1]);
+
+        let mut cells = Vec::new();
+        let mut head = 0;
+        let mut chars = parts[2].chars().filter(|&c| c != '_');
+        while let Some(separator) = chars.next() {
+            if separator == '^' {
+                head = cells.len();
+            }
+            let bits = chars.by_ref().take(self.width).collect::<String>();
+            cells.push(self.decode_character(&bits));
+        }
+        let tape = Tape::new(
+            cells[..head].to_vec(),
+            cells[head],
+            cells[head + 1..].to_vec(),
+            self.alphabet[0],
+        );
+        TMConfiguration::new(state, tape)
+    }
+    fn encode_machine(&self, dtm: &DTM) -> String {
+        let configuration = dtm.current_configuration();
+        let tape = configuration.tape();
+        let rules = dtm
+            .rulebook()
+            .rules()
+            .iter()
+            .map(|r| {
+                format!(
+                    ";{}{}>{}{}{}",
+                    self.encode_state(r.state()),
+                    self.encode_character(r.character()),
+                    self.encode_state(r.next_state()),
+                    self.encode_character(r.write_character()),
+                    match r.direction() {
+                        Direction::Left => 'L',
+                        Direction::Right => 'R',
+                    }
+                )
+            })
+            .collect::<String>();
+        let cells = tape
+            .left()
+            .iter()
+            .map(|&c| format!("|{}", self.encode_character(c)))
+            .chain(std::iter::once(format!(
+                "^{}",
+                self.encode_character(tape.middle())
+            )))
+            .chain(
+                tape.right()
+                    .iter()
+                    .map(|&c| format!("|{}", self.encode_character(c))),
+            )
+            .collect::<String>();
+        format!(
+            "${}#{}#{}",
+            rules,
+            self.encode_state(configuration.state()),
+            cells
+        )
+    }
+    fn encode_state(&self, state: State) -> String {
+        let index = self.states.iter().position(|&s| s == state).unwrap();
+        let flag = if self.accept_states.contains(&state) {
+            "1"
+        } else {
+            "0"
+        };
+        format!("{}{}", flag, to_bits(index, self.width - 1))
+    }
+    fn encode_character(&self, character: char) -> String {
+        let index = self.alphabet.iter().position(|&c| c == character).unwrap();
+        to_bits(index, self.width)
+    }
+    fn decode_state(&self, bits: &str) -> State {
+        self.states[from_bits(&bits[1..])]
+    }
+    fn decode_character(&self, bits: &str) -> char {
+        self.alphabet[from_bits(bits)]
+    }
+    fn decode_rule(&self, rule: &str) -> TMRule {
+        let w = self.width;
+        let direction = match &rule[4 * w + 1..] {
+            "L" => Direction::Left,
+            _ => Direction::Right,
+        };
+        TMRule::new(
+            self.decode_state(&rule[..w]),
+            self.decode_character(&rule[w..2 * w]),
+            self.decode_state(&rule[2 * w + 1..3 * w + 1]),
+            self.decode_character(&rule[3 * w + 1..4 * w + 1]),
+            direction,
+        )
+    }
+}
+
+fn bits_for(n: usize) -> usize {
+    let mut bits = 1;
+    while (1 << bits) < n {
+        bits += 1;
+    }
+    bits
+}
+
+fn to_bits(n: usize, width: usize) -> String {
+    format!("{:0width$b}", n, width = width)
+}
+
+fn from_bits(bits: &str) -> usize {
+    if bits.is_empty() {
+        0
+    } else {
+        usize::from_str_radix(bits, 2).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::increment_dtm;
+
+    #[test]
+    fn encode_test() {
+        let dtm = increment_dtm(Tape::new(vec!['1', '0'], '1', vec![], '_'));
+        let encoding = TMEncoding::encode(&dtm);
+        assert_eq!(encoding.width(), 3);
+        assert_eq!(encoding.alphabet(), &vec!['_', '0', '1']);
+        assert_eq!(
+            encoding.to_text(),
+            "$;000001>001010R;000010>000001L;000000>001010R\
+             ;001001>001001R;001010>001010R;001000>110000L\
+             #000#|010|001^010"
+        );
+    }
+
+    #[test]
+    fn binary_test() {
+        let dtm = increment_dtm(Tape::new(vec!['1', '0'], '1', vec![], '_'));
+        let encoding = TMEncoding::encode(&dtm);
+        let binary = encoding.to_binary();
+        assert!(binary.chars().all(|c| c == '0' || c == '1'));
+        assert_eq!(TMEncoding::text_from_binary(&binary), encoding.to_text());
+    }
+
+    #[test]
+    fn decode_test() {
+        let dtm = increment_dtm(Tape::new(vec!['1', '0', '1'], '1', vec![], '_'));
+        let encoding = TMEncoding::encode(&dtm);
+        let mut decoded = encoding.decode(encoding.to_text());
+        assert_eq!(decoded.current_configuration(), dtm.current_configuration());
+        decoded.run();
+        assert!(decoded.is_accept());
+        assert_eq!(decoded.current_configuration().tape().contents(), "1100");
+    }
+}
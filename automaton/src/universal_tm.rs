@@ -0,0 +1,342 @@
+use crate::dtm::{DTMRulebook, DTM};
+use crate::state::State;
+use crate::tape::Tape;
+use crate::tm_configuration::Direction::{Left, Right};
+use crate::tm_configuration::{Direction, TMConfiguration};
+use crate::tm_encoding::TMEncoding;
+
+// 万能チューリングマシン自身のテープの記号
+// a, b は比較やコピーの途中で印をつけた0, 1
+// * は照合中の規則、! は照合に失敗した規則
+const ALPHABET: [char; 15] = [
+    '0', '1', 'a', 'b', '$', ';', '*', '!', '>', 'L', 'R', '#', '|', '^', '_',
+];
+
+const START: State = 1;
+const CHECK_ACCEPT: State = 2;
+const ACCEPT: State = 3;
+const REWIND_TO_START: State = 4;
+const REWIND_TO_SELECT: State = 5;
+const SELECT: State = 6;
+const NO_MATCH: State = 7;
+const FETCH: State = 8;
+const CARRY_0: State = 10;
+const CARRY_1: State = 11;
+const KEY_0: State = 12;
+const KEY_1: State = 13;
+const SEEK_HEAD_0: State = 14;
+const SEEK_HEAD_1: State = 15;
+const KEY_CELL_0: State = 16;
+const KEY_CELL_1: State = 17;
+const RETURN: State = 18;
+const FAIL: State = 19;
+const COPY: State = 20;
+const PUT_0: State = 21;
+const PUT_1: State = 22;
+const PUT_STATE_0: State = 23;
+const PUT_STATE_1: State = 24;
+const PUT_SEEK_HEAD_0: State = 25;
+const PUT_SEEK_HEAD_1: State = 26;
+const PUT_CELL_0: State = 27;
+const PUT_CELL_1: State = 28;
+const BACK: State = 29;
+const SKIP_PREFIX: State = 30;
+const MOVE_LEFT: State = 31;
+const MOVE_RIGHT: State = 32;
+const MOVE_LEFT_SKIP: State = 33;
+const MOVE_RIGHT_SKIP: State = 34;
+const EXTEND_TO_STATE: State = 35;
+const EXTEND_STATE_LEFT: State = 36;
+const EXTEND_MARK: State = 37;
+const EXTEND_APPEND: State = 38;
+const UNMARK_STATE: State = 39;
+const INSERT_STATE_LEFT: State = 40;
+const INSERT_MARK: State = 41;
+const INSERT_GO: State = 42;
+const INSERT_ZERO: State = 43;
+const INSERT_HEAD: State = 44;
+const INSERT_BACK: State = 45;
+const INSERT_FINAL_BACK: State = 46;
+// 挿入で右にずらしている記号を覚えておく状態
+// (挿入した記号が0か^か) x (運んでいる記号)
+const INSERT_CARRY: State = 50;
+
+const CELL_SYMBOLS: [char; 4] = ['0', '1', '|', '^'];
+
+fn carry_state(head: bool, symbol: char) -> State {
+    let index = CELL_SYMBOLS.iter().position(|&c| c == symbol).unwrap() as State;
+    INSERT_CARRY + if head { 4 } else { 0 } + index
+}
+
+struct Rules(Vec<(State, char, State, char, Direction)>);
+
+impl Rules {
+    fn add(&mut self, state: State, read: char, next_state: State, write: char, d: Direction) {
+        self.0.push((state, read, next_state, write, d));
+    }
+    // exceptに含まれない記号はそのままにして同じ方向に進み続ける
+    fn skip(&mut self, state: State, direction: Direction, except: &[char]) {
+        for &c in ALPHABET.iter().filter(|c| !except.contains(c)) {
+            self.add(state, c, state, c, direction);
+        }
+    }
+}
+
+pub fn universal_rulebook() -> DTMRulebook {
+    let mut r = Rules(Vec::new());
+
+    // 1ステップの始まり: 規則の印を消しながら現在の状態まで進む
+    r.add(START, 'a', START, '0', Right);
+    r.add(START, 'b', START, '1', Right);
+    r.add(START, '*', START, ';', Right);
+    r.add(START, '!', START, ';', Right);
+    r.add(START, '#', CHECK_ACCEPT, '#', Right);
+    r.skip(START, Right, &['a', 'b', '*', '!', '#']);
+
+    // 状態の先頭ビットが1なら受理
+    r.add(CHECK_ACCEPT, '1', ACCEPT, '1', Right);
+    r.add(CHECK_ACCEPT, '0', REWIND_TO_SELECT, '0', Left);
+
+    r.add(REWIND_TO_START, '$', START, '$', Right);
+    r.skip(REWIND_TO_START, Left, &['$']);
+    r.add(REWIND_TO_SELECT, '$', SELECT, '$', Right);
+    r.skip(REWIND_TO_SELECT, Left, &['$']);
+
+    // まだ試していない規則を選ぶ。なければ行き詰まり
+    r.add(SELECT, ';', FETCH, '*', Right);
+    r.add(SELECT, '#', NO_MATCH, '#', Right);
+    r.skip(SELECT, Right, &[';', '#']);
+
+    // 規則の左辺から印のついていない最初のビットを取り出す
+    r.add(FETCH, '0', CARRY_0, 'a', Right);
+    r.add(FETCH, '1', CARRY_1, 'b', Right);
+    r.add(FETCH, '>', COPY, '>', Right);
+    r.skip(FETCH, Right, &['0', '1', '>']);
+
+    // 取り出したビットを状態とヘッドの下の文字の列と比べる
+    for &(bit, marked, carry, key, seek, key_cell) in &[
+        ('0', 'a', CARRY_0, KEY_0, SEEK_HEAD_0, KEY_CELL_0),
+        ('1', 'b', CARRY_1, KEY_1, SEEK_HEAD_1, KEY_CELL_1),
+    ] {
+        let other = if bit == '0' { '1' } else { '0' };
+        r.add(carry, '#', key, '#', Right);
+        r.skip(carry, Right, &['#']);
+
+        r.add(key, bit, RETURN, marked, Left);
+        r.add(key, other, FAIL, other, Left);
+        r.add(key, '#', seek, '#', Right);
+        r.skip(key, Right, &['0', '1', '#']);
+
+        r.add(seek, '^', key_cell, '^', Right);
+        r.skip(seek, Right, &['^']);
+
+        r.add(key_cell, bit, RETURN, marked, Left);
+        r.add(key_cell, other, FAIL, other, Left);
+        r.skip(key_cell, Right, &['0', '1']);
+    }
+
+    r.add(RETURN, '*', FETCH, '*', Right);
+    r.skip(RETURN, Left, &['*']);
+
+    // 一致しなかったら印を消して規則を失敗済みにする
+    r.add(FAIL, 'a', FAIL, '0', Left);
+    r.add(FAIL, 'b', FAIL, '1', Left);
+    r.add(FAIL, '*', SELECT, '!', Right);
+    r.skip(FAIL, Left, &['a', 'b', '*']);
+
+    // 一致した規則の右辺を状態とヘッドの下の文字に書き写す
+    r.add(COPY, '0', PUT_0, 'a', Right);
+    r.add(COPY, '1', PUT_1, 'b', Right);
+    r.add(COPY, 'L', MOVE_LEFT, 'L', Right);
+    r.add(COPY, 'R', MOVE_RIGHT, 'R', Right);
+    r.skip(COPY, Right, &['0', '1', 'L', 'R']);
+
+    for &(bit, put, put_state, seek, put_cell) in &[
+        ('0', PUT_0, PUT_STATE_0, PUT_SEEK_HEAD_0, PUT_CELL_0),
+        ('1', PUT_1, PUT_STATE_1, PUT_SEEK_HEAD_1, PUT_CELL_1),
+    ] {
+        r.add(put, '#', put_state, '#', Right);
+        r.skip(put, Right, &['#']);
+
+        r.add(put_state, 'a', BACK, bit, Left);
+        r.add(put_state, 'b', BACK, bit, Left);
+        r.add(put_state, '#', seek, '#', Right);
+        r.skip(put_state, Right, &['a', 'b', '#']);
+
+        r.add(seek, '^', put_cell, '^', Right);
+        r.skip(seek, Right, &['^']);
+
+        r.add(put_cell, 'a', BACK, bit, Left);
+        r.add(put_cell, 'b', BACK, bit, Left);
+        r.skip(put_cell, Right, &['a', 'b']);
+    }
+
+    r.add(BACK, '*', SKIP_PREFIX, '*', Right);
+    r.skip(BACK, Left, &['*']);
+    r.add(SKIP_PREFIX, '>', COPY, '>', Right);
+    r.skip(SKIP_PREFIX, Right, &['>']);
+
+    // ヘッドの印を隣のセルに動かす
+    r.add(MOVE_RIGHT, '^', MOVE_RIGHT_SKIP, '|', Right);
+    r.skip(MOVE_RIGHT, Right, &['^']);
+    r.add(MOVE_RIGHT_SKIP, '|', REWIND_TO_START, '^', Left);
+    r.add(MOVE_RIGHT_SKIP, '_', EXTEND_TO_STATE, '^', Left);
+    r.skip(MOVE_RIGHT_SKIP, Right, &['|', '_']);
+
+    r.add(MOVE_LEFT, '^', MOVE_LEFT_SKIP, '|', Left);
+    r.skip(MOVE_LEFT, Right, &['^']);
+    r.add(MOVE_LEFT_SKIP, '|', REWIND_TO_START, '^', Left);
+    r.add(MOVE_LEFT_SKIP, '#', INSERT_STATE_LEFT, '#', Left);
+    r.skip(MOVE_LEFT_SKIP, Left, &['|', '#']);
+
+    // 右端を越えたら状態の幅だけ空白(0...0)を書き足す
+    r.add(EXTEND_TO_STATE, '#', EXTEND_STATE_LEFT, '#', Left);
+    r.skip(EXTEND_TO_STATE, Left, &['#']);
+    r.add(EXTEND_STATE_LEFT, '#', EXTEND_MARK, '#', Right);
+    r.skip(EXTEND_STATE_LEFT, Left, &['#']);
+    r.add(EXTEND_MARK, '0', EXTEND_APPEND, 'a', Right);
+    r.add(EXTEND_MARK, '1', EXTEND_APPEND, 'b', Right);
+    r.add(EXTEND_MARK, '#', UNMARK_STATE, '#', Left);
+    r.skip(EXTEND_MARK, Right, &['0', '1', '#']);
+    r.add(EXTEND_APPEND, '_', EXTEND_TO_STATE, '0', Left);
+    r.skip(EXTEND_APPEND, Right, &['_']);
+
+    r.add(UNMARK_STATE, 'a', UNMARK_STATE, '0', Left);
+    r.add(UNMARK_STATE, 'b', UNMARK_STATE, '1', Left);
+    r.add(UNMARK_STATE, '#', REWIND_TO_START, '#', Left);
+    r.skip(UNMARK_STATE, Left, &['a', 'b', '#']);
+
+    // 左端を越えたら先頭にセルを1つ差し込む
+    r.add(INSERT_STATE_LEFT, '#', INSERT_MARK, '#', Right);
+    r.skip(INSERT_STATE_LEFT, Left, &['#']);
+    r.add(INSERT_MARK, '0', INSERT_GO, 'a', Right);
+    r.add(INSERT_MARK, '1', INSERT_GO, 'b', Right);
+    r.add(INSERT_MARK, '#', INSERT_HEAD, '#', Right);
+    r.skip(INSERT_MARK, Right, &['0', '1', '#']);
+    r.add(INSERT_GO, '#', INSERT_ZERO, '#', Right);
+    r.skip(INSERT_GO, Right, &['#']);
+
+    for &(insert, head, inserted) in &[(INSERT_ZERO, false, '0'), (INSERT_HEAD, true, '^')] {
+        for &c in CELL_SYMBOLS.iter() {
+            r.add(insert, c, carry_state(head, c), inserted, Right);
+            for &d in CELL_SYMBOLS.iter() {
+                r.add(carry_state(head, c), d, carry_state(head, d), c, Right);
+            }
+            let back = if head { INSERT_FINAL_BACK } else { INSERT_BACK };
+            r.add(carry_state(head, c), '_', back, c, Left);
+        }
+    }
+    r.add(INSERT_BACK, '#', INSERT_STATE_LEFT, '#', Left);
+    r.skip(INSERT_BACK, Left, &['#']);
+    r.add(INSERT_FINAL_BACK, '#', UNMARK_STATE, '#', Left);
+    r.skip(INSERT_FINAL_BACK, Left, &['#']);
+
+    DTMRulebook::build(r.0)
+}
+
+pub struct UniversalTM {
+    encoding: TMEncoding,
+    dtm: DTM,
+}
+
+impl UniversalTM {
+    pub fn new(encoding: TMEncoding) -> Self {
+        let text = encoding.to_text().to_string();
+        UniversalTM::with_text(encoding, &text)
+    }
+    pub fn from_binary(encoding: TMEncoding, binary: &str) -> Self {
+        UniversalTM::with_text(encoding, &TMEncoding::text_from_binary(binary))
+    }
+    fn with_text(encoding: TMEncoding, text: &str) -> Self {
+        let tape = Tape::from_input(text, '_');
+        let dtm = DTM::new(
+            TMConfiguration::new(START, tape),
+            vec![ACCEPT].into_iter().collect(),
+            universal_rulebook(),
+        );
+        UniversalTM { encoding, dtm }
+    }
+    pub fn dtm(&self) -> &DTM {
+        &self.dtm
+    }
+    pub fn run(&mut self) {
+        self.dtm.run();
+    }
+    pub fn is_accept(&self) -> bool {
+        self.dtm.is_accept()
+    }
+    // シミュレートしている機械の現在の構成をテープから読み出す
+    pub fn simulated_configuration(&self) -> TMConfiguration {
+        self.encoding
+            .decode_configuration(&self.dtm.current_configuration().tape().contents())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dtm::DTMDesign;
+    use crate::test_helpers::{anbncn_rulebook, increment_dtm};
+
+    fn assert_same_as_direct(mut dtm: DTM) {
+        let mut utm = UniversalTM::new(TMEncoding::encode(&dtm));
+        dtm.run();
+        utm.run();
+        assert_eq!(utm.is_accept(), dtm.is_accept());
+        assert_eq!(&utm.simulated_configuration(), dtm.current_configuration());
+    }
+
+    #[test]
+    fn universal_rulebook_is_deterministic() {
+        let rulebook = universal_rulebook();
+        for (i, rule) in rulebook.rules().iter().enumerate() {
+            assert!(
+                !rulebook.rules()[i + 1..]
+                    .iter()
+                    .any(|r| r.state() == rule.state() && r.character() == rule.character()),
+                "duplicate rule for {} {}",
+                rule.state(),
+                rule.character()
+            );
+        }
+    }
+
+    #[test]
+    fn increment_test() {
+        assert_same_as_direct(increment_dtm(Tape::new(
+            vec!['1', '0', '1'],
+            '1',
+            vec![],
+            '_',
+        )));
+        // 右端を越える
+        assert_same_as_direct(increment_dtm(Tape::new(vec!['1', '0'], '0', vec![], '_')));
+        // 左端を越える
+        assert_same_as_direct(increment_dtm(Tape::new(vec!['1', '1'], '1', vec![], '_')));
+    }
+
+    #[test]
+    fn stuck_test() {
+        let dtm = increment_dtm(Tape::new(vec!['1', '2', '1'], '1', vec![], '_'));
+        assert_same_as_direct(dtm);
+    }
+
+    #[test]
+    fn binary_test() {
+        let dtm = increment_dtm(Tape::new(vec!['1', '1'], '1', vec![], '_'));
+        let binary = TMEncoding::encode(&dtm).to_binary();
+        let mut utm = UniversalTM::from_binary(TMEncoding::encode(&dtm), &binary);
+        utm.run();
+        assert!(utm.is_accept());
+        assert_eq!(utm.simulated_configuration().tape().contents(), "1000");
+    }
+
+    #[test]
+    fn design_test() {
+        let rulebook = anbncn_rulebook();
+        let dtm_design = DTMDesign::new((1, '_', vec![6].into_iter().collect(), rulebook));
+        for input in &["abc", "aabbc", "acb"] {
+            assert_same_as_direct(dtm_design.to_dtm(input));
+        }
+    }
+}
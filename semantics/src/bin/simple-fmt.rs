@@ -0,0 +1,62 @@
+use semantics::formatter::format;
+use std::io::Read;
+use std::process::exit;
+
+// simple-fmt [--check] [FILE...]
+// ファイルがなければ標準入力を整形して標準出力に書く。
+// --checkでは書き換えず、整形されていないファイルがあれば失敗する
+fn main() {
+    let arguments = std::env::args().skip(1).collect::<Vec<_>>();
+    let check = arguments.iter().any(|a| a == "--check");
+    let files = arguments
+        .iter()
+        .filter(|a| *a != "--check")
+        .collect::<Vec<_>>();
+
+    if files.is_empty() {
+        let mut source = String::new();
+        if let Err(error) = std::io::stdin().read_to_string(&mut source) {
+            eprintln!("<stdin>: {}", error);
+            exit(1);
+        }
+        match format(&source) {
+            Ok(formatted) if check && formatted != source => {
+                eprintln!("<stdin> is not formatted");
+                exit(1);
+            }
+            Ok(formatted) if !check => print!("{}", formatted),
+            Ok(_) => {}
+            Err(error) => {
+                eprintln!("<stdin>: {}", error);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    let mut failed = false;
+    for file in files {
+        let result = std::fs::read_to_string(file)
+            .map_err(|e| e.to_string())
+            .and_then(|source| format(&source).map(|formatted| (source, formatted)));
+        match result {
+            Ok((source, formatted)) if formatted != source => {
+                if check {
+                    eprintln!("{} is not formatted", file);
+                    failed = true;
+                } else if let Err(error) = std::fs::write(file, formatted) {
+                    eprintln!("{}: {}", file, error);
+                    failed = true;
+                }
+            }
+            Ok(_) => {}
+            Err(error) => {
+                eprintln!("{}: {}", file, error);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        exit(1);
+    }
+}
@@ -0,0 +1,486 @@
+use std::collections::BTreeSet;
+
+use crate::expression::{Environment, Expression};
+use crate::statement::Statement;
+
+// 変数は型の付いた値として持つ。型の合わない演算や未定義の変数は実行時に止める
+const PRELUDE: &str = r#"#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+typedef enum { UNDEFINED, NUMBER, BOOLEAN } kind;
+typedef struct { kind kind; uint32_t value; } value;
+
+static inline value number(uint32_t n) { value v = { NUMBER, n }; return v; }
+static inline value boolean(int b) { value v = { BOOLEAN, b != 0 }; return v; }
+static inline void fail(const char *message) { fprintf(stderr, "%s\n", message); exit(1); }
+static inline value get(value v) { if (v.kind == UNDEFINED) fail("undefined variable"); return v; }
+static inline uint32_t to_number(value v) { if (v.kind != NUMBER) fail("expression is not number"); return v.value; }
+static inline int is_true(value v) { if (v.kind != BOOLEAN) fail("condition is not bool"); return v.value; }
+static inline value add(value l, value r) {
+    uint32_t a = to_number(l), b = to_number(r);
+    if (a > UINT32_MAX - b) fail("overflow");
+    return number(a + b);
+}
+static inline value multiply(value l, value r) {
+    uint32_t a = to_number(l), b = to_number(r);
+    if (b != 0 && a > UINT32_MAX / b) fail("overflow");
+    return number(a * b);
+}
+static inline value less_than(value l, value r) { return boolean(to_number(l) < to_number(r)); }
+static inline value equal(value l, value r) { return boolean(l.kind == r.kind && l.value == r.value); }
+
+static void print_value(const char *name, value v, int *first) {
+    if (v.kind == UNDEFINED) return;
+    printf("%s\"%s\": ", *first ? "" : ", ", name);
+    if (v.kind == NUMBER) printf("Number(%u)", (unsigned) v.value);
+    else printf("Boolean(%s)", v.value ? "true" : "false");
+    *first = 0;
+}
+"#;
+
+fn c_name(name: &str) -> String {
+    format!("v_{}", name)
+}
+
+fn c_value(value: &Expression) -> String {
+    match value {
+        Expression::Number(n) => format!("number({}u)", n),
+        Expression::Boolean(b) => format!("boolean({})", *b as u8),
+        _ => unreachable!(),
+    }
+}
+
+impl Expression {
+    pub fn to_c(&self) -> Result<String, String> {
+        self.check_supported(&[])?;
+        Ok(self.c_expression())
+    }
+    fn c_expression(&self) -> String {
+        match self {
+            Expression::Number(_) | Expression::Boolean(_) => c_value(self),
+            Expression::Variable(name) => format!("get({})", c_name(name)),
+            Expression::Add { left, right } => {
+                format!("add({}, {})", left.c_expression(), right.c_expression())
+            }
+            Expression::Multiply { left, right } => {
+                format!(
+                    "multiply({}, {})",
+                    left.c_expression(),
+                    right.c_expression()
+                )
+            }
+            Expression::LessThan { left, right } => {
+                format!(
+                    "less_than({}, {})",
+                    left.c_expression(),
+                    right.c_expression()
+                )
+            }
+            Expression::Equal { left, right } => {
+                format!("equal({}, {})", left.c_expression(), right.c_expression())
+            }
+            Expression::Tuple(_)
+            | Expression::Record(_)
+            | Expression::Field { .. }
+            | Expression::Function { .. }
+            | Expression::Closure { .. }
+            | Expression::Call { .. } => unreachable!(),
+        }
+    }
+    fn c_variables(&self, names: &mut BTreeSet<String>) {
+        match self {
+            Expression::Variable(name) => {
+                names.insert(name.clone());
+            }
+            Expression::Add { left, right }
+            | Expression::Multiply { left, right }
+            | Expression::LessThan { left, right }
+            | Expression::Equal { left, right } => {
+                left.c_variables(names);
+                right.c_variables(names);
+            }
+            Expression::Number(_) | Expression::Boolean(_) => {}
+            Expression::Tuple(_)
+            | Expression::Record(_)
+            | Expression::Field { .. }
+            | Expression::Function { .. }
+            | Expression::Closure { .. }
+            | Expression::Call { .. } => unreachable!(),
+        }
+    }
+}
+
+impl Statement {
+    pub fn to_c(&self) -> Result<String, String> {
+        self.to_c_with_environment(&Environment::new())
+    }
+    // environmentを最初の値として埋め込み、最後の環境をMachineと同じ形式で出力する
+    pub fn to_c_with_environment(&self, environment: &Environment) -> Result<String, String> {
+        self.check_supported(&[])?;
+        let mut names = environment.keys().cloned().collect::<BTreeSet<_>>();
+        self.c_variables(&mut names);
+
+        let mut lines = vec![PRELUDE.to_string(), "int main(void) {".to_string()];
+        for name in &names {
+            lines.push(format!("    value {} = {{ UNDEFINED, 0 }};", c_name(name)));
+        }
+        let mut initial = environment.iter().collect::<Vec<_>>();
+        initial.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, value) in initial {
+            match value {
+                Expression::Number(_) | Expression::Boolean(_) => {
+                    lines.push(format!("    {} = {};", c_name(name), c_value(value)))
+                }
+                _ => {
+                    return Err(format!(
+                        "environment value of {} is not number or bool: {}",
+                        name, value
+                    ))
+                }
+            }
+        }
+        self.c_statements(1, &mut lines);
+        lines.push("    int first = 1;".to_string());
+        lines.push(format!("    printf(\"{}, {{\");", Statement::DoNothing));
+        for name in &names {
+            lines.push(format!(
+                "    print_value(\"{}\", {}, &first);",
+                name,
+                c_name(name)
+            ));
+        }
+        lines.push("    printf(\"}\\n\");".to_string());
+        lines.push("    return 0;".to_string());
+        lines.push("}".to_string());
+        Ok(lines.join("\n") + "\n")
+    }
+    fn c_statements(&self, depth: usize, lines: &mut Vec<String>) {
+        let indent = "    ".repeat(depth);
+        match self {
+            Statement::DoNothing => {}
+            Statement::Choose { .. }
+            | Statement::Parallel { .. }
+            | Statement::Assert { .. }
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Throw { .. }
+            | Statement::Try { .. }
+            | Statement::Let { .. }
+            | Statement::Block { .. }
+            | Statement::Var { .. }
+            | Statement::Update { .. } => unreachable!(),
+            Statement::Assignment { name, expression } => {
+                lines.push(format!(
+                    "{}{} = {};",
+                    indent,
+                    c_name(name),
+                    expression.c_expression()
+                ));
+            }
+            Statement::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                lines.push(format!(
+                    "{}if (is_true({})) {{",
+                    indent,
+                    condition.c_expression()
+                ));
+                consequence.c_statements(depth + 1, lines);
+                lines.push(format!("{}}} else {{", indent));
+                alternative.c_statements(depth + 1, lines);
+                lines.push(format!("{}}}", indent));
+            }
+            Statement::Sequence { first, second } => {
+                first.c_statements(depth, lines);
+                second.c_statements(depth, lines);
+            }
+            Statement::While { condition, body } => {
+                lines.push(format!(
+                    "{}while (is_true({})) {{",
+                    indent,
+                    condition.c_expression()
+                ));
+                body.c_statements(depth + 1, lines);
+                lines.push(format!("{}}}", indent));
+            }
+        }
+    }
+    fn c_variables(&self, names: &mut BTreeSet<String>) {
+        match self {
+            Statement::DoNothing => {}
+            Statement::Choose { .. }
+            | Statement::Parallel { .. }
+            | Statement::Assert { .. }
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Throw { .. }
+            | Statement::Try { .. }
+            | Statement::Let { .. }
+            | Statement::Block { .. }
+            | Statement::Var { .. }
+            | Statement::Update { .. } => unreachable!(),
+            Statement::Assignment { name, expression } => {
+                names.insert(name.clone());
+                expression.c_variables(names);
+            }
+            Statement::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                condition.c_variables(names);
+                consequence.c_variables(names);
+                alternative.c_variables(names);
+            }
+            Statement::Sequence { first, second } => {
+                first.c_variables(names);
+                second.c_variables(names);
+            }
+            Statement::While { condition, body } => {
+                condition.c_variables(names);
+                body.c_variables(names);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::format_environment;
+    use std::fs;
+    use std::process::{Command, Output};
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn multiply(left: Expression, right: Expression) -> Expression {
+        Expression::Multiply {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn equal(left: Expression, right: Expression) -> Expression {
+        Expression::Equal {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+
+    // Cコンパイラでビルドして実行する
+    fn compile_and_run(test_name: &str, source: &str) -> Output {
+        let directory =
+            std::env::temp_dir().join(format!("simple-c-{}-{}", std::process::id(), test_name));
+        fs::create_dir_all(&directory).unwrap();
+        let source_path = directory.join("program.c");
+        let binary_path = directory.join("program");
+        fs::write(&source_path, source).unwrap();
+        let status = Command::new("cc")
+            .args(["-std=c99", "-Wall", "-Werror", "-o"])
+            .arg(&binary_path)
+            .arg(&source_path)
+            .status()
+            .expect("C compiler not found");
+        assert!(status.success(), "{}", source);
+        let output = Command::new(&binary_path).output().unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+        output
+    }
+
+    fn assert_same_as_evaluate(test_name: &str, statement: &Statement, environment: &Environment) {
+        let source = statement.to_c_with_environment(environment).unwrap();
+        let expected = statement.evaluate(&mut environment.clone());
+        let output = compile_and_run(test_name, &source);
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            format!(
+                "{}, {}\n",
+                Statement::DoNothing,
+                format_environment(&expected)
+            )
+        );
+    }
+
+    #[test]
+    fn expression_test() {
+        let expression = add(variable("x"), multiply(number(2), number(3)));
+        assert_eq!(
+            expression.to_c(),
+            Ok("add(get(v_x), multiply(number(2u), number(3u)))".to_string())
+        );
+        let expression = Expression::Field {
+            expression: Box::new(variable("p")),
+            name: "x".to_string(),
+        };
+        assert_eq!(
+            expression.to_c(),
+            Err("field access is not supported: p.x".to_string())
+        );
+    }
+
+    #[test]
+    fn assignment_test() {
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(2));
+        let statement = assign("x", add(variable("x"), number(1)));
+        assert_same_as_evaluate("assignment", &statement, &environment);
+    }
+
+    #[test]
+    fn equal_test() {
+        // 真偽値どうし、数と真偽値も比べられる
+        let statement = sequence(
+            assign("b", equal(variable("x"), number(3))),
+            sequence(
+                assign(
+                    "c",
+                    equal(variable("b"), less_than(variable("x"), number(5))),
+                ),
+                assign("d", equal(variable("x"), Expression::Boolean(true))),
+            ),
+        );
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(3));
+        assert_same_as_evaluate("equal", &statement, &environment);
+    }
+
+    #[test]
+    fn if_test() {
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), Expression::Boolean(true));
+        let statement = Statement::If {
+            condition: variable("x"),
+            consequence: Box::new(assign("y", number(1))),
+            alternative: Box::new(assign("y", number(2))),
+        };
+        assert_same_as_evaluate("if", &statement, &environment);
+    }
+
+    #[test]
+    fn while_test() {
+        // 階乗
+        let statement = sequence(
+            assign("result", number(1)),
+            sequence(
+                assign("i", number(1)),
+                Statement::While {
+                    condition: less_than(variable("i"), add(variable("n"), number(1))),
+                    body: Box::new(sequence(
+                        assign("result", multiply(variable("result"), variable("i"))),
+                        sequence(
+                            assign("i", add(variable("i"), number(1))),
+                            assign("done", less_than(variable("n"), variable("i"))),
+                        ),
+                    )),
+                },
+            ),
+        );
+        let mut environment = Environment::new();
+        environment.insert("n".to_string(), number(10));
+        assert_same_as_evaluate("while", &statement, &environment);
+    }
+
+    #[test]
+    fn undefined_variable_test() {
+        let source = assign("x", variable("y")).to_c().unwrap();
+        let output = compile_and_run("undefined", &source);
+        assert!(!output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            "undefined variable\n"
+        );
+    }
+
+    #[test]
+    fn overflow_test() {
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(u32::MAX));
+        for (test_name, expression) in [
+            ("add_overflow", add(variable("x"), number(1))),
+            ("multiply_overflow", multiply(variable("x"), number(2))),
+        ] {
+            let source = assign("y", expression)
+                .to_c_with_environment(&environment)
+                .unwrap();
+            let output = compile_and_run(test_name, &source);
+            assert!(!output.status.success());
+            assert_eq!(String::from_utf8(output.stderr).unwrap(), "overflow\n");
+        }
+        // 桁あふれしない境目は通る
+        let statement = sequence(
+            assign("y", add(variable("x"), number(0))),
+            assign("z", multiply(variable("x"), number(1))),
+        );
+        assert_same_as_evaluate("no_overflow", &statement, &environment);
+    }
+
+    #[test]
+    fn environment_error_test() {
+        let mut environment = Environment::new();
+        environment.insert(
+            "p".to_string(),
+            Expression::Tuple(vec![number(1), number(2)]),
+        );
+        assert_eq!(
+            assign("x", number(1)).to_c_with_environment(&environment),
+            Err("environment value of p is not number or bool: (1, 2)".to_string())
+        );
+    }
+
+    #[test]
+    fn unsupported_test() {
+        let statement = Statement::Choose {
+            first: Box::new(assign("x", number(1))),
+            second: Box::new(assign("x", number(2))),
+        };
+        assert_eq!(
+            statement.to_c(),
+            Err(
+                "nondeterministic statement is not supported: choose { x = 1 } or { x = 2 }"
+                    .to_string()
+            )
+        );
+        let statement = assign(
+            "y",
+            Expression::Call {
+                function: Box::new(variable("f")),
+                arguments: vec![number(1)],
+            },
+        );
+        assert_eq!(
+            statement.to_c(),
+            Err("function call is not supported: f(1)".to_string())
+        );
+    }
+}
@@ -0,0 +1,1417 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::expression::{Environment, Expression};
+use crate::scope::{restore, save};
+use crate::statement::Statement;
+
+// 次に何をするか。式や文は最初に一度だけ部分木をRcで共有する形に変換しておき、
+// 機械はその部分木を指すだけにする。ループを回しても関数を呼んでも木を複製しないので、
+// 1ステップは本体の大きさによらない
+#[derive(Clone, Debug)]
+pub enum Control {
+    Expression(Rc<Term>),
+    Value(Value),
+    Statement(Rc<Command>),
+    Completion(Completion),
+}
+
+impl fmt::Display for Control {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Control::Expression(term) => write!(f, "{}", term),
+            Control::Value(value) => write!(f, "value {}", value),
+            Control::Statement(command) => write!(f, "{}", command),
+            Control::Completion(Completion::Normal) => write!(f, "normal"),
+            Control::Completion(Completion::Break) => write!(f, "break"),
+            Control::Completion(Completion::Continue) => write!(f, "continue"),
+            Control::Completion(Completion::Throw(value)) => write!(f, "throw {}", value),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Completion {
+    Normal,
+    Break,
+    Continue,
+    Throw(Value),
+}
+
+// 機械の上の式
+#[derive(Debug)]
+pub enum Term {
+    Value(Value),
+    Variable(String),
+    Binary {
+        operator: Operator,
+        left: Rc<Term>,
+        right: Rc<Term>,
+    },
+    Tuple(Rc<Vec<Rc<Term>>>),
+    Record(Rc<Vec<(String, Rc<Term>)>>),
+    Field {
+        term: Rc<Term>,
+        name: String,
+    },
+    // 取り込む変数は変換するときに求めておく
+    Function {
+        params: Rc<Vec<String>>,
+        body: Rc<Term>,
+        free: Vec<String>,
+    },
+    Call {
+        function: Rc<Term>,
+        arguments: Rc<Vec<Rc<Term>>>,
+    },
+}
+
+// 機械の上の文
+#[derive(Debug)]
+pub enum Command {
+    DoNothing,
+    Assignment {
+        name: String,
+        term: Rc<Term>,
+    },
+    If {
+        condition: Rc<Term>,
+        consequence: Rc<Command>,
+        alternative: Rc<Command>,
+    },
+    Update {
+        name: String,
+        fields: Rc<Vec<String>>,
+        term: Rc<Term>,
+    },
+    Sequence {
+        first: Rc<Command>,
+        second: Rc<Command>,
+    },
+    While {
+        condition: Rc<Term>,
+        body: Rc<Command>,
+    },
+    Choose {
+        first: Rc<Command>,
+        second: Rc<Command>,
+    },
+    Parallel {
+        left: Rc<Command>,
+        right: Rc<Command>,
+    },
+    Assert {
+        condition: Rc<Term>,
+    },
+    Break,
+    Continue,
+    Throw {
+        term: Rc<Term>,
+    },
+    Try {
+        body: Rc<Command>,
+        name: String,
+        handler: Rc<Command>,
+    },
+    Let {
+        name: String,
+        term: Rc<Term>,
+        body: Rc<Command>,
+    },
+    // 巻き上げる変数も変換するときに求めておく
+    Block {
+        body: Rc<Command>,
+        hoisted: Rc<Vec<String>>,
+    },
+    Var {
+        name: String,
+        term: Rc<Term>,
+    },
+}
+
+// 機械の上の値。組やレコード、関数も共有するので、変数から読んでも複製しない
+#[derive(Clone, Debug)]
+pub enum Value {
+    Number(u32),
+    Boolean(bool),
+    Tuple(Rc<Vec<Value>>),
+    Record(Rc<Vec<(String, Value)>>),
+    Closure {
+        params: Rc<Vec<String>>,
+        body: Rc<Term>,
+        environment: Rc<Vec<(String, Value)>>,
+    },
+}
+
+fn compile_expression(expression: &Expression) -> Rc<Term> {
+    let binary = |operator, left: &Expression, right: &Expression| Term::Binary {
+        operator,
+        left: compile_expression(left),
+        right: compile_expression(right),
+    };
+    Rc::new(match expression {
+        Expression::Number(_) | Expression::Boolean(_) | Expression::Closure { .. } => {
+            Term::Value(compile_value(expression))
+        }
+        Expression::Variable(name) => Term::Variable(name.clone()),
+        Expression::Add { left, right } => binary(Operator::Add, left, right),
+        Expression::Multiply { left, right } => binary(Operator::Multiply, left, right),
+        Expression::LessThan { left, right } => binary(Operator::LessThan, left, right),
+        Expression::Equal { left, right } => binary(Operator::Equal, left, right),
+        Expression::Tuple(elements) => {
+            Term::Tuple(Rc::new(elements.iter().map(compile_expression).collect()))
+        }
+        Expression::Record(fields) => Term::Record(Rc::new(
+            fields
+                .iter()
+                .map(|(name, value)| (name.clone(), compile_expression(value)))
+                .collect(),
+        )),
+        Expression::Field { expression, name } => Term::Field {
+            term: compile_expression(expression),
+            name: name.clone(),
+        },
+        Expression::Function { params, body } => Term::Function {
+            params: Rc::new(params.clone()),
+            body: compile_expression(body),
+            free: expression.free_variables().into_iter().collect(),
+        },
+        Expression::Call {
+            function,
+            arguments,
+        } => Term::Call {
+            function: compile_expression(function),
+            arguments: Rc::new(arguments.iter().map(compile_expression).collect()),
+        },
+    })
+}
+
+fn compile_value(expression: &Expression) -> Value {
+    match expression {
+        Expression::Number(value) => Value::Number(*value),
+        Expression::Boolean(value) => Value::Boolean(*value),
+        Expression::Tuple(elements) => {
+            Value::Tuple(Rc::new(elements.iter().map(compile_value).collect()))
+        }
+        Expression::Record(fields) => Value::Record(Rc::new(
+            fields
+                .iter()
+                .map(|(name, value)| (name.clone(), compile_value(value)))
+                .collect(),
+        )),
+        Expression::Closure {
+            params,
+            body,
+            environment,
+        } => Value::Closure {
+            params: Rc::new(params.clone()),
+            body: compile_expression(body),
+            environment: Rc::new(
+                environment
+                    .iter()
+                    .map(|(name, value)| (name.clone(), compile_value(value)))
+                    .collect(),
+            ),
+        },
+        _ => panic!("expression is not value"),
+    }
+}
+
+fn compile_statement(statement: &Statement) -> Rc<Command> {
+    Rc::new(match statement {
+        Statement::DoNothing => Command::DoNothing,
+        Statement::Assignment { name, expression } => Command::Assignment {
+            name: name.clone(),
+            term: compile_expression(expression),
+        },
+        Statement::If {
+            condition,
+            consequence,
+            alternative,
+        } => Command::If {
+            condition: compile_expression(condition),
+            consequence: compile_statement(consequence),
+            alternative: compile_statement(alternative),
+        },
+        Statement::Update {
+            name,
+            fields,
+            expression,
+        } => Command::Update {
+            name: name.clone(),
+            fields: Rc::new(fields.clone()),
+            term: compile_expression(expression),
+        },
+        Statement::Sequence { first, second } => Command::Sequence {
+            first: compile_statement(first),
+            second: compile_statement(second),
+        },
+        Statement::While { condition, body } => Command::While {
+            condition: compile_expression(condition),
+            body: compile_statement(body),
+        },
+        Statement::Choose { first, second } => Command::Choose {
+            first: compile_statement(first),
+            second: compile_statement(second),
+        },
+        Statement::Parallel { left, right } => Command::Parallel {
+            left: compile_statement(left),
+            right: compile_statement(right),
+        },
+        Statement::Assert { condition } => Command::Assert {
+            condition: compile_expression(condition),
+        },
+        Statement::Break => Command::Break,
+        Statement::Continue => Command::Continue,
+        Statement::Throw { expression } => Command::Throw {
+            term: compile_expression(expression),
+        },
+        Statement::Try {
+            body,
+            name,
+            handler,
+        } => Command::Try {
+            body: compile_statement(body),
+            name: name.clone(),
+            handler: compile_statement(handler),
+        },
+        Statement::Let {
+            name,
+            expression,
+            body,
+        } => Command::Let {
+            name: name.clone(),
+            term: compile_expression(expression),
+            body: compile_statement(body),
+        },
+        Statement::Block { body } => Command::Block {
+            body: compile_statement(body),
+            hoisted: Rc::new(body.hoisted_variables()),
+        },
+        Statement::Var { name, expression } => Command::Var {
+            name: name.clone(),
+            term: compile_expression(expression),
+        },
+    })
+}
+
+// 表示や結果の比較のために元の形に戻す。実行中は使わない
+impl Term {
+    pub fn to_expression(&self) -> Expression {
+        let expressions = |terms: &[Rc<Term>]| terms.iter().map(|t| t.to_expression()).collect();
+        match self {
+            Term::Value(value) => value.to_expression(),
+            Term::Variable(name) => Expression::Variable(name.clone()),
+            Term::Binary {
+                operator,
+                left,
+                right,
+            } => {
+                let left = Box::new(left.to_expression());
+                let right = Box::new(right.to_expression());
+                match operator {
+                    Operator::Add => Expression::Add { left, right },
+                    Operator::Multiply => Expression::Multiply { left, right },
+                    Operator::LessThan => Expression::LessThan { left, right },
+                    Operator::Equal => Expression::Equal { left, right },
+                }
+            }
+            Term::Tuple(elements) => Expression::Tuple(expressions(elements)),
+            Term::Record(fields) => Expression::Record(
+                fields
+                    .iter()
+                    .map(|(name, term)| (name.clone(), term.to_expression()))
+                    .collect(),
+            ),
+            Term::Field { term, name } => Expression::Field {
+                expression: Box::new(term.to_expression()),
+                name: name.clone(),
+            },
+            Term::Function { params, body, .. } => Expression::Function {
+                params: params.to_vec(),
+                body: Box::new(body.to_expression()),
+            },
+            Term::Call {
+                function,
+                arguments,
+            } => Expression::Call {
+                function: Box::new(function.to_expression()),
+                arguments: expressions(arguments),
+            },
+        }
+    }
+}
+
+impl Value {
+    pub fn to_expression(&self) -> Expression {
+        let fields = |fields: &[(String, Value)]| {
+            fields
+                .iter()
+                .map(|(name, value)| (name.clone(), value.to_expression()))
+                .collect()
+        };
+        match self {
+            Value::Number(value) => Expression::Number(*value),
+            Value::Boolean(value) => Expression::Boolean(*value),
+            Value::Tuple(elements) => {
+                Expression::Tuple(elements.iter().map(|v| v.to_expression()).collect())
+            }
+            Value::Record(values) => Expression::Record(fields(values)),
+            Value::Closure {
+                params,
+                body,
+                environment,
+            } => Expression::Closure {
+                params: params.to_vec(),
+                body: Box::new(body.to_expression()),
+                environment: fields(environment),
+            },
+        }
+    }
+    fn field(&self, name: &str) -> Value {
+        let value = match self {
+            Value::Tuple(elements) => name.parse::<usize>().ok().and_then(|i| elements.get(i)),
+            Value::Record(fields) => fields.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => panic!("expression is not tuple or record"),
+        };
+        match value {
+            Some(value) => value.clone(),
+            None => panic!("undefined field {}", name),
+        }
+    }
+    // 書き換える段だけを複製する
+    fn update(&self, fields: &[String], value: Value) -> Value {
+        match fields.split_first() {
+            None => value,
+            Some((name, rest)) => {
+                let updated = self.field(name).update(rest, value);
+                match self {
+                    Value::Tuple(elements) => {
+                        let mut elements = elements.to_vec();
+                        elements[name.parse::<usize>().unwrap()] = updated;
+                        Value::Tuple(Rc::new(elements))
+                    }
+                    Value::Record(fields) => {
+                        let mut fields = fields.to_vec();
+                        for (n, v) in fields.iter_mut() {
+                            if n == name {
+                                *v = updated.clone();
+                            }
+                        }
+                        Value::Record(Rc::new(fields))
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+impl Command {
+    pub fn to_statement(&self) -> Statement {
+        let statement = |command: &Rc<Command>| Box::new(command.to_statement());
+        match self {
+            Command::DoNothing => Statement::DoNothing,
+            Command::Assignment { name, term } => Statement::Assignment {
+                name: name.clone(),
+                expression: term.to_expression(),
+            },
+            Command::If {
+                condition,
+                consequence,
+                alternative,
+            } => Statement::If {
+                condition: condition.to_expression(),
+                consequence: statement(consequence),
+                alternative: statement(alternative),
+            },
+            Command::Update { name, fields, term } => Statement::Update {
+                name: name.clone(),
+                fields: fields.to_vec(),
+                expression: term.to_expression(),
+            },
+            Command::Sequence { first, second } => Statement::Sequence {
+                first: statement(first),
+                second: statement(second),
+            },
+            Command::While { condition, body } => Statement::While {
+                condition: condition.to_expression(),
+                body: statement(body),
+            },
+            Command::Choose { first, second } => Statement::Choose {
+                first: statement(first),
+                second: statement(second),
+            },
+            Command::Parallel { left, right } => Statement::Parallel {
+                left: statement(left),
+                right: statement(right),
+            },
+            Command::Assert { condition } => Statement::Assert {
+                condition: condition.to_expression(),
+            },
+            Command::Break => Statement::Break,
+            Command::Continue => Statement::Continue,
+            Command::Throw { term } => Statement::Throw {
+                expression: term.to_expression(),
+            },
+            Command::Try {
+                body,
+                name,
+                handler,
+            } => Statement::Try {
+                body: statement(body),
+                name: name.clone(),
+                handler: statement(handler),
+            },
+            Command::Let { name, term, body } => Statement::Let {
+                name: name.clone(),
+                expression: term.to_expression(),
+                body: statement(body),
+            },
+            Command::Block { body, .. } => Statement::Block {
+                body: statement(body),
+            },
+            Command::Var { name, term } => Statement::Var {
+                name: name.clone(),
+                expression: term.to_expression(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_expression())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_expression())
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_statement())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Add,
+    Multiply,
+    LessThan,
+    Equal,
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operator::Add => write!(f, "+"),
+            Operator::Multiply => write!(f, "*"),
+            Operator::LessThan => write!(f, "<"),
+            Operator::Equal => write!(f, "=="),
+        }
+    }
+}
+
+impl Operator {
+    fn apply(self, left: Value, right: Value) -> Value {
+        match (self, left, right) {
+            (Operator::Add, Value::Number(l), Value::Number(r)) => Value::Number(l + r),
+            (Operator::Multiply, Value::Number(l), Value::Number(r)) => Value::Number(l * r),
+            (Operator::LessThan, Value::Number(l), Value::Number(r)) => Value::Boolean(l < r),
+            // 等しさを比べるのは値の大きさだけかかる
+            (Operator::Equal, left, right) => {
+                Value::Boolean(left.to_expression().is_equal(&right.to_expression()))
+            }
+            _ => panic!("expression is not number"),
+        }
+    }
+}
+
+// 継続の1段。[]は値が入る穴
+#[derive(Clone, Debug)]
+pub enum Frame {
+    Left {
+        operator: Operator,
+        right: Rc<Term>,
+    },
+    Right {
+        operator: Operator,
+        left: Value,
+    },
+    Assign(String),
+    // 左から順に評価する。valuesは評価済みで、次はvalues.len()番目
+    Tuple {
+        values: Vec<Value>,
+        elements: Rc<Vec<Rc<Term>>>,
+    },
+    Record {
+        values: Vec<(String, Value)>,
+        fields: Rc<Vec<(String, Rc<Term>)>>,
+    },
+    Field(String),
+    Callee(Rc<Vec<Rc<Term>>>),
+    Argument {
+        function: Value,
+        values: Vec<Value>,
+        arguments: Rc<Vec<Rc<Term>>>,
+    },
+    Update {
+        name: String,
+        fields: Rc<Vec<String>>,
+    },
+    Branch {
+        consequence: Rc<Command>,
+        alternative: Rc<Command>,
+    },
+    // 条件を評価しているwhile
+    WhileCondition(Rc<Command>),
+    Then(Rc<Command>),
+    Assert,
+    // ループ本体の実行中。続きのwhileを持つ
+    Loop(Rc<Command>),
+    Throw,
+    Catch {
+        name: String,
+        handler: Rc<Command>,
+    },
+    Bind {
+        name: String,
+        body: Rc<Command>,
+    },
+    // スコープや関数の本体を出るときに戻す値
+    Restore(Vec<(String, Option<Value>)>),
+}
+
+// 評価済みのもの、穴、まだのものを並べる
+fn holes(done: Vec<String>, hole: String, rest: Vec<String>) -> String {
+    done.into_iter()
+        .chain(std::iter::once(hole))
+        .chain(rest)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let elements = |values: &[Value], terms: &[Rc<Term>]| {
+            holes(
+                values.iter().map(|v| v.to_string()).collect(),
+                "[]".to_string(),
+                terms[values.len() + 1..]
+                    .iter()
+                    .map(|t| t.to_string())
+                    .collect(),
+            )
+        };
+        match self {
+            Frame::Left { operator, right } => write!(f, "[] {} {}", operator, right),
+            Frame::Right { operator, left } => write!(f, "{} {} []", left, operator),
+            Frame::Assign(name) => write!(f, "{} = []", name),
+            Frame::Tuple {
+                values,
+                elements: terms,
+            } => write!(f, "({})", elements(values, terms)),
+            Frame::Record { values, fields } => {
+                let fields = holes(
+                    values
+                        .iter()
+                        .map(|(n, v)| format!("{}: {}", n, v))
+                        .collect(),
+                    format!("{}: []", fields[values.len()].0),
+                    fields[values.len() + 1..]
+                        .iter()
+                        .map(|(n, t)| format!("{}: {}", n, t))
+                        .collect(),
+                );
+                write!(f, "{{ {} }}", fields)
+            }
+            Frame::Field(name) => write!(f, "[].{}", name),
+            Frame::Callee(arguments) => {
+                let arguments = arguments.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+                write!(f, "[]({})", arguments.join(", "))
+            }
+            Frame::Argument {
+                function,
+                values,
+                arguments,
+            } => write!(f, "({})({})", function, elements(values, arguments)),
+            Frame::Update { name, fields } => write!(f, "{}.{} = []", name, fields.join(".")),
+            Frame::Branch {
+                consequence,
+                alternative,
+            } => write!(
+                f,
+                "if ([]) {{ {} }} else {{ {} }}",
+                consequence, alternative
+            ),
+            Frame::WhileCondition(command) => match &**command {
+                Command::While { condition, body } => {
+                    write!(f, "while ([] from {}), {{ {} }}", condition, body)
+                }
+                _ => unreachable!(),
+            },
+            Frame::Assert => write!(f, "assert ([])"),
+            Frame::Then(second) => write!(f, "[]; {}", second),
+            Frame::Loop(next) => write!(f, "loop {{ [] }}; {}", next),
+            Frame::Throw => write!(f, "throw []"),
+            Frame::Catch { name, handler } => {
+                write!(f, "try {{ [] }} catch ({}) {{ {} }}", name, handler)
+            }
+            Frame::Bind { name, body } => write!(f, "let {} = [] in {{ {} }}", name, body),
+            Frame::Restore(saved) => {
+                let names = saved
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<_>>();
+                write!(f, "scope {{ [] }} restoring [{}]", names.join(", "))
+            }
+        }
+    }
+}
+
+pub struct CekMachine {
+    pub control: Control,
+    values: HashMap<String, Value>,
+    pub continuation: Vec<Frame>,
+    pub steps: usize,
+}
+
+impl CekMachine {
+    pub fn new(statement: Statement, environment: Environment) -> Self {
+        CekMachine::start(
+            Control::Statement(compile_statement(&statement)),
+            environment,
+        )
+    }
+    pub fn from_expression(expression: Expression, environment: Environment) -> Self {
+        CekMachine::start(
+            Control::Expression(compile_expression(&expression)),
+            environment,
+        )
+    }
+    fn start(control: Control, environment: Environment) -> Self {
+        CekMachine {
+            control,
+            values: environment
+                .iter()
+                .map(|(name, value)| (name.clone(), compile_value(value)))
+                .collect(),
+            continuation: vec![],
+            steps: 0,
+        }
+    }
+    pub fn environment(&self) -> Environment {
+        self.values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.to_expression()))
+            .collect()
+    }
+    pub fn is_final(&self) -> bool {
+        match self.control {
+            Control::Value(_) | Control::Completion(_) => self.continuation.is_empty(),
+            _ => false,
+        }
+    }
+    pub fn step(&mut self) {
+        let control = std::mem::replace(&mut self.control, Control::Completion(Completion::Normal));
+        self.control = match control {
+            Control::Expression(term) => self.step_expression(&term),
+            Control::Value(value) => self.step_value(value),
+            Control::Statement(command) => self.step_statement(command),
+            Control::Completion(completion) => self.step_completion(completion),
+        };
+        self.steps += 1;
+    }
+    pub fn run(&mut self) {
+        while !self.is_final() {
+            self.step();
+        }
+    }
+    // Machineの最後の文と同じ形にする
+    pub fn final_statement(&self) -> Statement {
+        match &self.control {
+            Control::Completion(Completion::Normal) => Statement::DoNothing,
+            Control::Completion(Completion::Break) => Statement::Break,
+            Control::Completion(Completion::Continue) => Statement::Continue,
+            Control::Completion(Completion::Throw(value)) => Statement::Throw {
+                expression: value.to_expression(),
+            },
+            _ => panic!("machine is not stopped at a statement"),
+        }
+    }
+    fn lookup(&self, name: &str) -> Value {
+        match self.values.get(name) {
+            Some(value) => value.clone(),
+            None => panic!("undefined variable"),
+        }
+    }
+    // 次に評価する要素があれば継続に積み、なければ組を作る
+    fn tuple(&mut self, values: Vec<Value>, elements: Rc<Vec<Rc<Term>>>) -> Control {
+        match elements.get(values.len()).cloned() {
+            Some(next) => {
+                self.continuation.push(Frame::Tuple { values, elements });
+                Control::Expression(next)
+            }
+            None => Control::Value(Value::Tuple(Rc::new(values))),
+        }
+    }
+    fn record(
+        &mut self,
+        values: Vec<(String, Value)>,
+        fields: Rc<Vec<(String, Rc<Term>)>>,
+    ) -> Control {
+        match fields.get(values.len()).map(|(_, term)| term.clone()) {
+            Some(next) => {
+                self.continuation.push(Frame::Record { values, fields });
+                Control::Expression(next)
+            }
+            None => Control::Value(Value::Record(Rc::new(values))),
+        }
+    }
+    fn arguments(
+        &mut self,
+        function: Value,
+        values: Vec<Value>,
+        arguments: Rc<Vec<Rc<Term>>>,
+    ) -> Control {
+        match arguments.get(values.len()).cloned() {
+            Some(next) => {
+                self.continuation.push(Frame::Argument {
+                    function,
+                    values,
+                    arguments,
+                });
+                Control::Expression(next)
+            }
+            None => self.call(function, values),
+        }
+    }
+    fn step_expression(&mut self, term: &Rc<Term>) -> Control {
+        match &**term {
+            Term::Value(value) => Control::Value(value.clone()),
+            Term::Variable(name) => Control::Value(self.lookup(name)),
+            Term::Binary {
+                operator,
+                left,
+                right,
+            } => {
+                self.continuation.push(Frame::Left {
+                    operator: *operator,
+                    right: right.clone(),
+                });
+                Control::Expression(left.clone())
+            }
+            Term::Tuple(elements) => self.tuple(vec![], elements.clone()),
+            Term::Record(fields) => self.record(vec![], fields.clone()),
+            Term::Field { term, name } => {
+                self.continuation.push(Frame::Field(name.clone()));
+                Control::Expression(term.clone())
+            }
+            Term::Function { params, body, free } => Control::Value(Value::Closure {
+                params: params.clone(),
+                body: body.clone(),
+                environment: Rc::new(
+                    free.iter()
+                        .map(|name| (name.clone(), self.lookup(name)))
+                        .collect(),
+                ),
+            }),
+            Term::Call {
+                function,
+                arguments,
+            } => {
+                self.continuation.push(Frame::Callee(arguments.clone()));
+                Control::Expression(function.clone())
+            }
+        }
+    }
+    fn step_value(&mut self, value: Value) -> Control {
+        match self.continuation.pop() {
+            Some(Frame::Left { operator, right }) => {
+                self.continuation.push(Frame::Right {
+                    operator,
+                    left: value,
+                });
+                Control::Expression(right)
+            }
+            Some(Frame::Right { operator, left }) => Control::Value(operator.apply(left, value)),
+            Some(Frame::Assign(name)) => {
+                self.values.insert(name, value);
+                Control::Completion(Completion::Normal)
+            }
+            Some(Frame::Tuple {
+                mut values,
+                elements,
+            }) => {
+                values.push(value);
+                self.tuple(values, elements)
+            }
+            Some(Frame::Record { mut values, fields }) => {
+                let name = fields[values.len()].0.clone();
+                values.push((name, value));
+                self.record(values, fields)
+            }
+            Some(Frame::Field(name)) => Control::Value(value.field(&name)),
+            Some(Frame::Callee(arguments)) => self.arguments(value, vec![], arguments),
+            Some(Frame::Argument {
+                function,
+                mut values,
+                arguments,
+            }) => {
+                values.push(value);
+                self.arguments(function, values, arguments)
+            }
+            Some(Frame::Update { name, fields }) => {
+                let updated = match self.values.get(&name) {
+                    Some(current) => current.update(&fields, value),
+                    None => panic!("undefined variable"),
+                };
+                self.values.insert(name, updated);
+                Control::Completion(Completion::Normal)
+            }
+            Some(Frame::Branch {
+                consequence,
+                alternative,
+            }) => match value {
+                Value::Boolean(true) => Control::Statement(consequence),
+                Value::Boolean(false) => Control::Statement(alternative),
+                _ => panic!("condition is not bool"),
+            },
+            Some(Frame::WhileCondition(command)) => match value {
+                Value::Boolean(true) => {
+                    let body = match &*command {
+                        Command::While { body, .. } => body.clone(),
+                        _ => unreachable!(),
+                    };
+                    self.continuation.push(Frame::Loop(command));
+                    Control::Statement(body)
+                }
+                Value::Boolean(false) => Control::Completion(Completion::Normal),
+                _ => panic!("condition is not bool"),
+            },
+            Some(Frame::Assert) => match value {
+                Value::Boolean(true) => Control::Completion(Completion::Normal),
+                Value::Boolean(false) => panic!("assertion failed"),
+                _ => panic!("condition is not bool"),
+            },
+            Some(Frame::Throw) => Control::Completion(Completion::Throw(value)),
+            Some(Frame::Bind { name, body }) => {
+                let saved = save(std::slice::from_ref(&name), &self.values);
+                self.values.insert(name, value);
+                self.continuation.push(Frame::Restore(saved));
+                Control::Statement(body)
+            }
+            // 関数の本体の値が出たので、呼ぶ前の変数に戻す
+            Some(Frame::Restore(saved)) => {
+                restore(&saved, &mut self.values);
+                Control::Value(value)
+            }
+            _ => unreachable!(),
+        }
+    }
+    // 本体に代入する代わりに、引数と取り込んだ値を本体の評価中だけ環境に置く。
+    // 本体の自由変数はどちらかに含まれるので、Machineで代入したのと同じ値になる
+    fn call(&mut self, function: Value, arguments: Vec<Value>) -> Control {
+        match function {
+            Value::Closure {
+                params,
+                body,
+                environment,
+            } => {
+                if params.len() != arguments.len() {
+                    panic!("wrong number of arguments");
+                }
+                let names = environment
+                    .iter()
+                    .map(|(name, _)| name.clone())
+                    .chain(params.iter().cloned())
+                    .collect::<Vec<_>>();
+                let saved = save(&names, &self.values);
+                // 引数が取り込んだ値より優先される
+                for (name, value) in environment.iter() {
+                    self.values.insert(name.clone(), value.clone());
+                }
+                for (name, value) in params.iter().zip(arguments) {
+                    self.values.insert(name.clone(), value);
+                }
+                self.continuation.push(Frame::Restore(saved));
+                Control::Expression(body)
+            }
+            _ => panic!("expression is not function"),
+        }
+    }
+    fn step_statement(&mut self, command: Rc<Command>) -> Control {
+        match &*command {
+            Command::DoNothing => Control::Completion(Completion::Normal),
+            Command::Assignment { name, term } | Command::Var { name, term } => {
+                self.continuation.push(Frame::Assign(name.clone()));
+                Control::Expression(term.clone())
+            }
+            Command::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.continuation.push(Frame::Branch {
+                    consequence: consequence.clone(),
+                    alternative: alternative.clone(),
+                });
+                Control::Expression(condition.clone())
+            }
+            Command::Update { name, fields, term } => {
+                self.continuation.push(Frame::Update {
+                    name: name.clone(),
+                    fields: fields.clone(),
+                });
+                Control::Expression(term.clone())
+            }
+            Command::Sequence { first, second }
+            | Command::Parallel {
+                left: first,
+                right: second,
+            } => {
+                self.continuation.push(Frame::Then(second.clone()));
+                Control::Statement(first.clone())
+            }
+            // 本体も条件も共有したまま、while全体を継続に置く
+            Command::While { condition, .. } => {
+                let condition = condition.clone();
+                self.continuation.push(Frame::WhileCondition(command));
+                Control::Expression(condition)
+            }
+            // Machineと同じく左を選ぶ
+            Command::Choose { first, .. } => Control::Statement(first.clone()),
+            Command::Assert { condition } => {
+                self.continuation.push(Frame::Assert);
+                Control::Expression(condition.clone())
+            }
+            Command::Break => Control::Completion(Completion::Break),
+            Command::Continue => Control::Completion(Completion::Continue),
+            Command::Throw { term } => {
+                self.continuation.push(Frame::Throw);
+                Control::Expression(term.clone())
+            }
+            Command::Try {
+                body,
+                name,
+                handler,
+            } => {
+                self.continuation.push(Frame::Catch {
+                    name: name.clone(),
+                    handler: handler.clone(),
+                });
+                Control::Statement(body.clone())
+            }
+            Command::Let { name, term, body } => {
+                self.continuation.push(Frame::Bind {
+                    name: name.clone(),
+                    body: body.clone(),
+                });
+                Control::Expression(term.clone())
+            }
+            Command::Block { body, hoisted } => {
+                let saved = save(hoisted, &self.values);
+                self.continuation.push(Frame::Restore(saved));
+                Control::Statement(body.clone())
+            }
+        }
+    }
+    // 中断したときは、受け止める段まで1段ずつ捨てていく
+    fn step_completion(&mut self, completion: Completion) -> Control {
+        match (completion, self.continuation.pop()) {
+            (Completion::Normal, Some(Frame::Then(next))) => Control::Statement(next),
+            (Completion::Normal, Some(Frame::Loop(next)))
+            | (Completion::Continue, Some(Frame::Loop(next))) => Control::Statement(next),
+            (Completion::Break, Some(Frame::Loop(_))) => Control::Completion(Completion::Normal),
+            (Completion::Throw(value), Some(Frame::Catch { name, handler })) => {
+                self.values.insert(name, value);
+                Control::Statement(handler)
+            }
+            // 中断しても、スコープは出るときに戻す
+            (completion, Some(Frame::Restore(saved))) => {
+                restore(&saved, &mut self.values);
+                Control::Completion(completion)
+            }
+            (completion, Some(_)) => Control::Completion(completion),
+            (_, None) => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{format_environment, Machine};
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn multiply(left: Expression, right: Expression) -> Expression {
+        Expression::Multiply {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+    fn when(condition: Expression, consequence: Statement) -> Statement {
+        Statement::If {
+            condition,
+            consequence: Box::new(consequence),
+            alternative: Box::new(Statement::DoNothing),
+        }
+    }
+
+    fn assert_same_as_machine(statement: &Statement, environment: &Environment) {
+        let mut machine = Machine {
+            statement: statement.clone(),
+            environment: environment.clone(),
+        };
+        machine.run();
+        let mut cek = CekMachine::new(statement.clone(), environment.clone());
+        cek.run();
+        assert_eq!(
+            cek.final_statement().to_string(),
+            machine.statement.to_string()
+        );
+        assert_eq!(
+            format_environment(&cek.environment()),
+            format_environment(&machine.environment)
+        );
+    }
+
+    #[test]
+    fn expression_test() {
+        // 左に深く入れ子になった足し算。葉で1ステップ、足し算ごとに3ステップ
+        let n = 200;
+        let mut expression = number(0);
+        for _ in 0..n {
+            expression = add(expression, variable("x"));
+        }
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(2));
+        let mut cek = CekMachine::from_expression(expression.clone(), environment.clone());
+        cek.run();
+        assert_eq!(cek.control.to_string(), "value 400");
+        assert_eq!(cek.steps, 4 * n + 1);
+
+        let mut reduced = expression;
+        while reduced.is_reducible() {
+            reduced = reduced.reduce(&environment);
+        }
+        assert_eq!(reduced, number(400));
+    }
+
+    #[test]
+    fn continuation_test() {
+        let statement = sequence(
+            assign("x", add(number(1), multiply(number(2), number(3)))),
+            assign("y", variable("x")),
+        );
+        let mut cek = CekMachine::new(statement, Environment::new());
+        for _ in 0..5 {
+            cek.step();
+        }
+        assert_eq!(cek.control.to_string(), "2 * 3");
+        let frames = cek
+            .continuation
+            .iter()
+            .map(|frame| frame.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(frames, vec!["[]; y = x", "x = []", "1 + []"]);
+        cek.run();
+        assert_eq!(
+            format_environment(&cek.environment()),
+            "{\"x\": Number(7), \"y\": Number(7)}"
+        );
+    }
+
+    #[test]
+    fn while_test() {
+        // 階乗
+        let statement = sequence(
+            assign("result", number(1)),
+            sequence(
+                assign("i", number(1)),
+                Statement::While {
+                    condition: less_than(variable("i"), add(variable("n"), number(1))),
+                    body: Box::new(sequence(
+                        assign("result", multiply(variable("result"), variable("i"))),
+                        assign("i", add(variable("i"), number(1))),
+                    )),
+                },
+            ),
+        );
+        let mut environment = Environment::new();
+        environment.insert("n".to_string(), number(6));
+        assert_same_as_machine(&statement, &environment);
+    }
+
+    #[test]
+    fn abrupt_test() {
+        let body = sequence(
+            assign("i", add(variable("i"), number(1))),
+            Statement::Try {
+                body: Box::new(sequence(
+                    when(less_than(variable("i"), number(3)), Statement::Continue),
+                    sequence(
+                        when(
+                            less_than(number(4), variable("i")),
+                            Statement::Throw {
+                                expression: variable("i"),
+                            },
+                        ),
+                        assign("s", add(variable("s"), variable("i"))),
+                    ),
+                )),
+                name: "e".to_string(),
+                handler: Box::new(Statement::Break),
+            },
+        );
+        let statement = Statement::While {
+            condition: Expression::Boolean(true),
+            body: Box::new(body),
+        };
+        let mut environment = Environment::new();
+        environment.insert("i".to_string(), number(0));
+        environment.insert("s".to_string(), number(0));
+        assert_same_as_machine(&statement, &environment);
+
+        let uncaught = sequence(
+            assign("x", number(1)),
+            sequence(
+                Statement::Throw {
+                    expression: add(variable("x"), number(1)),
+                },
+                assign("x", number(2)),
+            ),
+        );
+        assert_same_as_machine(&uncaught, &Environment::new());
+    }
+
+    #[test]
+    fn scope_test() {
+        // ループの中のletはbreakで抜けても戻る
+        let body = Statement::Let {
+            name: "x".to_string(),
+            expression: add(variable("x"), number(1)),
+            body: Box::new(sequence(
+                assign("s", add(variable("s"), variable("x"))),
+                Statement::Block {
+                    body: Box::new(sequence(
+                        Statement::Var {
+                            name: "i".to_string(),
+                            expression: variable("x"),
+                        },
+                        when(less_than(number(2), variable("i")), Statement::Break),
+                    )),
+                },
+            )),
+        };
+        let statement = Statement::While {
+            condition: Expression::Boolean(true),
+            body: Box::new(body),
+        };
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(2));
+        environment.insert("s".to_string(), number(0));
+        assert_same_as_machine(&statement, &environment);
+
+        let mut cek = CekMachine::new(statement, environment);
+        while cek.continuation.len() < 4 {
+            cek.step();
+        }
+        let frames = cek
+            .continuation
+            .iter()
+            .map(|frame| frame.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(frames[1], "scope { [] } restoring [x]");
+    }
+
+    #[test]
+    fn record_test() {
+        let point = Expression::Record(vec![
+            ("x".to_string(), add(number(1), number(2))),
+            (
+                "y".to_string(),
+                Expression::Tuple(vec![number(3), variable("z")]),
+            ),
+        ]);
+        let statement = sequence(
+            assign("p", point),
+            sequence(
+                Statement::Update {
+                    name: "p".to_string(),
+                    fields: vec!["y".to_string(), "0".to_string()],
+                    expression: Expression::Field {
+                        expression: Box::new(variable("p")),
+                        name: "x".to_string(),
+                    },
+                },
+                assign(
+                    "same",
+                    Expression::Equal {
+                        left: Box::new(variable("p")),
+                        right: Box::new(Expression::Record(vec![
+                            (
+                                "y".to_string(),
+                                Expression::Tuple(vec![number(3), number(4)]),
+                            ),
+                            ("x".to_string(), number(3)),
+                        ])),
+                    },
+                ),
+            ),
+        );
+        let mut environment = Environment::new();
+        environment.insert("z".to_string(), number(4));
+        assert_same_as_machine(&statement, &environment);
+
+        let mut cek = CekMachine::new(statement, environment);
+        while cek.continuation.len() < 4 {
+            cek.step();
+        }
+        assert_eq!(cek.continuation[2].to_string(), "{ x: [], y: (3, z) }");
+        cek.run();
+        assert_eq!(cek.environment()["same"], Expression::Boolean(true));
+    }
+
+    #[test]
+    fn function_test() {
+        let twice = Expression::Function {
+            params: vec!["f".to_string(), "x".to_string()],
+            body: Box::new(Expression::Call {
+                function: Box::new(variable("f")),
+                arguments: vec![Expression::Call {
+                    function: Box::new(variable("f")),
+                    arguments: vec![variable("x")],
+                }],
+            }),
+        };
+        let add_n = Expression::Function {
+            params: vec!["x".to_string()],
+            body: Box::new(add(variable("x"), variable("n"))),
+        };
+        let statement = sequence(
+            assign("n", number(3)),
+            sequence(
+                assign("twice", twice),
+                sequence(
+                    assign("add_n", add_n),
+                    assign(
+                        "y",
+                        Expression::Call {
+                            function: Box::new(variable("twice")),
+                            arguments: vec![variable("add_n"), variable("x")],
+                        },
+                    ),
+                ),
+            ),
+        );
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(1));
+        assert_same_as_machine(&statement, &environment);
+
+        let mut cek = CekMachine::new(statement, environment);
+        cek.run();
+        assert_eq!(cek.environment()["y"], number(7));
+        assert_eq!(
+            cek.environment()["add_n"].to_string(),
+            "function (x) { x + n } where { n: 3 }"
+        );
+    }
+
+    #[test]
+    fn sharing_test() {
+        // ループの本体も関数の本体も、回るたびに同じ木を指す。
+        // 複製しないので、1ステップの手間は本体の大きさによらない
+        let mut body = assign("i", add(variable("i"), number(1)));
+        for _ in 0..100 {
+            body = sequence(assign("x", add(variable("x"), variable("i"))), body);
+        }
+        let statement = Statement::While {
+            condition: less_than(variable("i"), number(3)),
+            body: Box::new(body),
+        };
+        let mut environment = Environment::new();
+        environment.insert("i".to_string(), number(0));
+        environment.insert("x".to_string(), number(0));
+        assert_same_as_machine(&statement, &environment);
+
+        let mut cek = CekMachine::new(statement, environment);
+        let mut bodies: Vec<Rc<Command>> = vec![];
+        while !cek.is_final() {
+            let entering = matches!(
+                (&cek.control, cek.continuation.last()),
+                (
+                    Control::Value(Value::Boolean(true)),
+                    Some(Frame::WhileCondition(_))
+                )
+            );
+            cek.step();
+            if let (true, Control::Statement(command)) = (entering, &cek.control) {
+                bodies.push(command.clone());
+            }
+        }
+        assert_eq!(bodies.len(), 3);
+        assert!(bodies.iter().all(|b| Rc::ptr_eq(b, &bodies[0])));
+
+        let increment = Expression::Function {
+            params: vec!["x".to_string()],
+            body: Box::new(add(variable("x"), number(1))),
+        };
+        let call = |argument| Expression::Call {
+            function: Box::new(variable("f")),
+            arguments: vec![argument],
+        };
+        let statement = sequence(assign("f", increment), assign("y", call(call(number(1)))));
+        let mut cek = CekMachine::new(statement, Environment::new());
+        let mut bodies: Vec<Rc<Term>> = vec![];
+        while !cek.is_final() {
+            if let (Control::Expression(term), Some(Frame::Restore(_))) =
+                (&cek.control, cek.continuation.last())
+            {
+                bodies.push(term.clone());
+            }
+            cek.step();
+        }
+        let body = match &cek.values["f"] {
+            Value::Closure { body, .. } => body.clone(),
+            _ => unreachable!(),
+        };
+        assert_eq!(bodies.len(), 2);
+        assert!(bodies.iter().all(|b| Rc::ptr_eq(b, &body)));
+        assert_eq!(cek.environment()["y"], number(3));
+    }
+}
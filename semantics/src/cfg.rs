@@ -0,0 +1,400 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::expression::{Environment, Expression};
+use crate::statement::Statement;
+
+pub type BlockId = usize;
+
+#[derive(Clone, Debug)]
+pub enum Instruction {
+    Assign {
+        name: String,
+        expression: Expression,
+    },
+    // 直前にいたブロックに対応する引数の値をとる
+    Phi {
+        name: String,
+        arguments: Vec<(BlockId, String)>,
+    },
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Assign { name, expression } => write!(f, "{} = {}", name, expression),
+            Instruction::Phi { name, arguments } => {
+                let arguments = arguments
+                    .iter()
+                    .map(|(block, argument)| format!("b{}: {}", block, argument))
+                    .collect::<Vec<_>>();
+                write!(f, "{} = phi({})", name, arguments.join(", "))
+            }
+        }
+    }
+}
+
+impl Instruction {
+    pub fn name(&self) -> &str {
+        match self {
+            Instruction::Assign { name, .. } | Instruction::Phi { name, .. } => name,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Terminator {
+    Jump(BlockId),
+    Branch {
+        condition: Expression,
+        consequence: BlockId,
+        alternative: BlockId,
+    },
+    Return,
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "goto b{}", target),
+            Terminator::Branch {
+                condition,
+                consequence,
+                alternative,
+            } => write!(
+                f,
+                "if ({}) goto b{} else goto b{}",
+                condition, consequence, alternative
+            ),
+            Terminator::Return => write!(f, "return"),
+        }
+    }
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch {
+                consequence,
+                alternative,
+                ..
+            } => vec![*consequence, *alternative],
+            Terminator::Return => vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BasicBlock {
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+#[derive(Clone, Debug)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+    pub entry: BlockId,
+}
+
+impl fmt::Display for ControlFlowGraph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (id, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "b{}:", id)?;
+            for instruction in &block.instructions {
+                writeln!(f, "  {}", instruction)?;
+            }
+            writeln!(f, "  {}", block.terminator)?;
+        }
+        Ok(())
+    }
+}
+
+struct Builder {
+    blocks: Vec<BasicBlock>,
+}
+
+impl Builder {
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock {
+            instructions: vec![],
+            terminator: Terminator::Return,
+        });
+        self.blocks.len() - 1
+    }
+    // currentから始めて、最後にいるブロックを返す
+    fn lower(&mut self, statement: &Statement, current: BlockId) -> BlockId {
+        match statement {
+            Statement::DoNothing => current,
+            Statement::Choose { .. }
+            | Statement::Parallel { .. }
+            | Statement::Assert { .. }
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Throw { .. }
+            | Statement::Try { .. }
+            | Statement::Let { .. }
+            | Statement::Block { .. }
+            | Statement::Var { .. }
+            | Statement::Update { .. } => unreachable!(),
+            Statement::Assignment { name, expression } => {
+                self.blocks[current].instructions.push(Instruction::Assign {
+                    name: name.clone(),
+                    expression: expression.clone(),
+                });
+                current
+            }
+            Statement::Sequence { first, second } => {
+                let middle = self.lower(first, current);
+                self.lower(second, middle)
+            }
+            Statement::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let consequence_block = self.new_block();
+                let alternative_block = self.new_block();
+                self.blocks[current].terminator = Terminator::Branch {
+                    condition: condition.clone(),
+                    consequence: consequence_block,
+                    alternative: alternative_block,
+                };
+                let consequence_end = self.lower(consequence, consequence_block);
+                let alternative_end = self.lower(alternative, alternative_block);
+                let join = self.new_block();
+                self.blocks[consequence_end].terminator = Terminator::Jump(join);
+                self.blocks[alternative_end].terminator = Terminator::Jump(join);
+                join
+            }
+            Statement::While { condition, body } => {
+                let header = self.new_block();
+                let body_block = self.new_block();
+                self.blocks[current].terminator = Terminator::Jump(header);
+                let body_end = self.lower(body, body_block);
+                self.blocks[body_end].terminator = Terminator::Jump(header);
+                let after = self.new_block();
+                self.blocks[header].terminator = Terminator::Branch {
+                    condition: condition.clone(),
+                    consequence: body_block,
+                    alternative: after,
+                };
+                after
+            }
+        }
+    }
+}
+
+impl Statement {
+    pub fn to_cfg(&self) -> Result<ControlFlowGraph, String> {
+        self.check_supported(&[])?;
+        let mut builder = Builder { blocks: vec![] };
+        let entry = builder.new_block();
+        builder.lower(self, entry);
+        Ok(ControlFlowGraph {
+            blocks: builder.blocks,
+            entry,
+        })
+    }
+}
+
+impl ControlFlowGraph {
+    pub fn successors(&self, block: BlockId) -> Vec<BlockId> {
+        self.blocks[block].terminator.successors()
+    }
+    pub fn predecessors(&self, block: BlockId) -> Vec<BlockId> {
+        (0..self.blocks.len())
+            .filter(|&b| self.successors(b).contains(&block))
+            .collect()
+    }
+    // entryから深さ優先でたどった逆後順
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        fn visit(
+            cfg: &ControlFlowGraph,
+            block: BlockId,
+            seen: &mut BTreeSet<BlockId>,
+            order: &mut Vec<BlockId>,
+        ) {
+            if !seen.insert(block) {
+                return;
+            }
+            for successor in cfg.successors(block) {
+                visit(cfg, successor, seen, order);
+            }
+            order.push(block);
+        }
+        let mut order = Vec::new();
+        visit(self, self.entry, &mut BTreeSet::new(), &mut order);
+        order.reverse();
+        order
+    }
+    pub fn evaluate(&self, environment: &mut Environment) -> Environment {
+        let mut previous = None;
+        let mut current = self.entry;
+        loop {
+            let block = &self.blocks[current];
+            // phiはブロックの入口でいっせいに評価する
+            let phis = block
+                .instructions
+                .iter()
+                .filter_map(|instruction| match instruction {
+                    Instruction::Phi { name, arguments } => {
+                        let argument = arguments
+                            .iter()
+                            .find(|(block, _)| Some(*block) == previous)
+                            .map(|(_, argument)| argument);
+                        argument
+                            .and_then(|a| environment.get(a))
+                            .map(|value| (name.clone(), value.clone()))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            environment.extend(phis);
+            for instruction in &block.instructions {
+                if let Instruction::Assign { name, expression } = instruction {
+                    let value = expression.evaluate(environment);
+                    environment.insert(name.clone(), value);
+                }
+            }
+            previous = Some(current);
+            current = match &block.terminator {
+                Terminator::Jump(target) => *target,
+                Terminator::Branch {
+                    condition,
+                    consequence,
+                    alternative,
+                } => match condition.evaluate(environment) {
+                    Expression::Boolean(true) => *consequence,
+                    Expression::Boolean(false) => *alternative,
+                    _ => panic!("condition is not bool"),
+                },
+                Terminator::Return => return environment.clone(),
+            };
+        }
+    }
+    pub fn to_dot(&self) -> String {
+        let mut lines = vec![
+            "digraph cfg {".to_string(),
+            "  node [shape=box, fontname=monospace];".to_string(),
+        ];
+        for (id, block) in self.blocks.iter().enumerate() {
+            let mut label = format!("b{}\\l", id);
+            for instruction in &block.instructions {
+                label.push_str(&format!("{}\\l", instruction));
+            }
+            if let Terminator::Branch { condition, .. } = &block.terminator {
+                label.push_str(&format!("if ({})\\l", condition));
+            }
+            lines.push(format!("  b{} [label=\"{}\"];", id, label));
+        }
+        for (id, block) in self.blocks.iter().enumerate() {
+            match &block.terminator {
+                Terminator::Jump(target) => lines.push(format!("  b{} -> b{};", id, target)),
+                Terminator::Branch {
+                    consequence,
+                    alternative,
+                    ..
+                } => {
+                    lines.push(format!("  b{} -> b{} [label=\"true\"];", id, consequence));
+                    lines.push(format!("  b{} -> b{} [label=\"false\"];", id, alternative));
+                }
+                Terminator::Return => {}
+            }
+        }
+        lines.push("}".to_string());
+        lines.join("\n") + "\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+
+    #[test]
+    fn lower_test() {
+        // x = 1; while (x < 5) { x = x + 1 }; y = x
+        let statement = sequence(
+            assign("x", number(1)),
+            sequence(
+                Statement::While {
+                    condition: less_than(variable("x"), number(5)),
+                    body: Box::new(assign("x", add(variable("x"), number(1)))),
+                },
+                assign("y", variable("x")),
+            ),
+        );
+        let cfg = statement.to_cfg().unwrap();
+        assert_eq!(
+            cfg.to_string(),
+            "b0:\n  x = 1\n  goto b1\n\
+             b1:\n  if (x < 5) goto b2 else goto b3\n\
+             b2:\n  x = x + 1\n  goto b1\n\
+             b3:\n  y = x\n  return\n"
+        );
+        assert_eq!(cfg.predecessors(1), vec![0, 2]);
+        assert_eq!(cfg.reverse_postorder(), vec![0, 1, 3, 2]);
+        let result = cfg.evaluate(&mut Environment::new());
+        assert_eq!(result["y"].to_string(), "5");
+    }
+
+    #[test]
+    fn if_test() {
+        let statement = Statement::If {
+            condition: less_than(variable("x"), number(5)),
+            consequence: Box::new(assign("y", number(1))),
+            alternative: Box::new(Statement::DoNothing),
+        };
+        let cfg = statement.to_cfg().unwrap();
+        assert_eq!(cfg.blocks.len(), 4);
+        assert_eq!(cfg.predecessors(3), vec![1, 2]);
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(3));
+        assert_eq!(cfg.evaluate(&mut environment)["y"].to_string(), "1");
+    }
+
+    #[test]
+    fn dot_test() {
+        let statement = Statement::While {
+            condition: less_than(variable("x"), number(5)),
+            body: Box::new(assign("x", add(variable("x"), number(1)))),
+        };
+        let dot = statement.to_cfg().unwrap().to_dot();
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.contains("  b1 [label=\"b1\\lif (x < 5)\\l\"];\n"));
+        assert!(dot.contains("  b1 -> b2 [label=\"true\"];\n"));
+        assert!(dot.contains("  b2 -> b1;\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
@@ -0,0 +1,562 @@
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+use crate::expression::{Environment, Expression};
+use crate::machine::format_environment;
+use crate::running::Running;
+use crate::statement::Statement;
+
+impl Running {
+    // 代入と条件の評価をそれぞれ1ステップとして、次にあり得る状態をすべて返す
+    pub fn transitions(&self, environment: &Environment) -> Vec<(Running, Environment)> {
+        if let Some(opened) = self.open() {
+            return opened.transitions(environment);
+        }
+        match self {
+            Running::Statement(statement) => statement_transitions(statement, environment),
+            Running::Sequence { first, second } => {
+                if let Running::Statement(Statement::DoNothing) = **first {
+                    return vec![(Running::Statement(*second.clone()), environment.clone())];
+                }
+                if first.is_abrupt() {
+                    return vec![(*first.clone(), environment.clone())];
+                }
+                first
+                    .transitions(environment)
+                    .into_iter()
+                    .map(|(first, env)| (Running::sequence(first, second.clone()), env))
+                    .collect()
+            }
+            Running::Parallel { left, right } => {
+                if let Running::Statement(Statement::DoNothing) = **left {
+                    return vec![(*right.clone(), environment.clone())];
+                }
+                if let Running::Statement(Statement::DoNothing) = **right {
+                    return vec![(*left.clone(), environment.clone())];
+                }
+                // 片方が中断したら全体が中断する
+                if left.is_abrupt() {
+                    return vec![(*left.clone(), environment.clone())];
+                }
+                if right.is_abrupt() {
+                    return vec![(*right.clone(), environment.clone())];
+                }
+                let mut results = Vec::new();
+                for (left, env) in left.transitions(environment) {
+                    results.push((Running::parallel(left, *right.clone()), env));
+                }
+                for (right, env) in right.transitions(environment) {
+                    results.push((Running::parallel(*left.clone(), right), env));
+                }
+                results
+            }
+            Running::Try {
+                body,
+                name,
+                handler,
+            } => {
+                if !body.is_reducible() {
+                    return vec![self.reduce(&mut environment.clone())];
+                }
+                body.transitions(environment)
+                    .into_iter()
+                    .map(|(body, env)| {
+                        (Running::try_catch(body, name.clone(), handler.clone()), env)
+                    })
+                    .collect()
+            }
+            Running::Loop { body, next } => {
+                if !body.is_reducible() {
+                    return vec![self.reduce(&mut environment.clone())];
+                }
+                body.transitions(environment)
+                    .into_iter()
+                    .map(|(body, env)| {
+                        let running = Running::Loop {
+                            body: Box::new(body),
+                            next: next.clone(),
+                        };
+                        (running, env)
+                    })
+                    .collect()
+            }
+            Running::Scope { saved, body } => {
+                if !body.is_reducible() {
+                    return vec![self.reduce(&mut environment.clone())];
+                }
+                body.transitions(environment)
+                    .into_iter()
+                    .map(|(body, env)| {
+                        let scope = Running::Scope {
+                            saved: saved.clone(),
+                            body: Box::new(body),
+                        };
+                        (scope, env)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+// 子を持たない文と、式を評価する文
+fn statement_transitions(
+    statement: &Statement,
+    environment: &Environment,
+) -> Vec<(Running, Environment)> {
+    let reduce = || vec![Running::Statement(statement.clone()).reduce(&mut environment.clone())];
+    let done =
+        |environment: Environment| vec![(Running::Statement(Statement::DoNothing), environment)];
+    match statement {
+        Statement::DoNothing => vec![],
+        Statement::Assignment { name, expression } => {
+            let mut new_env = environment.clone();
+            new_env.insert(name.clone(), expression.evaluate(environment));
+            done(new_env)
+        }
+        Statement::Update { .. } => done(statement.evaluate(&mut environment.clone())),
+        Statement::If {
+            condition,
+            consequence,
+            alternative,
+        } => match condition.evaluate(environment) {
+            Expression::Boolean(true) => {
+                vec![(consequence.as_ref().clone().into(), environment.clone())]
+            }
+            Expression::Boolean(false) => {
+                vec![(alternative.as_ref().clone().into(), environment.clone())]
+            }
+            _ => panic!("condition is not bool"),
+        },
+        Statement::While { .. } | Statement::Block { .. } => reduce(),
+        Statement::Choose { first, second } => vec![
+            (first.as_ref().clone().into(), environment.clone()),
+            (second.as_ref().clone().into(), environment.clone()),
+        ],
+        // 失敗する表明からは進めない。Explorerが違反として報告する
+        Statement::Assert { condition } => match condition.evaluate(environment) {
+            Expression::Boolean(true) => done(environment.clone()),
+            Expression::Boolean(false) => vec![],
+            _ => panic!("condition is not bool"),
+        },
+        Statement::Break | Statement::Continue => vec![],
+        Statement::Throw { expression } => {
+            if statement.is_abrupt() {
+                return vec![];
+            }
+            let value = expression.evaluate(environment);
+            vec![(
+                Statement::Throw { expression: value }.into(),
+                environment.clone(),
+            )]
+        }
+        // 束縛と宣言は代入と同じく1ステップにする
+        Statement::Let {
+            name,
+            expression,
+            body,
+        } => {
+            let value = Statement::Let {
+                name: name.clone(),
+                expression: expression.evaluate(environment),
+                body: body.clone(),
+            };
+            vec![Running::Statement(value).reduce(&mut environment.clone())]
+        }
+        Statement::Var { name, expression } => {
+            let assignment = Statement::Assignment {
+                name: name.clone(),
+                expression: expression.clone(),
+            };
+            statement_transitions(&assignment, environment)
+        }
+        Statement::Sequence { .. } | Statement::Parallel { .. } | Statement::Try { .. } => {
+            unreachable!()
+        }
+    }
+}
+
+impl Running {
+    // 次に実行する表明のうち、この環境で偽になるもの
+    fn failed_assertion(&self, environment: &Environment) -> Option<&Statement> {
+        match self {
+            Running::Statement(statement) => statement.failed_assertion(environment),
+            Running::Sequence { first, .. } => first.failed_assertion(environment),
+            Running::Parallel { left, right } => left
+                .failed_assertion(environment)
+                .or_else(|| right.failed_assertion(environment)),
+            Running::Try { body, .. }
+            | Running::Loop { body, .. }
+            | Running::Scope { body, .. } => body.failed_assertion(environment),
+        }
+    }
+}
+
+impl Statement {
+    fn failed_assertion(&self, environment: &Environment) -> Option<&Statement> {
+        match self {
+            Statement::Assert { condition } => match condition.evaluate(environment) {
+                Expression::Boolean(false) => Some(self),
+                _ => None,
+            },
+            Statement::Sequence { first, .. } => first.failed_assertion(environment),
+            Statement::Parallel { left, right } => left
+                .failed_assertion(environment)
+                .or_else(|| right.failed_assertion(environment)),
+            Statement::Try { body, .. } => body.failed_assertion(environment),
+            _ => None,
+        }
+    }
+}
+
+fn is_defined(expression: &Expression, environment: &Environment) -> bool {
+    match expression {
+        Expression::Number(_) | Expression::Boolean(_) => true,
+        Expression::Variable(name) => environment.contains_key(name),
+        Expression::Add { left, right }
+        | Expression::Multiply { left, right }
+        | Expression::LessThan { left, right }
+        | Expression::Equal { left, right } => {
+            is_defined(left, environment) && is_defined(right, environment)
+        }
+        Expression::Tuple(elements) => elements.iter().all(|e| is_defined(e, environment)),
+        Expression::Record(fields) => fields.iter().all(|(_, e)| is_defined(e, environment)),
+        Expression::Field { expression, .. } => is_defined(expression, environment),
+        Expression::Function { .. } => expression
+            .free_variables()
+            .iter()
+            .all(|name| environment.contains_key(name)),
+        Expression::Closure { .. } => true,
+        Expression::Call {
+            function,
+            arguments,
+        } => {
+            is_defined(function, environment)
+                && arguments.iter().all(|a| is_defined(a, environment))
+        }
+    }
+}
+
+// 不変条件か、失敗した表明
+pub enum Property {
+    Invariant(Expression),
+    Assertion(Statement),
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Property::Invariant(invariant) => write!(f, "{}", invariant),
+            Property::Assertion(assertion) => write!(f, "{}", assertion),
+        }
+    }
+}
+
+pub struct Violation {
+    pub property: Property,
+    // 最初の状態から違反した状態まで
+    pub trace: Vec<(Running, Environment)>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (_, environment) = self.trace.last().unwrap();
+        write!(
+            f,
+            "{} is violated after {} steps: {}",
+            self.property,
+            self.trace.len() - 1,
+            format_environment(environment)
+        )
+    }
+}
+
+pub struct Exploration {
+    pub finals: Vec<Environment>,
+    pub violations: Vec<Violation>,
+    pub states: usize,
+    pub is_complete: bool,
+}
+
+pub struct Explorer {
+    invariants: Vec<Expression>,
+    max_states: usize,
+}
+
+impl Explorer {
+    pub fn new(invariants: Vec<Expression>, max_states: usize) -> Self {
+        Explorer {
+            invariants,
+            max_states,
+        }
+    }
+    // 幅優先でたどるので、違反は不変条件や表明ごとに一番短い手順を報告する
+    pub fn explore(&self, statement: &Statement, environment: &Environment) -> Exploration {
+        let key = |running: &Running, environment: &Environment| {
+            format!("{:?} {}", running, format_environment(environment))
+        };
+        let start = Running::Statement(statement.clone());
+        let mut seen = HashSet::new();
+        seen.insert(key(&start, environment));
+        let mut states: Vec<(Running, Environment, Option<usize>)> =
+            vec![(start, environment.clone(), None)];
+        let mut queue = VecDeque::from(vec![0]);
+        let mut finals = Vec::new();
+        let mut final_keys = HashSet::new();
+        let mut violated = vec![false; self.invariants.len()];
+        let mut violations = Vec::new();
+        let mut failed = HashSet::new();
+        let mut is_complete = true;
+
+        while let Some(index) = queue.pop_front() {
+            let (current, env, _) = states[index].clone();
+            for (i, invariant) in self.invariants.iter().enumerate() {
+                if violated[i] || !is_defined(invariant, &env) {
+                    continue;
+                }
+                match invariant.evaluate(&env) {
+                    Expression::Boolean(true) => {}
+                    Expression::Boolean(false) => {
+                        violated[i] = true;
+                        violations.push(Violation {
+                            property: Property::Invariant(invariant.clone()),
+                            trace: self.trace(&states, index),
+                        });
+                    }
+                    _ => panic!("invariant is not bool"),
+                }
+            }
+            if let Some(assertion) = current.failed_assertion(&env) {
+                if failed.insert(assertion.to_string()) {
+                    violations.push(Violation {
+                        property: Property::Assertion(assertion.clone()),
+                        trace: self.trace(&states, index),
+                    });
+                }
+                continue;
+            }
+            if !current.is_reducible() {
+                if final_keys.insert(format_environment(&env)) {
+                    finals.push(env);
+                }
+                continue;
+            }
+            for (next, next_env) in current.transitions(&env) {
+                if !seen.insert(key(&next, &next_env)) {
+                    continue;
+                }
+                if states.len() == self.max_states {
+                    is_complete = false;
+                    continue;
+                }
+                states.push((next, next_env, Some(index)));
+                queue.push_back(states.len() - 1);
+            }
+        }
+        finals.sort_by_key(format_environment);
+        Exploration {
+            finals,
+            violations,
+            states: states.len(),
+            is_complete,
+        }
+    }
+    fn trace(
+        &self,
+        states: &[(Running, Environment, Option<usize>)],
+        index: usize,
+    ) -> Vec<(Running, Environment)> {
+        let mut trace = Vec::new();
+        let mut current = Some(index);
+        while let Some(i) = current {
+            let (statement, environment, parent) = &states[i];
+            trace.push((statement.clone(), environment.clone()));
+            current = *parent;
+        }
+        trace.reverse();
+        trace
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn boolean(value: bool) -> Expression {
+        Expression::Boolean(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+    fn parallel(left: Statement, right: Statement) -> Statement {
+        Statement::Parallel {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn finals(exploration: &Exploration) -> Vec<String> {
+        exploration.finals.iter().map(format_environment).collect()
+    }
+
+    #[test]
+    fn display_test() {
+        let statement = Statement::Choose {
+            first: Box::new(assign("x", number(1))),
+            second: Box::new(parallel(assign("x", number(2)), assign("y", number(3)))),
+        };
+        assert_eq!(
+            statement.to_string(),
+            "choose { x = 1 } or { { x = 2 } || { y = 3 } }"
+        );
+    }
+
+    #[test]
+    fn choose_test() {
+        let statement = sequence(
+            Statement::Choose {
+                first: Box::new(assign("x", number(1))),
+                second: Box::new(assign("x", number(2))),
+            },
+            assign("y", add(variable("x"), number(10))),
+        );
+        let exploration = Explorer::new(vec![], 100).explore(&statement, &Environment::new());
+        assert!(exploration.is_complete);
+        assert_eq!(
+            finals(&exploration),
+            vec![
+                "{\"x\": Number(1), \"y\": Number(11)}",
+                "{\"x\": Number(2), \"y\": Number(12)}"
+            ]
+        );
+        // 決定的な意味論では左が選ばれる
+        assert_eq!(
+            format_environment(&statement.evaluate(&mut Environment::new())),
+            finals(&exploration)[0]
+        );
+    }
+
+    #[test]
+    fn lost_update_test() {
+        // t = x; x = t + 1 を二つ並べると、片方の更新が失われることがある
+        let increment = |t: &str| {
+            sequence(
+                assign(t, variable("x")),
+                assign("x", add(variable(t), number(1))),
+            )
+        };
+        let statement = parallel(increment("a"), increment("b"));
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(0));
+        let exploration = Explorer::new(vec![], 1000).explore(&statement, &environment);
+        let xs = exploration
+            .finals
+            .iter()
+            .map(|env| env["x"].to_string())
+            .collect::<HashSet<_>>();
+        assert_eq!(xs, ["1", "2"].iter().map(|x| x.to_string()).collect());
+    }
+
+    #[test]
+    fn mutual_exclusion_test() {
+        // 確認してから鍵をかけるまでの間に割り込まれると、二人とも入れてしまう
+        let enter = sequence(
+            Statement::While {
+                condition: variable("locked"),
+                body: Box::new(Statement::DoNothing),
+            },
+            sequence(
+                assign("locked", boolean(true)),
+                assign("inside", add(variable("inside"), number(1))),
+            ),
+        );
+        let statement = parallel(enter.clone(), enter);
+        let mut environment = Environment::new();
+        environment.insert("locked".to_string(), boolean(false));
+        environment.insert("inside".to_string(), number(0));
+        let invariant = less_than(variable("inside"), number(2));
+        let exploration = Explorer::new(vec![invariant], 1000).explore(&statement, &environment);
+        assert!(exploration.is_complete);
+        assert_eq!(exploration.violations.len(), 1);
+        let violation = &exploration.violations[0];
+        assert!(violation
+            .to_string()
+            .starts_with("inside < 2 is violated after"));
+        assert_eq!(violation.trace.last().unwrap().1["inside"], number(2));
+        // 一人しか入れない実行では鍵がかかったまま回り続けるので、最後の状態にはならない
+        assert_eq!(
+            finals(&exploration),
+            vec!["{\"inside\": Number(2), \"locked\": Boolean(true)}"]
+        );
+    }
+
+    #[test]
+    fn max_states_test() {
+        let statement = Statement::While {
+            condition: boolean(true),
+            body: Box::new(assign("x", add(variable("x"), number(1)))),
+        };
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(0));
+        let exploration = Explorer::new(vec![], 50).explore(&statement, &environment);
+        assert!(!exploration.is_complete);
+        assert_eq!(exploration.states, 50);
+        assert!(exploration.finals.is_empty());
+    }
+
+    #[test]
+    fn assertion_test() {
+        // 更新が失われると、終わった後の表明が成り立たない
+        let increment = |t: &str| {
+            sequence(
+                assign(t, variable("x")),
+                assign("x", add(variable(t), number(1))),
+            )
+        };
+        let statement = sequence(
+            parallel(increment("a"), increment("b")),
+            Statement::Assert {
+                condition: less_than(number(1), variable("x")),
+            },
+        );
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(0));
+        let exploration = Explorer::new(vec![], 1000).explore(&statement, &environment);
+        assert!(exploration.is_complete);
+        assert_eq!(exploration.violations.len(), 1);
+        let violation = &exploration.violations[0];
+        assert!(violation
+            .to_string()
+            .starts_with("assert (1 < x) is violated after"));
+        assert_eq!(violation.trace.last().unwrap().1["x"], number(1));
+        // 表明が成り立つ実行だけが最後まで進む
+        assert!(!exploration.finals.is_empty());
+        assert!(exploration.finals.iter().all(|env| env["x"] == number(2)));
+    }
+}
@@ -0,0 +1,749 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::expression::Expression;
+use crate::statement::Statement;
+use crate::subset::Construct;
+
+// 代入と条件とdo-nothingを基本ブロックとして前から順に番号(ラベル)を付け、
+// ラベルの間の流れの上でデータフロー解析をする
+
+pub type Label = usize;
+
+#[derive(Clone, Debug)]
+pub enum ElementaryBlock {
+    Skip,
+    Assignment {
+        name: String,
+        expression: Expression,
+    },
+    Condition(Expression),
+}
+
+impl fmt::Display for ElementaryBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ElementaryBlock::Skip => write!(f, "do-nothing"),
+            ElementaryBlock::Assignment { name, expression } => {
+                write!(f, "{} = {}", name, expression)
+            }
+            ElementaryBlock::Condition(condition) => write!(f, "{}", condition),
+        }
+    }
+}
+
+impl ElementaryBlock {
+    pub fn used_variables(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        match self {
+            ElementaryBlock::Skip => {}
+            ElementaryBlock::Assignment { expression, .. } => variables(expression, &mut names),
+            ElementaryBlock::Condition(condition) => variables(condition, &mut names),
+        }
+        names
+    }
+    pub fn defined_variable(&self) -> Option<&str> {
+        match self {
+            ElementaryBlock::Assignment { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+}
+
+fn variables(expression: &Expression, names: &mut BTreeSet<String>) {
+    match expression {
+        Expression::Variable(name) => {
+            names.insert(name.clone());
+        }
+        Expression::Add { left, right }
+        | Expression::Multiply { left, right }
+        | Expression::LessThan { left, right }
+        | Expression::Equal { left, right } => {
+            variables(left, names);
+            variables(right, names);
+        }
+        Expression::Number(_) | Expression::Boolean(_) => {}
+        Expression::Tuple(_)
+        | Expression::Record(_)
+        | Expression::Field { .. }
+        | Expression::Function { .. }
+        | Expression::Closure { .. }
+        | Expression::Call { .. } => unreachable!(),
+    }
+}
+
+// 条件から出る流れには、条件が真か偽かを付ける
+pub type Flow = (Label, Label, Option<bool>);
+
+pub struct LabelledProgram {
+    pub blocks: Vec<ElementaryBlock>,
+    pub flow: Vec<Flow>,
+    pub init: Label,
+    pub finals: Vec<(Label, Option<bool>)>,
+}
+
+impl fmt::Display for LabelledProgram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (label, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "{}: {}", label, block)?;
+        }
+        Ok(())
+    }
+}
+
+impl LabelledProgram {
+    fn new_block(&mut self, block: ElementaryBlock) -> Label {
+        self.blocks.push(block);
+        self.blocks.len() - 1
+    }
+    // 最初のラベルと、最後のラベル(と出るときの条件)を返す
+    fn label(&mut self, statement: &Statement) -> (Label, Vec<(Label, Option<bool>)>) {
+        match statement {
+            Statement::DoNothing => {
+                let label = self.new_block(ElementaryBlock::Skip);
+                (label, vec![(label, None)])
+            }
+            Statement::Assignment { name, expression } => {
+                let label = self.new_block(ElementaryBlock::Assignment {
+                    name: name.clone(),
+                    expression: expression.clone(),
+                });
+                (label, vec![(label, None)])
+            }
+            Statement::Sequence { first, second } => {
+                let (init, first_finals) = self.label(first);
+                let (second_init, finals) = self.label(second);
+                for (label, kind) in first_finals {
+                    self.flow.push((label, second_init, kind));
+                }
+                (init, finals)
+            }
+            Statement::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let label = self.new_block(ElementaryBlock::Condition(condition.clone()));
+                let (consequence_init, mut finals) = self.label(consequence);
+                let (alternative_init, alternative_finals) = self.label(alternative);
+                self.flow.push((label, consequence_init, Some(true)));
+                self.flow.push((label, alternative_init, Some(false)));
+                finals.extend(alternative_finals);
+                (label, finals)
+            }
+            Statement::While { condition, body } => {
+                let label = self.new_block(ElementaryBlock::Condition(condition.clone()));
+                let (body_init, body_finals) = self.label(body);
+                self.flow.push((label, body_init, Some(true)));
+                for (final_label, kind) in body_finals {
+                    self.flow.push((final_label, label, kind));
+                }
+                (label, vec![(label, Some(false))])
+            }
+            // 選ぶ場所は何もしないブロックにする
+            Statement::Choose { first, second } => {
+                let label = self.new_block(ElementaryBlock::Skip);
+                let (first_init, mut finals) = self.label(first);
+                let (second_init, second_finals) = self.label(second);
+                self.flow.push((label, first_init, None));
+                self.flow.push((label, second_init, None));
+                finals.extend(second_finals);
+                (label, finals)
+            }
+            // 偽なら止まるので、真のときだけ次へ進む
+            Statement::Assert { condition } => {
+                let label = self.new_block(ElementaryBlock::Condition(condition.clone()));
+                (label, vec![(label, Some(true))])
+            }
+            Statement::Parallel { .. }
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Throw { .. }
+            | Statement::Try { .. }
+            | Statement::Let { .. }
+            | Statement::Block { .. }
+            | Statement::Var { .. }
+            | Statement::Update { .. } => unreachable!(),
+        }
+    }
+    pub fn predecessors(&self, label: Label) -> Vec<Label> {
+        self.flow
+            .iter()
+            .filter(|(_, to, _)| *to == label)
+            .map(|(from, _, _)| *from)
+            .collect()
+    }
+    pub fn successors(&self, label: Label) -> Vec<Label> {
+        self.flow
+            .iter()
+            .filter(|(from, _, _)| *from == label)
+            .map(|(_, to, _)| *to)
+            .collect()
+    }
+    pub fn is_final(&self, label: Label) -> bool {
+        self.finals.iter().any(|(l, _)| *l == label)
+    }
+}
+
+impl Statement {
+    pub fn to_labelled(&self) -> Result<LabelledProgram, String> {
+        self.check_supported(&[Construct::Choose, Construct::Assert])?;
+        let mut program = LabelledProgram {
+            blocks: vec![],
+            flow: vec![],
+            init: 0,
+            finals: vec![],
+        };
+        let (init, finals) = program.label(self);
+        program.init = init;
+        program.finals = finals;
+        Ok(program)
+    }
+}
+
+// 変数と、それを定義したラベル。Noneは最初の環境から来た値(未定義かもしれない)
+pub type Definition = (String, Option<Label>);
+
+// 各ラベルの入口と出口で届いているかもしれない定義
+pub fn reaching_definitions(
+    program: &LabelledProgram,
+    inputs: &[&str],
+) -> Vec<(BTreeSet<Definition>, BTreeSet<Definition>)> {
+    let mut names = BTreeSet::new();
+    for block in &program.blocks {
+        names.extend(block.used_variables());
+        if let Some(name) = block.defined_variable() {
+            names.insert(name.to_string());
+        }
+    }
+    let initial = names
+        .iter()
+        .filter(|name| !inputs.contains(&name.as_str()))
+        .map(|name| (name.clone(), None))
+        .collect::<BTreeSet<_>>();
+
+    let size = program.blocks.len();
+    let mut result = vec![(BTreeSet::new(), BTreeSet::new()); size];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for label in 0..size {
+            let mut entry = if label == program.init {
+                initial.clone()
+            } else {
+                BTreeSet::new()
+            };
+            for predecessor in program.predecessors(label) {
+                entry.extend(result[predecessor].1.iter().cloned());
+            }
+            let exit = match program.blocks[label].defined_variable() {
+                Some(name) => entry
+                    .iter()
+                    .filter(|(n, _)| n != name)
+                    .cloned()
+                    .chain(vec![(name.to_string(), Some(label))])
+                    .collect(),
+                None => entry.clone(),
+            };
+            if (entry.clone(), exit.clone()) != result[label] {
+                result[label] = (entry, exit);
+                changed = true;
+            }
+        }
+    }
+    result
+}
+
+// 各ラベルの入口と出口で生きている変数。outputsは最後に読まれるものとする
+pub fn live_variables(
+    program: &LabelledProgram,
+    outputs: &[&str],
+) -> Vec<(BTreeSet<String>, BTreeSet<String>)> {
+    let size = program.blocks.len();
+    let mut result = vec![(BTreeSet::new(), BTreeSet::new()); size];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for label in (0..size).rev() {
+            let mut exit = if program.is_final(label) {
+                outputs.iter().map(|s| s.to_string()).collect()
+            } else {
+                BTreeSet::new()
+            };
+            for successor in program.successors(label) {
+                exit.extend(result[successor].0.iter().cloned());
+            }
+            let block = &program.blocks[label];
+            let mut entry = exit.clone();
+            if let Some(name) = block.defined_variable() {
+                entry.remove(name);
+            }
+            entry.extend(block.used_variables());
+            if (entry.clone(), exit.clone()) != result[label] {
+                result[label] = (entry, exit);
+                changed = true;
+            }
+        }
+    }
+    result
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Constant {
+    Value(Expression),
+    Unknown,
+}
+
+// Noneはそこへ来ることがないことを表す
+type ConstantState = Option<BTreeMap<String, Constant>>;
+
+fn fold(expression: &Expression, state: &BTreeMap<String, Constant>) -> Option<Expression> {
+    match expression {
+        Expression::Number(_) | Expression::Boolean(_) => Some(expression.clone()),
+        Expression::Variable(name) => match state.get(name) {
+            Some(Constant::Value(value)) => Some(value.clone()),
+            _ => None,
+        },
+        Expression::Add { left, right } => match (fold(left, state)?, fold(right, state)?) {
+            (Expression::Number(l), Expression::Number(r)) => {
+                l.checked_add(r).map(Expression::Number)
+            }
+            _ => None,
+        },
+        Expression::Multiply { left, right } => match (fold(left, state)?, fold(right, state)?) {
+            (Expression::Number(l), Expression::Number(r)) => {
+                l.checked_mul(r).map(Expression::Number)
+            }
+            _ => None,
+        },
+        Expression::LessThan { left, right } => match (fold(left, state)?, fold(right, state)?) {
+            (Expression::Number(l), Expression::Number(r)) => Some(Expression::Boolean(l < r)),
+            _ => None,
+        },
+        // 畳み込んだ値は数か真偽値なので、そのまま比べられる
+        Expression::Equal { left, right } => Some(Expression::Boolean(
+            fold(left, state)? == fold(right, state)?,
+        )),
+        Expression::Tuple(_)
+        | Expression::Record(_)
+        | Expression::Field { .. }
+        | Expression::Function { .. }
+        | Expression::Closure { .. }
+        | Expression::Call { .. } => unreachable!(),
+    }
+}
+
+fn join(left: &ConstantState, right: &ConstantState) -> ConstantState {
+    match (left, right) {
+        (None, state) | (state, None) => state.clone(),
+        (Some(l), Some(r)) => {
+            let names = l.keys().chain(r.keys()).cloned().collect::<BTreeSet<_>>();
+            Some(
+                names
+                    .into_iter()
+                    .map(|name| {
+                        let value = match (l.get(&name), r.get(&name)) {
+                            (Some(a), Some(b)) if a == b => a.clone(),
+                            _ => Constant::Unknown,
+                        };
+                        (name, value)
+                    })
+                    .collect(),
+            )
+        }
+    }
+}
+
+// 定数伝播。条件が定数なら、通らない側へは何も流さない
+fn constant_propagation(program: &LabelledProgram) -> Vec<ConstantState> {
+    let size = program.blocks.len();
+    let mut entries: Vec<ConstantState> = vec![None; size];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for label in 0..size {
+            let mut entry = if label == program.init {
+                Some(BTreeMap::new())
+            } else {
+                None
+            };
+            for (from, _, kind) in program.flow.iter().filter(|(_, to, _)| *to == label) {
+                entry = join(
+                    &entry,
+                    &constant_exit(program, *from, &entries[*from], *kind),
+                );
+            }
+            if entry != entries[label] {
+                entries[label] = entry;
+                changed = true;
+            }
+        }
+    }
+    entries
+}
+
+fn constant_exit(
+    program: &LabelledProgram,
+    label: Label,
+    entry: &ConstantState,
+    kind: Option<bool>,
+) -> ConstantState {
+    let state = entry.as_ref()?;
+    match &program.blocks[label] {
+        ElementaryBlock::Skip => Some(state.clone()),
+        ElementaryBlock::Assignment { name, expression } => {
+            let mut state = state.clone();
+            let value = match fold(expression, &state) {
+                Some(value) => Constant::Value(value),
+                None => Constant::Unknown,
+            };
+            state.insert(name.clone(), value);
+            Some(state)
+        }
+        ElementaryBlock::Condition(condition) => match (fold(condition, state), kind) {
+            (Some(Expression::Boolean(value)), Some(expected)) if value != expected => None,
+            _ => Some(state.clone()),
+        },
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LintKind {
+    // alwaysなら、どの経路でも定義されずに読まれる
+    UninitializedRead { name: String, always: bool },
+    UnusedAssignment { name: String },
+    ConstantCondition(bool),
+}
+
+#[derive(Clone, Debug)]
+pub struct Lint {
+    pub label: Label,
+    pub block: String,
+    pub kind: LintKind,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: ", self.label, self.block)?;
+        match &self.kind {
+            LintKind::UninitializedRead { name, always: true } => {
+                write!(f, "{} is read before it is assigned", name)
+            }
+            LintKind::UninitializedRead {
+                name,
+                always: false,
+            } => write!(f, "{} may be read before it is assigned", name),
+            LintKind::UnusedAssignment { name } => {
+                write!(f, "value assigned to {} is never read", name)
+            }
+            LintKind::ConstantCondition(value) => write!(f, "condition is always {}", value),
+        }
+    }
+}
+
+// inputsは最初の環境で定義されている変数、outputsは実行後に読まれる変数
+pub struct Linter {
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+}
+
+impl Linter {
+    pub fn new(inputs: Vec<&str>, outputs: Vec<&str>) -> Self {
+        Linter {
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+    pub fn lint(&self, statement: &Statement) -> Result<Vec<Lint>, String> {
+        let program = statement.to_labelled()?;
+        let inputs = self.inputs.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+        let outputs = self.outputs.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+        let reaching = reaching_definitions(&program, &inputs);
+        let live = live_variables(&program, &outputs);
+        let constants = constant_propagation(&program);
+
+        let mut lints = Vec::new();
+        for (label, block) in program.blocks.iter().enumerate() {
+            let mut push = |kind| {
+                lints.push(Lint {
+                    label,
+                    block: block.to_string(),
+                    kind,
+                })
+            };
+            for name in block.used_variables() {
+                let definitions = reaching[label]
+                    .0
+                    .iter()
+                    .filter(|(n, _)| *n == name)
+                    .collect::<Vec<_>>();
+                if definitions.iter().any(|(_, l)| l.is_none()) {
+                    push(LintKind::UninitializedRead {
+                        always: definitions.len() == 1,
+                        name,
+                    });
+                }
+            }
+            if let Some(name) = block.defined_variable() {
+                if !live[label].1.contains(name) {
+                    push(LintKind::UnusedAssignment {
+                        name: name.to_string(),
+                    });
+                }
+            }
+            if let (ElementaryBlock::Condition(condition), Some(state)) = (block, &constants[label])
+            {
+                if let Some(Expression::Boolean(value)) = fold(condition, state) {
+                    push(LintKind::ConstantCondition(value));
+                }
+            }
+        }
+        Ok(lints)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+
+    // x = 1; while (x < n) { x = x + 1 }; y = x
+    fn program() -> Statement {
+        sequence(
+            assign("x", number(1)),
+            sequence(
+                Statement::While {
+                    condition: less_than(variable("x"), variable("n")),
+                    body: Box::new(assign("x", add(variable("x"), number(1)))),
+                },
+                assign("y", variable("x")),
+            ),
+        )
+    }
+
+    #[test]
+    fn labelled_test() {
+        let program = program().to_labelled().unwrap();
+        assert_eq!(
+            program.to_string(),
+            "0: x = 1\n1: x < n\n2: x = x + 1\n3: y = x\n"
+        );
+        assert_eq!(
+            program.flow,
+            vec![
+                (1, 2, Some(true)),
+                (2, 1, None),
+                (1, 3, Some(false)),
+                (0, 1, None)
+            ]
+        );
+        assert_eq!(program.init, 0);
+        assert_eq!(program.finals, vec![(3, None)]);
+    }
+
+    #[test]
+    fn reaching_definitions_test() {
+        let program = program().to_labelled().unwrap();
+        let reaching = reaching_definitions(&program, &["n"]);
+        let entry = reaching[3].0.iter().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            entry,
+            vec![
+                ("x".to_string(), Some(0)),
+                ("x".to_string(), Some(2)),
+                ("y".to_string(), None)
+            ]
+        );
+    }
+
+    #[test]
+    fn live_variables_test() {
+        let program = program().to_labelled().unwrap();
+        let live = live_variables(&program, &["y"]);
+        assert_eq!(
+            live[1].0.iter().cloned().collect::<Vec<_>>(),
+            vec!["n".to_string(), "x".to_string()]
+        );
+        assert!(live[0].0.contains("n"));
+        assert!(!live[0].0.contains("x"));
+    }
+
+    #[test]
+    fn clean_program_test() {
+        let linter = Linter::new(vec!["n"], vec!["y"]);
+        assert!(linter.lint(&program()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn uninitialized_read_test() {
+        // if (a < 1) { x = 1 } else { do-nothing }; y = x + z
+        let statement = sequence(
+            Statement::If {
+                condition: less_than(variable("a"), number(1)),
+                consequence: Box::new(assign("x", number(1))),
+                alternative: Box::new(Statement::DoNothing),
+            },
+            assign("y", add(variable("x"), variable("z"))),
+        );
+        let lints = Linter::new(vec!["a"], vec!["y"]).lint(&statement).unwrap();
+        let messages = lints.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "3: y = x + z: x may be read before it is assigned",
+                "3: y = x + z: z is read before it is assigned",
+            ]
+        );
+    }
+
+    #[test]
+    fn unused_assignment_test() {
+        // x = 1; x = 2; y = x; z = y
+        let statement = sequence(
+            assign("x", number(1)),
+            sequence(
+                assign("x", number(2)),
+                sequence(assign("y", variable("x")), assign("z", variable("y"))),
+            ),
+        );
+        let lints = Linter::new(vec![], vec!["y"]).lint(&statement).unwrap();
+        let kinds = lints
+            .iter()
+            .map(|l| (l.label, l.kind.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                (
+                    0,
+                    LintKind::UnusedAssignment {
+                        name: "x".to_string()
+                    }
+                ),
+                (
+                    3,
+                    LintKind::UnusedAssignment {
+                        name: "z".to_string()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn constant_condition_test() {
+        // x = 3; if (x < 5) { y = 1 } else { y = 2 }; while (y < 1) { y = y + 1 }
+        let statement = sequence(
+            assign("x", number(3)),
+            sequence(
+                Statement::If {
+                    condition: less_than(variable("x"), number(5)),
+                    consequence: Box::new(assign("y", number(1))),
+                    alternative: Box::new(assign("y", number(2))),
+                },
+                Statement::While {
+                    condition: less_than(variable("y"), number(1)),
+                    body: Box::new(assign("y", add(variable("y"), number(1)))),
+                },
+            ),
+        );
+        let lints = Linter::new(vec![], vec!["y"]).lint(&statement).unwrap();
+        let messages = lints.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        // 偽の側は通らないので、whileの条件も定数になる
+        assert_eq!(
+            messages,
+            vec![
+                "1: x < 5: condition is always true",
+                "4: y < 1: condition is always false",
+            ]
+        );
+
+        // ループの中で値が変わる条件は定数ではない
+        let linter = Linter::new(vec!["n"], vec!["y"]);
+        let statement = sequence(
+            assign("y", number(0)),
+            Statement::While {
+                condition: less_than(variable("y"), number(3)),
+                body: Box::new(assign("y", add(variable("y"), number(1)))),
+            },
+        );
+        assert!(linter.lint(&statement).unwrap().is_empty());
+    }
+
+    #[test]
+    fn equal_condition_test() {
+        // b = x == 3 の値も畳み込める
+        let statement = sequence(
+            assign("x", number(3)),
+            sequence(
+                assign(
+                    "b",
+                    Expression::Equal {
+                        left: Box::new(variable("x")),
+                        right: Box::new(number(3)),
+                    },
+                ),
+                Statement::If {
+                    condition: variable("b"),
+                    consequence: Box::new(assign("y", number(1))),
+                    alternative: Box::new(assign("y", number(2))),
+                },
+            ),
+        );
+        let lints = Linter::new(vec![], vec!["y"]).lint(&statement).unwrap();
+        let messages = lints.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        assert_eq!(messages, vec!["2: b: condition is always true"]);
+    }
+
+    #[test]
+    fn assert_test() {
+        // assert (x < 5); y = x
+        let statement = sequence(
+            Statement::Assert {
+                condition: less_than(variable("x"), number(5)),
+            },
+            assign("y", variable("x")),
+        );
+        let program = statement.to_labelled().unwrap();
+        assert_eq!(program.flow, vec![(0, 1, Some(true))]);
+        let lints = Linter::new(vec![], vec!["y"]).lint(&statement).unwrap();
+        let messages = lints.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "0: x < 5: x is read before it is assigned",
+                "1: y = x: x is read before it is assigned",
+            ]
+        );
+    }
+}
@@ -0,0 +1,452 @@
+use std::cell::Cell;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+use crate::expression::{Environment, Expression};
+use crate::running::Running;
+use crate::statement::Statement;
+
+// 2つのプログラムを同じ入力で実行して、出力の変数を比べる。
+// 燃料を使い切った実行は、どちらとも決められないので比べない。
+// 実行時エラーで止まった実行は、片方だけなら違いとして報告する
+
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    // 出力の変数の値。代入されていなければNone
+    Normal(Vec<(String, Option<Expression>)>),
+    Abrupt(String),
+    OutOfFuel,
+    // あふれや未定義の変数などで止まった
+    Error(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Counterexample {
+    pub input: Environment,
+    pub first: Outcome,
+    pub second: Outcome,
+}
+
+#[derive(Clone, Debug)]
+pub enum Equivalence {
+    Equivalent { tested: usize, inconclusive: usize },
+    Different(Counterexample),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Normal(values) => {
+                let values = values
+                    .iter()
+                    .map(|(name, value)| match value {
+                        Some(value) => format!("{}: {}", name, value),
+                        None => format!("{}: undefined", name),
+                    })
+                    .collect::<Vec<_>>();
+                write!(f, "{{{}}}", values.join(", "))
+            }
+            Outcome::Abrupt(statement) => write!(f, "{}", statement),
+            Outcome::OutOfFuel => write!(f, "out of fuel"),
+            Outcome::Error(message) => write!(f, "error ({})", message),
+        }
+    }
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names = self.input.keys().collect::<Vec<_>>();
+        names.sort();
+        let input = names
+            .iter()
+            .map(|name| format!("{}: {}", name, self.input[*name]))
+            .collect::<Vec<_>>();
+        write!(
+            f,
+            "input {{{}}}: first gives {}, second gives {}",
+            input.join(", "),
+            self.first,
+            self.second
+        )
+    }
+}
+
+impl Outcome {
+    fn is_same(&self, other: &Outcome) -> bool {
+        match (self, other) {
+            (Outcome::Normal(left), Outcome::Normal(right)) => {
+                left.iter().zip(right).all(|(l, r)| match (&l.1, &r.1) {
+                    (Some(l), Some(r)) => l.is_equal(r),
+                    (None, None) => true,
+                    _ => false,
+                })
+            }
+            (Outcome::Abrupt(left), Outcome::Abrupt(right)) => left == right,
+            (Outcome::Error(left), Outcome::Error(right)) => left == right,
+            _ => false,
+        }
+    }
+}
+
+// 実行時エラーはpanicで伝わるので、runの中で起きたpanicは表示しない。
+// フックはプロセスで共有なので一度だけ差し替え、スレッドごとに切り替える
+thread_local! {
+    static SILENT: Cell<bool> = const { Cell::new(false) };
+}
+
+fn catch_silently<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !SILENT.with(|silent| silent.get()) {
+                previous(info);
+            }
+        }));
+    });
+    SILENT.with(|silent| silent.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    SILENT.with(|silent| silent.set(false));
+    result.map_err(|payload| match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "unknown error".to_string(),
+        },
+    })
+}
+
+pub struct EquivalenceChecker {
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    fuel: usize,
+}
+
+impl EquivalenceChecker {
+    pub fn new(inputs: Vec<&str>, outputs: Vec<&str>, fuel: usize) -> Self {
+        EquivalenceChecker {
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+            fuel,
+        }
+    }
+    pub fn inputs(&self) -> &Vec<String> {
+        &self.inputs
+    }
+    pub fn outputs(&self) -> &Vec<String> {
+        &self.outputs
+    }
+    pub fn fuel(&self) -> usize {
+        self.fuel
+    }
+    // 各入力に0からmaxまでを割り当てる組み合わせすべて
+    pub fn exhaustive(&self, max: u32) -> Vec<Environment> {
+        let mut environments = vec![Environment::new()];
+        for name in &self.inputs {
+            environments = environments
+                .iter()
+                .flat_map(|environment| {
+                    (0..=max).map(move |value| {
+                        let mut environment = environment.clone();
+                        environment.insert(name.clone(), Expression::Number(value));
+                        environment
+                    })
+                })
+                .collect();
+        }
+        environments
+    }
+    // 同じseedならいつも同じ入力になる
+    pub fn random(&self, seed: u64, count: usize, max: u32) -> Vec<Environment> {
+        let mut state = seed.max(1);
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % (max as u64 + 1)) as u32
+        };
+        (0..count)
+            .map(|_| {
+                self.inputs
+                    .iter()
+                    .map(|name| (name.clone(), Expression::Number(next())))
+                    .collect()
+            })
+            .collect()
+    }
+    pub fn run(&self, statement: &Statement, input: &Environment) -> Outcome {
+        let result = catch_silently(|| {
+            let mut statement = Running::Statement(statement.clone());
+            let mut environment = input.clone();
+            for _ in 0..self.fuel {
+                if !statement.is_reducible() {
+                    break;
+                }
+                let (next, next_env) = statement.reduce(&mut environment);
+                statement = next;
+                environment = next_env;
+            }
+            (statement, environment)
+        });
+        let (statement, environment) = match result {
+            Ok(result) => result,
+            Err(message) => return Outcome::Error(message),
+        };
+        if statement.is_abrupt() {
+            Outcome::Abrupt(statement.to_string())
+        } else if statement.is_reducible() {
+            Outcome::OutOfFuel
+        } else {
+            Outcome::Normal(
+                self.outputs
+                    .iter()
+                    .map(|name| (name.clone(), environment.get(name).cloned()))
+                    .collect(),
+            )
+        }
+    }
+    // 入力の順に試し、最初に違いが出た入力を返す
+    pub fn check(
+        &self,
+        first: &Statement,
+        second: &Statement,
+        inputs: &[Environment],
+    ) -> Equivalence {
+        let mut inconclusive = 0;
+        for input in inputs {
+            let (left, right) = (self.run(first, input), self.run(second, input));
+            if let (Outcome::OutOfFuel, _) | (_, Outcome::OutOfFuel) = (&left, &right) {
+                inconclusive += 1;
+            } else if !left.is_same(&right) {
+                return Equivalence::Different(Counterexample {
+                    input: input.clone(),
+                    first: left,
+                    second: right,
+                });
+            }
+        }
+        Equivalence::Equivalent {
+            tested: inputs.len() - inconclusive,
+            inconclusive,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn multiply(left: Expression, right: Expression) -> Expression {
+        Expression::Multiply {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(statements: Vec<Statement>) -> Statement {
+        statements
+            .into_iter()
+            .rev()
+            .reduce(|second, first| Statement::Sequence {
+                first: Box::new(first),
+                second: Box::new(second),
+            })
+            .unwrap()
+    }
+    // 1からnまでの和。shiftedでは足してから進めるので、0からn-1までの和になる
+    fn sum(shifted: bool) -> Statement {
+        let step = assign("i", add(variable("i"), number(1)));
+        let accumulate = assign("s", add(variable("s"), variable("i")));
+        let body = if shifted {
+            sequence(vec![accumulate, step])
+        } else {
+            sequence(vec![step, accumulate])
+        };
+        sequence(vec![
+            assign("i", number(0)),
+            assign("s", number(0)),
+            Statement::While {
+                condition: less_than(variable("i"), variable("n")),
+                body: Box::new(body),
+            },
+        ])
+    }
+
+    #[test]
+    fn equivalent_test() {
+        // 出力に選ばなかった一時変数は違ってもよい
+        let checker = EquivalenceChecker::new(vec!["x", "y"], vec!["z"], 100);
+        let first = assign("z", multiply(add(variable("x"), variable("y")), number(2)));
+        let second = sequence(vec![
+            assign("t", add(variable("x"), variable("x"))),
+            assign("z", add(variable("t"), add(variable("y"), variable("y")))),
+        ]);
+        let inputs = checker.exhaustive(3);
+        assert_eq!(inputs.len(), 16);
+        match checker.check(&first, &second, &inputs) {
+            Equivalence::Equivalent {
+                tested,
+                inconclusive,
+            } => assert_eq!((tested, inconclusive), (16, 0)),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn different_test() {
+        let checker = EquivalenceChecker::new(vec!["n"], vec!["s"], 1000);
+        match checker.check(&sum(false), &sum(true), &checker.exhaustive(5)) {
+            Equivalence::Different(counterexample) => assert_eq!(
+                counterexample.to_string(),
+                "input {n: 1}: first gives {s: 1}, second gives {s: 0}"
+            ),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn fuel_test() {
+        // x = 0のときは止まらないので比べられない
+        let checker = EquivalenceChecker::new(vec!["x"], vec!["x"], 50);
+        let spin = Statement::While {
+            condition: less_than(variable("x"), number(1)),
+            body: Box::new(Statement::DoNothing),
+        };
+        match checker.check(&spin, &Statement::DoNothing, &checker.exhaustive(4)) {
+            Equivalence::Equivalent {
+                tested,
+                inconclusive,
+            } => assert_eq!((tested, inconclusive), (4, 1)),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn error_test() {
+        // x = 0のときだけ片方が未定義の変数を読む
+        let checker = EquivalenceChecker::new(vec!["x"], vec!["y"], 100);
+        let first = Statement::If {
+            condition: less_than(variable("x"), number(1)),
+            consequence: Box::new(assign("y", variable("z"))),
+            alternative: Box::new(assign("y", number(1))),
+        };
+        let second = assign("y", number(1));
+        match checker.check(&first, &second, &checker.exhaustive(3)) {
+            Equivalence::Different(counterexample) => assert_eq!(
+                counterexample.to_string(),
+                "input {x: 0}: first gives error (undefined variable), second gives {y: 1}"
+            ),
+            result => panic!("{:?}", result),
+        }
+        // 両方があふれるなら同じ
+        let overflow = assign("y", add(variable("x"), number(u32::MAX)));
+        let doubled = assign("y", add(number(u32::MAX), variable("x")));
+        let inputs = checker.exhaustive(1);
+        assert!(matches!(
+            checker.run(&overflow, &inputs[1]),
+            Outcome::Error(_)
+        ));
+        assert!(matches!(
+            checker.check(&overflow, &doubled, &inputs),
+            Equivalence::Equivalent { tested: 2, .. }
+        ));
+        // 違う理由で止まるなら違う
+        let undefined = assign("y", variable("z"));
+        match checker.check(&overflow, &undefined, &inputs) {
+            Equivalence::Different(counterexample) => assert_eq!(
+                counterexample.to_string(),
+                "input {x: 0}: first gives {y: 4294967295}, second gives error (undefined variable)"
+            ),
+            result => panic!("{:?}", result),
+        }
+        match checker.check(&overflow, &undefined, &inputs[1..]) {
+            Equivalence::Different(Counterexample {
+                first: Outcome::Error(first),
+                second: Outcome::Error(second),
+                ..
+            }) => assert_ne!(first, second),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn abrupt_test() {
+        let checker = EquivalenceChecker::new(vec!["x"], vec!["y"], 100);
+        let first = Statement::If {
+            condition: less_than(number(2), variable("x")),
+            consequence: Box::new(Statement::Throw {
+                expression: variable("x"),
+            }),
+            alternative: Box::new(assign("y", variable("x"))),
+        };
+        let second = assign("y", variable("x"));
+        match checker.check(&first, &second, &checker.exhaustive(9)) {
+            Equivalence::Different(counterexample) => assert_eq!(
+                counterexample.to_string(),
+                "input {x: 3}: first gives throw 3, second gives {y: 3}"
+            ),
+            result => panic!("{:?}", result),
+        }
+        assert_eq!(
+            checker
+                .run(&Statement::DoNothing, &Environment::new())
+                .to_string(),
+            "{y: undefined}"
+        );
+    }
+
+    #[test]
+    fn random_test() {
+        let checker = EquivalenceChecker::new(vec!["n"], vec!["s"], 10000);
+        let inputs = checker.random(42, 20, 30);
+        assert_eq!(inputs.len(), 20);
+        assert_eq!(
+            format!("{:?}", inputs),
+            format!("{:?}", checker.random(42, 20, 30))
+        );
+        assert!(inputs.iter().all(|input| match input["n"] {
+            Expression::Number(n) => n <= 30,
+            _ => false,
+        }));
+        // ずらした方にnを足せば同じになる
+        let fixed = sequence(vec![
+            sum(true),
+            assign("s", add(variable("s"), variable("n"))),
+        ]);
+        assert!(matches!(
+            checker.check(&sum(false), &fixed, &inputs),
+            Equivalence::Equivalent {
+                inconclusive: 0,
+                ..
+            }
+        ));
+        assert!(matches!(
+            checker.check(&sum(false), &sum(true), &inputs),
+            Equivalence::Different(_)
+        ));
+    }
+}
@@ -1,9 +1,12 @@
 use std::collections::HashMap;
 use std::fmt;
 
+use crate::profile::{within, Node, Recorder, Shape};
+use crate::record::ruby_key;
+
 pub type Environment = HashMap<String, Expression>;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Expression {
     Number(u32),
     Boolean(bool),
@@ -20,6 +23,32 @@ pub enum Expression {
         right: Box<Expression>,
     },
     Variable(String),
+    Tuple(Vec<Expression>),
+    // フィールドは書いた順に並べておく
+    Record(Vec<(String, Expression)>),
+    // タプルのフィールド名は0から始まる番号
+    Field {
+        expression: Box<Expression>,
+        name: String,
+    },
+    Equal {
+        left: Box<Expression>,
+        right: Box<Expression>,
+    },
+    // 関数リテラル。評価すると使っている変数の値を取り込んでClosureになる
+    Function {
+        params: Vec<String>,
+        body: Box<Expression>,
+    },
+    Closure {
+        params: Vec<String>,
+        body: Box<Expression>,
+        environment: Vec<(String, Expression)>,
+    },
+    Call {
+        function: Box<Expression>,
+        arguments: Vec<Expression>,
+    },
 }
 
 impl fmt::Display for Expression {
@@ -40,6 +69,47 @@ impl fmt::Display for Expression {
                 ref left,
                 ref right,
             } => write!(f, "{} < {}", left, right),
+            Expression::Tuple(elements) => {
+                let elements = elements.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                write!(f, "({})", elements.join(", "))
+            }
+            Expression::Record(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect::<Vec<_>>();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Expression::Field { expression, name } => write!(f, "{}.{}", expression, name),
+            Expression::Equal { left, right } => write!(f, "{} == {}", left, right),
+            Expression::Function { params, body } => {
+                write!(f, "function ({}) {{ {} }}", params.join(", "), body)
+            }
+            Expression::Closure {
+                params,
+                body,
+                environment,
+            } => {
+                write!(f, "function ({}) {{ {} }}", params.join(", "), body)?;
+                if !environment.is_empty() {
+                    let captured = environment
+                        .iter()
+                        .map(|(name, value)| format!("{}: {}", name, value))
+                        .collect::<Vec<_>>();
+                    write!(f, " where {{ {} }}", captured.join(", "))?;
+                }
+                Ok(())
+            }
+            Expression::Call {
+                function,
+                arguments,
+            } => {
+                let arguments = arguments.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+                match function.as_ref() {
+                    Expression::Variable(_) => write!(f, "{}({})", function, arguments.join(", ")),
+                    _ => write!(f, "({})({})", function, arguments.join(", ")),
+                }
+            }
         }
     }
 }
@@ -49,10 +119,23 @@ impl Expression {
         match self {
             Expression::Number(_) => false,
             Expression::Boolean(_) => false,
+            Expression::Tuple(elements) => elements.iter().any(|e| e.is_reducible()),
+            Expression::Record(fields) => fields.iter().any(|(_, value)| value.is_reducible()),
+            Expression::Closure { .. } => false,
             _ => true,
         }
     }
     pub fn reduce(&self, environment: &Environment) -> Expression {
+        self.reduce_with_recorder(environment, &mut ())
+    }
+    pub fn reduce_with_recorder(
+        &self,
+        environment: &Environment,
+        recorder: &mut dyn Recorder,
+    ) -> Expression {
+        let mut reduce = |index: usize, e: &Expression| {
+            within(recorder, index, |r| e.reduce_with_recorder(environment, r))
+        };
         match self {
             Expression::Add {
                 ref left,
@@ -60,15 +143,16 @@ impl Expression {
             } => {
                 if left.is_reducible() {
                     Expression::Add {
-                        left: Box::new(left.reduce(environment)),
+                        left: Box::new(reduce(0, left)),
                         right: right.clone(),
                     }
                 } else if right.is_reducible() {
                     Expression::Add {
                         left: left.clone(),
-                        right: Box::new(right.reduce(environment)),
+                        right: Box::new(reduce(1, right)),
                     }
                 } else {
+                    recorder.rule(&self.operands(), Shape::Derived(vec![]));
                     match (left.as_ref(), right.as_ref()) {
                         (Expression::Number(left_value), Expression::Number(right_value)) => {
                             Expression::Number(left_value + right_value)
@@ -83,15 +167,16 @@ impl Expression {
             } => {
                 if left.is_reducible() {
                     Expression::Multiply {
-                        left: Box::new(left.reduce(environment)),
+                        left: Box::new(reduce(0, left)),
                         right: right.clone(),
                     }
                 } else if right.is_reducible() {
                     Expression::Multiply {
                         left: left.clone(),
-                        right: Box::new(right.reduce(environment)),
+                        right: Box::new(reduce(1, right)),
                     }
                 } else {
+                    recorder.rule(&self.operands(), Shape::Derived(vec![]));
                     match (left.as_ref(), right.as_ref()) {
                         (Expression::Number(left_value), Expression::Number(right_value)) => {
                             Expression::Number(left_value * right_value)
@@ -106,15 +191,16 @@ impl Expression {
             } => {
                 if left.is_reducible() {
                     Expression::LessThan {
-                        left: Box::new(left.reduce(environment)),
+                        left: Box::new(reduce(0, left)),
                         right: right.clone(),
                     }
                 } else if right.is_reducible() {
                     Expression::LessThan {
                         left: left.clone(),
-                        right: Box::new(right.reduce(environment)),
+                        right: Box::new(reduce(1, right)),
                     }
                 } else {
+                    recorder.rule(&self.operands(), Shape::Derived(vec![]));
                     match (left.as_ref(), right.as_ref()) {
                         (Expression::Number(left_value), Expression::Number(right_value)) => {
                             Expression::Boolean(left_value < right_value)
@@ -125,16 +211,113 @@ impl Expression {
             }
             Expression::Variable(name) => {
                 if let Some(expression) = environment.get(name) {
+                    recorder.rule(&[], Shape::Derived(vec![]));
                     expression.clone()
                 } else {
                     panic!("undefined variable")
                 }
             }
+            // 左から順に1つずつ簡約する
+            Expression::Tuple(elements) => {
+                let mut elements = elements.clone();
+                let index = elements.iter().position(|e| e.is_reducible()).unwrap();
+                elements[index] = reduce(index, &elements[index]);
+                Expression::Tuple(elements)
+            }
+            Expression::Record(fields) => {
+                let mut fields = fields.clone();
+                let index = fields.iter().position(|(_, v)| v.is_reducible()).unwrap();
+                fields[index].1 = reduce(index, &fields[index].1);
+                Expression::Record(fields)
+            }
+            Expression::Field {
+                ref expression,
+                ref name,
+            } => {
+                if expression.is_reducible() {
+                    Expression::Field {
+                        expression: Box::new(reduce(0, expression)),
+                        name: name.clone(),
+                    }
+                } else {
+                    recorder.rule(&self.operands(), Shape::Derived(vec![]));
+                    expression.field(name)
+                }
+            }
+            Expression::Equal {
+                ref left,
+                ref right,
+            } => {
+                if left.is_reducible() {
+                    Expression::Equal {
+                        left: Box::new(reduce(0, left)),
+                        right: right.clone(),
+                    }
+                } else if right.is_reducible() {
+                    Expression::Equal {
+                        left: left.clone(),
+                        right: Box::new(reduce(1, right)),
+                    }
+                } else {
+                    recorder.rule(&self.operands(), Shape::Derived(vec![]));
+                    Expression::Boolean(left.is_equal(right))
+                }
+            }
+            Expression::Function { .. } => {
+                recorder.rule(&[], Shape::Derived(vec![]));
+                self.capture(environment)
+            }
+            // 関数、引数の順に簡約し、値がそろったら本体に代入する
+            Expression::Call {
+                ref function,
+                ref arguments,
+            } => {
+                if function.is_reducible() {
+                    Expression::Call {
+                        function: Box::new(reduce(0, function)),
+                        arguments: arguments.clone(),
+                    }
+                } else if arguments.iter().any(|a| a.is_reducible()) {
+                    let mut arguments = arguments.clone();
+                    let index = arguments.iter().position(|a| a.is_reducible()).unwrap();
+                    arguments[index] = reduce(index + 1, &arguments[index]);
+                    Expression::Call {
+                        function: function.clone(),
+                        arguments,
+                    }
+                } else {
+                    // 代入した本体の中の簡約は、呼び出しで起きたものとする
+                    recorder.rule(&self.operands(), Shape::Derived(vec![]));
+                    let (body, bindings) = function.bind(arguments);
+                    body.substitute(&bindings)
+                }
+            }
             _ => unreachable!(),
         }
     }
+    // 子をすべて値として使う規則のため
+    fn operands(&self) -> Vec<(usize, Node<'_>)> {
+        Node::Expression(self)
+            .children()
+            .into_iter()
+            .enumerate()
+            .collect()
+    }
 
     pub fn evaluate(&self, environment: &Environment) -> Expression {
+        self.evaluate_with_recorder(environment, &mut ())
+    }
+    pub fn evaluate_with_recorder(
+        &self,
+        environment: &Environment,
+        recorder: &mut dyn Recorder,
+    ) -> Expression {
+        recorder.execute();
+        let mut evaluate = |index: usize, e: &Expression| {
+            within(recorder, index, |r| {
+                e.evaluate_with_recorder(environment, r)
+            })
+        };
         match self {
             Expression::Number(_) => self.clone(),
             Expression::Boolean(_) => self.clone(),
@@ -148,7 +331,7 @@ impl Expression {
             Expression::Add {
                 ref left,
                 ref right,
-            } => match (left.evaluate(environment), right.evaluate(environment)) {
+            } => match (evaluate(0, left), evaluate(1, right)) {
                 (Expression::Number(left_value), Expression::Number(right_value)) => {
                     Expression::Number(left_value + right_value)
                 }
@@ -157,7 +340,7 @@ impl Expression {
             Expression::Multiply {
                 ref left,
                 ref right,
-            } => match (left.evaluate(environment), right.evaluate(environment)) {
+            } => match (evaluate(0, left), evaluate(1, right)) {
                 (Expression::Number(left_value), Expression::Number(right_value)) => {
                     Expression::Number(left_value * right_value)
                 }
@@ -166,12 +349,50 @@ impl Expression {
             Expression::LessThan {
                 ref left,
                 ref right,
-            } => match (left.evaluate(environment), right.evaluate(environment)) {
+            } => match (evaluate(0, left), evaluate(1, right)) {
                 (Expression::Number(left_value), Expression::Number(right_value)) => {
                     Expression::Boolean(left_value < right_value)
                 }
                 _ => unreachable!(),
             },
+            Expression::Tuple(elements) => Expression::Tuple(
+                elements
+                    .iter()
+                    .enumerate()
+                    .map(|(i, e)| evaluate(i, e))
+                    .collect(),
+            ),
+            Expression::Record(fields) => Expression::Record(
+                fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (name, value))| (name.clone(), evaluate(i, value)))
+                    .collect(),
+            ),
+            Expression::Field {
+                ref expression,
+                ref name,
+            } => evaluate(0, expression).field(name),
+            Expression::Equal {
+                ref left,
+                ref right,
+            } => Expression::Boolean(evaluate(0, left).is_equal(&evaluate(1, right))),
+            Expression::Function { .. } => self.capture(environment),
+            Expression::Closure { .. } => self.clone(),
+            // 本体は代入してから記録せずに評価する。小ステップでも本体の中は数えない
+            Expression::Call {
+                ref function,
+                ref arguments,
+            } => {
+                let function = evaluate(0, function);
+                let arguments = arguments
+                    .iter()
+                    .enumerate()
+                    .map(|(i, a)| evaluate(i + 1, a))
+                    .collect::<Vec<_>>();
+                let (body, bindings) = function.bind(&arguments);
+                body.substitute(&bindings).evaluate(environment)
+            }
         }
     }
     pub fn to_ruby(&self) -> String {
@@ -209,6 +430,92 @@ impl Expression {
                     right.to_ruby()
                 )
             }
+            Expression::Tuple(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|e| format!("({}).call(e)", e.to_ruby()))
+                    .collect::<Vec<_>>();
+                format!("-> e {{ [{}] }}", elements.join(", "))
+            }
+            Expression::Record(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, value)| format!(":{} => ({}).call(e)", name, value.to_ruby()))
+                    .collect::<Vec<_>>();
+                format!("-> e {{ {{ {} }} }}", fields.join(", "))
+            }
+            Expression::Field {
+                ref expression,
+                ref name,
+            } => format!(
+                "-> e {{ ({}).call(e)[{}] }}",
+                expression.to_ruby(),
+                ruby_key(name)
+            ),
+            Expression::Equal {
+                ref left,
+                ref right,
+            } => {
+                format!(
+                    "-> e {{ ({}).call(e) == ({}).call(e) }}",
+                    left.to_ruby(),
+                    right.to_ruby()
+                )
+            }
+            // 作ったときの環境eに引数を足して本体を呼ぶ。
+            // 仮引数がeを隠さないように、Rubyの引数はp0, p1, ...にする
+            Expression::Function {
+                ref params,
+                ref body,
+            } => {
+                let locals = (0..params.len())
+                    .map(|i| format!("p{}", i))
+                    .collect::<Vec<_>>();
+                let bindings = params
+                    .iter()
+                    .zip(&locals)
+                    .map(|(p, local)| format!(":{} => {}", p, local))
+                    .collect::<Vec<_>>();
+                format!(
+                    "-> e {{ -> {} {{ ({}).call(e.merge({{ {} }})) }} }}",
+                    locals.join(", "),
+                    body.to_ruby(),
+                    bindings.join(", ")
+                )
+            }
+            Expression::Closure {
+                ref params,
+                ref body,
+                ref environment,
+            } => {
+                let captured = environment
+                    .iter()
+                    .map(|(name, value)| format!(":{} => ({}).call({{}})", name, value.to_ruby()))
+                    .collect::<Vec<_>>();
+                let function = Expression::Function {
+                    params: params.clone(),
+                    body: body.clone(),
+                };
+                format!(
+                    "-> e {{ ({}).call({{ {} }}) }}",
+                    function.to_ruby(),
+                    captured.join(", ")
+                )
+            }
+            Expression::Call {
+                ref function,
+                ref arguments,
+            } => {
+                let arguments = arguments
+                    .iter()
+                    .map(|a| format!("({}).call(e)", a.to_ruby()))
+                    .collect::<Vec<_>>();
+                format!(
+                    "-> e {{ ({}).call(e).call({}) }}",
+                    function.to_ruby(),
+                    arguments.join(", ")
+                )
+            }
         }
     }
 }
@@ -0,0 +1,434 @@
+use crate::expression::Expression;
+use crate::parser::{parse_with_comments, Comments};
+use crate::statement::Statement;
+
+// 1行に1文、字下げは4つ、単純な文は;で終える。括弧は結合の順に必要なものだけ書く。
+// 文の番号はparserと同じ数え方なので、コメントを元の文のところに戻せる
+
+const INDENT: &str = "    ";
+
+// 大きいほど強く結びつく
+fn level(expression: &Expression) -> usize {
+    match expression {
+        Expression::Equal { .. } => 0,
+        Expression::LessThan { .. } => 1,
+        Expression::Add { .. } => 2,
+        Expression::Multiply { .. } => 3,
+        Expression::Function { .. } => 4,
+        Expression::Field { .. } | Expression::Call { .. } => 5,
+        _ => 6,
+    }
+}
+
+fn operand(expression: &Expression, minimum: usize) -> String {
+    if level(expression) < minimum {
+        format!("({})", format_expression(expression))
+    } else {
+        format_expression(expression)
+    }
+}
+
+pub fn format_expression(expression: &Expression) -> String {
+    let list = |expressions: &[Expression]| {
+        expressions
+            .iter()
+            .map(format_expression)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match expression {
+        Expression::Number(value) => value.to_string(),
+        Expression::Boolean(value) => value.to_string(),
+        Expression::Variable(name) => name.clone(),
+        Expression::Equal { left, right }
+        | Expression::LessThan { left, right }
+        | Expression::Add { left, right }
+        | Expression::Multiply { left, right } => {
+            let operator = ["==", "<", "+", "*"][level(expression)];
+            // 左結合なので、右側は同じ強さでも括弧がいる
+            format!(
+                "{} {} {}",
+                operand(left, level(expression)),
+                operator,
+                operand(right, level(expression) + 1)
+            )
+        }
+        Expression::Tuple(elements) if elements.len() == 1 => format!("({},)", list(elements)),
+        Expression::Tuple(elements) => format!("({})", list(elements)),
+        Expression::Record(fields) if fields.is_empty() => "{}".to_string(),
+        Expression::Record(fields) => {
+            let fields = fields
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, format_expression(value)))
+                .collect::<Vec<_>>();
+            format!("{{ {} }}", fields.join(", "))
+        }
+        Expression::Field { expression, name } => format!("{}.{}", operand(expression, 5), name),
+        Expression::Function { params, body } => format!(
+            "function ({}) {{ {} }}",
+            params.join(", "),
+            format_expression(body)
+        ),
+        Expression::Call {
+            function,
+            arguments,
+        } => format!("{}({})", operand(function, 5), list(arguments)),
+        Expression::Closure { .. } => panic!("{} is not source syntax", expression),
+    }
+}
+
+struct Printer<'a> {
+    comments: &'a Comments,
+    count: usize,
+    lines: Vec<String>,
+}
+
+impl<'a> Printer<'a> {
+    fn comment_lines(&mut self, comments: Option<&Vec<String>>, indent: usize) {
+        for comment in comments.into_iter().flatten() {
+            self.lines.push(INDENT.repeat(indent) + comment);
+        }
+    }
+    fn is_empty(&self, body: &Statement, owner: Option<usize>, index: usize) -> bool {
+        matches!(body, Statement::DoNothing) && !self.comments.closing.contains_key(&(owner, index))
+    }
+    fn body(&mut self, body: &Statement, owner: Option<usize>, index: usize, indent: usize) {
+        if !matches!(body, Statement::DoNothing) {
+            self.statements(body, indent);
+        }
+        self.comment_lines(self.comments.closing.get(&(owner, index)), indent);
+    }
+    fn statements(&mut self, statement: &Statement, indent: usize) {
+        match statement {
+            Statement::Sequence { first, second } => {
+                self.statements(first, indent);
+                self.statements(second, indent);
+            }
+            _ => self.statement(statement, indent),
+        }
+    }
+    // headerに続くブロックを並べる。空のブロックは{}と1行に書く
+    fn blocks(&mut self, id: usize, parts: Vec<(String, &Statement)>, indent: usize) {
+        let pad = INDENT.repeat(indent);
+        let mut line = pad.clone();
+        for (index, (header, body)) in parts.into_iter().enumerate() {
+            if !header.is_empty() {
+                if line != pad {
+                    line.push(' ');
+                }
+                line.push_str(&header);
+            }
+            line.push_str(if line == pad { "{" } else { " {" });
+            if self.is_empty(body, Some(id), index) {
+                line.push('}');
+            } else {
+                self.lines.push(line);
+                self.body(body, Some(id), index, indent + 1);
+                line = pad.clone() + "}";
+            }
+        }
+        self.lines.push(line);
+    }
+    fn statement(&mut self, statement: &Statement, indent: usize) {
+        let id = self.count;
+        self.count += 1;
+        self.comment_lines(self.comments.leading.get(&id), indent);
+        let pad = INDENT.repeat(indent);
+        let simple = |text: String| format!("{}{};", pad, text);
+        match statement {
+            Statement::DoNothing => self.lines.push(simple("do-nothing".to_string())),
+            Statement::Break => self.lines.push(simple("break".to_string())),
+            Statement::Continue => self.lines.push(simple("continue".to_string())),
+            Statement::Assignment { name, expression } => self.lines.push(simple(format!(
+                "{} = {}",
+                name,
+                format_expression(expression)
+            ))),
+            Statement::Update {
+                name,
+                fields,
+                expression,
+            } => self.lines.push(simple(format!(
+                "{}.{} = {}",
+                name,
+                fields.join("."),
+                format_expression(expression)
+            ))),
+            Statement::Var { name, expression } => self.lines.push(simple(format!(
+                "var {} = {}",
+                name,
+                format_expression(expression)
+            ))),
+            Statement::Throw { expression } => self
+                .lines
+                .push(simple(format!("throw {}", format_expression(expression)))),
+            Statement::Assert { condition } => self
+                .lines
+                .push(simple(format!("assert ({})", format_expression(condition)))),
+            Statement::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let header = format!("if ({})", format_expression(condition));
+                let next = self.count + count_statements(consequence);
+                let is_else_if = matches!(**alternative, Statement::If { .. })
+                    && !self.comments.leading.contains_key(&next)
+                    && !self.comments.trailing.contains_key(&next)
+                    && !self.comments.closing.contains_key(&(Some(id), 1));
+                if is_else_if {
+                    // else ifは、続くifの最初の行につなげる
+                    self.blocks(id, vec![(header, consequence)], indent);
+                    let line = self.lines.pop().unwrap() + " else ";
+                    let start = self.lines.len();
+                    self.statement(alternative, indent);
+                    self.lines[start] = line + self.lines[start].trim_start();
+                } else if self.is_empty(alternative, Some(id), 1) {
+                    self.blocks(id, vec![(header, consequence)], indent);
+                } else {
+                    self.blocks(
+                        id,
+                        vec![(header, consequence), ("else".to_string(), alternative)],
+                        indent,
+                    );
+                }
+            }
+            Statement::While { condition, body } => self.blocks(
+                id,
+                vec![(format!("while ({})", format_expression(condition)), body)],
+                indent,
+            ),
+            Statement::Choose { first, second } => self.blocks(
+                id,
+                vec![("choose".to_string(), first), ("or".to_string(), second)],
+                indent,
+            ),
+            Statement::Parallel { left, right } => self.blocks(
+                id,
+                vec![(String::new(), left), ("||".to_string(), right)],
+                indent,
+            ),
+            Statement::Try {
+                body,
+                name,
+                handler,
+            } => self.blocks(
+                id,
+                vec![
+                    ("try".to_string(), body),
+                    (format!("catch ({})", name), handler),
+                ],
+                indent,
+            ),
+            Statement::Let {
+                name,
+                expression,
+                body,
+            } => self.blocks(
+                id,
+                vec![(
+                    format!("let {} = {} in", name, format_expression(expression)),
+                    body,
+                )],
+                indent,
+            ),
+            Statement::Block { body } => self.blocks(id, vec![("block".to_string(), body)], indent),
+            // statementsで分けてから呼ぶ
+            Statement::Sequence { .. } => unreachable!(),
+        }
+        if let Some(comment) = self.comments.trailing.get(&id) {
+            let last = self.lines.last_mut().unwrap();
+            last.push(' ');
+            last.push_str(comment);
+        }
+    }
+}
+
+// 番号のつく文の数。空のブロックのdo-nothingは数えない
+fn count_statements(body: &Statement) -> usize {
+    match body {
+        Statement::DoNothing => 0,
+        _ => count_nested(body),
+    }
+}
+
+fn count_nested(statement: &Statement) -> usize {
+    match statement {
+        Statement::Sequence { first, second } => count_nested(first) + count_nested(second),
+        Statement::If {
+            consequence: first,
+            alternative: second,
+            ..
+        }
+        | Statement::Choose { first, second }
+        | Statement::Parallel {
+            left: first,
+            right: second,
+        }
+        | Statement::Try {
+            body: first,
+            handler: second,
+            ..
+        } => 1 + count_statements(first) + count_statements(second),
+        Statement::While { body, .. } | Statement::Let { body, .. } | Statement::Block { body } => {
+            1 + count_statements(body)
+        }
+        _ => 1,
+    }
+}
+
+pub fn format_statement(statement: &Statement, comments: &Comments) -> String {
+    let mut printer = Printer {
+        comments,
+        count: 0,
+        lines: Vec::new(),
+    };
+    printer.body(statement, None, 0, 0);
+    printer
+        .lines
+        .iter()
+        .map(|line| line.clone() + "\n")
+        .collect()
+}
+
+pub fn format(source: &str) -> Result<String, String> {
+    let (statement, comments) = parse_with_comments(source)?;
+    Ok(format_statement(&statement, &comments))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn sources() -> Vec<&'static str> {
+        vec![
+            "",
+            "// only a comment",
+            "x=1;y = (x+2)*3 ;;  // trailing\n",
+            "if(x<y){z=x}else if (y<x) { z = y } else {\n  // nothing here\n}",
+            "if (a) { b = 1 } else { // why\n if (c) { d = 2 } }",
+            "while (x < 10), { x = x + 1; if (x == 5) { break } }",
+            "p = {a: (1,), b: ()}; p.a.0 = function(n){n*2}(4)",
+            "try { throw (1, 2) } catch (e) { do-nothing }",
+            "assert(x<1 == true) // checked",
+            "{ a = 1 } || { b = 2 } choose {} or { c = 1 + (2 + 3) }",
+            "let t = 1 in { var u = t; block { var w = 1 } } // end\n// last",
+            "f = function (g, x) { g(g(x)) }; r = f(function (y) { y + 1 }, 1).0",
+            "{\n// left\n} || {}\nx = (1 < 2) == (3 < 4)",
+        ]
+    }
+
+    #[test]
+    fn format_test() {
+        let source = "// sum up\nx=1;y = (x+2)*3 ;;  // trailing\nif(x<y){z=x}else if (y<x) { z = y } else {\n  // nothing here\n}\nwhile (x < 10), { x = x + 1; if (x == 5) { break } }\n";
+        assert_eq!(
+            format(source).unwrap(),
+            [
+                "// sum up",
+                "x = 1;",
+                "y = (x + 2) * 3; // trailing",
+                "if (x < y) {",
+                "    z = x;",
+                "} else if (y < x) {",
+                "    z = y;",
+                "} else {",
+                "    // nothing here",
+                "}",
+                "while (x < 10) {",
+                "    x = x + 1;",
+                "    if (x == 5) {",
+                "        break;",
+                "    }",
+                "}",
+                ""
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn idempotent_test() {
+        for source in sources() {
+            let formatted = format(source).unwrap();
+            assert_eq!(format(&formatted).unwrap(), formatted, "{}", source);
+        }
+    }
+
+    #[test]
+    fn preserve_test() {
+        // 整形しても同じプログラムのままで、コメントもなくならない
+        for source in sources() {
+            let formatted = format(source).unwrap();
+            assert_eq!(
+                format!("{:?}", parse(&formatted).unwrap()),
+                format!("{:?}", parse(source).unwrap())
+            );
+            let comments = |text: &str| {
+                text.lines()
+                    .filter_map(|line| line.find("//").map(|i| line[i..].trim_end().to_string()))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(comments(&formatted), comments(source));
+        }
+    }
+
+    #[test]
+    fn comment_placement_test() {
+        // 続くifにコメントがあるときはelse ifにしない
+        assert_eq!(
+            format("if (a) { b = 1 } else { // why\n if (c) { d = 2 } }").unwrap(),
+            [
+                "if (a) {",
+                "    b = 1;",
+                "} else {",
+                "    // why",
+                "    if (c) {",
+                "        d = 2;",
+                "    }",
+                "}",
+                ""
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            format("{\n// left\n} || {}").unwrap(),
+            "{\n    // left\n} || {}\n"
+        );
+        assert_eq!(format("do-nothing").unwrap(), "");
+        // 余分な`;`の後ろのコメントも同じ行の文のもの
+        assert_eq!(
+            format("x = 1;;; // a\n;\ny = 2").unwrap(),
+            "x = 1; // a\ny = 2;\n"
+        );
+        // 式の途中のコメントは文の前に出す
+        assert_eq!(
+            format("x = 1 + // mid\n2;\ny = 3").unwrap(),
+            "// mid\nx = 1 + 2;\ny = 3;\n"
+        );
+        assert_eq!(
+            format("if (x < // c\n2) { y = 1 }").unwrap(),
+            "// c\nif (x < 2) {\n    y = 1;\n}\n"
+        );
+    }
+
+    #[test]
+    fn expression_test() {
+        let cases = [
+            ("1 + (2 + 3)", "1 + (2 + 3)"),
+            ("((1 + 2)) + 3", "1 + 2 + 3"),
+            ("(1 + 2) * (3 * 4)", "(1 + 2) * (3 * 4)"),
+            ("(p).x", "p.x"),
+            ("(function (x) { x })(1)", "(function (x) { x })(1)"),
+            ("f(1)(2)", "f(1)(2)"),
+            ("{ x: (1,) }.x.0", "{ x: (1,) }.x.0"),
+            ("(a + b).c", "(a + b).c"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(
+                format(&format!("v = {}", source)).unwrap(),
+                format!("v = {};\n", expected)
+            );
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::BTreeSet;
+
+use crate::expression::{Environment, Expression};
+
+// 関数は作ったときの変数の値を取り込む。取り込んだ値も引数も値なので、
+// 本体に代入しても別の変数を捕まえてしまうことはない
+
+impl Expression {
+    pub fn free_variables(&self) -> BTreeSet<String> {
+        match self {
+            Expression::Number(_) | Expression::Boolean(_) | Expression::Closure { .. } => {
+                BTreeSet::new()
+            }
+            Expression::Variable(name) => std::iter::once(name.clone()).collect(),
+            Expression::Add { left, right }
+            | Expression::Multiply { left, right }
+            | Expression::LessThan { left, right }
+            | Expression::Equal { left, right } => {
+                let mut names = left.free_variables();
+                names.extend(right.free_variables());
+                names
+            }
+            Expression::Tuple(elements) => {
+                elements.iter().flat_map(|e| e.free_variables()).collect()
+            }
+            Expression::Record(fields) => fields
+                .iter()
+                .flat_map(|(_, value)| value.free_variables())
+                .collect(),
+            Expression::Field { expression, .. } => expression.free_variables(),
+            Expression::Function { params, body } => {
+                let mut names = body.free_variables();
+                for param in params {
+                    names.remove(param);
+                }
+                names
+            }
+            Expression::Call {
+                function,
+                arguments,
+            } => {
+                let mut names = function.free_variables();
+                for argument in arguments {
+                    names.extend(argument.free_variables());
+                }
+                names
+            }
+        }
+    }
+    // 関数リテラルを、使っている変数の今の値を持ったClosureにする
+    pub fn capture(&self, environment: &Environment) -> Expression {
+        match self {
+            Expression::Function { params, body } => {
+                let captured = self
+                    .free_variables()
+                    .into_iter()
+                    .map(|name| match environment.get(&name) {
+                        Some(value) => (name, value.clone()),
+                        None => panic!("undefined variable"),
+                    })
+                    .collect();
+                Expression::Closure {
+                    params: params.clone(),
+                    body: body.clone(),
+                    environment: captured,
+                }
+            }
+            _ => panic!("expression is not function"),
+        }
+    }
+    // 呼び出すときの本体と、その中での変数の値。引数が取り込んだ値より優先される
+    pub fn bind(&self, arguments: &[Expression]) -> (Expression, Vec<(String, Expression)>) {
+        match self {
+            Expression::Closure {
+                params,
+                body,
+                environment,
+            } => {
+                if params.len() != arguments.len() {
+                    panic!("wrong number of arguments");
+                }
+                let mut bindings = environment.clone();
+                bindings.extend(params.iter().cloned().zip(arguments.iter().cloned()));
+                (*body.clone(), bindings)
+            }
+            _ => panic!("expression is not function"),
+        }
+    }
+    pub fn substitute(&self, bindings: &[(String, Expression)]) -> Expression {
+        let sub = |e: &Expression| Box::new(e.substitute(bindings));
+        match self {
+            Expression::Number(_) | Expression::Boolean(_) | Expression::Closure { .. } => {
+                self.clone()
+            }
+            Expression::Variable(name) => match bindings.iter().rev().find(|(n, _)| n == name) {
+                Some((_, value)) => value.clone(),
+                None => self.clone(),
+            },
+            Expression::Add { left, right } => Expression::Add {
+                left: sub(left),
+                right: sub(right),
+            },
+            Expression::Multiply { left, right } => Expression::Multiply {
+                left: sub(left),
+                right: sub(right),
+            },
+            Expression::LessThan { left, right } => Expression::LessThan {
+                left: sub(left),
+                right: sub(right),
+            },
+            Expression::Equal { left, right } => Expression::Equal {
+                left: sub(left),
+                right: sub(right),
+            },
+            Expression::Tuple(elements) => {
+                Expression::Tuple(elements.iter().map(|e| e.substitute(bindings)).collect())
+            }
+            Expression::Record(fields) => Expression::Record(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.substitute(bindings)))
+                    .collect(),
+            ),
+            Expression::Field { expression, name } => Expression::Field {
+                expression: sub(expression),
+                name: name.clone(),
+            },
+            // 引数と同じ名前の変数は置き換えない
+            Expression::Function { params, body } => {
+                let inner = bindings
+                    .iter()
+                    .filter(|(name, _)| !params.contains(name))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                Expression::Function {
+                    params: params.clone(),
+                    body: Box::new(body.substitute(&inner)),
+                }
+            }
+            Expression::Call {
+                function,
+                arguments,
+            } => Expression::Call {
+                function: sub(function),
+                arguments: arguments.iter().map(|a| a.substitute(bindings)).collect(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::format_environment;
+    use crate::running::Running;
+    use crate::statement::Statement;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn multiply(left: Expression, right: Expression) -> Expression {
+        Expression::Multiply {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn function(params: &[&str], body: Expression) -> Expression {
+        Expression::Function {
+            params: params.iter().map(|p| p.to_string()).collect(),
+            body: Box::new(body),
+        }
+    }
+    fn call(function: Expression, arguments: Vec<Expression>) -> Expression {
+        Expression::Call {
+            function: Box::new(function),
+            arguments,
+        }
+    }
+    fn field(expression: Expression, name: &str) -> Expression {
+        Expression::Field {
+            expression: Box::new(expression),
+            name: name.to_string(),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(statements: Vec<Statement>) -> Statement {
+        statements
+            .into_iter()
+            .rev()
+            .reduce(|second, first| Statement::Sequence {
+                first: Box::new(first),
+                second: Box::new(second),
+            })
+            .unwrap()
+    }
+
+    // 小ステップと大ステップで同じ環境になることを確かめる
+    fn run(statement: &Statement) -> Environment {
+        let mut current = Running::from(statement.clone());
+        let mut small = Environment::new();
+        while current.is_reducible() {
+            let (next, next_env) = current.reduce(&mut small);
+            current = next;
+            small = next_env;
+        }
+        let big = statement.evaluate(&mut Environment::new());
+        assert_eq!(format_environment(&small), format_environment(&big));
+        small
+    }
+
+    #[test]
+    fn display_test() {
+        let compose = function(
+            &["f", "g"],
+            function(
+                &["x"],
+                call(
+                    variable("f"),
+                    vec![call(variable("g"), vec![variable("x")])],
+                ),
+            ),
+        );
+        assert_eq!(
+            compose.to_string(),
+            "function (f, g) { function (x) { f(g(x)) } }"
+        );
+        assert_eq!(
+            call(function(&["x"], variable("x")), vec![number(1)]).to_string(),
+            "(function (x) { x })(1)"
+        );
+        let mut environment = Environment::new();
+        environment.insert("n".to_string(), number(2));
+        assert_eq!(
+            function(&["x"], add(variable("x"), variable("n")))
+                .evaluate(&environment)
+                .to_string(),
+            "function (x) { x + n } where { n: 2 }"
+        );
+    }
+
+    #[test]
+    fn capture_test() {
+        // 作ったときのnを使う。引数は取り込んだ変数を隠す
+        let statement = sequence(vec![
+            assign("n", number(1)),
+            assign("x", number(100)),
+            assign("f", function(&["x"], add(variable("x"), variable("n")))),
+            assign("n", number(10)),
+            assign("y", call(variable("f"), vec![number(5)])),
+        ]);
+        let environment = run(&statement);
+        assert_eq!(environment["y"], number(6));
+    }
+
+    #[test]
+    fn higher_order_test() {
+        let compose = function(
+            &["f", "g"],
+            function(
+                &["x"],
+                call(
+                    variable("f"),
+                    vec![call(variable("g"), vec![variable("x")])],
+                ),
+            ),
+        );
+        let map = function(
+            &["f", "p"],
+            Expression::Tuple(vec![
+                call(variable("f"), vec![field(variable("p"), "0")]),
+                call(variable("f"), vec![field(variable("p"), "1")]),
+            ]),
+        );
+        let statement = sequence(vec![
+            assign("compose", compose),
+            assign("map", map),
+            assign("k", number(3)),
+            assign(
+                "double",
+                function(&["x"], multiply(variable("x"), number(2))),
+            ),
+            assign("add_k", function(&["x"], add(variable("x"), variable("k")))),
+            assign(
+                "h",
+                call(
+                    variable("compose"),
+                    vec![variable("double"), variable("add_k")],
+                ),
+            ),
+            assign(
+                "result",
+                call(
+                    variable("map"),
+                    vec![variable("h"), Expression::Tuple(vec![number(1), number(2)])],
+                ),
+            ),
+        ]);
+        let environment = run(&statement);
+        assert_eq!(environment["result"].to_string(), "(8, 10)");
+    }
+
+    #[test]
+    #[should_panic(expected = "wrong number of arguments")]
+    fn arity_test() {
+        call(function(&["x", "y"], variable("x")), vec![number(1)]).evaluate(&Environment::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "undefined variable")]
+    fn undefined_capture_test() {
+        function(&["x"], variable("y")).evaluate(&Environment::new());
+    }
+
+    #[test]
+    fn to_ruby_test() {
+        assert_eq!(
+            call(function(&["x"], variable("x")), vec![number(1)]).to_ruby(),
+            "-> e { (-> e { -> p0 { (-> e { e[:x] }).call(e.merge({ :x => p0 })) } }).call(e).call((-> e { 1 }).call(e)) }"
+        );
+        // 仮引数eは環境の引数eと重ならない
+        assert_eq!(
+            function(&["e", "y"], add(variable("e"), variable("y"))).to_ruby(),
+            "-> e { -> p0, p1 { (-> e { (-> e { e[:e] }).call(e) + (-> e { e[:y] }).call(e) }).call(e.merge({ :e => p0, :y => p1 })) } }"
+        );
+    }
+}
@@ -0,0 +1,1136 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::expression::{Environment, Expression};
+use crate::solver::{solve, LinearConstraint, LinearExpression, Satisfiability};
+use crate::statement::Statement;
+use crate::subset::Construct;
+
+// 表明の言語。数の比較と真偽値の変数を論理演算でつなぐ
+#[derive(Clone, Debug)]
+pub enum Assertion {
+    True,
+    False,
+    LessThan(Expression, Expression),
+    Equal(Expression, Expression),
+    Variable(String),
+    Not(Box<Assertion>),
+    And(Box<Assertion>, Box<Assertion>),
+    Or(Box<Assertion>, Box<Assertion>),
+    Implies(Box<Assertion>, Box<Assertion>),
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Assertion::True => write!(f, "true"),
+            Assertion::False => write!(f, "false"),
+            Assertion::LessThan(left, right) => write!(f, "{} < {}", left, right),
+            Assertion::Equal(left, right) => write!(f, "{} == {}", left, right),
+            Assertion::Variable(name) => write!(f, "{}", name),
+            Assertion::Not(a) => write!(f, "!{}", a.bracket(self.precedence() + 2)),
+            Assertion::And(a, b) => write!(
+                f,
+                "{} && {}",
+                a.bracket(self.precedence()),
+                b.bracket(self.precedence())
+            ),
+            Assertion::Or(a, b) => write!(
+                f,
+                "{} || {}",
+                a.bracket(self.precedence()),
+                b.bracket(self.precedence())
+            ),
+            Assertion::Implies(a, b) => write!(
+                f,
+                "{} -> {}",
+                a.bracket(self.precedence() + 1),
+                b.bracket(self.precedence())
+            ),
+        }
+    }
+}
+
+impl Assertion {
+    pub fn less_equal(left: Expression, right: Expression) -> Assertion {
+        Assertion::Not(Box::new(Assertion::LessThan(right, left)))
+    }
+    pub fn negate(a: Assertion) -> Assertion {
+        Assertion::Not(Box::new(a))
+    }
+    pub fn and(a: Assertion, b: Assertion) -> Assertion {
+        Assertion::And(Box::new(a), Box::new(b))
+    }
+    pub fn or(a: Assertion, b: Assertion) -> Assertion {
+        Assertion::Or(Box::new(a), Box::new(b))
+    }
+    pub fn implies(a: Assertion, b: Assertion) -> Assertion {
+        Assertion::Implies(Box::new(a), Box::new(b))
+    }
+    // If や While の条件式を表明にする
+    pub fn from_condition(condition: &Expression) -> Result<Assertion, String> {
+        let assertion = match condition {
+            Expression::Boolean(true) => Assertion::True,
+            Expression::Boolean(false) => Assertion::False,
+            Expression::Variable(name) => Assertion::Variable(name.clone()),
+            Expression::LessThan { left, right } => {
+                Assertion::LessThan(*left.clone(), *right.clone())
+            }
+            // 片方が条件なら真偽値どうし、片方が数の式なら数どうしの比較
+            Expression::Equal { left, right } => match (is_condition(left), is_condition(right)) {
+                (Some(true), Some(false)) | (Some(false), Some(true)) => Assertion::False,
+                (Some(true), _) | (_, Some(true)) => {
+                    let (l, r) = (
+                        Assertion::from_condition(left)?,
+                        Assertion::from_condition(right)?,
+                    );
+                    Assertion::or(
+                        Assertion::and(l.clone(), r.clone()),
+                        Assertion::and(Assertion::negate(l), Assertion::negate(r)),
+                    )
+                }
+                _ => Assertion::Equal(*left.clone(), *right.clone()),
+            },
+            _ => return Err(format!("condition is not bool: {}", condition)),
+        };
+        Ok(assertion)
+    }
+    // 式の中のタプルやレコードはエラー
+    fn check_supported(&self) -> Result<(), String> {
+        match self {
+            Assertion::True | Assertion::False | Assertion::Variable(_) => Ok(()),
+            Assertion::LessThan(left, right) | Assertion::Equal(left, right) => {
+                left.check_supported(&[])?;
+                right.check_supported(&[])
+            }
+            Assertion::Not(a) => a.check_supported(),
+            Assertion::And(a, b) | Assertion::Or(a, b) | Assertion::Implies(a, b) => {
+                a.check_supported()?;
+                b.check_supported()
+            }
+        }
+    }
+    fn precedence(&self) -> u32 {
+        match self {
+            Assertion::Implies(_, _) => 0,
+            Assertion::Or(_, _) => 1,
+            Assertion::And(_, _) => 2,
+            Assertion::Not(_) => 3,
+            Assertion::LessThan(_, _) | Assertion::Equal(_, _) => 4,
+            _ => 5,
+        }
+    }
+    fn bracket(&self, outer_precedence: u32) -> String {
+        if self.precedence() < outer_precedence {
+            format!("({})", self)
+        } else {
+            self.to_string()
+        }
+    }
+    // 真偽値の変数を数の式で置き換えるとエラー
+    pub fn substitute(&self, name: &str, replacement: &Expression) -> Result<Assertion, String> {
+        let sub = |e: &Expression| substitute(e, name, replacement);
+        let assertion = match self {
+            Assertion::True | Assertion::False => self.clone(),
+            Assertion::LessThan(left, right) => Assertion::LessThan(sub(left), sub(right)),
+            Assertion::Equal(left, right) => Assertion::Equal(sub(left), sub(right)),
+            Assertion::Variable(n) => {
+                if n == name {
+                    Assertion::from_condition(replacement)?
+                } else {
+                    self.clone()
+                }
+            }
+            Assertion::Not(a) => Assertion::negate(a.substitute(name, replacement)?),
+            Assertion::And(a, b) => Assertion::and(
+                a.substitute(name, replacement)?,
+                b.substitute(name, replacement)?,
+            ),
+            Assertion::Or(a, b) => Assertion::or(
+                a.substitute(name, replacement)?,
+                b.substitute(name, replacement)?,
+            ),
+            Assertion::Implies(a, b) => Assertion::implies(
+                a.substitute(name, replacement)?,
+                b.substitute(name, replacement)?,
+            ),
+        };
+        Ok(assertion)
+    }
+    // 算術は数学の整数で行う。u32に収まることは検証条件の側で確かめる
+    pub fn evaluate(&self, environment: &Environment) -> bool {
+        match self {
+            Assertion::True => true,
+            Assertion::False => false,
+            Assertion::LessThan(left, right) => {
+                integer_value(left, environment) < integer_value(right, environment)
+            }
+            Assertion::Equal(left, right) => {
+                integer_value(left, environment) == integer_value(right, environment)
+            }
+            Assertion::Variable(name) => match environment.get(name) {
+                Some(Expression::Boolean(b)) => *b,
+                Some(_) => panic!("condition is not bool"),
+                None => panic!("undefined variable"),
+            },
+            Assertion::Not(a) => !a.evaluate(environment),
+            Assertion::And(a, b) => a.evaluate(environment) && b.evaluate(environment),
+            Assertion::Or(a, b) => a.evaluate(environment) || b.evaluate(environment),
+            Assertion::Implies(a, b) => !a.evaluate(environment) || b.evaluate(environment),
+        }
+    }
+    fn variables(&self, numbers: &mut BTreeSet<String>, booleans: &mut BTreeSet<String>) {
+        match self {
+            Assertion::True | Assertion::False => {}
+            Assertion::LessThan(left, right) | Assertion::Equal(left, right) => {
+                expression_variables(left, numbers);
+                expression_variables(right, numbers);
+            }
+            Assertion::Variable(name) => {
+                booleans.insert(name.clone());
+            }
+            Assertion::Not(a) => a.variables(numbers, booleans),
+            Assertion::And(a, b) | Assertion::Or(a, b) | Assertion::Implies(a, b) => {
+                a.variables(numbers, booleans);
+                b.variables(numbers, booleans);
+            }
+        }
+    }
+}
+
+// 真偽値の式ならSome(true)、数の式ならSome(false)。変数はどちらか分からない
+fn is_condition(expression: &Expression) -> Option<bool> {
+    match expression {
+        Expression::Boolean(_) | Expression::LessThan { .. } | Expression::Equal { .. } => {
+            Some(true)
+        }
+        Expression::Number(_) | Expression::Add { .. } | Expression::Multiply { .. } => Some(false),
+        _ => None,
+    }
+}
+
+fn substitute(expression: &Expression, name: &str, replacement: &Expression) -> Expression {
+    let sub = |e: &Expression| Box::new(substitute(e, name, replacement));
+    match expression {
+        Expression::Variable(n) if n == name => replacement.clone(),
+        Expression::Number(_) | Expression::Boolean(_) | Expression::Variable(_) => {
+            expression.clone()
+        }
+        Expression::Add { left, right } => Expression::Add {
+            left: sub(left),
+            right: sub(right),
+        },
+        Expression::Multiply { left, right } => Expression::Multiply {
+            left: sub(left),
+            right: sub(right),
+        },
+        Expression::LessThan { left, right } => Expression::LessThan {
+            left: sub(left),
+            right: sub(right),
+        },
+        Expression::Equal { left, right } => Expression::Equal {
+            left: sub(left),
+            right: sub(right),
+        },
+        Expression::Tuple(_)
+        | Expression::Record(_)
+        | Expression::Field { .. }
+        | Expression::Function { .. }
+        | Expression::Closure { .. }
+        | Expression::Call { .. } => unreachable!(),
+    }
+}
+
+fn integer_value(expression: &Expression, environment: &Environment) -> i128 {
+    match expression {
+        Expression::Number(n) => *n as i128,
+        Expression::Variable(name) => match environment.get(name) {
+            Some(Expression::Number(n)) => *n as i128,
+            Some(_) => panic!("variable is not number"),
+            None => panic!("undefined variable"),
+        },
+        Expression::Add { left, right } => {
+            integer_value(left, environment) + integer_value(right, environment)
+        }
+        Expression::Multiply { left, right } => {
+            integer_value(left, environment) * integer_value(right, environment)
+        }
+        _ => panic!("expression is not number"),
+    }
+}
+
+fn expression_variables(expression: &Expression, names: &mut BTreeSet<String>) {
+    match expression {
+        Expression::Variable(name) => {
+            names.insert(name.clone());
+        }
+        Expression::Add { left, right }
+        | Expression::Multiply { left, right }
+        | Expression::LessThan { left, right }
+        | Expression::Equal { left, right } => {
+            expression_variables(left, names);
+            expression_variables(right, names);
+        }
+        Expression::Number(_) | Expression::Boolean(_) => {}
+        Expression::Tuple(_)
+        | Expression::Record(_)
+        | Expression::Field { .. }
+        | Expression::Function { .. }
+        | Expression::Closure { .. }
+        | Expression::Call { .. } => unreachable!(),
+    }
+}
+
+// 変数どうしの掛け算は項全体を1つの変数とみなす。この変数はu32に収まるとは限らない
+fn linearize(expression: &Expression) -> Result<LinearExpression, String> {
+    match expression {
+        Expression::Number(n) => Ok(LinearExpression::constant(*n as i128)),
+        Expression::Variable(name) => Ok(LinearExpression::variable(name)),
+        Expression::Add { left, right } => Ok(linearize(left)?.add(&linearize(right)?)),
+        Expression::Multiply { left, right } => {
+            let (l, r) = (linearize(left)?, linearize(right)?);
+            Ok(match (l.constant_value(), r.constant_value()) {
+                (Some(c), _) => r.scale(c),
+                (_, Some(c)) => l.scale(c),
+                _ => LinearExpression::variable(&expression.to_string()),
+            })
+        }
+        _ => Err(format!("expression is not number: {}", expression)),
+    }
+}
+
+// 式の中の足し算と掛け算の結果がどれもu32に収まるという条件
+fn no_overflow(expression: &Expression) -> Assertion {
+    fn collect(expression: &Expression, conditions: &mut Vec<Assertion>) {
+        match expression {
+            Expression::Add { left, right } | Expression::Multiply { left, right } => {
+                collect(left, conditions);
+                collect(right, conditions);
+                conditions.push(Assertion::less_equal(
+                    expression.clone(),
+                    Expression::Number(u32::MAX),
+                ));
+            }
+            Expression::LessThan { left, right } | Expression::Equal { left, right } => {
+                collect(left, conditions);
+                collect(right, conditions);
+            }
+            _ => {}
+        }
+    }
+    let mut conditions = Vec::new();
+    collect(expression, &mut conditions);
+    conditions
+        .into_iter()
+        .reduce(Assertion::and)
+        .unwrap_or(Assertion::True)
+}
+
+// 条件が自明でなければ前に付ける
+fn guarded(condition: Assertion, assertion: Assertion) -> Assertion {
+    match condition {
+        Assertion::True => assertion,
+        condition => Assertion::and(condition, assertion),
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Literal {
+    Constraint(LinearConstraint),
+    Boolean(String, bool),
+}
+
+// positiveがfalseなら否定したものを、連言の選言の形にする
+fn disjunctive_normal_form(
+    assertion: &Assertion,
+    positive: bool,
+) -> Result<Vec<Vec<Literal>>, String> {
+    let constraint = |c: LinearConstraint| vec![vec![Literal::Constraint(c)]];
+    Ok(match (assertion, positive) {
+        (Assertion::True, true) | (Assertion::False, false) => vec![vec![]],
+        (Assertion::True, false) | (Assertion::False, true) => vec![],
+        (Assertion::LessThan(left, right), true) => constraint(LinearConstraint::less_than(
+            &linearize(left)?,
+            &linearize(right)?,
+        )),
+        (Assertion::LessThan(left, right), false) => constraint(LinearConstraint::less_equal(
+            &linearize(right)?,
+            &linearize(left)?,
+        )),
+        (Assertion::Equal(left, right), true) => {
+            vec![
+                LinearConstraint::equal(&linearize(left)?, &linearize(right)?)
+                    .into_iter()
+                    .map(Literal::Constraint)
+                    .collect(),
+            ]
+        }
+        (Assertion::Equal(left, right), false) => {
+            let (l, r) = (linearize(left)?, linearize(right)?);
+            vec![
+                vec![Literal::Constraint(LinearConstraint::less_than(&l, &r))],
+                vec![Literal::Constraint(LinearConstraint::less_than(&r, &l))],
+            ]
+        }
+        (Assertion::Variable(name), _) => vec![vec![Literal::Boolean(name.clone(), positive)]],
+        (Assertion::Not(a), _) => disjunctive_normal_form(a, !positive)?,
+        (Assertion::And(a, b), true) | (Assertion::Or(a, b), false) => {
+            let (left, right) = (
+                disjunctive_normal_form(a, positive)?,
+                disjunctive_normal_form(b, positive)?,
+            );
+            left.iter()
+                .flat_map(|l| {
+                    right.iter().map(move |r| {
+                        let mut conjunction = l.clone();
+                        conjunction.extend(r.iter().cloned());
+                        conjunction
+                    })
+                })
+                .collect()
+        }
+        (Assertion::Or(a, b), true) | (Assertion::And(a, b), false) => {
+            let mut disjunction = disjunctive_normal_form(a, positive)?;
+            disjunction.extend(disjunctive_normal_form(b, positive)?);
+            disjunction
+        }
+        (Assertion::Implies(a, b), _) => disjunctive_normal_form(
+            &Assertion::or(Assertion::negate(*a.clone()), *b.clone()),
+            positive,
+        )?,
+    })
+}
+
+#[derive(Clone, Debug)]
+pub enum Verdict {
+    Valid,
+    Invalid(Environment),
+    Unknown,
+}
+
+// 表明が常に成り立つか。成り立たなければ反例を返す。
+// 数の変数はu32の範囲の値をとる。数の比較に真偽値の式があればエラー
+pub fn check_validity(assertion: &Assertion) -> Result<Verdict, String> {
+    assertion.check_supported()?;
+    let mut numbers = BTreeSet::new();
+    let mut booleans = BTreeSet::new();
+    assertion.variables(&mut numbers, &mut booleans);
+    if let Some(name) = numbers.intersection(&booleans).next() {
+        return Err(format!("variable is both number and bool: {}", name));
+    }
+
+    let mut undecided = false;
+    for conjunction in disjunctive_normal_form(assertion, false)? {
+        let mut truth = BTreeMap::new();
+        let mut constraints = Vec::new();
+        let mut consistent = true;
+        for literal in conjunction {
+            match literal {
+                Literal::Constraint(c) => constraints.push(c),
+                Literal::Boolean(name, value) => {
+                    if *truth.entry(name).or_insert(value) != value {
+                        consistent = false;
+                    }
+                }
+            }
+        }
+        if !consistent {
+            continue;
+        }
+        match solve(&constraints, &numbers) {
+            Satisfiability::Unsatisfiable => {}
+            Satisfiability::Unknown => undecided = true,
+            Satisfiability::Satisfiable(model) => {
+                let mut counterexample = Environment::new();
+                for name in &numbers {
+                    let value = *model.get(name).unwrap_or(&0) as u32;
+                    counterexample.insert(name.clone(), Expression::Number(value));
+                }
+                for name in &booleans {
+                    let value = *truth.get(name).unwrap_or(&false);
+                    counterexample.insert(name.clone(), Expression::Boolean(value));
+                }
+                // 掛け算を変数とみなした解は本物の反例とは限らない
+                if assertion.evaluate(&counterexample) {
+                    undecided = true;
+                } else {
+                    return Ok(Verdict::Invalid(counterexample));
+                }
+            }
+        }
+    }
+    if undecided {
+        Ok(Verdict::Unknown)
+    } else {
+        Ok(Verdict::Valid)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct VerificationCondition {
+    pub description: String,
+    pub assertion: Assertion,
+}
+
+impl fmt::Display for VerificationCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.description, self.assertion)
+    }
+}
+
+pub struct Failure {
+    pub condition: VerificationCondition,
+    pub counterexample: Option<Environment>,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.counterexample {
+            Some(environment) => {
+                let values = environment
+                    .iter()
+                    .collect::<BTreeMap<_, _>>()
+                    .iter()
+                    .map(|(name, value)| format!("{} = {}", name, value))
+                    .collect::<Vec<_>>();
+                write!(f, "{} fails when {}", self.condition, values.join(", "))
+            }
+            None => write!(f, "{} could not be decided", self.condition),
+        }
+    }
+}
+
+// {precondition} statement {postcondition}
+// invariantsにはWhileのループ不変条件をプログラムに現れる順に並べる。
+// 式を計算するたびに、途中の値がu32に収まることも確かめる
+pub struct HoareTriple {
+    pub precondition: Assertion,
+    pub statement: Statement,
+    pub postcondition: Assertion,
+    pub invariants: Vec<Assertion>,
+}
+
+fn count_loops(statement: &Statement) -> usize {
+    match statement {
+        Statement::DoNothing | Statement::Assignment { .. } | Statement::Assert { .. } => 0,
+        Statement::If {
+            consequence,
+            alternative,
+            ..
+        } => count_loops(consequence) + count_loops(alternative),
+        Statement::Sequence { first, second } => count_loops(first) + count_loops(second),
+        Statement::While { body, .. } => 1 + count_loops(body),
+        Statement::Choose { first, second } => count_loops(first) + count_loops(second),
+        Statement::Parallel { .. }
+        | Statement::Break
+        | Statement::Continue
+        | Statement::Throw { .. }
+        | Statement::Try { .. }
+        | Statement::Let { .. }
+        | Statement::Block { .. }
+        | Statement::Var { .. }
+        | Statement::Update { .. } => unreachable!(),
+    }
+}
+
+impl HoareTriple {
+    // loopsはstatementより前に現れたWhileの数
+    fn weakest_precondition(
+        &self,
+        statement: &Statement,
+        postcondition: Assertion,
+        loops: usize,
+        conditions: &mut Vec<VerificationCondition>,
+    ) -> Result<Assertion, String> {
+        let precondition = match statement {
+            Statement::DoNothing => postcondition,
+            Statement::Assignment { name, expression } => guarded(
+                no_overflow(expression),
+                postcondition.substitute(name, expression)?,
+            ),
+            // 表明は実行時に確かめるのではなく、必ず成り立つことを示す
+            Statement::Assert { condition } => guarded(
+                no_overflow(condition),
+                Assertion::and(Assertion::from_condition(condition)?, postcondition),
+            ),
+            Statement::Sequence { first, second } => {
+                let middle = self.weakest_precondition(
+                    second,
+                    postcondition,
+                    loops + count_loops(first),
+                    conditions,
+                )?;
+                self.weakest_precondition(first, middle, loops, conditions)?
+            }
+            Statement::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let safe = no_overflow(condition);
+                let condition = Assertion::from_condition(condition)?;
+                let consequence_pre = self.weakest_precondition(
+                    consequence,
+                    postcondition.clone(),
+                    loops,
+                    conditions,
+                )?;
+                let alternative_pre = self.weakest_precondition(
+                    alternative,
+                    postcondition,
+                    loops + count_loops(consequence),
+                    conditions,
+                )?;
+                guarded(
+                    safe,
+                    Assertion::and(
+                        Assertion::implies(condition.clone(), consequence_pre),
+                        Assertion::implies(Assertion::negate(condition), alternative_pre),
+                    ),
+                )
+            }
+            Statement::While {
+                condition: loop_condition,
+                body,
+            } => {
+                let invariant = self.invariants[loops].clone();
+                let condition = Assertion::from_condition(loop_condition)?;
+                let body_pre =
+                    self.weakest_precondition(body, invariant.clone(), loops + 1, conditions)?;
+                if let safe @ (Assertion::Not(_) | Assertion::And(_, _)) =
+                    no_overflow(loop_condition)
+                {
+                    conditions.push(VerificationCondition {
+                        description: format!(
+                            "invariant {} keeps while ({}) within u32",
+                            invariant, loop_condition
+                        ),
+                        assertion: Assertion::implies(invariant.clone(), safe),
+                    });
+                }
+                conditions.push(VerificationCondition {
+                    description: format!(
+                        "invariant {} is preserved by while ({})",
+                        invariant, loop_condition
+                    ),
+                    assertion: Assertion::implies(
+                        Assertion::and(invariant.clone(), condition.clone()),
+                        body_pre,
+                    ),
+                });
+                conditions.push(VerificationCondition {
+                    description: format!(
+                        "invariant {} establishes the postcondition after while ({})",
+                        invariant, loop_condition
+                    ),
+                    assertion: Assertion::implies(
+                        Assertion::and(invariant.clone(), Assertion::negate(condition)),
+                        postcondition,
+                    ),
+                });
+                invariant
+            }
+            // どちらが選ばれても成り立つ必要がある
+            Statement::Choose { first, second } => {
+                let first_pre =
+                    self.weakest_precondition(first, postcondition.clone(), loops, conditions)?;
+                let second_pre = self.weakest_precondition(
+                    second,
+                    postcondition,
+                    loops + count_loops(first),
+                    conditions,
+                )?;
+                Assertion::and(first_pre, second_pre)
+            }
+            Statement::Parallel { .. }
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Throw { .. }
+            | Statement::Try { .. }
+            | Statement::Let { .. }
+            | Statement::Block { .. }
+            | Statement::Var { .. }
+            | Statement::Update { .. } => unreachable!(),
+        };
+        Ok(precondition)
+    }
+    pub fn verification_conditions(&self) -> Result<Vec<VerificationCondition>, String> {
+        self.statement
+            .check_supported(&[Construct::Choose, Construct::Assert])?;
+        self.precondition.check_supported()?;
+        self.postcondition.check_supported()?;
+        for invariant in &self.invariants {
+            invariant.check_supported()?;
+        }
+        let loops = count_loops(&self.statement);
+        if loops != self.invariants.len() {
+            return Err(format!(
+                "{} loop invariants are given for {} loops",
+                self.invariants.len(),
+                loops
+            ));
+        }
+        let mut conditions = Vec::new();
+        let pre = self.weakest_precondition(
+            &self.statement,
+            self.postcondition.clone(),
+            0,
+            &mut conditions,
+        )?;
+        conditions.insert(
+            0,
+            VerificationCondition {
+                description: "precondition implies the weakest precondition".to_string(),
+                assertion: Assertion::implies(self.precondition.clone(), pre),
+            },
+        );
+        Ok(conditions)
+    }
+    pub fn check(&self) -> Result<Vec<Failure>, String> {
+        let mut failures = Vec::new();
+        for condition in self.verification_conditions()? {
+            match check_validity(&condition.assertion)? {
+                Verdict::Valid => {}
+                Verdict::Invalid(counterexample) => failures.push(Failure {
+                    condition,
+                    counterexample: Some(counterexample),
+                }),
+                Verdict::Unknown => failures.push(Failure {
+                    condition,
+                    counterexample: None,
+                }),
+            }
+        }
+        Ok(failures)
+    }
+    pub fn is_valid(&self) -> Result<bool, String> {
+        Ok(self.check()?.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn multiply(left: Expression, right: Expression) -> Expression {
+        Expression::Multiply {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn equal(left: Expression, right: Expression) -> Expression {
+        Expression::Equal {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+
+    #[test]
+    fn display_test() {
+        let assertion = Assertion::implies(
+            Assertion::and(
+                Assertion::LessThan(variable("x"), number(3)),
+                Assertion::or(Assertion::Variable("b".to_string()), Assertion::False),
+            ),
+            Assertion::negate(Assertion::Equal(
+                variable("y"),
+                add(variable("x"), number(1)),
+            )),
+        );
+        assert_eq!(
+            assertion.to_string(),
+            "x < 3 && (b || false) -> !(y == x + 1)"
+        );
+    }
+
+    #[test]
+    fn assignment_test() {
+        // {x < 5} y = x + 1 {y < 6}
+        let triple = HoareTriple {
+            precondition: Assertion::LessThan(variable("x"), number(5)),
+            statement: assign("y", add(variable("x"), number(1))),
+            postcondition: Assertion::LessThan(variable("y"), number(6)),
+            invariants: vec![],
+        };
+        let conditions = triple.verification_conditions().unwrap();
+        assert_eq!(conditions.len(), 1);
+        assert_eq!(
+            conditions[0].assertion.to_string(),
+            "x < 5 -> !(4294967295 < x + 1) && x + 1 < 6"
+        );
+        assert!(triple.is_valid().unwrap());
+
+        // {x < 6} y = x + 1 {y < 6} は x = 5 で成り立たない
+        let triple = HoareTriple {
+            precondition: Assertion::LessThan(variable("x"), number(6)),
+            ..triple
+        };
+        let failures = triple.check().unwrap();
+        assert_eq!(failures.len(), 1);
+        let counterexample = failures[0].counterexample.as_ref().unwrap();
+        assert_eq!(counterexample["x"].to_string(), "5");
+        assert_eq!(
+            failures[0].to_string(),
+            "precondition implies the weakest precondition: x < 6 -> !(4294967295 < x + 1) && x + 1 < 6 fails when x = 5"
+        );
+    }
+
+    #[test]
+    fn if_test() {
+        // 最大値
+        let statement = Statement::If {
+            condition: less_than(variable("x"), variable("y")),
+            consequence: Box::new(assign("m", variable("y"))),
+            alternative: Box::new(assign("m", variable("x"))),
+        };
+        let postcondition = Assertion::and(
+            Assertion::and(
+                Assertion::less_equal(variable("x"), variable("m")),
+                Assertion::less_equal(variable("y"), variable("m")),
+            ),
+            Assertion::or(
+                Assertion::Equal(variable("m"), variable("x")),
+                Assertion::Equal(variable("m"), variable("y")),
+            ),
+        );
+        let triple = HoareTriple {
+            precondition: Assertion::True,
+            statement,
+            postcondition,
+            invariants: vec![],
+        };
+        assert!(triple.is_valid().unwrap());
+
+        let triple = HoareTriple {
+            postcondition: Assertion::Equal(variable("m"), variable("y")),
+            ..triple
+        };
+        let failures = triple.check().unwrap();
+        assert_eq!(failures.len(), 1);
+        let counterexample = failures[0].counterexample.as_ref().unwrap();
+        assert!(!triple
+            .postcondition
+            .evaluate(&triple.statement.evaluate(&mut counterexample.clone())));
+    }
+
+    #[test]
+    fn while_test() {
+        // i = 0; s = 0; while (i < n) { s = s + 2; i = i + 1 }  =>  s == 2n
+        let statement = sequence(
+            assign("i", number(0)),
+            sequence(
+                assign("s", number(0)),
+                Statement::While {
+                    condition: less_than(variable("i"), variable("n")),
+                    body: Box::new(sequence(
+                        assign("s", add(variable("s"), number(2))),
+                        assign("i", add(variable("i"), number(1))),
+                    )),
+                },
+            ),
+        );
+        // nが大きいとsがあふれるので、nを抑える
+        let bound = Assertion::LessThan(variable("n"), number(1000));
+        let invariant = Assertion::and(
+            Assertion::and(
+                Assertion::Equal(variable("s"), multiply(number(2), variable("i"))),
+                Assertion::less_equal(variable("i"), variable("n")),
+            ),
+            bound.clone(),
+        );
+        let triple = HoareTriple {
+            precondition: bound.clone(),
+            statement,
+            postcondition: Assertion::Equal(variable("s"), multiply(number(2), variable("n"))),
+            invariants: vec![invariant],
+        };
+        assert_eq!(triple.verification_conditions().unwrap().len(), 3);
+        assert!(triple.is_valid().unwrap());
+
+        // i <= n がないと終わった後に i == n が言えない
+        let triple = HoareTriple {
+            invariants: vec![Assertion::and(
+                Assertion::Equal(variable("s"), multiply(number(2), variable("i"))),
+                bound,
+            )],
+            ..triple
+        };
+        let failures = triple.check().unwrap();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0]
+            .condition
+            .description
+            .starts_with("invariant s == 2 * i && n < 1000 establishes the postcondition"));
+        let counterexample = failures[0].counterexample.as_ref().unwrap();
+        assert!(!failures[0].condition.assertion.evaluate(counterexample));
+    }
+
+    #[test]
+    fn error_test() {
+        let loop_statement = Statement::While {
+            condition: less_than(variable("i"), number(3)),
+            body: Box::new(assign("i", add(variable("i"), number(1)))),
+        };
+        let triple = HoareTriple {
+            precondition: Assertion::True,
+            statement: loop_statement,
+            postcondition: Assertion::True,
+            invariants: vec![],
+        };
+        assert_eq!(
+            triple.check().err().unwrap(),
+            "0 loop invariants are given for 1 loops"
+        );
+
+        // 条件が真偽値でなければ検証できない
+        let triple = HoareTriple {
+            statement: Statement::Assert {
+                condition: add(variable("x"), number(1)),
+            },
+            invariants: vec![],
+            ..triple
+        };
+        assert_eq!(
+            triple.check().err().unwrap(),
+            "condition is not bool: x + 1"
+        );
+        let triple = HoareTriple {
+            statement: assign("b", number(1)),
+            postcondition: Assertion::Variable("b".to_string()),
+            ..triple
+        };
+        assert_eq!(triple.check().err().unwrap(), "condition is not bool: 1");
+    }
+
+    #[test]
+    fn overflow_test() {
+        // 数学の整数なら成り立つが、x = u32::MAXであふれる
+        let triple = HoareTriple {
+            precondition: Assertion::True,
+            statement: assign("y", add(variable("x"), number(1))),
+            postcondition: Assertion::LessThan(variable("x"), variable("y")),
+            invariants: vec![],
+        };
+        let failures = triple.check().unwrap();
+        assert_eq!(failures.len(), 1);
+        let counterexample = failures[0].counterexample.as_ref().unwrap();
+        assert_eq!(counterexample["x"], number(u32::MAX));
+
+        // 条件式の計算も確かめる
+        let triple = HoareTriple {
+            precondition: Assertion::LessThan(variable("x"), number(10)),
+            statement: Statement::While {
+                condition: less_than(multiply(variable("x"), number(2)), number(20)),
+                body: Box::new(assign("x", add(variable("x"), number(1)))),
+            },
+            postcondition: Assertion::Equal(variable("x"), number(10)),
+            invariants: vec![Assertion::less_equal(variable("x"), number(10))],
+        };
+        let conditions = triple.verification_conditions().unwrap();
+        assert_eq!(conditions.len(), 4);
+        assert_eq!(
+            conditions[1].to_string(),
+            "invariant !(10 < x) keeps while (x * 2 < 20) within u32: !(10 < x) -> !(4294967295 < x * 2)"
+        );
+        assert!(triple.is_valid().unwrap());
+        let triple = HoareTriple {
+            invariants: vec![Assertion::True],
+            ..triple
+        };
+        assert!(!triple.is_valid().unwrap());
+    }
+
+    #[test]
+    fn boolean_equal_test() {
+        // 真偽値を数として比べる表明はエラー
+        let assertion = Assertion::Equal(
+            less_than(variable("x"), number(1)),
+            Expression::Boolean(true),
+        );
+        assert_eq!(
+            check_validity(&assertion).unwrap_err(),
+            "expression is not number: x < 1"
+        );
+    }
+
+    #[test]
+    fn equal_condition_test() {
+        // {true} if (x == 5) { y = x + 1 } else { y = 0 } {y == 0 || y == 6}
+        let triple = HoareTriple {
+            precondition: Assertion::True,
+            statement: Statement::If {
+                condition: equal(variable("x"), number(5)),
+                consequence: Box::new(assign("y", add(variable("x"), number(1)))),
+                alternative: Box::new(assign("y", number(0))),
+            },
+            postcondition: Assertion::or(
+                Assertion::Equal(variable("y"), number(0)),
+                Assertion::Equal(variable("y"), number(6)),
+            ),
+            invariants: vec![],
+        };
+        assert!(triple.is_valid().unwrap());
+
+        // 条件どうしの比較は、両方成り立つか両方成り立たないか
+        let triple = HoareTriple {
+            precondition: Assertion::LessThan(variable("x"), variable("y")),
+            statement: assign(
+                "b",
+                equal(
+                    less_than(variable("x"), number(3)),
+                    less_than(variable("y"), number(3)),
+                ),
+            ),
+            postcondition: Assertion::Variable("b".to_string()),
+            invariants: vec![],
+        };
+        let failures = triple.check().unwrap();
+        assert_eq!(failures.len(), 1);
+        let counterexample = failures[0].counterexample.as_ref().unwrap();
+        assert!(!triple
+            .postcondition
+            .evaluate(&triple.statement.evaluate(&mut counterexample.clone())));
+
+        let triple = HoareTriple {
+            statement: assign("p", Expression::Tuple(vec![number(1), number(2)])),
+            ..triple
+        };
+        assert_eq!(
+            triple.check().err(),
+            Some("tuple is not supported: (1, 2)".to_string())
+        );
+    }
+
+    #[test]
+    fn invariant_not_preserved_test() {
+        // while (x < 10) { x = x + 3 } で x < 10 は保たれない
+        let triple = HoareTriple {
+            precondition: Assertion::LessThan(variable("x"), number(10)),
+            statement: Statement::While {
+                condition: less_than(variable("x"), number(10)),
+                body: Box::new(assign("x", add(variable("x"), number(3)))),
+            },
+            postcondition: Assertion::LessThan(variable("x"), number(13)),
+            invariants: vec![Assertion::LessThan(variable("x"), number(10))],
+        };
+        let failures = triple.check().unwrap();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].condition.description.contains("is preserved"));
+        let counterexample = failures[0].counterexample.as_ref().unwrap();
+        assert!(counterexample["x"].to_string().parse::<u32>().unwrap() >= 7);
+
+        let triple = HoareTriple {
+            invariants: vec![Assertion::LessThan(variable("x"), number(13))],
+            ..triple
+        };
+        assert!(triple.is_valid().unwrap());
+    }
+
+    #[test]
+    fn boolean_test() {
+        // {true} done = x < 3; if (done) { y = 1 } else { y = 2 } {done -> y == 1}
+        let triple = HoareTriple {
+            precondition: Assertion::True,
+            statement: sequence(
+                assign("done", less_than(variable("x"), number(3))),
+                Statement::If {
+                    condition: variable("done"),
+                    consequence: Box::new(assign("y", number(1))),
+                    alternative: Box::new(assign("y", number(2))),
+                },
+            ),
+            postcondition: Assertion::implies(
+                Assertion::Variable("done".to_string()),
+                Assertion::Equal(variable("y"), number(1)),
+            ),
+            invariants: vec![],
+        };
+        assert!(triple.is_valid().unwrap());
+    }
+
+    #[test]
+    fn nonlinear_test() {
+        // x * x は変数とみなすので x * x < 0 がないことは分かる
+        assert!(matches!(
+            check_validity(&Assertion::less_equal(
+                number(0),
+                multiply(variable("x"), variable("x"))
+            )),
+            Ok(Verdict::Valid)
+        ));
+        // x * y == y * x は分からない
+        assert!(matches!(
+            check_validity(&Assertion::Equal(
+                multiply(variable("x"), variable("y")),
+                multiply(variable("y"), variable("x"))
+            )),
+            Ok(Verdict::Unknown)
+        ));
+    }
+
+    #[test]
+    fn assert_test() {
+        // {x < 5} y = x + 1; assert (y < 6) {true}
+        let triple = HoareTriple {
+            precondition: Assertion::LessThan(variable("x"), number(5)),
+            statement: sequence(
+                assign("y", add(variable("x"), number(1))),
+                Statement::Assert {
+                    condition: less_than(variable("y"), number(6)),
+                },
+            ),
+            postcondition: Assertion::True,
+            invariants: vec![],
+        };
+        assert!(triple.is_valid().unwrap());
+        let triple = HoareTriple {
+            precondition: Assertion::LessThan(variable("x"), number(6)),
+            ..triple
+        };
+        let failures = triple.check().unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].counterexample.as_ref().unwrap()["x"], number(5));
+
+        let triple = HoareTriple {
+            statement: Statement::Parallel {
+                left: Box::new(assign("x", number(1))),
+                right: Box::new(assign("x", number(2))),
+            },
+            ..triple
+        };
+        assert_eq!(
+            triple.check().err(),
+            Some("parallel composition is not supported: { x = 1 } || { x = 2 }".to_string())
+        );
+    }
+}
@@ -0,0 +1,784 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::expression::Expression;
+use crate::statement::Statement;
+use crate::subset::Construct;
+
+const MAX: u64 = u32::MAX as u64;
+
+// 両端を含む区間。途中の計算でu32をはみ出すことがあるのでu64で持つ
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    low: u64,
+    high: u64,
+}
+
+impl Interval {
+    pub fn new(low: u64, high: u64) -> Self {
+        Interval { low, high }
+    }
+    pub fn constant(value: u32) -> Self {
+        Interval::new(value as u64, value as u64)
+    }
+    pub fn top() -> Self {
+        Interval::new(0, MAX)
+    }
+    pub fn low(&self) -> u64 {
+        self.low
+    }
+    pub fn high(&self) -> u64 {
+        self.high
+    }
+    pub fn contains(&self, value: u32) -> bool {
+        self.low <= value as u64 && value as u64 <= self.high
+    }
+    pub fn is_subset(&self, other: &Interval) -> bool {
+        other.low <= self.low && self.high <= other.high
+    }
+    pub fn join(&self, other: &Interval) -> Interval {
+        Interval::new(self.low.min(other.low), self.high.max(other.high))
+    }
+    // 動いた端は一気に端まで飛ばす
+    pub fn widen(&self, other: &Interval) -> Interval {
+        Interval::new(
+            if other.low < self.low { 0 } else { self.low },
+            if other.high > self.high {
+                MAX
+            } else {
+                self.high
+            },
+        )
+    }
+    // 広げすぎた端だけを戻す
+    pub fn narrow(&self, other: &Interval) -> Interval {
+        Interval::new(
+            if self.low == 0 { other.low } else { self.low },
+            if self.high == MAX {
+                other.high
+            } else {
+                self.high
+            },
+        )
+    }
+    fn add(&self, other: &Interval) -> Interval {
+        Interval::new(self.low + other.low, self.high + other.high)
+    }
+    fn multiply(&self, other: &Interval) -> Interval {
+        Interval::new(self.low * other.low, self.high * other.high)
+    }
+    fn less_than(&self, other: &Interval) -> Option<bool> {
+        if self.high < other.low {
+            Some(true)
+        } else if self.low >= other.high {
+            Some(false)
+        } else {
+            None
+        }
+    }
+    fn equal(&self, other: &Interval) -> Option<bool> {
+        if self.high < other.low || other.high < self.low {
+            Some(false)
+        } else if self.low == self.high && *self == *other {
+            Some(true)
+        } else {
+            None
+        }
+    }
+    fn may_overflow(&self) -> bool {
+        self.high > MAX
+    }
+    fn clamp(&self) -> Interval {
+        Interval::new(self.low.min(MAX), self.high.min(MAX))
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}, {}]", self.low, self.high)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntervalValue {
+    Number(Interval),
+    Boolean(Option<bool>),
+    Unknown,
+}
+
+impl IntervalValue {
+    pub fn of(value: &Expression) -> IntervalValue {
+        match value {
+            Expression::Number(n) => IntervalValue::Number(Interval::constant(*n)),
+            Expression::Boolean(b) => IntervalValue::Boolean(Some(*b)),
+            _ => IntervalValue::Unknown,
+        }
+    }
+    pub fn contains(&self, value: &Expression) -> bool {
+        match (self, value) {
+            (IntervalValue::Unknown, _) => true,
+            (IntervalValue::Number(interval), Expression::Number(n)) => interval.contains(*n),
+            (IntervalValue::Boolean(None), Expression::Boolean(_)) => true,
+            (IntervalValue::Boolean(Some(a)), Expression::Boolean(b)) => a == b,
+            _ => false,
+        }
+    }
+    fn interval(&self) -> Interval {
+        match self {
+            IntervalValue::Number(interval) => *interval,
+            _ => Interval::top(),
+        }
+    }
+    // 数と真偽値は等しくならない
+    fn equal(&self, other: &IntervalValue) -> Option<bool> {
+        match (self, other) {
+            (IntervalValue::Number(a), IntervalValue::Number(b)) => a.equal(b),
+            (IntervalValue::Boolean(Some(a)), IntervalValue::Boolean(Some(b))) => Some(a == b),
+            (IntervalValue::Number(_), IntervalValue::Boolean(_))
+            | (IntervalValue::Boolean(_), IntervalValue::Number(_)) => Some(false),
+            _ => None,
+        }
+    }
+    fn combine(
+        &self,
+        other: &IntervalValue,
+        number: fn(&Interval, &Interval) -> Interval,
+    ) -> IntervalValue {
+        match (self, other) {
+            (IntervalValue::Number(a), IntervalValue::Number(b)) => {
+                IntervalValue::Number(number(a, b))
+            }
+            (IntervalValue::Boolean(a), IntervalValue::Boolean(b)) if a == b => *self,
+            (IntervalValue::Boolean(_), IntervalValue::Boolean(_)) => IntervalValue::Boolean(None),
+            _ => IntervalValue::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for IntervalValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntervalValue::Number(interval) => write!(f, "{}", interval),
+            IntervalValue::Boolean(Some(b)) => write!(f, "{}", b),
+            IntervalValue::Boolean(None) => write!(f, "boolean"),
+            IntervalValue::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+pub type IntervalEnvironment = HashMap<String, IntervalValue>;
+
+fn combine_environments(
+    first: &IntervalEnvironment,
+    second: &IntervalEnvironment,
+    number: fn(&Interval, &Interval) -> Interval,
+) -> IntervalEnvironment {
+    let mut environment = first.clone();
+    for (name, value) in second {
+        let combined = match first.get(name) {
+            Some(v) => v.combine(value, number),
+            None => *value,
+        };
+        environment.insert(name.clone(), combined);
+    }
+    environment
+}
+
+// 到達しない場合はNoneで表す
+fn join_reachable(
+    first: Option<IntervalEnvironment>,
+    second: Option<IntervalEnvironment>,
+) -> Option<IntervalEnvironment> {
+    match (first, second) {
+        (Some(a), Some(b)) => Some(combine_environments(&a, &b, Interval::join)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OverflowWarning {
+    pub expression: Expression,
+    pub interval: Interval,
+}
+
+impl fmt::Display for OverflowWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} may overflow u32: result in {}",
+            self.expression, self.interval
+        )
+    }
+}
+
+pub struct IntervalAnalysis {
+    warnings: Vec<OverflowWarning>,
+    // 不動点を探している途中の広すぎる区間では警告しない
+    reporting: bool,
+}
+
+impl Default for IntervalAnalysis {
+    fn default() -> Self {
+        IntervalAnalysis::new()
+    }
+}
+
+impl IntervalAnalysis {
+    pub fn new() -> Self {
+        IntervalAnalysis {
+            warnings: Vec::new(),
+            reporting: true,
+        }
+    }
+    pub fn warnings(&self) -> &Vec<OverflowWarning> {
+        &self.warnings
+    }
+    pub fn evaluate(
+        &mut self,
+        expression: &Expression,
+        environment: &IntervalEnvironment,
+    ) -> Result<IntervalValue, String> {
+        expression.check_supported(&[])?;
+        Ok(self.value(expression, environment))
+    }
+    fn value(
+        &mut self,
+        expression: &Expression,
+        environment: &IntervalEnvironment,
+    ) -> IntervalValue {
+        match expression {
+            Expression::Number(_) | Expression::Boolean(_) => IntervalValue::of(expression),
+            Expression::Variable(name) => {
+                if let Some(value) = environment.get(name) {
+                    *value
+                } else {
+                    panic!("undefined variable")
+                }
+            }
+            Expression::Add {
+                ref left,
+                ref right,
+            } => {
+                let result = self
+                    .value(left, environment)
+                    .interval()
+                    .add(&self.value(right, environment).interval());
+                self.check_overflow(expression, result)
+            }
+            Expression::Multiply {
+                ref left,
+                ref right,
+            } => {
+                let result = self
+                    .value(left, environment)
+                    .interval()
+                    .multiply(&self.value(right, environment).interval());
+                self.check_overflow(expression, result)
+            }
+            Expression::LessThan {
+                ref left,
+                ref right,
+            } => IntervalValue::Boolean(
+                self.value(left, environment)
+                    .interval()
+                    .less_than(&self.value(right, environment).interval()),
+            ),
+            Expression::Equal {
+                ref left,
+                ref right,
+            } => IntervalValue::Boolean(
+                self.value(left, environment)
+                    .equal(&self.value(right, environment)),
+            ),
+            Expression::Tuple(_)
+            | Expression::Record(_)
+            | Expression::Field { .. }
+            | Expression::Function { .. }
+            | Expression::Closure { .. }
+            | Expression::Call { .. } => unreachable!(),
+        }
+    }
+    fn check_overflow(&mut self, expression: &Expression, result: Interval) -> IntervalValue {
+        if result.may_overflow() && self.reporting {
+            let expression = expression.clone();
+            if !self
+                .warnings
+                .iter()
+                .any(|w| w.expression.to_string() == expression.to_string())
+            {
+                self.warnings.push(OverflowWarning {
+                    expression,
+                    interval: result,
+                });
+            }
+        }
+        IntervalValue::Number(result.clamp())
+    }
+    // 条件が成り立つ(truth)と仮定して比較に出てくる変数の区間を絞る
+    fn assume(
+        &mut self,
+        condition: &Expression,
+        truth: bool,
+        environment: &IntervalEnvironment,
+    ) -> Option<IntervalEnvironment> {
+        if let Expression::LessThan {
+            ref left,
+            ref right,
+        } = condition
+        {
+            let a = self.value(left, environment).interval();
+            let b = self.value(right, environment).interval();
+            let (new_left, new_right) = if truth {
+                if a.low >= b.high {
+                    return None;
+                }
+                (
+                    Interval::new(a.low, a.high.min(b.high - 1)),
+                    Interval::new(b.low.max(a.low + 1), b.high),
+                )
+            } else {
+                if a.high < b.low {
+                    return None;
+                }
+                (
+                    Interval::new(a.low.max(b.low), a.high),
+                    Interval::new(b.low, b.high.min(a.high)),
+                )
+            };
+            let mut new_env = environment.clone();
+            if let Expression::Variable(name) = left.as_ref() {
+                new_env.insert(name.clone(), IntervalValue::Number(new_left));
+            }
+            if let Expression::Variable(name) = right.as_ref() {
+                new_env.insert(name.clone(), IntervalValue::Number(new_right));
+            }
+            return Some(new_env);
+        }
+        // 等しいと仮定すれば両辺とも共通部分に入る
+        if let (
+            Expression::Equal {
+                ref left,
+                ref right,
+            },
+            true,
+        ) = (condition, truth)
+        {
+            if let (IntervalValue::Number(a), IntervalValue::Number(b)) = (
+                self.value(left, environment),
+                self.value(right, environment),
+            ) {
+                if a.equal(&b) == Some(false) {
+                    return None;
+                }
+                let common = Interval::new(a.low.max(b.low), a.high.min(b.high));
+                let mut new_env = environment.clone();
+                for side in [left, right] {
+                    if let Expression::Variable(name) = side.as_ref() {
+                        new_env.insert(name.clone(), IntervalValue::Number(common));
+                    }
+                }
+                return Some(new_env);
+            }
+        }
+        match self.value(condition, environment) {
+            IntervalValue::Boolean(Some(b)) if b != truth => None,
+            _ => {
+                let mut new_env = environment.clone();
+                if let Expression::Variable(name) = condition {
+                    new_env.insert(name.clone(), IntervalValue::Boolean(Some(truth)));
+                }
+                Some(new_env)
+            }
+        }
+    }
+    // 終わった後の環境。決して終わらないならNone
+    pub fn analyze(
+        &mut self,
+        statement: &Statement,
+        environment: &IntervalEnvironment,
+    ) -> Result<Option<IntervalEnvironment>, String> {
+        statement.check_supported(&[Construct::Choose, Construct::Assert])?;
+        Ok(self.analyze_statement(statement, environment))
+    }
+    fn analyze_statement(
+        &mut self,
+        statement: &Statement,
+        environment: &IntervalEnvironment,
+    ) -> Option<IntervalEnvironment> {
+        match statement {
+            Statement::DoNothing => Some(environment.clone()),
+            Statement::Assignment { name, expression } => {
+                let mut new_env = environment.clone();
+                new_env.insert(name.to_string(), self.value(expression, environment));
+                Some(new_env)
+            }
+            Statement::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let consequence_env = self
+                    .assume(condition, true, environment)
+                    .and_then(|env| self.analyze_statement(consequence, &env));
+                let alternative_env = self
+                    .assume(condition, false, environment)
+                    .and_then(|env| self.analyze_statement(alternative, &env));
+                join_reachable(consequence_env, alternative_env)
+            }
+            Statement::Sequence { first, second } => self
+                .analyze_statement(first, environment)
+                .and_then(|env| self.analyze_statement(second, &env)),
+            Statement::While { condition, body } => {
+                let invariant = self.loop_invariant(condition, body, environment);
+                if self.reporting {
+                    if let Some(env) = self.assume(condition, true, &invariant) {
+                        self.analyze_statement(body, &env);
+                    }
+                }
+                self.assume(condition, false, &invariant)
+            }
+            Statement::Choose { first, second } => {
+                let first_env = self.analyze_statement(first, environment);
+                let second_env = self.analyze_statement(second, environment);
+                join_reachable(first_env, second_env)
+            }
+            // 偽なら止まるので、後は条件が成り立つ
+            Statement::Assert { condition } => self.assume(condition, true, environment),
+            Statement::Parallel { .. }
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Throw { .. }
+            | Statement::Try { .. }
+            | Statement::Let { .. }
+            | Statement::Block { .. }
+            | Statement::Var { .. }
+            | Statement::Update { .. } => unreachable!(),
+        }
+    }
+    // ループの先頭での環境を、まず広げて不動点にしてから狭めて求める
+    fn loop_invariant(
+        &mut self,
+        condition: &Expression,
+        body: &Statement,
+        environment: &IntervalEnvironment,
+    ) -> IntervalEnvironment {
+        let reporting = self.reporting;
+        self.reporting = false;
+        let mut current = environment.clone();
+        loop {
+            let next = join_reachable(
+                Some(environment.clone()),
+                self.iterate(condition, body, &current),
+            )
+            .unwrap();
+            let widened = combine_environments(&current, &next, Interval::widen);
+            if widened == current {
+                break;
+            }
+            current = widened;
+        }
+        for _ in 0..3 {
+            let next = join_reachable(
+                Some(environment.clone()),
+                self.iterate(condition, body, &current),
+            )
+            .unwrap();
+            let narrowed = combine_environments(&current, &next, Interval::narrow);
+            if narrowed == current {
+                break;
+            }
+            current = narrowed;
+        }
+        self.reporting = reporting;
+        current
+    }
+    fn iterate(
+        &mut self,
+        condition: &Expression,
+        body: &Statement,
+        environment: &IntervalEnvironment,
+    ) -> Option<IntervalEnvironment> {
+        self.assume(condition, true, environment)
+            .and_then(|env| self.analyze_statement(body, &env))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expression::Environment;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn multiply(left: Expression, right: Expression) -> Expression {
+        Expression::Multiply {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn equal(left: Expression, right: Expression) -> Expression {
+        Expression::Equal {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+    fn environment(values: Vec<(&str, IntervalValue)>) -> IntervalEnvironment {
+        values
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect()
+    }
+
+    fn assert_sound(statement: &Statement, inputs: Vec<Environment>) {
+        for input in inputs {
+            let abstract_input = input
+                .iter()
+                .map(|(name, value)| (name.clone(), IntervalValue::of(value)))
+                .collect();
+            let analysis = IntervalAnalysis::new()
+                .analyze(statement, &abstract_input)
+                .unwrap()
+                .unwrap();
+            for (name, value) in statement.evaluate(&mut input.clone()) {
+                assert!(
+                    analysis[&name].contains(&value),
+                    "{} = {} is not in {}",
+                    name,
+                    value,
+                    analysis[&name]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn interval_test() {
+        let a = Interval::new(1, 3);
+        let b = Interval::new(2, 5);
+        assert_eq!(a.join(&b), Interval::new(1, 5));
+        assert_eq!(a.widen(&b), Interval::new(1, MAX));
+        assert_eq!(b.widen(&a), Interval::new(0, 5));
+        assert_eq!(Interval::new(1, MAX).narrow(&b), Interval::new(1, 5));
+        assert_eq!(a.add(&b), Interval::new(3, 8));
+        assert_eq!(a.multiply(&b), Interval::new(2, 15));
+        assert_eq!(a.less_than(&Interval::new(4, 4)), Some(true));
+        assert_eq!(b.less_than(&a), None);
+        assert_eq!(a.to_string(), "[1, 3]");
+    }
+
+    #[test]
+    fn while_test() {
+        let statement = Statement::While {
+            condition: less_than(variable("x"), number(5)),
+            body: Box::new(assign("x", multiply(variable("x"), number(3)))),
+        };
+        let mut analysis = IntervalAnalysis::new();
+        let result = analysis
+            .analyze(
+                &statement,
+                &environment(vec![("x", IntervalValue::Number(Interval::constant(1)))]),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(result["x"], IntervalValue::Number(Interval::new(5, 12)));
+        assert!(result["x"].interval().is_subset(&Interval::new(5, 27)));
+        assert!(analysis.warnings().is_empty());
+
+        // x = 0 だと止まらないので1から
+        let inputs = (1..8)
+            .map(|x| vec![("x".to_string(), number(x))].into_iter().collect())
+            .collect();
+        assert_sound(&statement, inputs);
+    }
+
+    #[test]
+    fn if_test() {
+        let statement = Statement::If {
+            condition: less_than(variable("x"), number(10)),
+            consequence: Box::new(assign("y", add(variable("x"), number(100)))),
+            alternative: Box::new(assign("y", number(0))),
+        };
+        let result = IntervalAnalysis::new()
+            .analyze(
+                &statement,
+                &environment(vec![("x", IntervalValue::Number(Interval::new(5, 20)))]),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(result["y"], IntervalValue::Number(Interval::new(0, 109)));
+
+        let inputs = (5..20)
+            .map(|x| vec![("x".to_string(), number(x))].into_iter().collect())
+            .collect();
+        assert_sound(&statement, inputs);
+    }
+
+    #[test]
+    fn equal_test() {
+        // 等しい側ではxが7に決まる
+        let statement = Statement::If {
+            condition: equal(variable("x"), number(7)),
+            consequence: Box::new(assign("y", add(variable("x"), number(1)))),
+            alternative: Box::new(assign("y", number(0))),
+        };
+        let result = IntervalAnalysis::new()
+            .analyze(
+                &statement,
+                &environment(vec![("x", IntervalValue::Number(Interval::new(5, 20)))]),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(result["y"], IntervalValue::Number(Interval::new(0, 8)));
+
+        let inputs = (5..20)
+            .map(|x| vec![("x".to_string(), number(x))].into_iter().collect())
+            .collect();
+        assert_sound(&statement, inputs);
+
+        let mut analysis = IntervalAnalysis::new();
+        let env = environment(vec![("x", IntervalValue::Number(Interval::new(5, 20)))]);
+        assert_eq!(
+            analysis.evaluate(&equal(variable("x"), number(30)), &env),
+            Ok(IntervalValue::Boolean(Some(false)))
+        );
+        assert_eq!(
+            analysis.evaluate(&equal(number(1), Expression::Boolean(true)), &env),
+            Ok(IntervalValue::Boolean(Some(false)))
+        );
+        let record = Expression::Record(vec![("a".to_string(), number(1))]);
+        assert_eq!(
+            analysis.evaluate(&record, &env),
+            Err(format!("record is not supported: {}", record))
+        );
+    }
+
+    #[test]
+    fn counter_test() {
+        let statement = sequence(
+            assign("i", number(0)),
+            sequence(
+                assign("sum", number(0)),
+                Statement::While {
+                    condition: less_than(variable("i"), variable("n")),
+                    body: Box::new(sequence(
+                        assign("sum", add(variable("sum"), variable("i"))),
+                        assign("i", add(variable("i"), number(1))),
+                    )),
+                },
+            ),
+        );
+        let mut analysis = IntervalAnalysis::new();
+        let result = analysis
+            .analyze(
+                &statement,
+                &environment(vec![("n", IntervalValue::Number(Interval::new(0, 10)))]),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(result["i"], IntervalValue::Number(Interval::new(0, 10)));
+        assert_eq!(result["n"], IntervalValue::Number(Interval::new(0, 10)));
+        // sumの上限は分からないので足し算があふれるかもしれない
+        assert_eq!(analysis.warnings().len(), 1);
+        assert_eq!(analysis.warnings()[0].expression.to_string(), "sum + i");
+
+        let inputs = (0..10)
+            .map(|n| vec![("n".to_string(), number(n))].into_iter().collect())
+            .collect();
+        assert_sound(&statement, inputs);
+    }
+
+    #[test]
+    fn overflow_test() {
+        let statement = assign("y", add(variable("x"), number(1)));
+        let mut analysis = IntervalAnalysis::new();
+        analysis
+            .analyze(
+                &statement,
+                &environment(vec![(
+                    "x",
+                    IntervalValue::Number(Interval::constant(u32::MAX)),
+                )]),
+            )
+            .unwrap();
+        assert_eq!(
+            analysis.warnings()[0].to_string(),
+            "x + 1 may overflow u32: result in [4294967296, 4294967296]"
+        );
+
+        let statement = Statement::While {
+            condition: Expression::Boolean(true),
+            body: Box::new(assign("x", multiply(variable("x"), number(2)))),
+        };
+        let mut analysis = IntervalAnalysis::new();
+        let result = analysis
+            .analyze(
+                &statement,
+                &environment(vec![("x", IntervalValue::Number(Interval::constant(1)))]),
+            )
+            .unwrap();
+        assert!(result.is_none());
+        assert_eq!(analysis.warnings().len(), 1);
+        assert_eq!(analysis.warnings()[0].expression.to_string(), "x * 2");
+
+        let statement = Statement::While {
+            condition: less_than(variable("x"), number(1000)),
+            body: Box::new(assign("x", multiply(variable("x"), number(2)))),
+        };
+        let mut analysis = IntervalAnalysis::new();
+        analysis
+            .analyze(
+                &statement,
+                &environment(vec![("x", IntervalValue::Number(Interval::constant(1)))]),
+            )
+            .unwrap();
+        assert!(analysis.warnings().is_empty());
+    }
+
+    #[test]
+    fn assert_test() {
+        // 表明の後は条件が成り立つ範囲に絞られる
+        let statement = sequence(
+            Statement::Assert {
+                condition: less_than(variable("x"), number(10)),
+            },
+            assign("y", add(variable("x"), number(1))),
+        );
+        let result = IntervalAnalysis::new()
+            .analyze(
+                &statement,
+                &environment(vec![("x", IntervalValue::Number(Interval::new(5, 20)))]),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(result["y"], IntervalValue::Number(Interval::new(6, 10)));
+    }
+}
@@ -0,0 +1,340 @@
+use crate::expression::{Environment, Expression};
+use crate::statement::Statement;
+
+// to_rubyと同じ形。環境はオブジェクトで、代入のたびに新しいオブジェクトを作る
+const PRELUDE: &str = r#"const get = (e, name) => {
+  if (!Object.prototype.hasOwnProperty.call(e, name)) throw new Error("undefined variable");
+  return e[name];
+};
+const number = (value) => {
+  if (typeof value !== "number") throw new Error("expression is not number");
+  return value;
+};
+const add = (left, right) => {
+  if (left + right > 0xffffffff) throw new Error("overflow");
+  return left + right;
+};
+const multiply = (left, right) => {
+  if (left * right > 0xffffffff) throw new Error("overflow");
+  return left * right;
+};
+const boolean = (value) => {
+  if (typeof value !== "boolean") throw new Error("condition is not bool");
+  return value;
+};
+const show = (e) =>
+  "{" +
+  Object.keys(e)
+    .sort()
+    .map((name) => `"${name}": ${typeof e[name] === "number" ? "Number" : "Boolean"}(${e[name]})`)
+    .join(", ") +
+  "}";
+"#;
+
+fn javascript_value(value: &Expression) -> String {
+    match value {
+        Expression::Number(n) => n.to_string(),
+        Expression::Boolean(b) => b.to_string(),
+        _ => unreachable!(),
+    }
+}
+
+impl Expression {
+    pub fn to_javascript(&self) -> Result<String, String> {
+        self.check_supported(&[])?;
+        Ok(self.javascript())
+    }
+    fn javascript(&self) -> String {
+        match self {
+            Expression::Number(_) | Expression::Boolean(_) => {
+                format!("(e => {})", javascript_value(self))
+            }
+            Expression::Variable(name) => format!("(e => get(e, {:?}))", name),
+            // evaluateと同じくu32で桁あふれしたら止める
+            Expression::Add { left, right } => format!(
+                "(e => add(number({}(e)), number({}(e))))",
+                left.javascript(),
+                right.javascript()
+            ),
+            Expression::Multiply { left, right } => format!(
+                "(e => multiply(number({}(e)), number({}(e))))",
+                left.javascript(),
+                right.javascript()
+            ),
+            Expression::LessThan { left, right } => format!(
+                "(e => number({}(e)) < number({}(e)))",
+                left.javascript(),
+                right.javascript()
+            ),
+            // 数と真偽値はどちらも比べられ、型が違えば等しくない
+            Expression::Equal { left, right } => format!(
+                "(e => {}(e) === {}(e))",
+                left.javascript(),
+                right.javascript()
+            ),
+            Expression::Tuple(_)
+            | Expression::Record(_)
+            | Expression::Field { .. }
+            | Expression::Function { .. }
+            | Expression::Closure { .. }
+            | Expression::Call { .. } => unreachable!(),
+        }
+    }
+}
+
+impl Statement {
+    pub fn to_javascript(&self) -> Result<String, String> {
+        self.check_supported(&[])?;
+        Ok(self.javascript())
+    }
+    fn javascript(&self) -> String {
+        match self {
+            Statement::DoNothing => "(e => e)".to_string(),
+            Statement::Choose { .. }
+            | Statement::Parallel { .. }
+            | Statement::Assert { .. }
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Throw { .. }
+            | Statement::Try { .. }
+            | Statement::Let { .. }
+            | Statement::Block { .. }
+            | Statement::Var { .. }
+            | Statement::Update { .. } => unreachable!(),
+            Statement::Assignment { name, expression } => format!(
+                "(e => ({{ ...e, [{:?}]: {}(e) }}))",
+                name,
+                expression.javascript()
+            ),
+            Statement::If {
+                condition,
+                consequence,
+                alternative,
+            } => format!(
+                "(e => boolean({}(e)) ? {}(e) : {}(e))",
+                condition.javascript(),
+                consequence.javascript(),
+                alternative.javascript()
+            ),
+            Statement::Sequence { first, second } => {
+                format!("(e => {}({}(e)))", second.javascript(), first.javascript())
+            }
+            Statement::While { condition, body } => format!(
+                "(e => {{ while (boolean({}(e))) {{ e = {}(e); }} return e; }})",
+                condition.javascript(),
+                body.javascript()
+            ),
+        }
+    }
+    // 実行して最後の環境をMachineと同じ形式で出力するプログラム
+    pub fn to_javascript_program(&self, environment: &Environment) -> Result<String, String> {
+        let program = self.to_javascript()?;
+        let mut initial = environment.iter().collect::<Vec<_>>();
+        initial.sort_by(|a, b| a.0.cmp(b.0));
+        let initial = initial
+            .iter()
+            .map(|(name, value)| match value {
+                Expression::Number(_) | Expression::Boolean(_) => {
+                    Ok(format!("{:?}: {}", name, javascript_value(value)))
+                }
+                _ => Err(format!(
+                    "environment value of {} is not number or bool: {}",
+                    name, value
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!(
+            "{}\nconst e = {}({{ {} }});\nconsole.log(\"{}, \" + show(e));\n",
+            PRELUDE,
+            program,
+            initial.join(", "),
+            Statement::DoNothing
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::format_environment;
+    use std::process::{Command, Output};
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn multiply(left: Expression, right: Expression) -> Expression {
+        Expression::Multiply {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn equal(left: Expression, right: Expression) -> Expression {
+        Expression::Equal {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+
+    fn run(source: &str) -> Output {
+        Command::new("node")
+            .args(["-e", source])
+            .output()
+            .expect("node not found")
+    }
+
+    fn assert_same_as_evaluate(statement: &Statement, environment: &Environment) {
+        let source = statement.to_javascript_program(environment).unwrap();
+        let expected = statement.evaluate(&mut environment.clone());
+        let output = run(&source);
+        assert!(output.status.success(), "{}", source);
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            format!(
+                "{}, {}\n",
+                Statement::DoNothing,
+                format_environment(&expected)
+            )
+        );
+    }
+
+    #[test]
+    fn expression_test() {
+        assert_eq!(
+            less_than(variable("x"), number(3)).to_javascript(),
+            Ok("(e => number((e => get(e, \"x\"))(e)) < number((e => 3)(e)))".to_string())
+        );
+    }
+
+    #[test]
+    fn equal_test() {
+        // 真偽値どうし、数と真偽値も比べられる
+        let statement = sequence(
+            assign("b", equal(variable("x"), number(3))),
+            sequence(
+                assign(
+                    "c",
+                    equal(variable("b"), less_than(variable("x"), number(5))),
+                ),
+                assign("d", equal(variable("x"), Expression::Boolean(true))),
+            ),
+        );
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(3));
+        assert_same_as_evaluate(&statement, &environment);
+    }
+
+    #[test]
+    fn if_test() {
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), Expression::Boolean(true));
+        let statement = Statement::If {
+            condition: variable("x"),
+            consequence: Box::new(assign("y", number(1))),
+            alternative: Box::new(assign("y", number(2))),
+        };
+        assert_same_as_evaluate(&statement, &environment);
+    }
+
+    #[test]
+    fn while_test() {
+        // 階乗。12!まではu32に収まる
+        let statement = sequence(
+            assign("result", number(1)),
+            sequence(
+                assign("i", number(1)),
+                Statement::While {
+                    condition: less_than(variable("i"), add(variable("n"), number(1))),
+                    body: Box::new(sequence(
+                        assign("result", multiply(variable("result"), variable("i"))),
+                        sequence(
+                            assign("i", add(variable("i"), number(1))),
+                            assign("done", less_than(variable("n"), variable("i"))),
+                        ),
+                    )),
+                },
+            ),
+        );
+        let mut environment = Environment::new();
+        environment.insert("n".to_string(), number(12));
+        assert_same_as_evaluate(&statement, &environment);
+    }
+
+    #[test]
+    fn undefined_variable_test() {
+        // プロトタイプにある名前も未定義
+        for name in ["y", "toString", "constructor"] {
+            let source = assign("x", variable(name))
+                .to_javascript_program(&Environment::new())
+                .unwrap();
+            let output = run(&source);
+            assert!(!output.status.success(), "{}", name);
+            assert!(String::from_utf8(output.stderr)
+                .unwrap()
+                .contains("undefined variable"));
+        }
+    }
+
+    #[test]
+    fn overflow_test() {
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(u32::MAX));
+        for expression in [
+            add(variable("x"), number(1)),
+            multiply(variable("x"), number(2)),
+        ] {
+            let source = assign("y", expression)
+                .to_javascript_program(&environment)
+                .unwrap();
+            let output = run(&source);
+            assert!(!output.status.success());
+            assert!(String::from_utf8(output.stderr)
+                .unwrap()
+                .contains("overflow"));
+        }
+        let statement = sequence(
+            assign("y", add(variable("x"), number(0))),
+            assign("z", multiply(variable("x"), number(1))),
+        );
+        assert_same_as_evaluate(&statement, &environment);
+    }
+
+    #[test]
+    fn environment_error_test() {
+        let mut environment = Environment::new();
+        environment.insert(
+            "p".to_string(),
+            Expression::Tuple(vec![number(1), number(2)]),
+        );
+        assert_eq!(
+            assign("x", number(1)).to_javascript_program(&environment),
+            Err("environment value of p is not number or bool: (1, 2)".to_string())
+        );
+    }
+}
@@ -0,0 +1,551 @@
+use std::collections::HashMap;
+
+use crate::expression::{Environment, Expression};
+use crate::scope::{restore, save};
+use crate::statement::{Completion, Statement};
+
+// 代入では式を計算せずにサンクとして束縛し、変数が使われたときに計算する。
+// Nameは使われるたびに計算し直し、Needは最初の結果を覚えておく
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    Name,
+    Need,
+}
+
+pub type ThunkId = usize;
+pub type Bindings = HashMap<String, ThunkId>;
+
+#[derive(Clone, Debug)]
+pub struct Thunk {
+    pub name: String,
+    pub expression: Expression,
+    bindings: Bindings,
+    pub value: Option<Expression>,
+    // 使われた回数と、実際に計算した回数
+    pub forces: usize,
+    pub evaluations: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Lazy {
+    pub strategy: Strategy,
+    pub thunks: Vec<Thunk>,
+}
+
+impl Lazy {
+    pub fn new(strategy: Strategy) -> Lazy {
+        Lazy {
+            strategy,
+            thunks: Vec::new(),
+        }
+    }
+    fn delay(&mut self, name: &str, expression: &Expression, bindings: &Bindings) -> ThunkId {
+        self.thunks.push(Thunk {
+            name: name.to_string(),
+            expression: expression.clone(),
+            bindings: bindings.clone(),
+            value: None,
+            forces: 0,
+            evaluations: 0,
+        });
+        self.thunks.len() - 1
+    }
+    // 計算済みのサンク
+    fn ready(&mut self, name: &str, value: Expression) -> ThunkId {
+        let id = self.delay(name, &value, &Bindings::new());
+        self.thunks[id].value = Some(value);
+        id
+    }
+    pub fn bind_values(&mut self, environment: &Environment) -> Bindings {
+        environment
+            .iter()
+            .map(|(name, value)| (name.clone(), self.ready(name, value.clone())))
+            .collect()
+    }
+    pub fn force(&mut self, id: ThunkId) -> Expression {
+        self.thunks[id].forces += 1;
+        if let Some(value) = &self.thunks[id].value {
+            return value.clone();
+        }
+        let (expression, bindings) = (
+            self.thunks[id].expression.clone(),
+            self.thunks[id].bindings.clone(),
+        );
+        let value = self.evaluate(&expression, &bindings);
+        self.thunks[id].evaluations += 1;
+        if self.strategy == Strategy::Need {
+            self.thunks[id].value = Some(value.clone());
+        }
+        value
+    }
+    pub fn evaluate(&mut self, expression: &Expression, bindings: &Bindings) -> Expression {
+        let mut value = |e: &Expression| Box::new(self.evaluate(e, bindings));
+        // 部分式を値にしてから、値についての計算はevaluateに任せる
+        let values = match expression {
+            Expression::Number(_) | Expression::Boolean(_) | Expression::Closure { .. } => {
+                return expression.clone();
+            }
+            Expression::Variable(name) => match bindings.get(name) {
+                Some(id) => return self.force(*id),
+                None => panic!("undefined variable"),
+            },
+            Expression::Add { left, right } => Expression::Add {
+                left: value(left),
+                right: value(right),
+            },
+            Expression::Multiply { left, right } => Expression::Multiply {
+                left: value(left),
+                right: value(right),
+            },
+            Expression::LessThan { left, right } => Expression::LessThan {
+                left: value(left),
+                right: value(right),
+            },
+            Expression::Equal { left, right } => Expression::Equal {
+                left: value(left),
+                right: value(right),
+            },
+            Expression::Field { expression, name } => Expression::Field {
+                expression: value(expression),
+                name: name.clone(),
+            },
+            Expression::Tuple(elements) => {
+                Expression::Tuple(elements.iter().map(|e| *value(e)).collect())
+            }
+            Expression::Record(fields) => Expression::Record(
+                fields
+                    .iter()
+                    .map(|(name, e)| (name.clone(), *value(e)))
+                    .collect(),
+            ),
+            // 取り込む変数はその場で使われる
+            Expression::Function { .. } => {
+                let environment = expression
+                    .free_variables()
+                    .into_iter()
+                    .filter_map(|name| {
+                        let id = *bindings.get(&name)?;
+                        Some((name, self.force(id)))
+                    })
+                    .collect();
+                return expression.capture(&environment);
+            }
+            // 引数はサンクにして本体に渡す
+            Expression::Call {
+                function,
+                arguments,
+            } => match self.evaluate(function, bindings) {
+                Expression::Closure {
+                    params,
+                    body,
+                    environment,
+                } => {
+                    if params.len() != arguments.len() {
+                        panic!("wrong number of arguments");
+                    }
+                    let mut inner = Bindings::new();
+                    for (name, captured) in environment {
+                        inner.insert(name.clone(), self.ready(&name, captured));
+                    }
+                    for (param, argument) in params.iter().zip(arguments) {
+                        inner.insert(param.clone(), self.delay(param, argument, bindings));
+                    }
+                    return self.evaluate(&body, &inner);
+                }
+                _ => panic!("expression is not function"),
+            },
+        };
+        values.evaluate(&Environment::new())
+    }
+    pub fn execute(&mut self, statement: &Statement, bindings: &mut Bindings) -> Completion {
+        match statement {
+            Statement::DoNothing => Completion::Normal,
+            Statement::Assignment { name, expression } | Statement::Var { name, expression } => {
+                let id = self.delay(name, expression, bindings);
+                bindings.insert(name.clone(), id);
+                Completion::Normal
+            }
+            // 元の値が要るので、フィールドの更新はその場で計算する
+            Statement::Update {
+                name,
+                fields,
+                expression,
+            } => {
+                let current = match bindings.get(name) {
+                    Some(id) => self.force(*id),
+                    None => panic!("undefined variable"),
+                };
+                let value = self.evaluate(expression, bindings);
+                let id = self.ready(name, current.update(fields, value));
+                bindings.insert(name.clone(), id);
+                Completion::Normal
+            }
+            Statement::If {
+                condition,
+                consequence,
+                alternative,
+            } => match self.evaluate(condition, bindings) {
+                Expression::Boolean(true) => self.execute(consequence, bindings),
+                Expression::Boolean(false) => self.execute(alternative, bindings),
+                _ => panic!("condition is not bool"),
+            },
+            Statement::While { condition, body } => loop {
+                match self.evaluate(condition, bindings) {
+                    Expression::Boolean(true) => match self.execute(body, bindings) {
+                        Completion::Normal | Completion::Continue => {}
+                        Completion::Break => return Completion::Normal,
+                        completion => return completion,
+                    },
+                    Expression::Boolean(false) => return Completion::Normal,
+                    _ => panic!("condition is not bool"),
+                }
+            },
+            Statement::Sequence { first, second }
+            | Statement::Parallel {
+                left: first,
+                right: second,
+            } => match self.execute(first, bindings) {
+                Completion::Normal => self.execute(second, bindings),
+                completion => completion,
+            },
+            Statement::Choose { first, .. } => self.execute(first, bindings),
+            Statement::Assert { condition } => match self.evaluate(condition, bindings) {
+                Expression::Boolean(true) => Completion::Normal,
+                Expression::Boolean(false) => panic!("assertion failed"),
+                _ => panic!("condition is not bool"),
+            },
+            Statement::Break => Completion::Break,
+            Statement::Continue => Completion::Continue,
+            Statement::Throw { expression } => {
+                Completion::Throw(self.evaluate(expression, bindings))
+            }
+            Statement::Try {
+                body,
+                name,
+                handler,
+            } => match self.execute(body, bindings) {
+                Completion::Throw(value) => {
+                    let id = self.ready(name, value);
+                    bindings.insert(name.clone(), id);
+                    self.execute(handler, bindings)
+                }
+                completion => completion,
+            },
+            Statement::Let {
+                name,
+                expression,
+                body,
+            } => {
+                let saved = save(std::slice::from_ref(name), bindings);
+                let id = self.delay(name, expression, bindings);
+                bindings.insert(name.clone(), id);
+                let completion = self.execute(body, bindings);
+                restore(&saved, bindings);
+                completion
+            }
+            Statement::Block { body } => {
+                let saved = save(&body.hoisted_variables(), bindings);
+                let completion = self.execute(body, bindings);
+                restore(&saved, bindings);
+                completion
+            }
+        }
+    }
+    // 正格な評価と比べるための値。使われた回数には数えない。
+    // 一度も使われなかった束縛は、計算すると止まるかもしれないので含めない
+    pub fn environment(&self, bindings: &Bindings) -> Environment {
+        let mut lazy = self.clone();
+        bindings
+            .iter()
+            .filter(|(_, id)| self.thunks[**id].value.is_some() || self.thunks[**id].forces > 0)
+            .map(|(name, id)| (name.clone(), lazy.force(*id)))
+            .collect()
+    }
+    // 束縛ごとの、使われた回数と計算した回数
+    pub fn report(&self) -> String {
+        self.thunks
+            .iter()
+            .map(|thunk| {
+                format!(
+                    "{} = {}: forced {}, evaluated {}\n",
+                    thunk.name, thunk.expression, thunk.forces, thunk.evaluations
+                )
+            })
+            .collect()
+    }
+}
+
+impl Statement {
+    pub fn evaluate_lazy(
+        &self,
+        environment: &Environment,
+        strategy: Strategy,
+    ) -> (Environment, Lazy) {
+        let mut lazy = Lazy::new(strategy);
+        let mut bindings = lazy.bind_values(environment);
+        match lazy.execute(self, &mut bindings) {
+            Completion::Normal => (lazy.environment(&bindings), lazy),
+            Completion::Break => panic!("break outside loop"),
+            Completion::Continue => panic!("continue outside loop"),
+            Completion::Throw(value) => panic!("uncaught exception {}", value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::format_environment;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn multiply(left: Expression, right: Expression) -> Expression {
+        Expression::Multiply {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn function(params: &[&str], body: Expression) -> Expression {
+        Expression::Function {
+            params: params.iter().map(|p| p.to_string()).collect(),
+            body: Box::new(body),
+        }
+    }
+    fn call(function: Expression, arguments: Vec<Expression>) -> Expression {
+        Expression::Call {
+            function: Box::new(function),
+            arguments,
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(statements: Vec<Statement>) -> Statement {
+        statements
+            .into_iter()
+            .rev()
+            .reduce(|second, first| Statement::Sequence {
+                first: Box::new(first),
+                second: Box::new(second),
+            })
+            .unwrap()
+    }
+
+    // どちらの方法でも、使われた束縛は正格な評価と同じ値になる
+    fn agree(statement: &Statement) -> (Lazy, Lazy) {
+        let strict = statement.evaluate(&mut Environment::new());
+        let (name_env, name) = statement.evaluate_lazy(&Environment::new(), Strategy::Name);
+        let (need_env, need) = statement.evaluate_lazy(&Environment::new(), Strategy::Need);
+        assert_eq!(format_environment(&name_env), format_environment(&need_env));
+        let expected = strict
+            .into_iter()
+            .filter(|(name, _)| need_env.contains_key(name))
+            .collect();
+        assert_eq!(format_environment(&need_env), format_environment(&expected));
+        (name, need)
+    }
+
+    #[test]
+    fn memoization_test() {
+        let statement = sequence(vec![
+            assign("x", add(number(2), number(3))),
+            assign("y", multiply(variable("x"), variable("x"))),
+            assign("unused", add(variable("x"), number(100))),
+            assign("x", number(0)),
+            Statement::If {
+                condition: less_than(variable("y"), number(30)),
+                consequence: Box::new(assign("z", add(variable("y"), variable("y")))),
+                alternative: Box::new(Statement::DoNothing),
+            },
+        ]);
+        let (name, need) = agree(&statement);
+        // 使われなかったunused、z、最後のxは含めない
+        let (environment, _) = statement.evaluate_lazy(&Environment::new(), Strategy::Need);
+        assert_eq!(format_environment(&environment), "{\"y\": Number(25)}");
+        assert_eq!(
+            need.report(),
+            [
+                "x = 2 + 3: forced 2, evaluated 1",
+                "y = x * x: forced 1, evaluated 1",
+                "unused = x + 100: forced 0, evaluated 0",
+                "x = 0: forced 0, evaluated 0",
+                "z = y + y: forced 0, evaluated 0",
+                ""
+            ]
+            .join("\n")
+        );
+        // 最後の環境を求めても、使われた回数は変わらない
+        assert_eq!(
+            name.thunks.iter().map(|t| t.forces).collect::<Vec<_>>(),
+            vec![2, 1, 0, 0, 0]
+        );
+        assert_eq!(
+            name.thunks
+                .iter()
+                .map(|t| t.evaluations)
+                .collect::<Vec<_>>(),
+            vec![2, 1, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn loop_test() {
+        // nは前のnを指すサンクの鎖になる。Nameでは鎖をたどるたびに計算し直す
+        let statement = sequence(vec![
+            assign("n", number(0)),
+            assign("k", add(number(1), number(1))),
+            Statement::While {
+                condition: less_than(variable("n"), number(6)),
+                body: Box::new(assign("n", add(variable("n"), variable("k")))),
+            },
+        ]);
+        let (name, need) = agree(&statement);
+        let total = |lazy: &Lazy| lazy.thunks.iter().map(|t| t.evaluations).sum::<usize>();
+        assert_eq!(total(&need), 5);
+        assert_eq!(total(&name), 1 + 3 + 5 + 7);
+        assert_eq!(need.thunks[1].forces, 3);
+        assert_eq!(need.thunks[1].evaluations, 1);
+        assert_eq!(name.thunks[1].evaluations, 6);
+    }
+
+    #[test]
+    fn argument_test() {
+        // 使わない引数は計算しないので、正格な評価と違って未定義の変数でも止まらない
+        let statement = sequence(vec![
+            assign("first", function(&["a", "b"], variable("a"))),
+            assign(
+                "r",
+                call(
+                    variable("first"),
+                    vec![add(number(1), number(2)), variable("missing")],
+                ),
+            ),
+            Statement::If {
+                condition: less_than(variable("r"), number(5)),
+                consequence: Box::new(assign("small", Expression::Boolean(true))),
+                alternative: Box::new(Statement::DoNothing),
+            },
+        ]);
+        let (environment, need) = statement.evaluate_lazy(&Environment::new(), Strategy::Need);
+        assert_eq!(environment["r"], number(3));
+        assert_eq!(
+            need.report(),
+            [
+                "first = function (a, b) { a }: forced 1, evaluated 1",
+                "r = first(1 + 2, missing): forced 1, evaluated 1",
+                "a = 1 + 2: forced 1, evaluated 1",
+                "b = missing: forced 0, evaluated 0",
+                "small = true: forced 0, evaluated 0",
+                ""
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn unused_binding_test() {
+        // 使われない束縛は、計算すると止まるものでもよい
+        let statement = sequence(vec![
+            assign("unused", variable("missing")),
+            assign("x", number(1)),
+            assign("y", add(variable("x"), number(1))),
+            Statement::If {
+                condition: less_than(variable("y"), number(5)),
+                consequence: Box::new(Statement::DoNothing),
+                alternative: Box::new(Statement::DoNothing),
+            },
+        ]);
+        for strategy in [Strategy::Name, Strategy::Need] {
+            let (environment, _) = statement.evaluate_lazy(&Environment::new(), strategy);
+            assert_eq!(
+                format_environment(&environment),
+                "{\"x\": Number(1), \"y\": Number(2)}"
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "undefined variable")]
+    fn strict_argument_test() {
+        assign(
+            "r",
+            call(
+                function(&["a", "b"], variable("a")),
+                vec![number(1), variable("missing")],
+            ),
+        )
+        .evaluate(&mut Environment::new());
+    }
+
+    #[test]
+    fn agreement_test() {
+        let statement = sequence(vec![
+            assign("k", number(3)),
+            assign(
+                "scale",
+                function(&["x"], multiply(variable("x"), variable("k"))),
+            ),
+            assign("k", number(100)),
+            assign("i", number(0)),
+            assign("total", number(0)),
+            Statement::While {
+                condition: Expression::Boolean(true),
+                body: Box::new(sequence(vec![
+                    Statement::Let {
+                        name: "i".to_string(),
+                        expression: add(variable("i"), number(1)),
+                        body: Box::new(assign(
+                            "total",
+                            add(
+                                variable("total"),
+                                call(variable("scale"), vec![variable("i")]),
+                            ),
+                        )),
+                    },
+                    assign("i", add(variable("i"), number(1))),
+                    Statement::Try {
+                        body: Box::new(Statement::If {
+                            condition: less_than(number(3), variable("i")),
+                            consequence: Box::new(Statement::Throw {
+                                expression: Expression::Tuple(vec![
+                                    variable("i"),
+                                    variable("total"),
+                                ]),
+                            }),
+                            alternative: Box::new(Statement::Block {
+                                body: Box::new(Statement::Var {
+                                    name: "i".to_string(),
+                                    expression: number(99),
+                                }),
+                            }),
+                        }),
+                        name: "e".to_string(),
+                        handler: Box::new(Statement::Break),
+                    },
+                ])),
+            },
+        ]);
+        let (_, need) = agree(&statement);
+        assert!(need.thunks.iter().all(|t| t.evaluations <= 1));
+    }
+}
@@ -1,3 +1,29 @@
+pub mod c;
+pub mod cek;
+pub mod cfg;
+pub mod concurrency;
+pub mod dataflow;
+pub mod equivalence;
 pub mod expression;
+pub mod formatter;
+pub mod function;
+pub mod hoare;
+pub mod interval;
+pub mod javascript;
+pub mod lazy;
 pub mod machine;
+pub mod parser;
+pub mod profile;
+pub mod record;
+pub mod running;
+pub mod rust;
+pub mod scope;
+pub mod sign;
+pub mod solver;
+pub mod specialize;
+pub mod ssa;
 pub mod statement;
+pub mod subset;
+pub mod symbolic;
+pub mod trace;
+pub mod wasm;
@@ -1,4 +1,5 @@
 use crate::expression::Environment;
+use crate::running::Running;
 use crate::statement::Statement;
 
 pub struct Machine {
@@ -7,18 +8,27 @@ pub struct Machine {
 }
 
 impl Machine {
-    fn step(&mut self) {
-        // 以下のようには現バージョンだと書けない？
-        // (self.statement, self.environment) = self.statement.reduce(&self.environment)
-        let (new_statement, new_env) = self.statement.reduce(&mut self.environment);
-        self.statement = new_statement;
-        self.environment = new_env;
-    }
+    // 実行中のループやスコープはStatementでは書けないので、Runningに移して進める
     pub fn run(&mut self) {
-        while self.statement.is_reducible() {
-            println!("{}, {:?}", self.statement, self.environment);
-            self.step();
+        let mut running = Running::from(self.statement.clone());
+        while running.is_reducible() {
+            println!("{}, {:?}", running, self.environment);
+            let (new_running, new_env) = running.reduce(&mut self.environment);
+            running = new_running;
+            self.environment = new_env;
         }
+        self.statement = running.into_statement();
         println!("{}, {:?}", self.statement, self.environment);
     }
 }
+
+// Machineと同じ形式で、変数名の順に並べる
+pub fn format_environment(environment: &Environment) -> String {
+    let mut names = environment.keys().collect::<Vec<_>>();
+    names.sort();
+    let pairs = names
+        .iter()
+        .map(|name| format!("{:?}: {:?}", name, environment[*name]))
+        .collect::<Vec<_>>();
+    format!("{{{}}}", pairs.join(", "))
+}
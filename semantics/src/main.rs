@@ -170,7 +170,7 @@ fn main() {
             right: Box::new(Expression::Number(3)),
         },
     };
-    println!("{}", stat.to_ruby());
+    println!("{}", stat.to_ruby().unwrap());
 
     let stat = Statement::If {
         condition: Expression::Variable(String::from("x")),
@@ -183,7 +183,7 @@ fn main() {
             expression: Expression::Number(2),
         }),
     };
-    println!("{}", stat.to_ruby());
+    println!("{}", stat.to_ruby().unwrap());
 
     let stat = Statement::While {
         condition: Expression::LessThan {
@@ -198,5 +198,5 @@ fn main() {
             },
         }),
     };
-    println!("{}", stat.to_ruby());
+    println!("{}", stat.to_ruby().unwrap());
 }
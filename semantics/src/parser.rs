@@ -0,0 +1,634 @@
+use std::collections::BTreeMap;
+
+use crate::expression::Expression;
+use crate::statement::Statement;
+
+// SIMPLEのソースをStatementにする。コメントは文に結びつけてCommentsにとっておく。
+// 文の番号は、Sequenceと書かれていないdo-nothing(elseのない場合や空のブロック)を除いて
+// 前順に数えたもの
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Comments {
+    // 文の前の行のコメント
+    pub leading: BTreeMap<usize, Vec<String>>,
+    // 文と同じ行の後ろのコメント
+    pub trailing: BTreeMap<usize, String>,
+    // ブロックの最後のコメント。(ブロックを持つ文の番号, 何番目のブロックか)で、Noneはファイルの最後
+    pub closing: BTreeMap<(Option<usize>, usize), Vec<String>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(u32),
+    Name(String),
+    Symbol(&'static str),
+    Comment(String),
+    End,
+}
+
+const SYMBOLS: [&str; 14] = [
+    "==", "||", "(", ")", "{", "}", ",", ";", ".", "=", "<", "+", "*", ":",
+];
+
+const KEYWORDS: [&str; 19] = [
+    "if",
+    "else",
+    "while",
+    "choose",
+    "or",
+    "try",
+    "catch",
+    "let",
+    "in",
+    "block",
+    "var",
+    "throw",
+    "assert",
+    "break",
+    "continue",
+    "do-nothing",
+    "function",
+    "true",
+    "false",
+];
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(value) => format!("`{}`", value),
+        Token::Name(name) => format!("`{}`", name),
+        Token::Symbol(symbol) => format!("`{}`", symbol),
+        Token::Comment(_) => "comment".to_string(),
+        Token::End => "end of input".to_string(),
+    }
+}
+
+// トークンと、それがある行
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let mut tokens = Vec::new();
+    let (mut i, mut line) = (0, 1);
+    while let Some(c) = source[i..].chars().next() {
+        let rest = &source[i..];
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += c.len_utf8();
+        } else if rest.starts_with("//") {
+            let text = rest.lines().next().unwrap();
+            i += text.len();
+            tokens.push((Token::Comment(text.trim_end().to_string()), line));
+        } else if c.is_ascii_digit() {
+            let digits = rest.split(|c: char| !c.is_ascii_digit()).next().unwrap();
+            i += digits.len();
+            match digits.parse() {
+                Ok(value) => tokens.push((Token::Number(value), line)),
+                Err(_) => return Err(format!("line {}: number {} is too large", line, digits)),
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let mut name = rest
+                .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .next()
+                .unwrap();
+            if name == "do" && rest.starts_with("do-nothing") {
+                name = "do-nothing";
+            }
+            i += name.len();
+            tokens.push((Token::Name(name.to_string()), line));
+        } else {
+            match SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+                Some(symbol) => {
+                    i += symbol.len();
+                    tokens.push((Token::Symbol(symbol), line));
+                }
+                None => return Err(format!("line {}: unexpected character `{}`", line, c)),
+            }
+        }
+    }
+    tokens.push((Token::End, line));
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    // 最後に読んだトークンの行
+    line: usize,
+    // まだどの文にも結びつけていないコメント
+    pending: Vec<String>,
+    count: usize,
+    comments: Comments,
+}
+
+impl Parser {
+    // コメントを飛ばした次のトークン。コメントはまだ読まない
+    fn peek(&self) -> &Token {
+        self.tokens[self.position..]
+            .iter()
+            .map(|(token, _)| token)
+            .find(|token| !matches!(token, Token::Comment(_)))
+            .unwrap()
+    }
+    fn is(&self, symbol: &str) -> bool {
+        match self.peek() {
+            Token::Symbol(s) => *s == symbol,
+            Token::Name(name) => name == symbol,
+            _ => false,
+        }
+    }
+    fn collect_comments(&mut self) {
+        while let (Token::Comment(text), _) = &self.tokens[self.position] {
+            self.pending.push(text.clone());
+            self.position += 1;
+        }
+    }
+    fn advance(&mut self) -> Token {
+        self.collect_comments();
+        let (token, line) = self.tokens[self.position].clone();
+        if token != Token::End {
+            self.position += 1;
+        }
+        self.line = line;
+        token
+    }
+    fn error<T>(&self, expected: &str) -> Result<T, String> {
+        let line = self.tokens[self.position..]
+            .iter()
+            .find(|(token, _)| !matches!(token, Token::Comment(_)))
+            .unwrap()
+            .1;
+        Err(format!(
+            "line {}: expected {}, found {}",
+            line,
+            expected,
+            describe(self.peek())
+        ))
+    }
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        if self.is(symbol) {
+            self.advance();
+            Ok(())
+        } else {
+            self.error(&format!("`{}`", symbol))
+        }
+    }
+    fn name(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Token::Name(name) if !KEYWORDS.contains(&name.as_str()) => match self.advance() {
+                Token::Name(name) => Ok(name),
+                _ => unreachable!(),
+            },
+            _ => self.error("a name"),
+        }
+    }
+    // '}'かファイルの終わりまでの文
+    fn body(&mut self, owner: Option<usize>, index: usize) -> Result<Statement, String> {
+        let mut statements = Vec::new();
+        while !self.is("}") && *self.peek() != Token::End {
+            let id = self.count;
+            statements.push(self.statement()?);
+            // 余分な`;`を読み飛ばす前に、同じ行のコメントを見る
+            loop {
+                if let (Token::Comment(text), line) = &self.tokens[self.position] {
+                    if *line == self.line && !self.comments.trailing.contains_key(&id) {
+                        self.comments.trailing.insert(id, text.clone());
+                        self.position += 1;
+                        continue;
+                    }
+                }
+                if !self.is(";") {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        self.collect_comments();
+        let mut closing = std::mem::take(&mut self.pending);
+        // do-nothingだけのブロックは空のブロックと同じなので、番号を返してコメントを移す
+        if let [Statement::DoNothing] = statements.as_slice() {
+            self.count -= 1;
+            let mut moved = self
+                .comments
+                .leading
+                .remove(&self.count)
+                .unwrap_or_default();
+            moved.extend(self.comments.trailing.remove(&self.count));
+            moved.append(&mut closing);
+            closing = moved;
+        }
+        if !closing.is_empty() {
+            self.comments.closing.insert((owner, index), closing);
+        }
+        Ok(statements
+            .into_iter()
+            .rev()
+            .reduce(|second, first| Statement::Sequence {
+                first: Box::new(first),
+                second: Box::new(second),
+            })
+            .unwrap_or(Statement::DoNothing))
+    }
+    fn block(&mut self, owner: usize, index: usize) -> Result<Box<Statement>, String> {
+        self.expect("{")?;
+        let body = self.body(Some(owner), index)?;
+        self.expect("}")?;
+        Ok(Box::new(body))
+    }
+    fn statement(&mut self) -> Result<Statement, String> {
+        self.collect_comments();
+        let id = self.count;
+        self.count += 1;
+        if !self.pending.is_empty() {
+            self.comments
+                .leading
+                .insert(id, std::mem::take(&mut self.pending));
+        }
+        let keyword = match self.peek() {
+            Token::Name(name) => name.clone(),
+            Token::Symbol("{") => "{".to_string(),
+            _ => return self.error("a statement"),
+        };
+        if KEYWORDS.contains(&keyword.as_str()) || keyword == "{" {
+            self.advance();
+        }
+        let statement = match keyword.as_str() {
+            "do-nothing" => Statement::DoNothing,
+            "break" => Statement::Break,
+            "continue" => Statement::Continue,
+            "throw" => Statement::Throw {
+                expression: self.expression()?,
+            },
+            "assert" => Statement::Assert {
+                condition: self.condition()?,
+            },
+            "if" => {
+                let condition = self.condition()?;
+                let consequence = self.block(id, 0)?;
+                let alternative = if self.is("else") {
+                    self.advance();
+                    if self.is("if") {
+                        Box::new(self.statement()?)
+                    } else {
+                        self.block(id, 1)?
+                    }
+                } else {
+                    Box::new(Statement::DoNothing)
+                };
+                Statement::If {
+                    condition,
+                    consequence,
+                    alternative,
+                }
+            }
+            "while" => {
+                let condition = self.condition()?;
+                // Displayの形 while (x), { ... } も読めるようにする
+                if self.is(",") {
+                    self.advance();
+                }
+                Statement::While {
+                    condition,
+                    body: self.block(id, 0)?,
+                }
+            }
+            "choose" => {
+                let first = self.block(id, 0)?;
+                self.expect("or")?;
+                Statement::Choose {
+                    first,
+                    second: self.block(id, 1)?,
+                }
+            }
+            "{" => {
+                let left = Box::new(self.body(Some(id), 0)?);
+                self.expect("}")?;
+                self.expect("||")?;
+                Statement::Parallel {
+                    left,
+                    right: self.block(id, 1)?,
+                }
+            }
+            "try" => {
+                let body = self.block(id, 0)?;
+                self.expect("catch")?;
+                self.expect("(")?;
+                let name = self.name()?;
+                self.expect(")")?;
+                Statement::Try {
+                    body,
+                    name,
+                    handler: self.block(id, 1)?,
+                }
+            }
+            "let" => {
+                let name = self.name()?;
+                self.expect("=")?;
+                let expression = self.expression()?;
+                self.expect("in")?;
+                Statement::Let {
+                    name,
+                    expression,
+                    body: self.block(id, 0)?,
+                }
+            }
+            "block" => Statement::Block {
+                body: self.block(id, 0)?,
+            },
+            "var" => {
+                let name = self.name()?;
+                self.expect("=")?;
+                Statement::Var {
+                    name,
+                    expression: self.expression()?,
+                }
+            }
+            _ => {
+                let name = self.name()?;
+                let mut fields = Vec::new();
+                while self.is(".") {
+                    self.advance();
+                    fields.push(self.field_name()?);
+                }
+                self.expect("=")?;
+                let expression = self.expression()?;
+                if fields.is_empty() {
+                    Statement::Assignment { name, expression }
+                } else {
+                    Statement::Update {
+                        name,
+                        fields,
+                        expression,
+                    }
+                }
+            }
+        };
+        Ok(statement)
+    }
+    fn condition(&mut self) -> Result<Expression, String> {
+        self.expect("(")?;
+        let condition = self.expression()?;
+        self.expect(")")?;
+        Ok(condition)
+    }
+    fn field_name(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Token::Number(_) => match self.advance() {
+                Token::Number(index) => Ok(index.to_string()),
+                _ => unreachable!(),
+            },
+            _ => self.name(),
+        }
+    }
+    // 結合の弱い順に ==, <, +, *
+    fn expression(&mut self) -> Result<Expression, String> {
+        let expression = self.binary(0)?;
+        // 式は1行に書くので、式の途中のコメントは読んでいる文の前に出す
+        if !self.pending.is_empty() {
+            self.comments
+                .leading
+                .entry(self.count - 1)
+                .or_default()
+                .append(&mut self.pending);
+        }
+        Ok(expression)
+    }
+    fn binary(&mut self, level: usize) -> Result<Expression, String> {
+        const OPERATORS: [&str; 4] = ["==", "<", "+", "*"];
+        if level == OPERATORS.len() {
+            return self.postfix();
+        }
+        let mut left = self.binary(level + 1)?;
+        while self.is(OPERATORS[level]) {
+            self.advance();
+            let left_box = Box::new(left);
+            let right = Box::new(self.binary(level + 1)?);
+            left = match level {
+                0 => Expression::Equal {
+                    left: left_box,
+                    right,
+                },
+                1 => Expression::LessThan {
+                    left: left_box,
+                    right,
+                },
+                2 => Expression::Add {
+                    left: left_box,
+                    right,
+                },
+                _ => Expression::Multiply {
+                    left: left_box,
+                    right,
+                },
+            };
+        }
+        Ok(left)
+    }
+    fn postfix(&mut self) -> Result<Expression, String> {
+        let mut expression = self.primary()?;
+        loop {
+            if self.is(".") {
+                self.advance();
+                expression = Expression::Field {
+                    expression: Box::new(expression),
+                    name: self.field_name()?,
+                };
+            } else if self.is("(") {
+                self.advance();
+                let arguments = self.list(")", |p| p.expression())?.0;
+                expression = Expression::Call {
+                    function: Box::new(expression),
+                    arguments,
+                };
+            } else {
+                return Ok(expression);
+            }
+        }
+    }
+    // closeまでの,区切りの並びと、最後に,があったか
+    fn list<T>(
+        &mut self,
+        close: &str,
+        mut item: impl FnMut(&mut Self) -> Result<T, String>,
+    ) -> Result<(Vec<T>, bool), String> {
+        let mut items = Vec::new();
+        let mut trailing = false;
+        while !self.is(close) {
+            items.push(item(self)?);
+            trailing = self.is(",");
+            if trailing {
+                self.advance();
+            } else if !self.is(close) {
+                return self.error(&format!("`,` or `{}`", close));
+            }
+        }
+        self.advance();
+        Ok((items, trailing))
+    }
+    fn primary(&mut self) -> Result<Expression, String> {
+        match self.peek().clone() {
+            Token::Number(value) => {
+                self.advance();
+                Ok(Expression::Number(value))
+            }
+            Token::Name(name) if name == "true" || name == "false" => {
+                self.advance();
+                Ok(Expression::Boolean(name == "true"))
+            }
+            Token::Name(name) if name == "function" => {
+                self.advance();
+                self.expect("(")?;
+                let params = self.list(")", |p| p.name())?.0;
+                self.expect("{")?;
+                let body = self.expression()?;
+                self.expect("}")?;
+                Ok(Expression::Function {
+                    params,
+                    body: Box::new(body),
+                })
+            }
+            Token::Name(_) => Ok(Expression::Variable(self.name()?)),
+            // (e)はただの括弧で、要素が1つのタプルは(e,)と書く
+            Token::Symbol("(") => {
+                self.advance();
+                match self.list(")", |p| p.expression())? {
+                    (mut elements, false) if elements.len() == 1 => Ok(elements.remove(0)),
+                    (elements, _) => Ok(Expression::Tuple(elements)),
+                }
+            }
+            Token::Symbol("{") => {
+                self.advance();
+                let line = self.line;
+                let (fields, _) = self.list("}", |p| {
+                    let name = p.field_name()?;
+                    p.expect(":")?;
+                    Ok((name, p.expression()?))
+                })?;
+                for (i, (name, _)) in fields.iter().enumerate() {
+                    if fields[..i].iter().any(|(n, _)| n == name) {
+                        return Err(format!("line {}: duplicate field `{}`", line, name));
+                    }
+                }
+                Ok(Expression::Record(fields))
+            }
+            _ => self.error("an expression"),
+        }
+    }
+}
+
+pub fn parse_with_comments(source: &str) -> Result<(Statement, Comments), String> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+        line: 1,
+        pending: Vec::new(),
+        count: 0,
+        comments: Comments::default(),
+    };
+    let statement = parser.body(None, 0)?;
+    if *parser.peek() != Token::End {
+        return parser.error("a statement");
+    }
+    Ok((statement, parser.comments))
+}
+
+pub fn parse(source: &str) -> Result<Statement, String> {
+    parse_with_comments(source).map(|(statement, _)| statement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expression::Environment;
+    use crate::machine::format_environment;
+
+    fn expression(source: &str) -> String {
+        match parse(&format!("x = {}", source)).unwrap() {
+            Statement::Assignment { expression, .. } => format!("{:?}", expression),
+            statement => panic!("{}", statement),
+        }
+    }
+
+    #[test]
+    fn precedence_test() {
+        assert_eq!(
+            expression("1 + 2 * 3 < 4 == true"),
+            expression("((1 + (2 * 3)) < 4) == true")
+        );
+        assert_eq!(expression("1 + 2 + 3"), expression("(1 + 2) + 3"));
+        assert_ne!(expression("1 + 2 + 3"), expression("1 + (2 + 3)"));
+        assert_eq!(expression("(1)"), "Number(1)");
+        assert_eq!(expression("(1,)"), "Tuple([Number(1)])");
+        assert_eq!(
+            expression("p.0.x"),
+            "Field { expression: Field { expression: Variable(\"p\"), name: \"0\" }, name: \"x\" }"
+        );
+        assert_eq!(expression("f(1)(2)"), expression("(f(1))(2)"));
+    }
+
+    #[test]
+    fn display_test() {
+        // Displayで書いたものも読める
+        let source = "x = 1; while (x < 5), { x = x * 2 }; p = { a: (x, true) }; p.a.1 = false; choose { y = 1 } or { y = 2 }";
+        let statement = parse(source).unwrap();
+        assert_eq!(statement.to_string(), source);
+        assert_eq!(
+            format_environment(&statement.evaluate(&mut Environment::new())),
+            format_environment(
+                &parse(&statement.to_string())
+                    .unwrap()
+                    .evaluate(&mut Environment::new())
+            )
+        );
+    }
+
+    #[test]
+    fn comments_test() {
+        let source = "\
+// first
+x = 1; // one
+if (x < 2) {
+    // inside
+    y = 2;
+    // closing
+} else {
+    do-nothing; // moved
+}
+// end of file
+";
+        let (_, comments) = parse_with_comments(source).unwrap();
+        assert_eq!(comments.leading[&0], vec!["// first"]);
+        assert_eq!(comments.trailing[&0], "// one");
+        assert_eq!(comments.leading[&2], vec!["// inside"]);
+        assert_eq!(comments.closing[&(Some(1), 0)], vec!["// closing"]);
+        assert_eq!(comments.closing[&(Some(1), 1)], vec!["// moved"]);
+        assert_eq!(comments.closing[&(None, 0)], vec!["// end of file"]);
+        assert_eq!(comments.leading.len() + comments.trailing.len(), 3);
+    }
+
+    #[test]
+    fn error_test() {
+        assert_eq!(
+            parse("x = 1;\nif (x < 2 {}").unwrap_err(),
+            "line 2: expected `)`, found `{`"
+        );
+        assert_eq!(
+            parse("x = 1;\n\nwhile = 2").unwrap_err(),
+            "line 3: expected `(`, found `=`"
+        );
+        assert_eq!(
+            parse("x = 1 }").unwrap_err(),
+            "line 1: expected a statement, found `}`"
+        );
+        assert_eq!(
+            parse("x = 99999999999").unwrap_err(),
+            "line 1: number 99999999999 is too large"
+        );
+        assert_eq!(
+            parse("x = 1 # 2").unwrap_err(),
+            "line 1: unexpected character `#`"
+        );
+        assert_eq!(
+            parse("p = {a: 1,\n b: 2, a: 3}").unwrap_err(),
+            "line 1: duplicate field `a`"
+        );
+    }
+}
@@ -0,0 +1,700 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::expression::{Environment, Expression};
+use crate::machine::Machine;
+use crate::running::Running;
+use crate::statement::{Completion, Statement};
+
+// ノードのidは、文と式をまとめて前順にたどったときの番号。
+// 同じプログラムならいつも同じidになる
+pub type NodeId = usize;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Node<'a> {
+    Statement(&'a Statement),
+    Expression(&'a Expression),
+    Running(&'a Running),
+}
+
+impl<'a> fmt::Display for Node<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Node::Statement(statement) => write!(f, "{}", statement),
+            Node::Expression(expression) => write!(f, "{}", expression),
+            Node::Running(running) => write!(f, "{}", running),
+        }
+    }
+}
+
+impl<'a> Node<'a> {
+    pub fn children(&self) -> Vec<Node<'a>> {
+        match *self {
+            Node::Statement(statement) => match statement {
+                Statement::DoNothing | Statement::Break | Statement::Continue => vec![],
+                Statement::Assignment { expression, .. }
+                | Statement::Update { expression, .. }
+                | Statement::Var { expression, .. }
+                | Statement::Throw { expression }
+                | Statement::Assert {
+                    condition: expression,
+                } => vec![Node::Expression(expression)],
+                Statement::If {
+                    condition,
+                    consequence,
+                    alternative,
+                } => vec![
+                    Node::Expression(condition),
+                    Node::Statement(consequence),
+                    Node::Statement(alternative),
+                ],
+                Statement::While { condition, body } => {
+                    vec![Node::Expression(condition), Node::Statement(body)]
+                }
+                Statement::Let {
+                    expression, body, ..
+                } => vec![Node::Expression(expression), Node::Statement(body)],
+                Statement::Sequence { first, second }
+                | Statement::Choose { first, second }
+                | Statement::Parallel {
+                    left: first,
+                    right: second,
+                }
+                | Statement::Try {
+                    body: first,
+                    handler: second,
+                    ..
+                } => vec![Node::Statement(first), Node::Statement(second)],
+                Statement::Block { body } => vec![Node::Statement(body)],
+            },
+            // 子の添字は、もとの文と同じ
+            Node::Running(running) => match running {
+                Running::Statement(statement) => Node::Statement(statement).children(),
+                Running::Sequence { first, second }
+                | Running::Try {
+                    body: first,
+                    handler: second,
+                    ..
+                }
+                | Running::Loop {
+                    body: first,
+                    next: second,
+                } => vec![Node::Running(first), Node::Statement(second)],
+                Running::Parallel { left, right } => {
+                    vec![Node::Running(left), Node::Running(right)]
+                }
+                Running::Scope { body, .. } => vec![Node::Running(body)],
+            },
+            Node::Expression(expression) => match expression {
+                Expression::Number(_)
+                | Expression::Boolean(_)
+                | Expression::Variable(_)
+                | Expression::Closure { .. } => vec![],
+                Expression::Add { left, right }
+                | Expression::Multiply { left, right }
+                | Expression::LessThan { left, right }
+                | Expression::Equal { left, right } => {
+                    vec![Node::Expression(left), Node::Expression(right)]
+                }
+                Expression::Tuple(elements) => elements.iter().map(Node::Expression).collect(),
+                Expression::Record(fields) => {
+                    fields.iter().map(|(_, v)| Node::Expression(v)).collect()
+                }
+                Expression::Field { expression, .. } => vec![Node::Expression(expression)],
+                Expression::Function { body, .. } => vec![Node::Expression(body)],
+                Expression::Call {
+                    function,
+                    arguments,
+                } => std::iter::once(Node::Expression(function.as_ref()))
+                    .chain(arguments.iter().map(Node::Expression))
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl Statement {
+    // 添字がid
+    pub fn nodes(&self) -> Vec<Node<'_>> {
+        let mut nodes = Vec::new();
+        Origin::number(Node::Statement(self), None, &mut nodes);
+        nodes.into_iter().map(|(node, _)| node).collect()
+    }
+}
+
+// 実行中の文の各ノードが、元のプログラムのどのノードから来たか。
+// 実行中に作られたノードは、作ったノードのidを持つがoriginalではない
+#[derive(Clone, Debug)]
+struct Origin {
+    id: NodeId,
+    original: bool,
+    children: Vec<Origin>,
+}
+
+impl Origin {
+    fn number<'a>(
+        node: Node<'a>,
+        parent: Option<NodeId>,
+        nodes: &mut Vec<(Node<'a>, Option<NodeId>)>,
+    ) -> Origin {
+        let id = nodes.len();
+        nodes.push((node, parent));
+        Origin {
+            id,
+            original: true,
+            children: node
+                .children()
+                .into_iter()
+                .map(|child| Origin::number(child, Some(id), nodes))
+                .collect(),
+        }
+    }
+    fn of(node: Node) -> Origin {
+        Origin::number(node, None, &mut Vec::new())
+    }
+    fn derived(id: NodeId, children: Vec<Origin>) -> Origin {
+        Origin {
+            id,
+            original: false,
+            children,
+        }
+    }
+    fn child(&self, index: usize) -> Origin {
+        match self.children.get(index) {
+            Some(child) => child.clone(),
+            None => Origin::derived(self.id, vec![]),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    // 実行された回数。小ステップでは、そのノードを書き換えたか、値や終わった文として使ったとき数える
+    pub executions: BTreeMap<NodeId, usize>,
+    // そのノードで起きた簡約の回数。大ステップでは数えない
+    pub steps: BTreeMap<NodeId, usize>,
+}
+
+impl Profile {
+    pub fn execution_count(&self, id: NodeId) -> usize {
+        self.executions.get(&id).cloned().unwrap_or(0)
+    }
+    pub fn step_count(&self, id: NodeId) -> usize {
+        self.steps.get(&id).cloned().unwrap_or(0)
+    }
+    fn execute(&mut self, origin: &Origin) {
+        if origin.original {
+            *self.executions.entry(origin.id).or_insert(0) += 1;
+        }
+    }
+    // 簡約しきった式や終わった文が使われた
+    fn consume(&mut self, node: Node, origin: &Origin) {
+        self.execute(origin);
+        for (i, child) in node.children().into_iter().enumerate() {
+            self.consume(child, &origin.child(i));
+        }
+    }
+}
+
+// 簡約や実行の途中で起きたことを、いまいるノードについて知らせてもらう。
+// 何もしない()を渡せば、ふつうの簡約や実行になる
+pub trait Recorder {
+    // index番目の子の中に入る、そこから出る
+    fn enter(&mut self, index: usize);
+    fn leave(&mut self);
+    // 大ステップで、いまいるノードを実行した
+    fn execute(&mut self);
+    // 小ステップで、いまいるノードに規則が当てはまった。
+    // consumedは値や終わった文として使われた子で、resultは簡約したあとの形
+    fn rule(&mut self, consumed: &[(usize, Node)], result: Shape);
+}
+
+impl Recorder for () {
+    fn enter(&mut self, _: usize) {}
+    fn leave(&mut self) {}
+    fn execute(&mut self) {}
+    fn rule(&mut self, _: &[(usize, Node)], _: Shape) {}
+}
+
+// 簡約したあとのノードが、簡約したノードのどこから来たか
+#[derive(Clone, Debug)]
+pub enum Shape {
+    // index番目の子がそのまま残る
+    Child(usize),
+    // 簡約したノード自身
+    Same,
+    // 簡約で新しく作ったノード
+    Derived(Vec<Shape>),
+}
+
+impl Shape {
+    fn origin(&self, origin: &Origin) -> Origin {
+        match self {
+            Shape::Child(index) => origin.child(*index),
+            Shape::Same => origin.clone(),
+            Shape::Derived(children) => Origin::derived(
+                origin.id,
+                children.iter().map(|child| child.origin(origin)).collect(),
+            ),
+        }
+    }
+}
+
+pub fn within<T>(
+    recorder: &mut dyn Recorder,
+    index: usize,
+    f: impl FnOnce(&mut dyn Recorder) -> T,
+) -> T {
+    recorder.enter(index);
+    let result = f(recorder);
+    recorder.leave();
+    result
+}
+
+// いまいるノードのOriginを、親をたどれるように積んでおく
+struct Profiler {
+    profile: Profile,
+    path: Vec<(usize, Origin)>,
+}
+
+impl Profiler {
+    fn new(origin: Origin) -> Profiler {
+        Profiler {
+            profile: Profile::default(),
+            path: vec![(0, origin)],
+        }
+    }
+    fn current(&mut self) -> &mut Origin {
+        &mut self.path.last_mut().unwrap().1
+    }
+}
+
+impl Recorder for Profiler {
+    fn enter(&mut self, index: usize) {
+        let child = self.current().child(index);
+        self.path.push((index, child));
+    }
+    fn leave(&mut self) {
+        let (index, child) = self.path.pop().unwrap();
+        let parent = self.current();
+        while parent.children.len() <= index {
+            parent.children.push(Origin::derived(parent.id, vec![]));
+        }
+        parent.children[index] = child;
+    }
+    fn execute(&mut self) {
+        let origin = self.current().clone();
+        self.profile.execute(&origin);
+    }
+    fn rule(&mut self, consumed: &[(usize, Node)], result: Shape) {
+        let origin = self.current().clone();
+        self.profile.execute(&origin);
+        *self.profile.steps.entry(origin.id).or_insert(0) += 1;
+        for (index, node) in consumed {
+            self.profile.consume(*node, &origin.child(*index));
+        }
+        *self.current() = result.origin(&origin);
+    }
+}
+
+impl Profile {
+    // 文ごとに1行。数は、実行回数と、その文とその中の式で起きた簡約の回数
+    pub fn listing(&self, statement: &Statement) -> String {
+        let mut lines = vec![format!("{:>6} {:>6} | source", "runs", "steps")];
+        self.list(
+            statement,
+            &Origin::of(Node::Statement(statement)),
+            0,
+            &mut lines,
+        );
+        lines.join("\n") + "\n"
+    }
+    fn own_steps(&self, node: Node, origin: &Origin) -> usize {
+        let mut steps = self.step_count(origin.id);
+        for (i, child) in node.children().into_iter().enumerate() {
+            if let Node::Expression(_) = child {
+                steps += self.own_steps(child, &origin.child(i));
+            }
+        }
+        steps
+    }
+    fn list(&self, statement: &Statement, origin: &Origin, depth: usize, lines: &mut Vec<String>) {
+        let indent = "  ".repeat(depth);
+        let line = |lines: &mut Vec<String>, text: String| {
+            let runs = match self.execution_count(origin.id) {
+                0 => "#####".to_string(),
+                n => n.to_string(),
+            };
+            let steps = self.own_steps(Node::Statement(statement), origin);
+            lines.push(format!("{:>6} {:>6} | {}{}", runs, steps, indent, text));
+        };
+        let close = |lines: &mut Vec<String>, text: &str| {
+            lines.push(format!("{:13} | {}{}", "", indent, text));
+        };
+        match statement {
+            Statement::Sequence { first, second } => {
+                self.list(first, &origin.child(0), depth, lines);
+                self.list(second, &origin.child(1), depth, lines);
+            }
+            Statement::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                line(lines, format!("if ({}) {{", condition));
+                self.list(consequence, &origin.child(1), depth + 1, lines);
+                close(lines, "} else {");
+                self.list(alternative, &origin.child(2), depth + 1, lines);
+                close(lines, "}");
+            }
+            Statement::While { condition, body } => {
+                line(lines, format!("while ({}) {{", condition));
+                self.list(body, &origin.child(1), depth + 1, lines);
+                close(lines, "}");
+            }
+            Statement::Choose { first, second }
+            | Statement::Parallel {
+                left: first,
+                right: second,
+            } => {
+                let (open, middle) = match statement {
+                    Statement::Choose { .. } => ("choose {", "} or {"),
+                    _ => ("{", "} || {"),
+                };
+                line(lines, open.to_string());
+                self.list(first, &origin.child(0), depth + 1, lines);
+                close(lines, middle);
+                self.list(second, &origin.child(1), depth + 1, lines);
+                close(lines, "}");
+            }
+            Statement::Try {
+                body,
+                name,
+                handler,
+            } => {
+                line(lines, "try {".to_string());
+                self.list(body, &origin.child(0), depth + 1, lines);
+                close(lines, &format!("}} catch ({}) {{", name));
+                self.list(handler, &origin.child(1), depth + 1, lines);
+                close(lines, "}");
+            }
+            Statement::Let {
+                name,
+                expression,
+                body,
+            } => {
+                line(lines, format!("let {} = {} in {{", name, expression));
+                self.list(body, &origin.child(1), depth + 1, lines);
+                close(lines, "}");
+            }
+            Statement::Block { body } => {
+                line(lines, "block {".to_string());
+                self.list(body, &origin.child(0), depth + 1, lines);
+                close(lines, "}");
+            }
+            _ => line(lines, statement.to_string()),
+        }
+    }
+    pub fn to_json(&self, statement: &Statement) -> String {
+        let mut nodes = Vec::new();
+        Origin::number(Node::Statement(statement), None, &mut nodes);
+        let entries = nodes
+            .iter()
+            .enumerate()
+            .map(|(id, (node, parent))| {
+                let kind = match node {
+                    Node::Statement(_) | Node::Running(_) => "statement",
+                    Node::Expression(_) => "expression",
+                };
+                let parent = match parent {
+                    Some(parent) => parent.to_string(),
+                    None => "null".to_string(),
+                };
+                format!(
+                    "    {{\"id\": {}, \"parent\": {}, \"kind\": \"{}\", \"source\": {}, \"executions\": {}, \"steps\": {}}}",
+                    id,
+                    parent,
+                    kind,
+                    json_string(&node.to_string()),
+                    self.execution_count(id),
+                    self.step_count(id)
+                )
+            })
+            .collect::<Vec<_>>();
+        format!("{{\n  \"nodes\": [\n{}\n  ]\n}}\n", entries.join(",\n"))
+    }
+}
+
+pub fn json_string(text: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+impl Machine {
+    // runと同じように最後まで進めるが、途中の状態は出力しない
+    pub fn run_with_profile(&mut self) -> Profile {
+        let mut running = Running::from(self.statement.clone());
+        let mut profiler = Profiler::new(Origin::of(Node::Running(&running)));
+        while running.is_reducible() {
+            let (new_running, environment) =
+                running.reduce_with_recorder(&mut self.environment, &mut profiler);
+            running = new_running;
+            self.environment = environment;
+        }
+        let origin = profiler.current().clone();
+        profiler.profile.consume(Node::Running(&running), &origin);
+        self.statement = running.into_statement();
+        profiler.profile
+    }
+}
+
+impl Statement {
+    pub fn evaluate_with_profile(&self, environment: &mut Environment) -> (Environment, Profile) {
+        let mut profiler = Profiler::new(Origin::of(Node::Statement(self)));
+        match self.execute_with_recorder(environment, &mut profiler) {
+            (Completion::Normal, new_env) => (new_env, profiler.profile),
+            (Completion::Break, _) => panic!("break outside loop"),
+            (Completion::Continue, _) => panic!("continue outside loop"),
+            (Completion::Throw(value), _) => panic!("uncaught exception {}", value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(statements: Vec<Statement>) -> Statement {
+        statements
+            .into_iter()
+            .rev()
+            .reduce(|second, first| Statement::Sequence {
+                first: Box::new(first),
+                second: Box::new(second),
+            })
+            .unwrap()
+    }
+    fn when(condition: Expression, consequence: Statement) -> Statement {
+        Statement::If {
+            condition,
+            consequence: Box::new(consequence),
+            alternative: Box::new(Statement::DoNothing),
+        }
+    }
+
+    fn profile_both(statement: &Statement) -> (Profile, Profile) {
+        let mut machine = Machine {
+            statement: statement.clone(),
+            environment: Environment::new(),
+        };
+        let small = machine.run_with_profile();
+        let (environment, big) = statement.evaluate_with_profile(&mut Environment::new());
+        assert_eq!(
+            crate::machine::format_environment(&machine.environment),
+            crate::machine::format_environment(&environment)
+        );
+        (small, big)
+    }
+
+    fn counting_loop() -> Statement {
+        sequence(vec![
+            assign("x", number(0)),
+            Statement::While {
+                condition: less_than(variable("x"), number(3)),
+                body: Box::new(assign("x", add(variable("x"), number(1)))),
+            },
+            when(less_than(number(5), variable("x")), assign("y", number(1))),
+        ])
+    }
+
+    #[test]
+    fn nodes_test() {
+        let statement = counting_loop();
+        let nodes = statement
+            .nodes()
+            .iter()
+            .map(|node| node.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(nodes[1], "x = 0");
+        assert_eq!(nodes[4], "while (x < 3), { x = x + 1 }");
+        assert_eq!(nodes[8], "x = x + 1");
+        assert_eq!(nodes[12], "if (5 < x) { y = 1 } else { do-nothing }");
+        assert_eq!(nodes.len(), 19);
+    }
+
+    #[test]
+    fn loop_test() {
+        let statement = counting_loop();
+        let (small, big) = profile_both(&statement);
+        assert_eq!(small.executions, big.executions);
+        assert!(big.steps.is_empty());
+        // whileは条件を調べるたび、本体は回るたびに数える
+        assert_eq!(small.execution_count(4), 4);
+        assert_eq!(small.execution_count(8), 3);
+        assert_eq!(small.execution_count(16), 0);
+        // 展開、展開したifの選択、本体の後とbreakでループを進める簡約はwhileのステップになる
+        assert_eq!(small.step_count(4), 4 + 4 + 3 + 1);
+        assert_eq!(small.step_count(8), 3);
+        // ステップの合計は簡約の回数と同じ
+        let mut reductions = 0;
+        let (mut current, mut environment) = (Running::from(statement), Environment::new());
+        while current.is_reducible() {
+            let (next, next_env) = current.reduce(&mut environment);
+            current = next;
+            environment = next_env;
+            reductions += 1;
+        }
+        assert_eq!(small.steps.values().sum::<usize>(), reductions);
+    }
+
+    #[test]
+    fn listing_test() {
+        let statement = counting_loop();
+        let mut machine = Machine {
+            statement: statement.clone(),
+            environment: Environment::new(),
+        };
+        let profile = machine.run_with_profile();
+        assert_eq!(
+            profile.listing(&statement),
+            [
+                "  runs  steps | source",
+                "     1      1 | x = 0",
+                "     4     20 | while (x < 3) {",
+                "     3      9 |   x = x + 1",
+                "              | }",
+                "     1      3 | if (5 < x) {",
+                " #####      0 |   y = 1",
+                "              | } else {",
+                "     1      0 |   do-nothing",
+                "              | }",
+                ""
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn agreement_test() {
+        // 中断、スコープ、タプル、関数を含むプログラムでも、小ステップと大ステップで実行回数がそろう
+        let increment = Expression::Function {
+            params: vec!["n".to_string()],
+            body: Box::new(add(variable("n"), number(1))),
+        };
+        let body = sequence(vec![
+            Statement::Let {
+                name: "t".to_string(),
+                expression: Expression::Tuple(vec![variable("i"), number(2)]),
+                body: Box::new(assign(
+                    "i",
+                    Expression::Call {
+                        function: Box::new(variable("increment")),
+                        arguments: vec![Expression::Field {
+                            expression: Box::new(variable("t")),
+                            name: "0".to_string(),
+                        }],
+                    },
+                )),
+            },
+            when(less_than(variable("i"), number(2)), Statement::Continue),
+            Statement::Try {
+                body: Box::new(when(
+                    less_than(number(3), variable("i")),
+                    Statement::Throw {
+                        expression: variable("i"),
+                    },
+                )),
+                name: "e".to_string(),
+                handler: Box::new(Statement::Break),
+            },
+            Statement::Block {
+                body: Box::new(Statement::Var {
+                    name: "s".to_string(),
+                    expression: variable("i"),
+                }),
+            },
+        ]);
+        let statement = sequence(vec![
+            assign("increment", increment),
+            assign("i", number(0)),
+            Statement::While {
+                condition: Expression::Boolean(true),
+                body: Box::new(body),
+            },
+            Statement::DoNothing,
+        ]);
+        let (small, big) = profile_both(&statement);
+        assert_eq!(small.executions, big.executions);
+        let runs = |text: &str| {
+            let id = statement
+                .nodes()
+                .iter()
+                .position(|node| node.to_string() == text)
+                .unwrap();
+            small.execution_count(id)
+        };
+        assert_eq!(runs("continue"), 1);
+        assert_eq!(runs("break"), 1);
+        assert_eq!(runs("var s = i"), 2);
+    }
+
+    #[test]
+    fn json_test() {
+        let statement = when(
+            Expression::Boolean(false),
+            assign("x", Expression::Variable("\"y\"".to_string())),
+        );
+        let (environment, profile) = statement.evaluate_with_profile(&mut Environment::new());
+        assert!(environment.is_empty());
+        assert_eq!(
+            profile.to_json(&statement),
+            [
+                "{",
+                "  \"nodes\": [",
+                "    {\"id\": 0, \"parent\": null, \"kind\": \"statement\", \"source\": \"if (false) { x = \\\"y\\\" } else { do-nothing }\", \"executions\": 1, \"steps\": 0},",
+                "    {\"id\": 1, \"parent\": 0, \"kind\": \"expression\", \"source\": \"false\", \"executions\": 1, \"steps\": 0},",
+                "    {\"id\": 2, \"parent\": 0, \"kind\": \"statement\", \"source\": \"x = \\\"y\\\"\", \"executions\": 0, \"steps\": 0},",
+                "    {\"id\": 3, \"parent\": 2, \"kind\": \"expression\", \"source\": \"\\\"y\\\"\", \"executions\": 0, \"steps\": 0},",
+                "    {\"id\": 4, \"parent\": 0, \"kind\": \"statement\", \"source\": \"do-nothing\", \"executions\": 1, \"steps\": 0}",
+                "  ]",
+                "}",
+                ""
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::BTreeSet;
+
+use crate::expression::{Environment, Expression};
+
+// タプルとレコードの値を扱う
+
+impl Expression {
+    pub fn field(&self, name: &str) -> Expression {
+        if !matches!(self, Expression::Tuple(_) | Expression::Record(_)) {
+            panic!("expression is not tuple or record");
+        }
+        match self.checked_field(name) {
+            Some(value) => value,
+            None => panic!("undefined field {}", name),
+        }
+    }
+    // タプルやレコードでないか、フィールドがなければNone
+    pub fn checked_field(&self, name: &str) -> Option<Expression> {
+        let value = match self {
+            Expression::Tuple(elements) => name.parse::<usize>().ok().and_then(|i| elements.get(i)),
+            Expression::Record(fields) => fields.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        };
+        value.cloned()
+    }
+    // nameのフィールドだけを置き換えた値
+    pub fn with_field(&self, name: &str, value: Expression) -> Expression {
+        self.field(name);
+        match self {
+            Expression::Tuple(elements) => {
+                let mut elements = elements.clone();
+                elements[name.parse::<usize>().unwrap()] = value;
+                Expression::Tuple(elements)
+            }
+            Expression::Record(fields) => {
+                let mut fields = fields.clone();
+                for (n, v) in fields.iter_mut() {
+                    if n == name {
+                        *v = value.clone();
+                    }
+                }
+                Expression::Record(fields)
+            }
+            _ => unreachable!(),
+        }
+    }
+    // p.a.b = v のように、フィールドをたどった先を置き換える
+    pub fn update(&self, fields: &[String], value: Expression) -> Expression {
+        match fields.split_first() {
+            None => value,
+            Some((name, rest)) => self.with_field(name, self.field(name).update(rest, value)),
+        }
+    }
+    pub fn checked_update(&self, fields: &[String], value: Expression) -> Option<Expression> {
+        match fields.split_first() {
+            None => Some(value),
+            Some((name, rest)) => {
+                let updated = self.checked_field(name)?.checked_update(rest, value)?;
+                Some(self.with_field(name, updated))
+            }
+        }
+    }
+    // 構造が同じなら等しい。レコードはフィールドの順番によらない
+    pub fn is_equal(&self, other: &Expression) -> bool {
+        match (self, other) {
+            (Expression::Tuple(left), Expression::Tuple(right)) => {
+                left.len() == right.len() && left.iter().zip(right).all(|(l, r)| l.is_equal(r))
+            }
+            // 同じ名前のフィールドが並んでいれば、fieldと同じく最初のものを見る
+            (Expression::Record(left), Expression::Record(right)) => {
+                let names = |fields: &[(String, Expression)]| {
+                    fields
+                        .iter()
+                        .map(|(n, _)| n.clone())
+                        .collect::<BTreeSet<_>>()
+                };
+                let left_names = names(left);
+                left_names == names(right)
+                    && left_names
+                        .iter()
+                        .all(|name| self.field(name).is_equal(&other.field(name)))
+            }
+            _ => self == other,
+        }
+    }
+}
+
+pub fn update_variable(
+    environment: &mut Environment,
+    name: &str,
+    fields: &[String],
+    value: Expression,
+) {
+    let updated = match environment.get(name) {
+        Some(current) => current.update(fields, value),
+        None => panic!("undefined variable"),
+    };
+    environment.insert(name.to_string(), updated);
+}
+
+// Rubyでは、タプルは配列、レコードはシンボルをキーにしたハッシュにする
+pub fn ruby_key(name: &str) -> String {
+    if name.parse::<usize>().is_ok() {
+        name.to_string()
+    } else {
+        format!(":{}", name)
+    }
+}
+
+pub fn ruby_update(target: &str, fields: &[String], value: &str) -> String {
+    match fields.split_first() {
+        None => value.to_string(),
+        Some((name, rest)) => {
+            let key = ruby_key(name);
+            format!(
+                "{}.dup.tap {{ |r| r[{}] = {} }}",
+                target,
+                key,
+                ruby_update(&format!("{}[{}]", target, key), rest, value)
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::format_environment;
+    use crate::running::Running;
+    use crate::statement::Statement;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn record(fields: &[(&str, Expression)]) -> Expression {
+        Expression::Record(
+            fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+        )
+    }
+    fn field(expression: Expression, name: &str) -> Expression {
+        Expression::Field {
+            expression: Box::new(expression),
+            name: name.to_string(),
+        }
+    }
+    fn equal(left: Expression, right: Expression) -> Expression {
+        Expression::Equal {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn update(name: &str, fields: &[&str], expression: Expression) -> Statement {
+        Statement::Update {
+            name: name.to_string(),
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+
+    fn reduce_all(expression: &Expression, environment: &Environment) -> Expression {
+        let mut expression = expression.clone();
+        while expression.is_reducible() {
+            expression = expression.reduce(environment);
+        }
+        expression
+    }
+
+    #[test]
+    fn expression_test() {
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(3));
+        let point = record(&[
+            ("x", add(variable("x"), number(1))),
+            ("y", Expression::Tuple(vec![number(5), variable("x")])),
+        ]);
+        assert_eq!(point.to_string(), "{ x: x + 1, y: (5, x) }");
+        assert!(point.is_reducible());
+        let expression = add(field(point.clone(), "x"), field(field(point, "y"), "1"));
+        assert_eq!(
+            expression.to_string(),
+            "{ x: x + 1, y: (5, x) }.x + { x: x + 1, y: (5, x) }.y.1"
+        );
+        assert_eq!(reduce_all(&expression, &environment), number(7));
+        assert_eq!(expression.evaluate(&environment), number(7));
+    }
+
+    #[test]
+    fn equal_test() {
+        let environment = Environment::new();
+        let left = record(&[("a", number(1)), ("b", Expression::Tuple(vec![number(2)]))]);
+        let right = record(&[("b", Expression::Tuple(vec![number(2)])), ("a", number(1))]);
+        assert_eq!(
+            reduce_all(&equal(left.clone(), right.clone()), &environment),
+            Expression::Boolean(true)
+        );
+        let other = record(&[("a", number(1)), ("b", Expression::Tuple(vec![number(3)]))]);
+        assert_eq!(
+            equal(left, other).evaluate(&environment),
+            Expression::Boolean(false)
+        );
+        assert_eq!(
+            equal(Expression::Tuple(vec![number(1)]), number(1)).evaluate(&environment),
+            Expression::Boolean(false)
+        );
+        // 名前が重なっていても、どちらから比べても同じ結果になる
+        let duplicated = record(&[("a", number(1)), ("a", number(2))]);
+        let other = record(&[("a", number(1)), ("b", number(2))]);
+        assert!(!duplicated.is_equal(&other));
+        assert!(!other.is_equal(&duplicated));
+        let single = record(&[("a", number(1))]);
+        assert_eq!(duplicated.is_equal(&single), single.is_equal(&duplicated));
+    }
+
+    #[test]
+    fn update_test() {
+        let statement = sequence(
+            Statement::Assignment {
+                name: "p".to_string(),
+                expression: record(&[
+                    ("name", number(1)),
+                    ("position", Expression::Tuple(vec![number(0), number(0)])),
+                ]),
+            },
+            sequence(
+                update(
+                    "p",
+                    &["position", "1"],
+                    add(field(variable("p"), "name"), number(4)),
+                ),
+                update("p", &["name"], number(2)),
+            ),
+        );
+        assert_eq!(
+            statement.to_string(),
+            "p = { name: 1, position: (0, 0) }; p.position.1 = p.name + 4; p.name = 2"
+        );
+        let mut small = Running::from(statement.clone());
+        let mut environment = Environment::new();
+        while small.is_reducible() {
+            let (next, next_env) = small.reduce(&mut environment);
+            small = next;
+            environment = next_env;
+        }
+        let expected = "{\"p\": Record([(\"name\", Number(2)), (\"position\", Tuple([Number(0), Number(5)]))])}";
+        assert_eq!(format_environment(&environment), expected);
+        assert_eq!(
+            format_environment(&statement.evaluate(&mut Environment::new())),
+            expected
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "undefined field z")]
+    fn undefined_field_test() {
+        let mut environment = Environment::new();
+        environment.insert("p".to_string(), record(&[("x", number(1))]));
+        update("p", &["z"], number(2)).evaluate(&mut environment);
+    }
+
+    #[test]
+    fn to_ruby_test() {
+        assert_eq!(
+            field(Expression::Tuple(vec![number(1), number(2)]), "1").to_ruby(),
+            "-> e { (-> e { [(-> e { 1 }).call(e), (-> e { 2 }).call(e)] }).call(e)[1] }"
+        );
+        assert_eq!(
+            update("p", &["a", "0"], number(3)).to_ruby().unwrap(),
+            "-> e { v = (-> e { 3 }).call(e); e.merge({ :p => e[:p].dup.tap { |r| r[:a] = e[:p][:a].dup.tap { |r| r[0] = v } } }) }"
+        );
+    }
+}
@@ -0,0 +1,443 @@
+use std::fmt;
+
+use crate::expression::{Environment, Expression};
+use crate::profile::{within, Node, Recorder, Shape};
+use crate::record::update_variable;
+use crate::scope::{restore, save};
+use crate::statement::Statement;
+
+// 小ステップ意味論で実行中の文。実行中のループのように、プログラムには書けない形を持つ。
+// 子に実行中の形がなければStatementにまとめるので、同じ状態はいつも同じ形になる
+#[derive(Clone, Debug)]
+pub enum Running {
+    Statement(Statement),
+    Sequence {
+        first: Box<Running>,
+        second: Box<Statement>,
+    },
+    Parallel {
+        left: Box<Running>,
+        right: Box<Running>,
+    },
+    Try {
+        body: Box<Running>,
+        name: String,
+        handler: Box<Statement>,
+    },
+    // 実行中のループ本体。終わるかcontinueでnextに、breakでループの外に出る
+    Loop {
+        body: Box<Running>,
+        next: Box<Statement>,
+    },
+    // 実行中のスコープ。出るときにsavedの値に戻す(Noneなら消す)
+    Scope {
+        saved: Vec<(String, Option<Expression>)>,
+        body: Box<Running>,
+    },
+}
+
+impl fmt::Display for Running {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Running::Statement(statement) => write!(f, "{}", statement),
+            Running::Sequence { first, second } => write!(f, "{}; {}", first, second),
+            Running::Parallel { left, right } => write!(f, "{{ {} }} || {{ {} }}", left, right),
+            Running::Try {
+                body,
+                name,
+                handler,
+            } => write!(f, "try {{ {} }} catch ({}) {{ {} }}", body, name, handler),
+            Running::Loop { body, next } => write!(f, "loop {{ {} }}; {}", body, next),
+            Running::Scope { body, .. } => write!(f, "scope {{ {} }}", body),
+        }
+    }
+}
+
+impl From<Statement> for Running {
+    fn from(statement: Statement) -> Running {
+        Running::Statement(statement)
+    }
+}
+
+impl Running {
+    pub fn sequence(first: Running, second: Box<Statement>) -> Running {
+        match first {
+            Running::Statement(first) => Running::Statement(Statement::Sequence {
+                first: Box::new(first),
+                second,
+            }),
+            first => Running::Sequence {
+                first: Box::new(first),
+                second,
+            },
+        }
+    }
+    pub fn parallel(left: Running, right: Running) -> Running {
+        match (left, right) {
+            (Running::Statement(left), Running::Statement(right)) => {
+                Running::Statement(Statement::Parallel {
+                    left: Box::new(left),
+                    right: Box::new(right),
+                })
+            }
+            (left, right) => Running::Parallel {
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+        }
+    }
+    pub fn try_catch(body: Running, name: String, handler: Box<Statement>) -> Running {
+        match body {
+            Running::Statement(body) => Running::Statement(Statement::Try {
+                body: Box::new(body),
+                name,
+                handler,
+            }),
+            body => Running::Try {
+                body: Box::new(body),
+                name,
+                handler,
+            },
+        }
+    }
+    // 子を持つ文を、子が実行中の形として開く。子の添字はStatementのときと同じ
+    pub fn open(&self) -> Option<Running> {
+        match self {
+            Running::Statement(Statement::Sequence { first, second }) => Some(Running::Sequence {
+                first: Box::new(Running::Statement(*first.clone())),
+                second: second.clone(),
+            }),
+            Running::Statement(Statement::Parallel { left, right }) => Some(Running::Parallel {
+                left: Box::new(Running::Statement(*left.clone())),
+                right: Box::new(Running::Statement(*right.clone())),
+            }),
+            Running::Statement(Statement::Try {
+                body,
+                name,
+                handler,
+            }) => Some(Running::Try {
+                body: Box::new(Running::Statement(*body.clone())),
+                name: name.clone(),
+                handler: handler.clone(),
+            }),
+            _ => None,
+        }
+    }
+    // 止まったときはいつもStatementにまとまっている
+    pub fn into_statement(self) -> Statement {
+        match self {
+            Running::Statement(statement) => statement,
+            running => panic!("statement is still running: {}", running),
+        }
+    }
+    pub fn is_reducible(&self) -> bool {
+        match self {
+            Running::Statement(statement) => statement.is_reducible(),
+            _ => true,
+        }
+    }
+    pub fn is_abrupt(&self) -> bool {
+        match self {
+            Running::Statement(statement) => statement.is_abrupt(),
+            _ => false,
+        }
+    }
+    fn is_done(&self) -> bool {
+        matches!(self, Running::Statement(Statement::DoNothing))
+    }
+    pub fn reduce(&self, environment: &mut Environment) -> (Running, Environment) {
+        self.reduce_with_recorder(environment, &mut ())
+    }
+    pub fn reduce_with_recorder(
+        &self,
+        environment: &mut Environment,
+        recorder: &mut dyn Recorder,
+    ) -> (Running, Environment) {
+        if let Some(opened) = self.open() {
+            return opened.reduce_with_recorder(environment, recorder);
+        }
+        let mut reduce = |index: usize, running: &Running, environment: &mut Environment| {
+            within(recorder, index, |r| {
+                running.reduce_with_recorder(environment, r)
+            })
+        };
+        match self {
+            Running::Statement(statement) => reduce_statement(statement, environment, recorder),
+            Running::Sequence { first, second } => {
+                if first.is_done() {
+                    recorder.rule(&[(0, Node::Running(first))], Shape::Child(1));
+                    (Running::Statement(*second.clone()), environment.clone())
+                } else if first.is_abrupt() {
+                    recorder.rule(&[], Shape::Child(0));
+                    (*first.clone(), environment.clone())
+                } else {
+                    let (first, new_env) = reduce(0, first, environment);
+                    (Running::sequence(first, second.clone()), new_env)
+                }
+            }
+            Running::Parallel { left, right } => {
+                if left.is_done() {
+                    recorder.rule(&[(0, Node::Running(left))], Shape::Child(1));
+                    (*right.clone(), environment.clone())
+                } else if left.is_abrupt() {
+                    recorder.rule(&[], Shape::Child(0));
+                    (*left.clone(), environment.clone())
+                } else {
+                    let (left, new_env) = reduce(0, left, environment);
+                    (Running::parallel(left, *right.clone()), new_env)
+                }
+            }
+            Running::Try {
+                body,
+                name,
+                handler,
+            } => match **body {
+                Running::Statement(Statement::DoNothing) => {
+                    recorder.rule(&[(0, Node::Running(body))], Shape::Derived(vec![]));
+                    (
+                        Running::Statement(Statement::DoNothing),
+                        environment.clone(),
+                    )
+                }
+                Running::Statement(Statement::Throw { ref expression }) if body.is_abrupt() => {
+                    recorder.rule(
+                        &[(0, Node::Running(body))],
+                        Shape::Derived(vec![Shape::Derived(vec![]), Shape::Child(1)]),
+                    );
+                    let handler = Statement::Sequence {
+                        first: Box::new(Statement::Assignment {
+                            name: name.clone(),
+                            expression: expression.clone(),
+                        }),
+                        second: handler.clone(),
+                    };
+                    (Running::Statement(handler), environment.clone())
+                }
+                _ if body.is_abrupt() => {
+                    recorder.rule(&[], Shape::Child(0));
+                    (*body.clone(), environment.clone())
+                }
+                _ => {
+                    let (body, new_env) = reduce(0, body, environment);
+                    (
+                        Running::try_catch(body, name.clone(), handler.clone()),
+                        new_env,
+                    )
+                }
+            },
+            Running::Loop { body, next } => match **body {
+                Running::Statement(Statement::DoNothing)
+                | Running::Statement(Statement::Continue) => {
+                    recorder.rule(&[(0, Node::Running(body))], Shape::Child(1));
+                    (Running::Statement(*next.clone()), environment.clone())
+                }
+                Running::Statement(Statement::Break) => {
+                    recorder.rule(&[(0, Node::Running(body))], Shape::Derived(vec![]));
+                    (
+                        Running::Statement(Statement::DoNothing),
+                        environment.clone(),
+                    )
+                }
+                _ if body.is_abrupt() => {
+                    recorder.rule(&[], Shape::Child(0));
+                    (*body.clone(), environment.clone())
+                }
+                _ => {
+                    let (body, new_env) = reduce(0, body, environment);
+                    (
+                        Running::Loop {
+                            body: Box::new(body),
+                            next: next.clone(),
+                        },
+                        new_env,
+                    )
+                }
+            },
+            Running::Scope { saved, body } => {
+                if body.is_reducible() {
+                    let (body, new_env) = reduce(0, body, environment);
+                    let scope = Running::Scope {
+                        saved: saved.clone(),
+                        body: Box::new(body),
+                    };
+                    (scope, new_env)
+                } else {
+                    recorder.rule(&[], Shape::Child(0));
+                    let mut new_env = environment.clone();
+                    restore(saved, &mut new_env);
+                    (*body.clone(), new_env)
+                }
+            }
+        }
+    }
+}
+
+// 子を持たない文と、式を簡約する文
+fn reduce_statement(
+    statement: &Statement,
+    environment: &mut Environment,
+    recorder: &mut dyn Recorder,
+) -> (Running, Environment) {
+    let done = |environment: Environment| (Running::Statement(Statement::DoNothing), environment);
+    let mut reduce = |expression: &Expression| {
+        within(recorder, 0, |r| {
+            expression.reduce_with_recorder(environment, r)
+        })
+    };
+    match statement {
+        Statement::Assignment { name, expression } => {
+            if expression.is_reducible() {
+                let assignment = Statement::Assignment {
+                    name: name.clone(),
+                    expression: reduce(expression),
+                };
+                (assignment.into(), environment.clone())
+            } else {
+                recorder.rule(&[(0, Node::Expression(expression))], Shape::Derived(vec![]));
+                let mut new_env = environment.clone();
+                new_env.insert(String::from(name), expression.clone());
+                done(new_env)
+            }
+        }
+        Statement::Update {
+            name,
+            fields,
+            expression,
+        } => {
+            if expression.is_reducible() {
+                let update = Statement::Update {
+                    name: name.clone(),
+                    fields: fields.clone(),
+                    expression: reduce(expression),
+                };
+                (update.into(), environment.clone())
+            } else {
+                recorder.rule(&[(0, Node::Expression(expression))], Shape::Derived(vec![]));
+                let mut new_env = environment.clone();
+                update_variable(&mut new_env, name, fields, expression.clone());
+                done(new_env)
+            }
+        }
+        Statement::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            if condition.is_reducible() {
+                let statement = Statement::If {
+                    condition: reduce(condition),
+                    consequence: consequence.clone(),
+                    alternative: alternative.clone(),
+                };
+                return (statement.into(), environment.clone());
+            }
+            let (index, branch) = match condition {
+                Expression::Boolean(true) => (1, consequence),
+                Expression::Boolean(false) => (2, alternative),
+                _ => panic!("condition is not bool"),
+            };
+            recorder.rule(&[(0, Node::Expression(condition))], Shape::Child(index));
+            (Running::Statement(*branch.clone()), environment.clone())
+        }
+        // 条件が偽ならbreakでループを出る
+        Statement::While { condition, body } => {
+            recorder.rule(
+                &[],
+                Shape::Derived(vec![
+                    Shape::Derived(vec![
+                        Shape::Child(0),
+                        Shape::Child(1),
+                        Shape::Derived(vec![]),
+                    ]),
+                    Shape::Same,
+                ]),
+            );
+            let body = Statement::If {
+                condition: condition.clone(),
+                consequence: body.clone(),
+                alternative: Box::new(Statement::Break),
+            };
+            (
+                Running::Loop {
+                    body: Box::new(body.into()),
+                    next: Box::new(statement.clone()),
+                },
+                environment.clone(),
+            )
+        }
+        // 決定的にするため、reduceとevaluateはいつも左を選ぶ
+        Statement::Choose { first, .. } => {
+            recorder.rule(&[], Shape::Child(0));
+            (Running::Statement(*first.clone()), environment.clone())
+        }
+        Statement::Assert { condition } => {
+            if condition.is_reducible() {
+                let assertion = Statement::Assert {
+                    condition: reduce(condition),
+                };
+                return (assertion.into(), environment.clone());
+            }
+            match condition {
+                Expression::Boolean(true) => {
+                    recorder.rule(&[(0, Node::Expression(condition))], Shape::Derived(vec![]));
+                    done(environment.clone())
+                }
+                Expression::Boolean(false) => panic!("assertion failed"),
+                _ => panic!("condition is not bool"),
+            }
+        }
+        Statement::Throw { expression } => {
+            let throw = Statement::Throw {
+                expression: reduce(expression),
+            };
+            (throw.into(), environment.clone())
+        }
+        Statement::Let {
+            name,
+            expression,
+            body,
+        } => {
+            if expression.is_reducible() {
+                let statement = Statement::Let {
+                    name: name.clone(),
+                    expression: reduce(expression),
+                    body: body.clone(),
+                };
+                return (statement.into(), environment.clone());
+            }
+            recorder.rule(
+                &[(0, Node::Expression(expression))],
+                Shape::Derived(vec![Shape::Child(1)]),
+            );
+            let saved = save(std::slice::from_ref(name), environment);
+            let mut new_env = environment.clone();
+            new_env.insert(name.clone(), expression.clone());
+            let scope = Running::Scope {
+                saved,
+                body: Box::new(Running::Statement(*body.clone())),
+            };
+            (scope, new_env)
+        }
+        Statement::Block { body } => {
+            recorder.rule(&[], Shape::Derived(vec![Shape::Child(0)]));
+            let scope = Running::Scope {
+                saved: save(&body.hoisted_variables(), environment),
+                body: Box::new(Running::Statement(*body.clone())),
+            };
+            (scope, environment.clone())
+        }
+        Statement::Var { name, expression } => {
+            let assignment = Statement::Assignment {
+                name: name.clone(),
+                expression: expression.clone(),
+            };
+            reduce_statement(&assignment, environment, recorder)
+        }
+        Statement::DoNothing
+        | Statement::Break
+        | Statement::Continue
+        | Statement::Sequence { .. }
+        | Statement::Parallel { .. }
+        | Statement::Try { .. } => unreachable!(),
+    }
+}
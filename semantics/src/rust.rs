@@ -0,0 +1,363 @@
+use crate::expression::{Environment, Expression};
+use crate::statement::Statement;
+
+// to_rubyと同じく、式は環境を受け取って値を返すクロージャ、
+// 文は環境を受け取って新しい環境を返すクロージャにする
+const PRELUDE: &str = r#"use std::collections::BTreeMap;
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Number(u32),
+    Boolean(bool),
+}
+
+type Env = BTreeMap<String, Value>;
+
+#[allow(dead_code)]
+fn get(e: &Env, name: &str) -> Value {
+    e.get(name).cloned().expect("undefined variable")
+}
+
+#[allow(dead_code)]
+fn number(value: Value) -> u32 {
+    match value {
+        Value::Number(n) => n,
+        _ => panic!("expression is not number"),
+    }
+}
+
+#[allow(dead_code)]
+fn boolean(value: Value) -> bool {
+    match value {
+        Value::Boolean(b) => b,
+        _ => panic!("condition is not bool"),
+    }
+}
+"#;
+
+fn rust_value(value: &Expression) -> String {
+    match value {
+        Expression::Number(n) => format!("Value::Number({})", n),
+        Expression::Boolean(b) => format!("Value::Boolean({})", b),
+        _ => unreachable!(),
+    }
+}
+
+impl Expression {
+    pub fn to_rust(&self) -> Result<String, String> {
+        self.check_supported(&[])?;
+        Ok(self.rust())
+    }
+    fn rust(&self) -> String {
+        match self {
+            Expression::Number(_) | Expression::Boolean(_) => {
+                format!("(|_: &Env| {})", rust_value(self))
+            }
+            Expression::Variable(name) => format!("(|e: &Env| get(e, {:?}))", name),
+            // ビルドの設定によらず、evaluateと同じく桁あふれしたら止める
+            Expression::Add { left, right } => format!(
+                "(|e: &Env| Value::Number(number({}(e)).checked_add(number({}(e))).expect(\"overflow\")))",
+                left.rust(),
+                right.rust()
+            ),
+            Expression::Multiply { left, right } => format!(
+                "(|e: &Env| Value::Number(number({}(e)).checked_mul(number({}(e))).expect(\"overflow\")))",
+                left.rust(),
+                right.rust()
+            ),
+            Expression::LessThan { left, right } => format!(
+                "(|e: &Env| Value::Boolean(number({}(e)) < number({}(e))))",
+                left.rust(),
+                right.rust()
+            ),
+            Expression::Equal { left, right } => format!(
+                "(|e: &Env| Value::Boolean({}(e) == {}(e)))",
+                left.rust(),
+                right.rust()
+            ),
+            Expression::Tuple(_)
+            | Expression::Record(_)
+            | Expression::Field { .. }
+            | Expression::Function { .. }
+            | Expression::Closure { .. }
+            | Expression::Call { .. } => unreachable!(),
+        }
+    }
+}
+
+impl Statement {
+    pub fn to_rust(&self) -> Result<String, String> {
+        self.check_supported(&[])?;
+        Ok(self.rust())
+    }
+    fn rust(&self) -> String {
+        match self {
+            Statement::DoNothing => "(|e: Env| e)".to_string(),
+            Statement::Choose { .. }
+            | Statement::Parallel { .. }
+            | Statement::Assert { .. }
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Throw { .. }
+            | Statement::Try { .. }
+            | Statement::Let { .. }
+            | Statement::Block { .. }
+            | Statement::Var { .. }
+            | Statement::Update { .. } => unreachable!(),
+            Statement::Assignment { name, expression } => format!(
+                "(|mut e: Env| {{ let value = {}(&e); e.insert({:?}.to_string(), value); e }})",
+                expression.rust(),
+                name
+            ),
+            Statement::If {
+                condition,
+                consequence,
+                alternative,
+            } => format!(
+                "(|e: Env| if boolean({}(&e)) {{ {}(e) }} else {{ {}(e) }})",
+                condition.rust(),
+                consequence.rust(),
+                alternative.rust()
+            ),
+            Statement::Sequence { first, second } => {
+                format!("(|e: Env| {}({}(e)))", second.rust(), first.rust())
+            }
+            Statement::While { condition, body } => format!(
+                "(|mut e: Env| {{ while boolean({}(&e)) {{ e = {}(e); }} e }})",
+                condition.rust(),
+                body.rust()
+            ),
+        }
+    }
+    // 実行して最後の環境をMachineと同じ形式で出力するプログラム
+    pub fn to_rust_program(&self, environment: &Environment) -> Result<String, String> {
+        let program = self.to_rust()?;
+        let mut initial = environment.iter().collect::<Vec<_>>();
+        initial.sort_by(|a, b| a.0.cmp(b.0));
+        let mut lines = vec![PRELUDE.to_string(), "fn main() {".to_string()];
+        lines.push(if initial.is_empty() {
+            "    let e = Env::new();".to_string()
+        } else {
+            "    let mut e = Env::new();".to_string()
+        });
+        for (name, value) in initial {
+            match value {
+                Expression::Number(_) | Expression::Boolean(_) => lines.push(format!(
+                    "    e.insert({:?}.to_string(), {});",
+                    name,
+                    rust_value(value)
+                )),
+                _ => {
+                    return Err(format!(
+                        "environment value of {} is not number or bool: {}",
+                        name, value
+                    ))
+                }
+            }
+        }
+        lines.push(format!("    let e = {}(e);", program));
+        lines.push(format!(
+            "    println!(\"{}, {{:?}}\", e);",
+            Statement::DoNothing
+        ));
+        lines.push("}".to_string());
+        Ok(lines.join("\n") + "\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::format_environment;
+    use std::fs;
+    use std::process::{Command, Output};
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn multiply(left: Expression, right: Expression) -> Expression {
+        Expression::Multiply {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn equal(left: Expression, right: Expression) -> Expression {
+        Expression::Equal {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+
+    // rustcでビルドして実行する
+    fn compile_and_run(test_name: &str, source: &str) -> Output {
+        let directory =
+            std::env::temp_dir().join(format!("simple-rust-{}-{}", std::process::id(), test_name));
+        fs::create_dir_all(&directory).unwrap();
+        let source_path = directory.join("program.rs");
+        let binary_path = directory.join("program");
+        fs::write(&source_path, source).unwrap();
+        let status = Command::new("rustc")
+            .args(["--edition", "2018", "-D", "warnings", "-o"])
+            .arg(&binary_path)
+            .arg(&source_path)
+            .status()
+            .expect("rustc not found");
+        assert!(status.success(), "{}", source);
+        let output = Command::new(&binary_path).output().unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+        output
+    }
+
+    fn assert_same_as_evaluate(test_name: &str, statement: &Statement, environment: &Environment) {
+        let source = statement.to_rust_program(environment).unwrap();
+        let expected = statement.evaluate(&mut environment.clone());
+        let output = compile_and_run(test_name, &source);
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            format!(
+                "{}, {}\n",
+                Statement::DoNothing,
+                format_environment(&expected)
+            )
+        );
+    }
+
+    #[test]
+    fn expression_test() {
+        assert_eq!(
+            number(5).to_rust(),
+            Ok("(|_: &Env| Value::Number(5))".to_string())
+        );
+        assert_eq!(
+            add(variable("x"), number(1)).to_rust(),
+            Ok("(|e: &Env| Value::Number(number((|e: &Env| get(e, \"x\"))(e)).checked_add(number((|_: &Env| Value::Number(1))(e))).expect(\"overflow\")))".to_string())
+        );
+    }
+
+    #[test]
+    fn equal_test() {
+        // 真偽値どうし、数と真偽値も比べられる
+        let statement = sequence(
+            assign("b", equal(variable("x"), number(3))),
+            sequence(
+                assign(
+                    "c",
+                    equal(variable("b"), less_than(variable("x"), number(5))),
+                ),
+                assign("d", equal(variable("x"), Expression::Boolean(true))),
+            ),
+        );
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(3));
+        assert_same_as_evaluate("equal", &statement, &environment);
+    }
+
+    #[test]
+    fn if_test() {
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), Expression::Boolean(false));
+        let statement = Statement::If {
+            condition: variable("x"),
+            consequence: Box::new(assign("y", number(1))),
+            alternative: Box::new(assign("y", number(2))),
+        };
+        assert_same_as_evaluate("if", &statement, &environment);
+    }
+
+    #[test]
+    fn while_test() {
+        // 階乗
+        let statement = sequence(
+            assign("result", number(1)),
+            sequence(
+                assign("i", number(1)),
+                Statement::While {
+                    condition: less_than(variable("i"), add(variable("n"), number(1))),
+                    body: Box::new(sequence(
+                        assign("result", multiply(variable("result"), variable("i"))),
+                        sequence(
+                            assign("i", add(variable("i"), number(1))),
+                            assign("done", less_than(variable("n"), variable("i"))),
+                        ),
+                    )),
+                },
+            ),
+        );
+        let mut environment = Environment::new();
+        environment.insert("n".to_string(), number(10));
+        assert_same_as_evaluate("while", &statement, &environment);
+    }
+
+    #[test]
+    fn undefined_variable_test() {
+        let source = assign("x", variable("y"))
+            .to_rust_program(&Environment::new())
+            .unwrap();
+        let output = compile_and_run("undefined", &source);
+        assert!(!output.status.success());
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("undefined variable"));
+    }
+
+    #[test]
+    fn overflow_test() {
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(u32::MAX));
+        for (test_name, expression) in [
+            ("add_overflow", add(variable("x"), number(1))),
+            ("multiply_overflow", multiply(variable("x"), number(2))),
+        ] {
+            let source = assign("y", expression)
+                .to_rust_program(&environment)
+                .unwrap();
+            let output = compile_and_run(test_name, &source);
+            assert!(!output.status.success());
+            assert!(String::from_utf8(output.stderr)
+                .unwrap()
+                .contains("overflow"));
+        }
+    }
+
+    #[test]
+    fn environment_error_test() {
+        let mut environment = Environment::new();
+        environment.insert(
+            "p".to_string(),
+            Expression::Tuple(vec![number(1), number(2)]),
+        );
+        assert_eq!(
+            assign("x", number(1)).to_rust_program(&environment),
+            Err("environment value of p is not number or bool: (1, 2)".to_string())
+        );
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::expression::Environment;
+use crate::statement::Statement;
+
+// 名前ごとに元の値を覚えておき、ブロックを出るときに戻す。
+// 遅延評価ではサンクの番号を覚えるので、値の型は決めない
+pub fn save<T: Clone>(
+    names: &[String],
+    environment: &HashMap<String, T>,
+) -> Vec<(String, Option<T>)> {
+    names
+        .iter()
+        .map(|name| (name.clone(), environment.get(name).cloned()))
+        .collect()
+}
+
+pub fn restore<T: Clone>(saved: &[(String, Option<T>)], environment: &mut HashMap<String, T>) {
+    for (name, value) in saved {
+        match value {
+            Some(value) => environment.insert(name.clone(), value.clone()),
+            None => environment.remove(name),
+        };
+    }
+}
+
+impl Statement {
+    // このブロックに属するvar。内側のブロックのものは含めない
+    pub fn hoisted_variables(&self) -> Vec<String> {
+        fn collect(statement: &Statement, names: &mut Vec<String>) {
+            match statement {
+                Statement::Var { name, .. } => {
+                    if !names.contains(name) {
+                        names.push(name.clone());
+                    }
+                }
+                Statement::DoNothing
+                | Statement::Assignment { .. }
+                | Statement::Update { .. }
+                | Statement::Assert { .. }
+                | Statement::Break
+                | Statement::Continue
+                | Statement::Throw { .. }
+                | Statement::Block { .. } => {}
+                Statement::If {
+                    consequence: first,
+                    alternative: second,
+                    ..
+                }
+                | Statement::Sequence { first, second }
+                | Statement::Choose { first, second }
+                | Statement::Parallel {
+                    left: first,
+                    right: second,
+                }
+                | Statement::Try {
+                    body: first,
+                    handler: second,
+                    ..
+                } => {
+                    collect(first, names);
+                    collect(second, names);
+                }
+                Statement::While { body, .. } | Statement::Let { body, .. } => collect(body, names),
+            }
+        }
+        let mut names = Vec::new();
+        collect(self, &mut names);
+        names
+    }
+    // strictモードでは、宣言されていない変数に代入できない。
+    // 最初の環境にある変数と一番外側のvarは宣言されているものとする
+    pub fn strict_errors(&self, environment: &Environment) -> Vec<String> {
+        let mut declared = environment.keys().cloned().collect::<BTreeSet<_>>();
+        declared.extend(self.hoisted_variables());
+        let mut errors = Vec::new();
+        self.check_declared(&declared, &mut errors);
+        errors
+    }
+    pub fn evaluate_strict(
+        &self,
+        environment: &mut Environment,
+    ) -> Result<Environment, Vec<String>> {
+        let errors = self.strict_errors(environment);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(self.evaluate(environment))
+    }
+    fn check_declared(&self, declared: &BTreeSet<String>, errors: &mut Vec<String>) {
+        let with = |names: Vec<String>| {
+            let mut inner = declared.clone();
+            inner.extend(names);
+            inner
+        };
+        match self {
+            Statement::Assignment { name, .. } | Statement::Update { name, .. } => {
+                if !declared.contains(name) {
+                    errors.push(format!("{}: {} is not declared", self, name));
+                }
+            }
+            Statement::DoNothing
+            | Statement::Assert { .. }
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Throw { .. }
+            | Statement::Var { .. } => {}
+            Statement::If {
+                consequence: first,
+                alternative: second,
+                ..
+            }
+            | Statement::Sequence { first, second }
+            | Statement::Choose { first, second }
+            | Statement::Parallel {
+                left: first,
+                right: second,
+            } => {
+                first.check_declared(declared, errors);
+                second.check_declared(declared, errors);
+            }
+            Statement::While { body, .. } => body.check_declared(declared, errors),
+            Statement::Try {
+                body,
+                name,
+                handler,
+            } => {
+                body.check_declared(declared, errors);
+                handler.check_declared(&with(vec![name.clone()]), errors);
+            }
+            Statement::Let { name, body, .. } => {
+                body.check_declared(&with(vec![name.clone()]), errors)
+            }
+            Statement::Block { body } => {
+                body.check_declared(&with(body.hoisted_variables()), errors)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expression::Expression;
+    use crate::machine::format_environment;
+    use crate::running::Running;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn var(name: &str, expression: Expression) -> Statement {
+        Statement::Var {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+    fn let_in(name: &str, expression: Expression, body: Statement) -> Statement {
+        Statement::Let {
+            name: name.to_string(),
+            expression,
+            body: Box::new(body),
+        }
+    }
+    fn block(body: Statement) -> Statement {
+        Statement::Block {
+            body: Box::new(body),
+        }
+    }
+
+    // 小ステップで最後まで進めて、大ステップと比べる
+    fn run(original: &Statement, environment: &Environment) -> String {
+        let mut statement = Running::from(original.clone());
+        let mut small = environment.clone();
+        while statement.is_reducible() {
+            let (next, next_env) = statement.reduce(&mut small);
+            statement = next;
+            small = next_env;
+        }
+        let big = original.evaluate(&mut environment.clone());
+        assert_eq!(format_environment(&small), format_environment(&big));
+        format_environment(&small)
+    }
+
+    #[test]
+    fn let_test() {
+        // 内側のxは外側のxを隠し、出ると元に戻る。yは外でも残る
+        let statement = sequence(
+            assign("x", number(1)),
+            let_in(
+                "x",
+                add(variable("x"), number(10)),
+                sequence(
+                    assign("y", variable("x")),
+                    let_in("z", number(5), assign("x", variable("z"))),
+                ),
+            ),
+        );
+        assert_eq!(
+            statement.to_string(),
+            "x = 1; let x = x + 10 in { y = x; let z = 5 in { x = z } }"
+        );
+        assert_eq!(
+            run(&statement, &Environment::new()),
+            "{\"x\": Number(1), \"y\": Number(11)}"
+        );
+    }
+
+    #[test]
+    fn block_test() {
+        let statement = sequence(
+            assign("x", number(1)),
+            sequence(
+                block(sequence(
+                    assign("y", variable("x")),
+                    sequence(
+                        var("x", number(2)),
+                        sequence(
+                            var("t", number(3)),
+                            assign("z", add(variable("x"), variable("t"))),
+                        ),
+                    ),
+                )),
+                assign("w", variable("x")),
+            ),
+        );
+        assert_eq!(
+            run(&statement, &Environment::new()),
+            "{\"w\": Number(1), \"x\": Number(1), \"y\": Number(1), \"z\": Number(5)}"
+        );
+    }
+
+    #[test]
+    fn scope_exit_on_break_test() {
+        let statement = Statement::While {
+            condition: Expression::Boolean(true),
+            body: Box::new(let_in(
+                "x",
+                number(2),
+                sequence(assign("y", variable("x")), Statement::Break),
+            )),
+        };
+        assert_eq!(run(&statement, &Environment::new()), "{\"y\": Number(2)}");
+    }
+
+    #[test]
+    fn hoisted_test() {
+        let statement = sequence(
+            var("a", number(1)),
+            sequence(
+                block(var("b", number(2))),
+                Statement::If {
+                    condition: Expression::Boolean(true),
+                    consequence: Box::new(let_in("c", number(3), var("d", number(4)))),
+                    alternative: Box::new(var("a", number(5))),
+                },
+            ),
+        );
+        assert_eq!(statement.hoisted_variables(), vec!["a", "d"]);
+    }
+
+    #[test]
+    fn strict_test() {
+        let statement = sequence(
+            var("x", number(1)),
+            sequence(
+                let_in("y", number(2), assign("y", add(variable("y"), number(1)))),
+                sequence(
+                    block(sequence(var("z", number(3)), assign("z", number(4)))),
+                    sequence(assign("z", number(5)), assign("input", number(6))),
+                ),
+            ),
+        );
+        assert_eq!(
+            statement.strict_errors(&Environment::new()),
+            vec![
+                "z = 5: z is not declared",
+                "input = 6: input is not declared"
+            ]
+        );
+        let mut environment = Environment::new();
+        environment.insert("input".to_string(), number(0));
+        environment.insert("z".to_string(), number(0));
+        assert!(statement.strict_errors(&environment).is_empty());
+        assert_eq!(
+            format_environment(&statement.evaluate_strict(&mut environment).unwrap()),
+            "{\"input\": Number(6), \"x\": Number(1), \"z\": Number(5)}"
+        );
+    }
+
+    #[test]
+    fn evaluate_strict_test() {
+        let statement = sequence(assign("y", number(1)), assign("w", number(2)));
+        assert_eq!(
+            statement.evaluate_strict(&mut Environment::new()).err(),
+            Some(vec![
+                "y = 1: y is not declared".to_string(),
+                "w = 2: w is not declared".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn to_ruby_test() {
+        assert_eq!(
+            let_in("x", number(1), Statement::DoNothing).to_ruby().unwrap(),
+            "-> e { -> r { r.merge(e.slice(:x)).reject { |k, _| [:x].include?(k) && !e.key?(k) } }.call((-> e { e }).call(e.merge({ :x => (-> e { 1 }).call(e) }))) }"
+        );
+    }
+}
@@ -0,0 +1,517 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Add, Mul};
+
+use crate::expression::Expression;
+use crate::statement::Statement;
+use crate::subset::Construct;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sign {
+    Negative,
+    Zero,
+    Positive,
+    Unknown,
+}
+
+impl Sign {
+    pub fn of(value: i64) -> Sign {
+        if value < 0 {
+            Sign::Negative
+        } else if value == 0 {
+            Sign::Zero
+        } else {
+            Sign::Positive
+        }
+    }
+    // selfの表す値がすべてotherにも含まれる
+    pub fn is_subset(&self, other: &Sign) -> bool {
+        self == other || *other == Sign::Unknown
+    }
+    pub fn join(&self, other: &Sign) -> Sign {
+        if self == other {
+            *self
+        } else {
+            Sign::Unknown
+        }
+    }
+    fn less_than(&self, other: &Sign) -> Option<bool> {
+        match (self, other) {
+            (Sign::Negative, Sign::Zero) | (Sign::Negative, Sign::Positive) => Some(true),
+            (Sign::Zero, Sign::Positive) => Some(true),
+            (Sign::Zero, Sign::Negative) | (Sign::Positive, Sign::Negative) => Some(false),
+            (Sign::Zero, Sign::Zero) | (Sign::Positive, Sign::Zero) => Some(false),
+            _ => None,
+        }
+    }
+    fn equal(&self, other: &Sign) -> Option<bool> {
+        match (self, other) {
+            (Sign::Zero, Sign::Zero) => Some(true),
+            (Sign::Unknown, _) | (_, Sign::Unknown) => None,
+            (a, b) if a != b => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl Mul for Sign {
+    type Output = Sign;
+    fn mul(self, other: Sign) -> Sign {
+        if self == Sign::Zero || other == Sign::Zero {
+            Sign::Zero
+        } else if self == Sign::Unknown || other == Sign::Unknown {
+            Sign::Unknown
+        } else if self == other {
+            Sign::Positive
+        } else {
+            Sign::Negative
+        }
+    }
+}
+
+impl Add for Sign {
+    type Output = Sign;
+    fn add(self, other: Sign) -> Sign {
+        if self == other || other == Sign::Zero {
+            self
+        } else if self == Sign::Zero {
+            other
+        } else {
+            Sign::Unknown
+        }
+    }
+}
+
+impl fmt::Display for Sign {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sign::Negative => write!(f, "negative"),
+            Sign::Zero => write!(f, "zero"),
+            Sign::Positive => write!(f, "positive"),
+            Sign::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+// 変数には真偽値も入るので、符号と合わせて抽象値にする
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignValue {
+    Number(Sign),
+    Boolean(Option<bool>),
+    Unknown,
+}
+
+impl SignValue {
+    pub fn of(value: &Expression) -> SignValue {
+        match value {
+            Expression::Number(n) => SignValue::Number(Sign::of(*n as i64)),
+            Expression::Boolean(b) => SignValue::Boolean(Some(*b)),
+            _ => SignValue::Unknown,
+        }
+    }
+    pub fn is_subset(&self, other: &SignValue) -> bool {
+        match (self, other) {
+            (_, SignValue::Unknown) => true,
+            (SignValue::Number(a), SignValue::Number(b)) => a.is_subset(b),
+            (SignValue::Boolean(a), SignValue::Boolean(b)) => a == b || b.is_none(),
+            _ => false,
+        }
+    }
+    pub fn join(&self, other: &SignValue) -> SignValue {
+        match (self, other) {
+            (SignValue::Number(a), SignValue::Number(b)) => SignValue::Number(a.join(b)),
+            (SignValue::Boolean(a), SignValue::Boolean(b)) if a == b => *self,
+            (SignValue::Boolean(_), SignValue::Boolean(_)) => SignValue::Boolean(None),
+            _ => SignValue::Unknown,
+        }
+    }
+    // 変化した値は一番上まで上げてしまう
+    fn widen(&self, other: &SignValue) -> SignValue {
+        if self == other {
+            *self
+        } else {
+            match self.join(other) {
+                SignValue::Number(_) => SignValue::Number(Sign::Unknown),
+                SignValue::Boolean(_) => SignValue::Boolean(None),
+                SignValue::Unknown => SignValue::Unknown,
+            }
+        }
+    }
+    fn sign(&self) -> Sign {
+        match self {
+            SignValue::Number(sign) => *sign,
+            _ => Sign::Unknown,
+        }
+    }
+    // 数と真偽値は等しくならない
+    fn equal(&self, other: &SignValue) -> Option<bool> {
+        match (self, other) {
+            (SignValue::Number(a), SignValue::Number(b)) => a.equal(b),
+            (SignValue::Boolean(Some(a)), SignValue::Boolean(Some(b))) => Some(a == b),
+            (SignValue::Number(_), SignValue::Boolean(_))
+            | (SignValue::Boolean(_), SignValue::Number(_)) => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SignValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignValue::Number(sign) => write!(f, "{}", sign),
+            SignValue::Boolean(Some(b)) => write!(f, "{}", b),
+            SignValue::Boolean(None) => write!(f, "boolean"),
+            SignValue::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+pub type SignEnvironment = HashMap<String, SignValue>;
+
+// 片方にしかない変数はもう片方では未定義で、参照すれば止まるのでそのまま残す
+fn join_environments(first: &SignEnvironment, second: &SignEnvironment) -> SignEnvironment {
+    let mut environment = first.clone();
+    for (name, value) in second {
+        let joined = match first.get(name) {
+            Some(v) => v.join(value),
+            None => *value,
+        };
+        environment.insert(name.clone(), joined);
+    }
+    environment
+}
+
+fn widen_environments(old: &SignEnvironment, new: &SignEnvironment) -> SignEnvironment {
+    let mut environment = old.clone();
+    for (name, value) in new {
+        let widened = match old.get(name) {
+            Some(v) => v.widen(value),
+            None => *value,
+        };
+        environment.insert(name.clone(), widened);
+    }
+    environment
+}
+
+impl Expression {
+    pub fn evaluate_sign(&self, environment: &SignEnvironment) -> Result<SignValue, String> {
+        self.check_supported(&[])?;
+        Ok(self.sign_value(environment))
+    }
+    fn sign_value(&self, environment: &SignEnvironment) -> SignValue {
+        match self {
+            Expression::Number(_) | Expression::Boolean(_) => SignValue::of(self),
+            Expression::Variable(name) => {
+                if let Some(value) = environment.get(name) {
+                    *value
+                } else {
+                    panic!("undefined variable")
+                }
+            }
+            Expression::Add {
+                ref left,
+                ref right,
+            } => SignValue::Number(
+                left.sign_value(environment).sign() + right.sign_value(environment).sign(),
+            ),
+            Expression::Multiply {
+                ref left,
+                ref right,
+            } => SignValue::Number(
+                left.sign_value(environment).sign() * right.sign_value(environment).sign(),
+            ),
+            Expression::LessThan {
+                ref left,
+                ref right,
+            } => SignValue::Boolean(
+                left.sign_value(environment)
+                    .sign()
+                    .less_than(&right.sign_value(environment).sign()),
+            ),
+            Expression::Equal {
+                ref left,
+                ref right,
+            } => SignValue::Boolean(
+                left.sign_value(environment)
+                    .equal(&right.sign_value(environment)),
+            ),
+            Expression::Tuple(_)
+            | Expression::Record(_)
+            | Expression::Field { .. }
+            | Expression::Function { .. }
+            | Expression::Closure { .. }
+            | Expression::Call { .. } => unreachable!(),
+        }
+    }
+}
+
+impl Statement {
+    pub fn evaluate_sign(&self, environment: &SignEnvironment) -> Result<SignEnvironment, String> {
+        self.check_supported(&[Construct::Choose, Construct::Assert])?;
+        Ok(self.sign_environment(environment))
+    }
+    fn sign_environment(&self, environment: &SignEnvironment) -> SignEnvironment {
+        match self {
+            Statement::DoNothing => environment.clone(),
+            Statement::Assignment { name, expression } => {
+                let mut new_env = environment.clone();
+                new_env.insert(name.to_string(), expression.sign_value(environment));
+                new_env
+            }
+            Statement::If {
+                condition,
+                consequence,
+                alternative,
+            } => match condition.sign_value(environment) {
+                SignValue::Boolean(Some(true)) => consequence.sign_environment(environment),
+                SignValue::Boolean(Some(false)) => alternative.sign_environment(environment),
+                _ => join_environments(
+                    &consequence.sign_environment(environment),
+                    &alternative.sign_environment(environment),
+                ),
+            },
+            Statement::Sequence { first, second } => {
+                second.sign_environment(&first.sign_environment(environment))
+            }
+            // ループの先頭での環境が変わらなくなるまで回す
+            Statement::While { condition, body } => {
+                let mut current = environment.clone();
+                loop {
+                    if let SignValue::Boolean(Some(false)) = condition.sign_value(&current) {
+                        return current;
+                    }
+                    let next = widen_environments(
+                        &current,
+                        &join_environments(&current, &body.sign_environment(&current)),
+                    );
+                    if next == current {
+                        return current;
+                    }
+                    current = next;
+                }
+            }
+            Statement::Choose { first, second } => join_environments(
+                &first.sign_environment(environment),
+                &second.sign_environment(environment),
+            ),
+            // 符号では条件を仮定して絞れないので、そのまま進む
+            Statement::Assert { .. } => environment.clone(),
+            Statement::Parallel { .. }
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Throw { .. }
+            | Statement::Try { .. }
+            | Statement::Let { .. }
+            | Statement::Block { .. }
+            | Statement::Var { .. }
+            | Statement::Update { .. } => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expression::Environment;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn multiply(left: Expression, right: Expression) -> Expression {
+        Expression::Multiply {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn equal(left: Expression, right: Expression) -> Expression {
+        Expression::Equal {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+
+    // 具体的な実行結果がすべて抽象解釈の結果に含まれていること
+    fn assert_sound(statement: &Statement, inputs: Vec<Environment>) {
+        for input in inputs {
+            let abstract_input = input
+                .iter()
+                .map(|(name, value)| (name.clone(), SignValue::of(value)))
+                .collect();
+            let analysis = statement.evaluate_sign(&abstract_input).unwrap();
+            let result = statement.evaluate(&mut input.clone());
+            for (name, value) in result {
+                assert!(
+                    SignValue::of(&value).is_subset(&analysis[&name]),
+                    "{} = {} is not {}",
+                    name,
+                    value,
+                    analysis[&name]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sign_test() {
+        assert_eq!(Sign::Positive * Sign::Negative, Sign::Negative);
+        assert_eq!(Sign::Negative * Sign::Negative, Sign::Positive);
+        assert_eq!(Sign::Unknown * Sign::Zero, Sign::Zero);
+        assert_eq!(Sign::Positive + Sign::Positive, Sign::Positive);
+        assert_eq!(Sign::Negative + Sign::Zero, Sign::Negative);
+        assert_eq!(Sign::Negative + Sign::Positive, Sign::Unknown);
+        assert!(Sign::Positive.is_subset(&Sign::Unknown));
+        assert!(!Sign::Unknown.is_subset(&Sign::Positive));
+        assert_eq!(Sign::of(-3), Sign::Negative);
+        assert_eq!(Sign::Zero.join(&Sign::Positive), Sign::Unknown);
+    }
+
+    #[test]
+    fn expression_sign_test() {
+        let mut environment = SignEnvironment::new();
+        environment.insert("x".to_string(), SignValue::Number(Sign::Negative));
+        assert_eq!(
+            multiply(variable("x"), variable("x")).evaluate_sign(&environment),
+            Ok(SignValue::Number(Sign::Positive))
+        );
+        assert_eq!(
+            add(variable("x"), number(1)).evaluate_sign(&environment),
+            Ok(SignValue::Number(Sign::Unknown))
+        );
+        assert_eq!(
+            less_than(number(0), variable("x")).evaluate_sign(&environment),
+            Ok(SignValue::Boolean(Some(false)))
+        );
+        assert_eq!(
+            less_than(variable("x"), number(2)).evaluate_sign(&environment),
+            Ok(SignValue::Boolean(Some(true)))
+        );
+        assert_eq!(
+            equal(variable("x"), number(0)).evaluate_sign(&environment),
+            Ok(SignValue::Boolean(Some(false)))
+        );
+        assert_eq!(
+            equal(variable("x"), variable("x")).evaluate_sign(&environment),
+            Ok(SignValue::Boolean(None))
+        );
+        assert_eq!(
+            equal(number(0), Expression::Boolean(false)).evaluate_sign(&environment),
+            Ok(SignValue::Boolean(Some(false)))
+        );
+        assert_eq!(
+            Expression::Tuple(vec![number(1), number(2)]).evaluate_sign(&environment),
+            Err("tuple is not supported: (1, 2)".to_string())
+        );
+    }
+
+    #[test]
+    fn if_test() {
+        let statement = Statement::If {
+            condition: less_than(variable("x"), number(3)),
+            consequence: Box::new(assign("y", number(0))),
+            alternative: Box::new(assign("y", multiply(variable("x"), number(2)))),
+        };
+        let mut environment = SignEnvironment::new();
+        environment.insert("x".to_string(), SignValue::Number(Sign::Zero));
+        assert_eq!(
+            statement.evaluate_sign(&environment).unwrap()["y"],
+            SignValue::Number(Sign::Zero)
+        );
+        environment.insert("x".to_string(), SignValue::Number(Sign::Positive));
+        assert_eq!(
+            statement.evaluate_sign(&environment).unwrap()["y"],
+            SignValue::Number(Sign::Unknown)
+        );
+
+        let inputs = (0..6)
+            .map(|x| vec![("x".to_string(), number(x))].into_iter().collect())
+            .collect();
+        assert_sound(&statement, inputs);
+    }
+
+    #[test]
+    fn while_test() {
+        let statement = Statement::While {
+            condition: less_than(variable("x"), number(5)),
+            body: Box::new(assign("x", multiply(variable("x"), number(3)))),
+        };
+        let mut environment = SignEnvironment::new();
+        environment.insert("x".to_string(), SignValue::Number(Sign::Positive));
+        assert_eq!(
+            statement.evaluate_sign(&environment).unwrap()["x"],
+            SignValue::Number(Sign::Positive)
+        );
+
+        // xは正のまま、yは0から正に変わるので広げられる
+        let statement = sequence(
+            assign("y", number(0)),
+            Statement::While {
+                condition: less_than(variable("y"), variable("x")),
+                body: Box::new(assign("y", add(variable("y"), number(1)))),
+            },
+        );
+        let result = statement.evaluate_sign(&environment).unwrap();
+        assert_eq!(result["x"], SignValue::Number(Sign::Positive));
+        assert_eq!(result["y"], SignValue::Number(Sign::Unknown));
+
+        let inputs = (1..6)
+            .map(|x| vec![("x".to_string(), number(x))].into_iter().collect())
+            .collect();
+        assert_sound(&statement, inputs);
+    }
+
+    #[test]
+    fn boolean_test() {
+        let statement = Statement::While {
+            condition: variable("running"),
+            body: Box::new(sequence(
+                assign("x", add(variable("x"), number(1))),
+                assign("running", less_than(variable("x"), number(4))),
+            )),
+        };
+        let inputs = (0..3)
+            .map(|x| {
+                vec![
+                    ("x".to_string(), number(x)),
+                    ("running".to_string(), Expression::Boolean(true)),
+                ]
+                .into_iter()
+                .collect()
+            })
+            .collect();
+        assert_sound(&statement, inputs);
+
+        let mut environment = SignEnvironment::new();
+        environment.insert("x".to_string(), SignValue::Number(Sign::Zero));
+        environment.insert("running".to_string(), SignValue::Boolean(Some(true)));
+        let result = statement.evaluate_sign(&environment).unwrap();
+        assert_eq!(result["x"], SignValue::Number(Sign::Unknown));
+        assert_eq!(result["running"], SignValue::Boolean(None));
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+// 線形整数算術の小さな決定手続き
+// Fourier-Motzkin法で変数を消去し、矛盾が出れば充足不能。
+// 出なければ消去した順と逆に整数値を割り当てて解を探す。
+// 変数はすべて0以上の整数で、boundedに挙げた変数はu32の範囲の値をとるものとする
+
+const MAX_CONSTRAINTS: usize = 5000;
+const MAX_TRIES: i128 = 64;
+
+// Σ c_i x_i + constant
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LinearExpression {
+    coefficients: BTreeMap<String, i128>,
+    constant: i128,
+}
+
+impl LinearExpression {
+    pub fn constant(value: i128) -> Self {
+        LinearExpression {
+            coefficients: BTreeMap::new(),
+            constant: value,
+        }
+    }
+    pub fn variable(name: &str) -> Self {
+        let mut coefficients = BTreeMap::new();
+        coefficients.insert(name.to_string(), 1);
+        LinearExpression {
+            coefficients,
+            constant: 0,
+        }
+    }
+    pub fn constant_value(&self) -> Option<i128> {
+        if self.coefficients.is_empty() {
+            Some(self.constant)
+        } else {
+            None
+        }
+    }
+    pub fn variables(&self) -> Vec<String> {
+        self.coefficients.keys().cloned().collect()
+    }
+    pub fn add(&self, other: &LinearExpression) -> LinearExpression {
+        let mut coefficients = self.coefficients.clone();
+        for (name, c) in &other.coefficients {
+            *coefficients.entry(name.clone()).or_insert(0) += c;
+        }
+        coefficients.retain(|_, c| *c != 0);
+        LinearExpression {
+            coefficients,
+            constant: self.constant + other.constant,
+        }
+    }
+    pub fn scale(&self, factor: i128) -> LinearExpression {
+        let mut coefficients = self
+            .coefficients
+            .iter()
+            .map(|(name, c)| (name.clone(), c * factor))
+            .collect::<BTreeMap<_, _>>();
+        coefficients.retain(|_, c| *c != 0);
+        LinearExpression {
+            coefficients,
+            constant: self.constant * factor,
+        }
+    }
+    pub fn subtract(&self, other: &LinearExpression) -> LinearExpression {
+        self.add(&other.scale(-1))
+    }
+    fn coefficient(&self, name: &str) -> i128 {
+        *self.coefficients.get(name).unwrap_or(&0)
+    }
+    fn assign(&self, name: &str, value: i128) -> LinearExpression {
+        let c = self.coefficient(name);
+        let mut coefficients = self.coefficients.clone();
+        coefficients.remove(name);
+        LinearExpression {
+            coefficients,
+            constant: self.constant + c * value,
+        }
+    }
+    pub fn evaluate(&self, model: &BTreeMap<String, i128>) -> i128 {
+        self.coefficients
+            .iter()
+            .map(|(name, c)| c * model.get(name).unwrap_or(&0))
+            .sum::<i128>()
+            + self.constant
+    }
+}
+
+impl fmt::Display for LinearExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut terms = self
+            .coefficients
+            .iter()
+            .map(|(name, c)| {
+                if *c == 1 {
+                    name.clone()
+                } else {
+                    format!("{}{}", c, name)
+                }
+            })
+            .collect::<Vec<_>>();
+        if self.constant != 0 || terms.is_empty() {
+            terms.push(self.constant.to_string());
+        }
+        write!(f, "{}", terms.join(" + "))
+    }
+}
+
+// expression <= 0
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LinearConstraint {
+    expression: LinearExpression,
+}
+
+impl LinearConstraint {
+    pub fn less_equal(left: &LinearExpression, right: &LinearExpression) -> Self {
+        LinearConstraint {
+            expression: left.subtract(right),
+        }
+    }
+    // 整数なので l < r は l - r + 1 <= 0
+    pub fn less_than(left: &LinearExpression, right: &LinearExpression) -> Self {
+        LinearConstraint {
+            expression: left.subtract(right).add(&LinearExpression::constant(1)),
+        }
+    }
+    pub fn equal(left: &LinearExpression, right: &LinearExpression) -> Vec<Self> {
+        vec![
+            LinearConstraint::less_equal(left, right),
+            LinearConstraint::less_equal(right, left),
+        ]
+    }
+    pub fn is_satisfied(&self, model: &BTreeMap<String, i128>) -> bool {
+        self.expression.evaluate(model) <= 0
+    }
+    // 係数の最大公約数で割り、定数項は切り上げて強める
+    fn normalize(&self) -> Result<Option<LinearConstraint>, ()> {
+        let g = self
+            .expression
+            .coefficients
+            .values()
+            .fold(0, |g, c| gcd(g, c.abs()));
+        if g == 0 {
+            return if self.expression.constant <= 0 {
+                Ok(None)
+            } else {
+                Err(())
+            };
+        }
+        let coefficients = self
+            .expression
+            .coefficients
+            .iter()
+            .map(|(name, c)| (name.clone(), c / g))
+            .collect();
+        Ok(Some(LinearConstraint {
+            expression: LinearExpression {
+                coefficients,
+                constant: div_ceil(self.expression.constant, g),
+            },
+        }))
+    }
+}
+
+impl fmt::Display for LinearConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} <= 0", self.expression)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Satisfiability {
+    Satisfiable(BTreeMap<String, i128>),
+    Unsatisfiable,
+    Unknown,
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn div_ceil(a: i128, b: i128) -> i128 {
+    -((-a).div_euclid(b))
+}
+
+fn div_floor(a: i128, b: i128) -> i128 {
+    a.div_euclid(b)
+}
+
+fn simplify(constraints: Vec<LinearConstraint>) -> Result<Vec<LinearConstraint>, ()> {
+    let mut simplified = Vec::new();
+    for constraint in constraints {
+        if let Some(c) = constraint.normalize()? {
+            simplified.push(c);
+        }
+    }
+    simplified.sort();
+    simplified.dedup();
+    Ok(simplified)
+}
+
+fn eliminate(constraints: &[LinearConstraint], name: &str) -> Vec<LinearConstraint> {
+    let (with, mut without): (Vec<_>, Vec<_>) = constraints
+        .iter()
+        .cloned()
+        .partition(|c| c.expression.coefficient(name) != 0);
+    let (uppers, lowers): (Vec<_>, Vec<_>) = with
+        .into_iter()
+        .partition(|c| c.expression.coefficient(name) > 0);
+    for upper in &uppers {
+        for lower in &lowers {
+            let a = upper.expression.coefficient(name);
+            let b = -lower.expression.coefficient(name);
+            without.push(LinearConstraint {
+                expression: upper.expression.scale(b).add(&lower.expression.scale(a)),
+            });
+        }
+    }
+    without
+}
+
+// ほかの変数に値を入れた後、nameの取りうる範囲
+fn bounds(
+    constraints: &[LinearConstraint],
+    name: &str,
+    model: &BTreeMap<String, i128>,
+) -> (i128, i128) {
+    let mut low = 0;
+    let mut high = i128::MAX;
+    for constraint in constraints {
+        let c = constraint.expression.coefficient(name);
+        if c == 0 {
+            continue;
+        }
+        let rest = model
+            .iter()
+            .fold(constraint.expression.clone(), |e, (n, v)| e.assign(n, *v))
+            .assign(name, 0)
+            .constant;
+        if c > 0 {
+            high = high.min(div_floor(-rest, c));
+        } else {
+            low = low.max(div_ceil(rest, -c));
+        }
+    }
+    (low, high)
+}
+
+pub fn solve(constraints: &[LinearConstraint], bounded: &BTreeSet<String>) -> Satisfiability {
+    let mut names = constraints
+        .iter()
+        .flat_map(|c| c.expression.variables())
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+
+    let mut system = constraints.to_vec();
+    for name in &names {
+        system.push(LinearConstraint::less_equal(
+            &LinearExpression::constant(0),
+            &LinearExpression::variable(name),
+        ));
+        if bounded.contains(name) {
+            system.push(LinearConstraint::less_equal(
+                &LinearExpression::variable(name),
+                &LinearExpression::constant(u32::MAX as i128),
+            ));
+        }
+    }
+    let mut stages = Vec::new();
+    let mut current = match simplify(system) {
+        Ok(s) => s,
+        Err(()) => return Satisfiability::Unsatisfiable,
+    };
+    for name in &names {
+        stages.push(current.clone());
+        current = match simplify(eliminate(&current, name)) {
+            Ok(s) => s,
+            Err(()) => return Satisfiability::Unsatisfiable,
+        };
+        if current.len() > MAX_CONSTRAINTS {
+            return Satisfiability::Unknown;
+        }
+    }
+
+    let mut model = BTreeMap::new();
+    if assign(&stages, &names, names.len(), &mut model) {
+        debug_assert!(constraints.iter().all(|c| c.is_satisfied(&model)));
+        Satisfiability::Satisfiable(model)
+    } else {
+        Satisfiability::Unknown
+    }
+}
+
+// 後ろの変数から順に整数値を決める。うまくいかなければ別の値を試す
+fn assign(
+    stages: &[Vec<LinearConstraint>],
+    names: &[String],
+    remaining: usize,
+    model: &mut BTreeMap<String, i128>,
+) -> bool {
+    if remaining == 0 {
+        return true;
+    }
+    let index = remaining - 1;
+    let name = &names[index];
+    let (low, high) = bounds(&stages[index], name, model);
+    let mut value = low;
+    while value <= high && value < low + MAX_TRIES {
+        model.insert(name.clone(), value);
+        // stages[index]に出てくる変数にはすべて値が入っている
+        if stages[index].iter().all(|c| c.is_satisfied(model))
+            && assign(stages, names, index, model)
+        {
+            return true;
+        }
+        value += 1;
+    }
+    model.remove(name);
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(name: &str) -> LinearExpression {
+        LinearExpression::variable(name)
+    }
+    fn num(value: i128) -> LinearExpression {
+        LinearExpression::constant(value)
+    }
+    fn bounded(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn linear_expression_test() {
+        let e = var("x").scale(2).add(&var("y")).subtract(&num(3));
+        assert_eq!(e.to_string(), "2x + y + -3");
+        assert_eq!(e.subtract(&var("y")).to_string(), "2x + -3");
+        let model = vec![("x".to_string(), 4), ("y".to_string(), 1)]
+            .into_iter()
+            .collect();
+        assert_eq!(e.evaluate(&model), 6);
+    }
+
+    #[test]
+    fn satisfiable_test() {
+        // x + y = 10, x < y, 3 < x
+        let mut constraints = LinearConstraint::equal(&var("x").add(&var("y")), &num(10));
+        constraints.push(LinearConstraint::less_than(&var("x"), &var("y")));
+        constraints.push(LinearConstraint::less_than(&num(3), &var("x")));
+        match solve(&constraints, &bounded(&["x", "y"])) {
+            Satisfiability::Satisfiable(model) => {
+                assert_eq!(model["x"], 4);
+                assert_eq!(model["y"], 6);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn unsatisfiable_test() {
+        // x < y, y < x
+        let constraints = vec![
+            LinearConstraint::less_than(&var("x"), &var("y")),
+            LinearConstraint::less_than(&var("y"), &var("x")),
+        ];
+        assert_eq!(
+            solve(&constraints, &bounded(&["x", "y"])),
+            Satisfiability::Unsatisfiable
+        );
+
+        // 2x = 2y + 1 は有理数なら解けるが整数では解けない
+        let constraints =
+            LinearConstraint::equal(&var("x").scale(2), &var("y").scale(2).add(&num(1)));
+        assert_eq!(
+            solve(&constraints, &bounded(&["x", "y"])),
+            Satisfiability::Unsatisfiable
+        );
+
+        // 負の値はとらない
+        let constraints = vec![LinearConstraint::less_than(&var("x"), &num(0))];
+        assert_eq!(
+            solve(&constraints, &bounded(&["x", "y"])),
+            Satisfiability::Unsatisfiable
+        );
+
+        // u32に収まる変数だけに上限がある
+        let constraints = vec![LinearConstraint::less_than(
+            &num(u32::MAX as i128),
+            &var("x"),
+        )];
+        assert_eq!(
+            solve(&constraints, &bounded(&["x"])),
+            Satisfiability::Unsatisfiable
+        );
+        assert_eq!(
+            solve(&constraints, &bounded(&[])),
+            Satisfiability::Satisfiable(
+                vec![("x".to_string(), u32::MAX as i128 + 1)]
+                    .into_iter()
+                    .collect()
+            )
+        );
+    }
+
+    #[test]
+    fn integer_search_test() {
+        // 3x = 2y + 1, y < 10
+        let mut constraints =
+            LinearConstraint::equal(&var("x").scale(3), &var("y").scale(2).add(&num(1)));
+        constraints.push(LinearConstraint::less_than(&var("y"), &num(10)));
+        match solve(&constraints, &bounded(&["x", "y"])) {
+            Satisfiability::Satisfiable(model) => {
+                assert_eq!(model["x"] * 3, model["y"] * 2 + 1);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
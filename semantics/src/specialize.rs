@@ -0,0 +1,521 @@
+use std::collections::BTreeSet;
+
+use crate::expression::{Environment, Expression};
+use crate::statement::Statement;
+use crate::subset::Construct;
+
+// 値が分かっている変数(静的な変数)だけを使う部分を実行し、
+// 残りを動的な変数についてのプログラムとして出力する
+
+impl Expression {
+    // 分かっている変数を値に置き換え、定数どうしの演算を計算する。
+    // 型の合わない演算やあふれる演算は、実行されないかもしれないので
+    // 実行時に止まるようにそのまま残す
+    pub fn specialize(&self, known: &Environment) -> Expression {
+        match self {
+            Expression::Number(_) | Expression::Boolean(_) => self.clone(),
+            Expression::Variable(name) => match known.get(name) {
+                Some(value) => value.clone(),
+                None => self.clone(),
+            },
+            Expression::Add { left, right } => {
+                match (left.specialize(known), right.specialize(known)) {
+                    (Expression::Number(l), Expression::Number(r))
+                        if l.checked_add(r).is_some() =>
+                    {
+                        Expression::Number(l + r)
+                    }
+                    (left, right) => Expression::Add {
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    },
+                }
+            }
+            Expression::Multiply { left, right } => {
+                match (left.specialize(known), right.specialize(known)) {
+                    (Expression::Number(l), Expression::Number(r))
+                        if l.checked_mul(r).is_some() =>
+                    {
+                        Expression::Number(l * r)
+                    }
+                    (left, right) => Expression::Multiply {
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    },
+                }
+            }
+            Expression::LessThan { left, right } => {
+                match (left.specialize(known), right.specialize(known)) {
+                    (Expression::Number(l), Expression::Number(r)) => Expression::Boolean(l < r),
+                    (left, right) => Expression::LessThan {
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    },
+                }
+            }
+            Expression::Tuple(elements) => {
+                Expression::Tuple(elements.iter().map(|e| e.specialize(known)).collect())
+            }
+            Expression::Record(fields) => Expression::Record(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.specialize(known)))
+                    .collect(),
+            ),
+            Expression::Field { expression, name } => match expression.specialize(known) {
+                value if is_value(&value) && value.checked_field(name).is_some() => {
+                    value.field(name)
+                }
+                expression => Expression::Field {
+                    expression: Box::new(expression),
+                    name: name.clone(),
+                },
+            },
+            Expression::Equal { left, right } => {
+                match (left.specialize(known), right.specialize(known)) {
+                    (left, right) if is_value(&left) && is_value(&right) => {
+                        Expression::Boolean(left.is_equal(&right))
+                    }
+                    (left, right) => Expression::Equal {
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    },
+                }
+            }
+            // 本体では引数が分かっている変数を隠す
+            Expression::Function { params, body } => {
+                let mut inner = known.clone();
+                for param in params {
+                    inner.remove(param);
+                }
+                Expression::Function {
+                    params: params.clone(),
+                    body: Box::new(body.specialize(&inner)),
+                }
+            }
+            Expression::Closure { .. } => self.clone(),
+            Expression::Call {
+                function,
+                arguments,
+            } => Expression::Call {
+                function: Box::new(function.specialize(known)),
+                arguments: arguments.iter().map(|a| a.specialize(known)).collect(),
+            },
+        }
+    }
+}
+
+fn is_value(expression: &Expression) -> bool {
+    !expression.is_reducible()
+}
+
+fn sequence(first: Statement, second: Statement) -> Statement {
+    match (first, second) {
+        (Statement::DoNothing, statement) | (statement, Statement::DoNothing) => statement,
+        (first, second) => Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        },
+    }
+}
+
+fn assigned_variables(statement: &Statement, names: &mut BTreeSet<String>) {
+    match statement {
+        Statement::DoNothing | Statement::Assert { .. } => {}
+        Statement::Assignment { name, .. } | Statement::Update { name, .. } => {
+            names.insert(name.clone());
+        }
+        Statement::If {
+            consequence,
+            alternative,
+            ..
+        } => {
+            assigned_variables(consequence, names);
+            assigned_variables(alternative, names);
+        }
+        Statement::Sequence { first, second } => {
+            assigned_variables(first, names);
+            assigned_variables(second, names);
+        }
+        Statement::While { body, .. } => assigned_variables(body, names),
+        Statement::Choose { .. }
+        | Statement::Parallel { .. }
+        | Statement::Break
+        | Statement::Continue
+        | Statement::Throw { .. }
+        | Statement::Try { .. }
+        | Statement::Let { .. }
+        | Statement::Block { .. }
+        | Statement::Var { .. } => unreachable!(),
+    }
+}
+
+// 分かっている値を代入する文にして、namesを分からない変数にする
+fn materialize(names: &BTreeSet<String>, known: &mut Environment) -> Statement {
+    let mut residual = Statement::DoNothing;
+    for name in names {
+        if let Some(value) = known.remove(name) {
+            residual = sequence(
+                residual,
+                Statement::Assignment {
+                    name: name.clone(),
+                    expression: value,
+                },
+            );
+        }
+    }
+    residual
+}
+
+pub struct PartialEvaluator {
+    max_unrolling: usize,
+}
+
+impl PartialEvaluator {
+    pub fn new(max_unrolling: usize) -> Self {
+        PartialEvaluator { max_unrolling }
+    }
+    // 残ったプログラムを動的な変数だけの環境で実行すると、
+    // 元のプログラムを全部の変数で実行したのと同じ環境になる
+    pub fn specialize(
+        &self,
+        statement: &Statement,
+        static_inputs: &Environment,
+    ) -> Result<Statement, String> {
+        statement.check_supported(&[
+            Construct::Assert,
+            Construct::Update,
+            Construct::Tuple,
+            Construct::Record,
+            Construct::Field,
+            Construct::Function,
+            Construct::Call,
+        ])?;
+        let mut known = static_inputs.clone();
+        let residual = self.residual(statement, &mut known);
+        let names = known.keys().cloned().collect();
+        Ok(sequence(residual, materialize(&names, &mut known)))
+    }
+    fn residual(&self, statement: &Statement, known: &mut Environment) -> Statement {
+        match statement {
+            Statement::DoNothing => Statement::DoNothing,
+            Statement::Assignment { name, expression } => {
+                let expression = expression.specialize(known);
+                if is_value(&expression) {
+                    known.insert(name.clone(), expression);
+                    Statement::DoNothing
+                } else {
+                    known.remove(name);
+                    Statement::Assignment {
+                        name: name.clone(),
+                        expression,
+                    }
+                }
+            }
+            Statement::Update {
+                name,
+                fields,
+                expression,
+            } => {
+                let expression = expression.specialize(known);
+                let updated = match known.get(name) {
+                    Some(current) if is_value(&expression) => {
+                        current.checked_update(fields, expression.clone())
+                    }
+                    _ => None,
+                };
+                match updated {
+                    Some(updated) => {
+                        known.insert(name.clone(), updated);
+                        Statement::DoNothing
+                    }
+                    // 一部だけ分かっていても、全体を代入してから書き換える
+                    _ => sequence(
+                        materialize(&std::iter::once(name.clone()).collect(), known),
+                        Statement::Update {
+                            name: name.clone(),
+                            fields: fields.clone(),
+                            expression,
+                        },
+                    ),
+                }
+            }
+            Statement::If {
+                condition,
+                consequence,
+                alternative,
+            } => match condition.specialize(known) {
+                Expression::Boolean(true) => self.residual(consequence, known),
+                Expression::Boolean(false) => self.residual(alternative, known),
+                condition => {
+                    let mut consequence_known = known.clone();
+                    let mut alternative_known = known.clone();
+                    let mut consequence = self.residual(consequence, &mut consequence_known);
+                    let mut alternative = self.residual(alternative, &mut alternative_known);
+                    // 両方で同じ値になった変数だけを分かっているものとして残す
+                    let mut differing = BTreeSet::new();
+                    for (name, value) in consequence_known.iter().chain(alternative_known.iter()) {
+                        if consequence_known.get(name) != Some(value)
+                            || alternative_known.get(name) != Some(value)
+                        {
+                            differing.insert(name.clone());
+                        }
+                    }
+                    consequence =
+                        sequence(consequence, materialize(&differing, &mut consequence_known));
+                    alternative =
+                        sequence(alternative, materialize(&differing, &mut alternative_known));
+                    *known = consequence_known;
+                    Statement::If {
+                        condition,
+                        consequence: Box::new(consequence),
+                        alternative: Box::new(alternative),
+                    }
+                }
+            },
+            // 成り立つと分かった表明は消す。偽の表明は実行時に止まるように残す
+            Statement::Assert { condition } => match condition.specialize(known) {
+                Expression::Boolean(true) => Statement::DoNothing,
+                condition => Statement::Assert { condition },
+            },
+            Statement::Sequence { first, second } => {
+                let first = self.residual(first, known);
+                sequence(first, self.residual(second, known))
+            }
+            Statement::While { condition, body } => {
+                let mut residual = Statement::DoNothing;
+                for _ in 0..self.max_unrolling {
+                    match condition.specialize(known) {
+                        Expression::Boolean(true) => {
+                            residual = sequence(residual, self.residual(body, known));
+                        }
+                        Expression::Boolean(false) => return residual,
+                        _ => break,
+                    }
+                }
+                // 条件が分からないか回しきれなかったら、本体で代入する変数を分からないものにしてループを残す
+                let mut modified = BTreeSet::new();
+                assigned_variables(body, &mut modified);
+                residual = sequence(residual, materialize(&modified, known));
+                let mut body_known = known.clone();
+                let body = self.residual(body, &mut body_known);
+                let body = sequence(body, materialize(&modified, &mut body_known));
+                sequence(
+                    residual,
+                    Statement::While {
+                        condition: condition.specialize(known),
+                        body: Box::new(body),
+                    },
+                )
+            }
+            Statement::Choose { .. }
+            | Statement::Parallel { .. }
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Throw { .. }
+            | Statement::Try { .. }
+            | Statement::Let { .. }
+            | Statement::Block { .. }
+            | Statement::Var { .. } => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::format_environment;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn multiply(left: Expression, right: Expression) -> Expression {
+        Expression::Multiply {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn seq(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+    fn environment(pairs: &[(&str, u32)]) -> Environment {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), number(*value)))
+            .collect()
+    }
+
+    // 動的な変数のとり得る値を全部試す
+    fn assert_agrees(statement: &Statement, residual: &Statement, static_inputs: &Environment) {
+        for x in 0..6 {
+            let dynamic_inputs = environment(&[("x", x)]);
+            let mut all_inputs = static_inputs.clone();
+            all_inputs.extend(dynamic_inputs.clone());
+            assert_eq!(
+                format_environment(&residual.evaluate(&mut dynamic_inputs.clone())),
+                format_environment(&statement.evaluate(&mut all_inputs)),
+                "x = {}",
+                x
+            );
+        }
+    }
+
+    fn power() -> Statement {
+        // result = x ** n
+        seq(
+            assign("result", number(1)),
+            seq(
+                assign("i", number(0)),
+                Statement::While {
+                    condition: less_than(variable("i"), variable("n")),
+                    body: Box::new(seq(
+                        assign("result", multiply(variable("result"), variable("x"))),
+                        assign("i", add(variable("i"), number(1))),
+                    )),
+                },
+            ),
+        )
+    }
+
+    #[test]
+    fn expression_test() {
+        let expression = add(multiply(variable("n"), number(2)), variable("x"));
+        assert_eq!(
+            expression.specialize(&environment(&[("n", 3)])).to_string(),
+            "6 + x"
+        );
+    }
+
+    #[test]
+    fn unroll_test() {
+        let static_inputs = environment(&[("n", 3)]);
+        let residual = PartialEvaluator::new(10)
+            .specialize(&power(), &static_inputs)
+            .unwrap();
+        assert_eq!(
+            residual.to_string(),
+            "result = 1 * x; result = result * x; result = result * x; i = 3; n = 3"
+        );
+        assert_agrees(&power(), &residual, &static_inputs);
+    }
+
+    #[test]
+    fn dynamic_loop_test() {
+        // 回数が動的なら、ループの中で変わる変数を先に代入してループを残す
+        let statement = seq(
+            assign("n", variable("x")),
+            seq(power(), assign("x", number(2))),
+        );
+        let residual = PartialEvaluator::new(10)
+            .specialize(&statement, &Environment::new())
+            .unwrap();
+        assert_eq!(
+            residual.to_string(),
+            "n = x; i = 0; result = 1; while (i < n), { result = result * x; i = i + 1 }; x = 2"
+        );
+        assert_agrees(&statement, &residual, &Environment::new());
+    }
+
+    #[test]
+    fn unrolling_limit_test() {
+        let static_inputs = environment(&[("n", 5)]);
+        let residual = PartialEvaluator::new(2)
+            .specialize(&power(), &static_inputs)
+            .unwrap();
+        assert_eq!(
+            residual.to_string(),
+            "result = 1 * x; result = result * x; i = 2; while (i < 5), { result = result * x; i = i + 1 }; n = 5"
+        );
+        assert_agrees(&power(), &residual, &static_inputs);
+    }
+
+    #[test]
+    fn if_test() {
+        // yはどちらでも1なので分かったまま、zは枝ごとに代入する
+        let statement = seq(
+            Statement::If {
+                condition: less_than(variable("x"), variable("k")),
+                consequence: Box::new(seq(assign("y", number(1)), assign("z", variable("k")))),
+                alternative: Box::new(seq(assign("y", number(1)), assign("z", variable("x")))),
+            },
+            seq(
+                assign("w", add(variable("y"), variable("k"))),
+                Statement::If {
+                    condition: less_than(variable("k"), number(10)),
+                    consequence: Box::new(assign("v", number(0))),
+                    alternative: Box::new(assign("v", variable("x"))),
+                },
+            ),
+        );
+        let static_inputs = environment(&[("k", 3)]);
+        let residual = PartialEvaluator::new(10)
+            .specialize(&statement, &static_inputs)
+            .unwrap();
+        assert_eq!(
+            residual.to_string(),
+            "if (x < 3) { z = 3 } else { z = x }; k = 3; v = 0; w = 4; y = 1"
+        );
+        assert_agrees(&statement, &residual, &static_inputs);
+    }
+
+    #[test]
+    fn failing_operation_test() {
+        // 実行されない枝にある、あふれる演算や存在しないフィールドは残す
+        let statement = seq(
+            assign("r", Expression::Record(vec![("a".to_string(), number(1))])),
+            Statement::If {
+                condition: less_than(variable("x"), number(1)),
+                consequence: Box::new(seq(
+                    assign("y", add(number(u32::MAX), number(1))),
+                    seq(
+                        assign("z", multiply(number(u32::MAX), number(2))),
+                        assign(
+                            "w",
+                            Expression::Field {
+                                expression: Box::new(variable("r")),
+                                name: "b".to_string(),
+                            },
+                        ),
+                    ),
+                )),
+                alternative: Box::new(assign("y", number(0))),
+            },
+        );
+        let residual = PartialEvaluator::new(10)
+            .specialize(&statement, &Environment::new())
+            .unwrap();
+        assert_eq!(
+            residual.to_string(),
+            "if (x < 1) { y = 4294967295 + 1; z = 4294967295 * 2; w = { a: 1 }.b } else { y = 0 }; r = { a: 1 }"
+        );
+        let mut inputs = environment(&[("x", 3)]);
+        assert_eq!(
+            format_environment(&residual.evaluate(&mut inputs.clone())),
+            format_environment(&statement.evaluate(&mut inputs))
+        );
+    }
+}
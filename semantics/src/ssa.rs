@@ -0,0 +1,471 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::cfg::{BlockId, ControlFlowGraph, Instruction, Terminator};
+use crate::expression::Expression;
+
+// SSA形式では変数xの定義ごとに x.1, x.2, ... と名前を付け直す。
+// どこでも定義されていない値(最初の環境から来る値)は元の名前 x のまま使う
+
+pub fn base_name(name: &str) -> &str {
+    name.split('.').next().unwrap()
+}
+
+fn rename(expression: &Expression, names: &BTreeMap<String, Vec<String>>) -> Expression {
+    let sub = |e: &Expression| Box::new(rename(e, names));
+    match expression {
+        Expression::Variable(name) => Expression::Variable(current_name(name, names)),
+        Expression::Number(_) | Expression::Boolean(_) => expression.clone(),
+        Expression::Add { left, right } => Expression::Add {
+            left: sub(left),
+            right: sub(right),
+        },
+        Expression::Multiply { left, right } => Expression::Multiply {
+            left: sub(left),
+            right: sub(right),
+        },
+        Expression::LessThan { left, right } => Expression::LessThan {
+            left: sub(left),
+            right: sub(right),
+        },
+        Expression::Equal { left, right } => Expression::Equal {
+            left: sub(left),
+            right: sub(right),
+        },
+        Expression::Tuple(_)
+        | Expression::Record(_)
+        | Expression::Field { .. }
+        | Expression::Function { .. }
+        | Expression::Closure { .. }
+        | Expression::Call { .. } => unreachable!(),
+    }
+}
+
+fn current_name(name: &str, names: &BTreeMap<String, Vec<String>>) -> String {
+    names
+        .get(name)
+        .and_then(|stack| stack.last())
+        .cloned()
+        .unwrap_or_else(|| name.to_string())
+}
+
+impl ControlFlowGraph {
+    // 各ブロックの直接支配ブロック。entryはNone
+    pub fn immediate_dominators(&self) -> Vec<Option<BlockId>> {
+        let order = self.reverse_postorder();
+        let all = order.iter().cloned().collect::<BTreeSet<_>>();
+        let mut dominators = vec![all; self.blocks.len()];
+        dominators[self.entry] = vec![self.entry].into_iter().collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in order.iter().filter(|&&b| b != self.entry) {
+                let mut set = self
+                    .predecessors(block)
+                    .iter()
+                    .filter(|p| order.contains(p))
+                    .map(|&p| dominators[p].clone())
+                    .reduce(|a, b| a.intersection(&b).cloned().collect())
+                    .unwrap_or_default();
+                set.insert(block);
+                if set != dominators[block] {
+                    dominators[block] = set;
+                    changed = true;
+                }
+            }
+        }
+        // 真の支配ブロックのうち、いちばん多くのブロックに支配されているもの
+        (0..self.blocks.len())
+            .map(|block| {
+                if block == self.entry {
+                    return None;
+                }
+                dominators[block]
+                    .iter()
+                    .filter(|&&d| d != block)
+                    .max_by_key(|&&d| dominators[d].len())
+                    .cloned()
+            })
+            .collect()
+    }
+    pub fn dominance_frontiers(&self) -> Vec<BTreeSet<BlockId>> {
+        let idom = self.immediate_dominators();
+        let mut frontiers = vec![BTreeSet::new(); self.blocks.len()];
+        for block in self.reverse_postorder() {
+            let predecessors = self.predecessors(block);
+            if predecessors.len() < 2 {
+                continue;
+            }
+            for predecessor in predecessors {
+                let mut runner = Some(predecessor);
+                while runner.is_some() && runner != idom[block] {
+                    let r = runner.unwrap();
+                    frontiers[r].insert(block);
+                    runner = idom[r];
+                }
+            }
+        }
+        frontiers
+    }
+    pub fn is_ssa(&self) -> bool {
+        let mut defined = BTreeSet::new();
+        self.blocks
+            .iter()
+            .flat_map(|block| block.instructions.iter())
+            .all(|instruction| defined.insert(instruction.name().to_string()))
+    }
+    pub fn to_ssa(&self) -> ControlFlowGraph {
+        let mut cfg = self.clone();
+        let frontiers = self.dominance_frontiers();
+
+        // 変数ごとに、定義のあるブロックの支配辺境にphiを置く
+        let mut definitions: BTreeMap<String, BTreeSet<BlockId>> = BTreeMap::new();
+        for (id, block) in self.blocks.iter().enumerate() {
+            for instruction in &block.instructions {
+                definitions
+                    .entry(instruction.name().to_string())
+                    .or_default()
+                    .insert(id);
+            }
+        }
+        for (name, blocks) in &definitions {
+            let mut has_phi = BTreeSet::new();
+            let mut work = blocks.iter().cloned().collect::<Vec<_>>();
+            while let Some(block) = work.pop() {
+                for &frontier in &frontiers[block] {
+                    if has_phi.insert(frontier) {
+                        cfg.blocks[frontier].instructions.insert(
+                            0,
+                            Instruction::Phi {
+                                name: name.clone(),
+                                arguments: vec![],
+                            },
+                        );
+                        if !blocks.contains(&frontier) {
+                            work.push(frontier);
+                        }
+                    }
+                }
+            }
+        }
+
+        let idom = self.immediate_dominators();
+        let mut renamer = Renamer {
+            children: (0..self.blocks.len())
+                .map(|b| {
+                    (0..self.blocks.len())
+                        .filter(|&c| idom[c] == Some(b))
+                        .collect()
+                })
+                .collect(),
+            names: BTreeMap::new(),
+            counters: BTreeMap::new(),
+        };
+        renamer.rename_block(&mut cfg, self.entry);
+        cfg
+    }
+    // to_ssaを取り消す。phiを取り除いて名前を元に戻すだけなので、
+    // to_ssaが作った形のように、phiの引数とその値を受ける変数が同じ元の名前を持ち、
+    // 同じ元の名前の版どうしの生存区間が重ならないことを前提にする。
+    // コピーの移動などでこれが崩れたSSA形式には使えない(先行ブロックにコピーを置く変換が要る)
+    pub fn undo_ssa(&self) -> ControlFlowGraph {
+        let mut cfg = self.clone();
+        for block in &mut cfg.blocks {
+            block.instructions = block
+                .instructions
+                .iter()
+                .filter_map(|instruction| match instruction {
+                    Instruction::Phi { name, arguments } => {
+                        if arguments
+                            .iter()
+                            .any(|(_, a)| base_name(a) != base_name(name))
+                        {
+                            panic!("phi arguments must share a variable");
+                        }
+                        None
+                    }
+                    Instruction::Assign { name, expression } => Some(Instruction::Assign {
+                        name: base_name(name).to_string(),
+                        expression: expression.strip_versions(),
+                    }),
+                })
+                .collect();
+            if let Terminator::Branch { condition, .. } = &mut block.terminator {
+                *condition = condition.strip_versions();
+            }
+        }
+        cfg
+    }
+}
+
+impl Expression {
+    fn strip_versions(&self) -> Expression {
+        let sub = |e: &Expression| Box::new(e.strip_versions());
+        match self {
+            Expression::Variable(name) => Expression::Variable(base_name(name).to_string()),
+            Expression::Number(_) | Expression::Boolean(_) => self.clone(),
+            Expression::Add { left, right } => Expression::Add {
+                left: sub(left),
+                right: sub(right),
+            },
+            Expression::Multiply { left, right } => Expression::Multiply {
+                left: sub(left),
+                right: sub(right),
+            },
+            Expression::LessThan { left, right } => Expression::LessThan {
+                left: sub(left),
+                right: sub(right),
+            },
+            Expression::Equal { left, right } => Expression::Equal {
+                left: sub(left),
+                right: sub(right),
+            },
+            Expression::Tuple(_)
+            | Expression::Record(_)
+            | Expression::Field { .. }
+            | Expression::Function { .. }
+            | Expression::Closure { .. }
+            | Expression::Call { .. } => unreachable!(),
+        }
+    }
+}
+
+struct Renamer {
+    children: Vec<Vec<BlockId>>,
+    names: BTreeMap<String, Vec<String>>,
+    counters: BTreeMap<String, usize>,
+}
+
+impl Renamer {
+    fn fresh(&mut self, name: &str) -> String {
+        let counter = self.counters.entry(name.to_string()).or_insert(0);
+        *counter += 1;
+        let fresh = format!("{}.{}", name, counter);
+        self.names
+            .entry(name.to_string())
+            .or_default()
+            .push(fresh.clone());
+        fresh
+    }
+    // 支配木をたどりながら、定義には新しい名前を、使用には今見えている名前を付ける
+    fn rename_block(&mut self, cfg: &mut ControlFlowGraph, block: BlockId) {
+        let mut pushed = Vec::new();
+        let instructions = cfg.blocks[block].instructions.clone();
+        let mut renamed = Vec::new();
+        for instruction in instructions {
+            renamed.push(match instruction {
+                Instruction::Phi { name, arguments } => {
+                    let fresh = self.fresh(&name);
+                    pushed.push(name);
+                    Instruction::Phi {
+                        name: fresh,
+                        arguments,
+                    }
+                }
+                Instruction::Assign { name, expression } => {
+                    let expression = rename(&expression, &self.names);
+                    let fresh = self.fresh(&name);
+                    pushed.push(name);
+                    Instruction::Assign {
+                        name: fresh,
+                        expression,
+                    }
+                }
+            });
+        }
+        cfg.blocks[block].instructions = renamed;
+        if let Terminator::Branch { condition, .. } = &mut cfg.blocks[block].terminator {
+            *condition = rename(condition, &self.names);
+        }
+
+        for successor in cfg.successors(block) {
+            for instruction in &mut cfg.blocks[successor].instructions {
+                if let Instruction::Phi { name, arguments } = instruction {
+                    let argument = current_name(base_name(name), &self.names);
+                    arguments.push((block, argument));
+                }
+            }
+        }
+        for child in self.children[block].clone() {
+            self.rename_block(cfg, child);
+        }
+        for name in pushed {
+            self.names.get_mut(&name).unwrap().pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expression::Environment;
+    use crate::statement::Statement;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn equal(left: Expression, right: Expression) -> Expression {
+        Expression::Equal {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+
+    // i = 0; s = 0; while (i < n) { if (i < 2) { s = s + i } else { s = s + 10 }; i = i + 1 }
+    fn program() -> Statement {
+        sequence(
+            assign("i", number(0)),
+            sequence(
+                assign("s", number(0)),
+                Statement::While {
+                    condition: less_than(variable("i"), variable("n")),
+                    body: Box::new(sequence(
+                        Statement::If {
+                            condition: less_than(variable("i"), number(2)),
+                            consequence: Box::new(assign("s", add(variable("s"), variable("i")))),
+                            alternative: Box::new(assign("s", add(variable("s"), number(10)))),
+                        },
+                        assign("i", add(variable("i"), number(1))),
+                    )),
+                },
+            ),
+        )
+    }
+
+    #[test]
+    fn dominator_test() {
+        let cfg = program().to_cfg().unwrap();
+        // b0 -> b1(header) -> b2(body) -> b3/b4 -> b5(join) -> b1, b1 -> b6
+        assert_eq!(
+            cfg.immediate_dominators(),
+            vec![None, Some(0), Some(1), Some(2), Some(2), Some(2), Some(1)]
+        );
+        let frontiers = cfg.dominance_frontiers();
+        assert_eq!(frontiers[3], vec![5].into_iter().collect());
+        assert_eq!(frontiers[5], vec![1].into_iter().collect());
+    }
+
+    #[test]
+    fn to_ssa_test() {
+        let cfg = program().to_cfg().unwrap();
+        let ssa = cfg.to_ssa();
+        assert!(!cfg.is_ssa());
+        assert!(ssa.is_ssa());
+        assert_eq!(
+            ssa.to_string(),
+            "b0:\n  i.1 = 0\n  s.1 = 0\n  goto b1\n\
+             b1:\n  s.2 = phi(b0: s.1, b5: s.5)\n  i.2 = phi(b0: i.1, b5: i.3)\n  \
+             if (i.2 < n) goto b2 else goto b6\n\
+             b2:\n  if (i.2 < 2) goto b3 else goto b4\n\
+             b3:\n  s.3 = s.2 + i.2\n  goto b5\n\
+             b4:\n  s.4 = s.2 + 10\n  goto b5\n\
+             b5:\n  s.5 = phi(b3: s.3, b4: s.4)\n  i.3 = i.2 + 1\n  goto b1\n\
+             b6:\n  return\n"
+        );
+        for n in 0..5 {
+            let mut environment = Environment::new();
+            environment.insert("n".to_string(), number(n));
+            let expected = program().evaluate(&mut environment.clone());
+            let result = ssa.evaluate(&mut environment.clone());
+            assert_eq!(result["s.2"].to_string(), expected["s"].to_string());
+        }
+    }
+
+    #[test]
+    fn undo_ssa_test() {
+        let cfg = program().to_cfg().unwrap();
+        let back = cfg.to_ssa().undo_ssa();
+        assert!(!back.is_ssa());
+        assert_eq!(back.to_string(), cfg.to_string());
+        let mut environment = Environment::new();
+        environment.insert("n".to_string(), number(4));
+        assert_eq!(back.evaluate(&mut environment)["s"].to_string(), "21");
+    }
+
+    #[test]
+    fn equal_test() {
+        // x = 1; if (x == n) { x = 2 } else { do-nothing }; b = x == 2
+        let statement = sequence(
+            assign("x", number(1)),
+            sequence(
+                Statement::If {
+                    condition: equal(variable("x"), variable("n")),
+                    consequence: Box::new(assign("x", number(2))),
+                    alternative: Box::new(Statement::DoNothing),
+                },
+                assign("b", equal(variable("x"), number(2))),
+            ),
+        );
+        let cfg = statement.to_cfg().unwrap();
+        let ssa = cfg.to_ssa();
+        assert!(ssa.to_string().contains("b.1 = x.3 == 2"));
+        assert_eq!(ssa.undo_ssa().to_string(), cfg.to_string());
+        for n in 0..3 {
+            let mut environment = Environment::new();
+            environment.insert("n".to_string(), number(n));
+            let expected = statement.evaluate(&mut environment.clone());
+            let result = ssa.evaluate(&mut environment.clone());
+            assert_eq!(result["b.1"], expected["b"]);
+        }
+    }
+
+    #[test]
+    fn undefined_on_one_path_test() {
+        // yは片方の分岐でしか定義されないので、phiの引数に元の名前が残る
+        let statement = Statement::If {
+            condition: less_than(variable("x"), number(1)),
+            consequence: Box::new(assign("y", number(1))),
+            alternative: Box::new(Statement::DoNothing),
+        };
+        let ssa = statement.to_cfg().unwrap().to_ssa();
+        assert_eq!(
+            ssa.blocks[3].instructions[0].to_string(),
+            "y.2 = phi(b1: y.1, b2: y)"
+        );
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(5));
+        assert!(!ssa.evaluate(&mut environment).contains_key("y.2"));
+    }
+
+    #[test]
+    #[should_panic(expected = "phi arguments must share a variable")]
+    fn undo_foreign_phi_test() {
+        // to_ssaが作らない形は戻せない
+        let mut ssa = program().to_cfg().unwrap().to_ssa();
+        let block = ssa
+            .blocks
+            .iter_mut()
+            .find(|block| matches!(block.instructions.first(), Some(Instruction::Phi { .. })))
+            .unwrap();
+        if let Instruction::Phi { arguments, .. } = &mut block.instructions[0] {
+            arguments[0].1 = "z.1".to_string();
+        }
+        ssa.undo_ssa();
+    }
+}
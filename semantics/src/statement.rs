@@ -1,6 +1,11 @@
 use std::fmt;
 
 use crate::expression::{Environment, Expression};
+use crate::profile::{within, Recorder};
+use crate::record::{ruby_update, update_variable};
+use crate::running::Running;
+use crate::scope::{restore, save};
+use crate::subset::Construct;
 
 #[derive(Clone, Debug)]
 pub enum Statement {
@@ -14,6 +19,12 @@ pub enum Statement {
         consequence: Box<Statement>,
         alternative: Box<Statement>,
     },
+    // name.fields[0].fields[1]... = expression
+    Update {
+        name: String,
+        fields: Vec<String>,
+        expression: Expression,
+    },
     Sequence {
         first: Box<Statement>,
         second: Box<Statement>,
@@ -22,6 +33,53 @@ pub enum Statement {
         condition: Expression,
         body: Box<Statement>,
     },
+    // どちらか一方を実行する
+    Choose {
+        first: Box<Statement>,
+        second: Box<Statement>,
+    },
+    // 代入や条件の評価を1ステップとして交互に実行する
+    Parallel {
+        left: Box<Statement>,
+        right: Box<Statement>,
+    },
+    // 条件が偽なら実行時エラーで止まる
+    Assert {
+        condition: Expression,
+    },
+    Break,
+    Continue,
+    Throw {
+        expression: Expression,
+    },
+    Try {
+        body: Box<Statement>,
+        name: String,
+        handler: Box<Statement>,
+    },
+    // nameはbodyの中だけで使える
+    Let {
+        name: String,
+        expression: Expression,
+        body: Box<Statement>,
+    },
+    // 中のvarで宣言された変数はブロックを出ると元に戻る
+    Block {
+        body: Box<Statement>,
+    },
+    Var {
+        name: String,
+        expression: Expression,
+    },
+}
+
+// 大ステップ意味論での文の終わり方
+#[derive(Clone, Debug, PartialEq)]
+pub enum Completion {
+    Normal,
+    Break,
+    Continue,
+    Throw(Expression),
 }
 
 impl fmt::Display for Statement {
@@ -38,10 +96,35 @@ impl fmt::Display for Statement {
                 "if ({}) {{ {} }} else {{ {} }}",
                 condition, consequence, alternative
             ),
+            Statement::Update {
+                name,
+                fields,
+                expression,
+            } => write!(f, "{}.{} = {}", name, fields.join("."), expression),
             Statement::Sequence { first, second } => write!(f, "{}; {}", first, second),
             Statement::While { condition, body } => {
                 write!(f, "while ({}), {{ {} }}", condition, body)
             }
+            Statement::Choose { first, second } => {
+                write!(f, "choose {{ {} }} or {{ {} }}", first, second)
+            }
+            Statement::Parallel { left, right } => write!(f, "{{ {} }} || {{ {} }}", left, right),
+            Statement::Assert { condition } => write!(f, "assert ({})", condition),
+            Statement::Break => write!(f, "break"),
+            Statement::Continue => write!(f, "continue"),
+            Statement::Throw { expression } => write!(f, "throw {}", expression),
+            Statement::Try {
+                body,
+                name,
+                handler,
+            } => write!(f, "try {{ {} }} catch ({}) {{ {} }}", body, name, handler),
+            Statement::Let {
+                name,
+                expression,
+                body,
+            } => write!(f, "let {} = {} in {{ {} }}", name, expression, body),
+            Statement::Block { body } => write!(f, "block {{ {} }}", body),
+            Statement::Var { name, expression } => write!(f, "var {} = {}", name, expression),
         }
     }
 }
@@ -50,103 +133,243 @@ impl Statement {
     pub fn is_reducible(&self) -> bool {
         match self {
             Statement::DoNothing => false,
-            _ => true,
+            _ => !self.is_abrupt(),
         }
     }
+    // 囲んでいる文に伝わっていくbreak、continue、throw
+    pub fn is_abrupt(&self) -> bool {
+        match self {
+            Statement::Break | Statement::Continue => true,
+            Statement::Throw { expression } => !expression.is_reducible(),
+            _ => false,
+        }
+    }
+    // 1ステップ進める。whileは元のようにifと並びに展開する。
+    // ループを出るbreakやcontinue、letやブロックは実行中の形が要るので、Machineで進める
     pub fn reduce(&self, environment: &mut Environment) -> (Statement, Environment) {
         match self {
-            Statement::Assignment { name, expression } => {
-                if expression.is_reducible() {
-                    (
-                        Statement::Assignment {
-                            name: name.clone(),
-                            expression: expression.reduce(environment),
-                        },
-                        environment.clone(),
-                    )
-                } else {
-                    let mut new_env = environment.clone();
-                    new_env.insert(String::from(name), expression.clone());
-                    (Statement::DoNothing, new_env)
-                }
-            }
-            Statement::If {
-                condition,
-                consequence,
-                alternative,
-            } => {
-                if condition.is_reducible() {
-                    (
-                        Statement::If {
-                            condition: condition.reduce(environment),
-                            consequence: consequence.clone(),
-                            alternative: alternative.clone(),
-                        },
-                        environment.clone(),
-                    )
-                } else {
-                    match condition {
-                        Expression::Boolean(true) => (*consequence.clone(), environment.clone()),
-                        Expression::Boolean(false) => (*alternative.clone(), environment.clone()),
-                        _ => panic!("condition is not bool"),
-                    }
-                }
-            }
-            Statement::Sequence { first, second } => {
-                if let Statement::DoNothing = **first {
-                    (*second.clone(), environment.clone())
-                } else {
-                    let (reduced_first, reduced_env) = first.reduce(environment);
-                    (
-                        Statement::Sequence {
-                            first: Box::new(reduced_first),
-                            second: second.clone(),
-                        },
-                        reduced_env,
-                    )
+            Statement::While { condition, body } => {
+                if body.exits_loop() {
+                    panic!("break and continue need a running loop: {}", self);
                 }
-            }
-            Statement::While { condition, body } => (
-                Statement::If {
+                let statement = Statement::If {
                     condition: condition.clone(),
                     consequence: Box::new(Statement::Sequence {
                         first: body.clone(),
                         second: Box::new(self.clone()),
                     }),
                     alternative: Box::new(Statement::DoNothing),
-                },
-                environment.clone(),
-            ),
-            _ => unreachable!(),
+                };
+                (statement, environment.clone())
+            }
+            Statement::Let { .. } | Statement::Block { .. } => {
+                panic!("scopes need a running scope: {}", self)
+            }
+            Statement::Sequence { first, second } if first.is_reducible() => {
+                let (first, new_env) = first.reduce(environment);
+                let statement = Statement::Sequence {
+                    first: Box::new(first),
+                    second: second.clone(),
+                };
+                (statement, new_env)
+            }
+            Statement::Parallel { left, right } if left.is_reducible() => {
+                let (left, new_env) = left.reduce(environment);
+                let statement = Statement::Parallel {
+                    left: Box::new(left),
+                    right: right.clone(),
+                };
+                (statement, new_env)
+            }
+            Statement::Try {
+                body,
+                name,
+                handler,
+            } if body.is_reducible() => {
+                let (body, new_env) = body.reduce(environment);
+                let statement = Statement::Try {
+                    body: Box::new(body),
+                    name: name.clone(),
+                    handler: handler.clone(),
+                };
+                (statement, new_env)
+            }
+            // 残りは1ステップで実行中の形を作らない
+            _ => {
+                let (running, new_env) = Running::from(self.clone()).reduce(environment);
+                (running.into_statement(), new_env)
+            }
+        }
+    }
+    // このループを出るbreakやcontinueを含むか。内側のループのものは数えない
+    fn exits_loop(&self) -> bool {
+        match self {
+            Statement::Break | Statement::Continue => true,
+            Statement::If {
+                consequence: first,
+                alternative: second,
+                ..
+            }
+            | Statement::Sequence { first, second }
+            | Statement::Choose { first, second }
+            | Statement::Parallel {
+                left: first,
+                right: second,
+            }
+            | Statement::Try {
+                body: first,
+                handler: second,
+                ..
+            } => first.exits_loop() || second.exits_loop(),
+            Statement::Let { body, .. } | Statement::Block { body } => body.exits_loop(),
+            _ => false,
         }
     }
     pub fn evaluate(&self, environment: &mut Environment) -> Environment {
+        match self.execute(environment) {
+            (Completion::Normal, new_env) => new_env,
+            (Completion::Break, _) => panic!("break outside loop"),
+            (Completion::Continue, _) => panic!("continue outside loop"),
+            (Completion::Throw(value), _) => panic!("uncaught exception {}", value),
+        }
+    }
+    pub fn execute(&self, environment: &mut Environment) -> (Completion, Environment) {
+        self.execute_with_recorder(environment, &mut ())
+    }
+    pub fn execute_with_recorder(
+        &self,
+        environment: &mut Environment,
+        recorder: &mut dyn Recorder,
+    ) -> (Completion, Environment) {
+        recorder.execute();
+        let evaluate = |recorder: &mut dyn Recorder, e: &Expression, env: &Environment| {
+            within(recorder, 0, |r| e.evaluate_with_recorder(env, r))
+        };
         match self {
-            Statement::DoNothing => environment.clone(),
-            Statement::Assignment { name, expression } => {
-                environment.insert(name.to_string(), expression.evaluate(environment));
-                environment.clone()
+            Statement::DoNothing => (Completion::Normal, environment.clone()),
+            Statement::Assignment { name, expression } | Statement::Var { name, expression } => {
+                let value = evaluate(recorder, expression, environment);
+                environment.insert(name.to_string(), value);
+                (Completion::Normal, environment.clone())
+            }
+            Statement::Update {
+                name,
+                fields,
+                expression,
+            } => {
+                let value = evaluate(recorder, expression, environment);
+                update_variable(environment, name, fields, value);
+                (Completion::Normal, environment.clone())
             }
             Statement::If {
                 condition,
                 consequence,
                 alternative,
-            } => match condition.evaluate(environment) {
-                Expression::Boolean(true) => consequence.evaluate(environment),
-                Expression::Boolean(false) => alternative.evaluate(environment),
+            } => match evaluate(recorder, condition, environment) {
+                Expression::Boolean(true) => within(recorder, 1, |r| {
+                    consequence.execute_with_recorder(environment, r)
+                }),
+                Expression::Boolean(false) => within(recorder, 2, |r| {
+                    alternative.execute_with_recorder(environment, r)
+                }),
                 _ => panic!("condition is not bool"),
             },
-            Statement::While { condition, body } => match condition.evaluate(environment) {
-                Expression::Boolean(true) => self.evaluate(&mut body.evaluate(environment)),
-                Expression::Boolean(false) => environment.clone(),
+            Statement::While { condition, body } => {
+                match evaluate(recorder, condition, environment) {
+                    Expression::Boolean(true) => {
+                        match within(recorder, 1, |r| body.execute_with_recorder(environment, r)) {
+                            (Completion::Normal, mut new_env)
+                            | (Completion::Continue, mut new_env) => {
+                                self.execute_with_recorder(&mut new_env, recorder)
+                            }
+                            (Completion::Break, new_env) => (Completion::Normal, new_env),
+                            result => result,
+                        }
+                    }
+                    Expression::Boolean(false) => (Completion::Normal, environment.clone()),
+                    _ => panic!("condition is not bool"),
+                }
+            }
+            Statement::Sequence { first, second }
+            | Statement::Parallel {
+                left: first,
+                right: second,
+            } => match within(recorder, 0, |r| first.execute_with_recorder(environment, r)) {
+                (Completion::Normal, mut new_env) => within(recorder, 1, |r| {
+                    second.execute_with_recorder(&mut new_env, r)
+                }),
+                result => result,
+            },
+            Statement::Choose { first, .. } => {
+                within(recorder, 0, |r| first.execute_with_recorder(environment, r))
+            }
+            Statement::Assert { condition } => match evaluate(recorder, condition, environment) {
+                Expression::Boolean(true) => (Completion::Normal, environment.clone()),
+                Expression::Boolean(false) => panic!("assertion failed"),
                 _ => panic!("condition is not bool"),
             },
-            Statement::Sequence { first, second } => {
-                second.evaluate(&mut first.evaluate(environment))
+            Statement::Break => (Completion::Break, environment.clone()),
+            Statement::Continue => (Completion::Continue, environment.clone()),
+            Statement::Throw { expression } => (
+                Completion::Throw(evaluate(recorder, expression, environment)),
+                environment.clone(),
+            ),
+            Statement::Try {
+                body,
+                name,
+                handler,
+            } => match within(recorder, 0, |r| body.execute_with_recorder(environment, r)) {
+                (Completion::Throw(value), mut new_env) => {
+                    new_env.insert(name.clone(), value);
+                    within(recorder, 1, |r| {
+                        handler.execute_with_recorder(&mut new_env, r)
+                    })
+                }
+                result => result,
+            },
+            Statement::Let {
+                name,
+                expression,
+                body,
+            } => {
+                let value = evaluate(recorder, expression, environment);
+                let saved = save(std::slice::from_ref(name), environment);
+                environment.insert(name.clone(), value);
+                let (completion, mut new_env) =
+                    within(recorder, 1, |r| body.execute_with_recorder(environment, r));
+                restore(&saved, &mut new_env);
+                (completion, new_env)
+            }
+            Statement::Block { body } => {
+                let saved = save(&body.hoisted_variables(), environment);
+                let (completion, mut new_env) =
+                    within(recorder, 0, |r| body.execute_with_recorder(environment, r));
+                restore(&saved, &mut new_env);
+                (completion, new_env)
             }
         }
     }
-    pub fn to_ruby(&self) -> String {
+    // 並行実行と非決定的な選択のほかは、Rubyの手続きにできる
+    pub fn to_ruby(&self) -> Result<String, String> {
+        self.check_supported(&[
+            Construct::Assert,
+            Construct::Break,
+            Construct::Continue,
+            Construct::Throw,
+            Construct::Try,
+            Construct::Let,
+            Construct::Block,
+            Construct::Var,
+            Construct::Update,
+            Construct::Tuple,
+            Construct::Record,
+            Construct::Field,
+            Construct::Function,
+            Construct::Call,
+        ])?;
+        Ok(self.ruby())
+    }
+    fn ruby(&self) -> String {
         match self {
             Statement::DoNothing => "-> e { e }".to_string(),
             Statement::Assignment { name, expression } => {
@@ -156,6 +379,18 @@ impl Statement {
                     expression.to_ruby()
                 )
             }
+            Statement::Update {
+                name,
+                fields,
+                expression,
+            } => {
+                format!(
+                    "-> e {{ v = ({}).call(e); e.merge({{ :{} => {} }}) }}",
+                    expression.to_ruby(),
+                    name,
+                    ruby_update(&format!("e[:{}]", name), fields, "v")
+                )
+            }
             Statement::If {
                 condition,
                 consequence,
@@ -164,24 +399,365 @@ impl Statement {
                 format!(
                     "-> e {{ if({}).call(e) then ({}).call(e) else ({}).call(e) end }}",
                     condition.to_ruby(),
-                    consequence.to_ruby(),
-                    alternative.to_ruby()
+                    consequence.ruby(),
+                    alternative.ruby()
                 )
             }
             Statement::Sequence { first, second } => {
                 format!(
                     "-> e {{ ({}).call(({}).call(e)) }}",
-                    second.to_ruby(),
-                    first.to_ruby()
+                    second.ruby(),
+                    first.ruby()
                 )
             }
             Statement::While { condition, body } => {
                 format!(
-                    "-> e {{ while ({}).call(e); e = ({}).call(e); end; e }}",
+                    "-> e {{ catch(:break) {{ while ({}).call(e); e = catch(:continue) {{ ({}).call(e) }}; end; e }} }}",
                     condition.to_ruby(),
-                    body.to_ruby()
+                    body.ruby()
+                )
+            }
+            Statement::Choose { .. } | Statement::Parallel { .. } => unreachable!(),
+            Statement::Assert { condition } => {
+                format!(
+                    "-> e {{ raise 'assertion failed' unless ({}).call(e); e }}",
+                    condition.to_ruby()
+                )
+            }
+            // 中断はcatch/throwで、そのときの環境といっしょに外へ伝える
+            Statement::Break => "-> e { throw :break, e }".to_string(),
+            Statement::Continue => "-> e { throw :continue, e }".to_string(),
+            Statement::Throw { expression } => {
+                format!(
+                    "-> e {{ throw :throw, [({}).call(e), e] }}",
+                    expression.to_ruby()
+                )
+            }
+            Statement::Try {
+                body,
+                name,
+                handler,
+            } => {
+                format!(
+                    "-> e {{ thrown = true; result = catch(:throw) {{ e = ({}).call(e); thrown = false; e }}; thrown ? ({}).call(result[1].merge({{ :{} => result[0] }})) : result }}",
+                    body.ruby(),
+                    handler.ruby(),
+                    name
+                )
+            }
+            Statement::Let {
+                name,
+                expression,
+                body,
+            } => {
+                format!(
+                    "-> e {{ {}.call(({}).call(e.merge({{ :{} => ({}).call(e) }}))) }}",
+                    ruby_restore(std::slice::from_ref(name)),
+                    body.ruby(),
+                    name,
+                    expression.to_ruby()
                 )
             }
+            Statement::Block { body } => {
+                format!(
+                    "-> e {{ {}.call(({}).call(e)) }}",
+                    ruby_restore(&body.hoisted_variables()),
+                    body.ruby()
+                )
+            }
+            Statement::Var { name, expression } => Statement::Assignment {
+                name: name.clone(),
+                expression: expression.clone(),
+            }
+            .ruby(),
+        }
+    }
+}
+
+// スコープを出たら、入る前の値に戻す。なかった変数は消す
+fn ruby_restore(names: &[String]) -> String {
+    let names = names
+        .iter()
+        .map(|name| format!(":{}", name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "-> r {{ r.merge(e.slice({})).reject {{ |k, _| [{}].include?(k) && !e.key?(k) }} }}",
+        names, names
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::format_environment;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
         }
     }
+    fn when(condition: Expression, consequence: Statement) -> Statement {
+        Statement::If {
+            condition,
+            consequence: Box::new(consequence),
+            alternative: Box::new(Statement::DoNothing),
+        }
+    }
+    fn throw(expression: Expression) -> Statement {
+        Statement::Throw { expression }
+    }
+
+    // 小ステップで止まるまで進める
+    fn run(statement: &Statement, environment: &Environment) -> (Running, Environment) {
+        let mut statement = Running::from(statement.clone());
+        let mut environment = environment.clone();
+        while statement.is_reducible() {
+            let (next, next_env) = statement.reduce(&mut environment);
+            statement = next;
+            environment = next_env;
+        }
+        (statement, environment)
+    }
+
+    fn assert_same_result(statement: &Statement, environment: &Environment) -> Environment {
+        let (rest, small) = run(statement, environment);
+        let (completion, big) = statement.execute(&mut environment.clone());
+        assert_eq!(format!("{:?}", small), format!("{:?}", big));
+        match completion {
+            Completion::Normal => assert_eq!(rest.to_string(), "do-nothing"),
+            Completion::Break => assert_eq!(rest.to_string(), "break"),
+            Completion::Continue => assert_eq!(rest.to_string(), "continue"),
+            Completion::Throw(value) => assert_eq!(rest.to_string(), format!("throw {}", value)),
+        }
+        big
+    }
+
+    #[test]
+    fn display_test() {
+        let statement = Statement::Try {
+            body: Box::new(sequence(Statement::Break, throw(number(1)))),
+            name: "e".to_string(),
+            handler: Box::new(Statement::Continue),
+        };
+        assert_eq!(
+            statement.to_string(),
+            "try { break; throw 1 } catch (e) { continue }"
+        );
+    }
+
+    #[test]
+    fn reduce_test() {
+        // Statementのまま進められるものは、Machineと同じ結果になる
+        let statement = sequence(
+            Statement::While {
+                condition: less_than(variable("i"), number(3)),
+                body: Box::new(assign("i", add(variable("i"), number(1)))),
+            },
+            Statement::Try {
+                body: Box::new(throw(variable("i"))),
+                name: "e".to_string(),
+                handler: Box::new(assign("x", variable("e"))),
+            },
+        );
+        let mut environment = Environment::new();
+        environment.insert("i".to_string(), number(0));
+        let mut current = statement.clone();
+        let mut small = environment.clone();
+        while current.is_reducible() {
+            let (next, next_env) = current.reduce(&mut small);
+            current = next;
+            small = next_env;
+        }
+        assert_eq!(current.to_string(), "do-nothing");
+        let big = assert_same_result(&statement, &environment);
+        assert_eq!(format_environment(&small), format_environment(&big));
+    }
+
+    #[test]
+    #[should_panic(expected = "break and continue need a running loop")]
+    fn reduce_break_test() {
+        let statement = Statement::While {
+            condition: Expression::Boolean(true),
+            body: Box::new(Statement::Break),
+        };
+        statement.reduce(&mut Environment::new());
+    }
+
+    #[test]
+    fn break_test() {
+        let statement = Statement::While {
+            condition: Expression::Boolean(true),
+            body: Box::new(sequence(
+                assign("x", add(variable("x"), number(1))),
+                when(less_than(number(4), variable("x")), Statement::Break),
+            )),
+        };
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(0));
+        assert_eq!(assert_same_result(&statement, &environment)["x"], number(5));
+    }
+
+    #[test]
+    fn continue_test() {
+        // 5から10までの和
+        let statement = Statement::While {
+            condition: less_than(variable("i"), number(10)),
+            body: Box::new(sequence(
+                assign("i", add(variable("i"), number(1))),
+                sequence(
+                    when(less_than(variable("i"), number(5)), Statement::Continue),
+                    assign("s", add(variable("s"), variable("i"))),
+                ),
+            )),
+        };
+        let mut environment = Environment::new();
+        environment.insert("i".to_string(), number(0));
+        environment.insert("s".to_string(), number(0));
+        assert_eq!(
+            assert_same_result(&statement, &environment)["s"],
+            number(45)
+        );
+    }
+
+    #[test]
+    fn try_test() {
+        let statement = Statement::Try {
+            body: Box::new(sequence(
+                assign("x", number(1)),
+                sequence(
+                    throw(add(variable("x"), number(1))),
+                    assign("x", number(100)),
+                ),
+            )),
+            name: "e".to_string(),
+            handler: Box::new(assign("y", variable("e"))),
+        };
+        let result = assert_same_result(&statement, &Environment::new());
+        assert_eq!(result["x"], number(1));
+        assert_eq!(result["y"], number(2));
+    }
+
+    #[test]
+    fn throw_out_of_loop_test() {
+        // ループの中から投げた例外を外で捕まえる。breakはtryを通り抜ける
+        let body = sequence(
+            assign("i", add(variable("i"), number(1))),
+            Statement::Try {
+                body: Box::new(sequence(
+                    when(less_than(number(2), variable("i")), throw(variable("i"))),
+                    when(less_than(number(1), variable("i")), Statement::Break),
+                )),
+                name: "e".to_string(),
+                handler: Box::new(Statement::DoNothing),
+            },
+        );
+        let statement = Statement::While {
+            condition: Expression::Boolean(true),
+            body: Box::new(body.clone()),
+        };
+        let mut environment = Environment::new();
+        environment.insert("i".to_string(), number(0));
+        assert_eq!(assert_same_result(&statement, &environment)["i"], number(2));
+
+        let statement = Statement::Try {
+            body: Box::new(Statement::While {
+                condition: Expression::Boolean(true),
+                body: Box::new(sequence(
+                    assign("i", add(variable("i"), number(1))),
+                    when(less_than(number(2), variable("i")), throw(variable("i"))),
+                )),
+            }),
+            name: "e".to_string(),
+            handler: Box::new(assign("caught", variable("e"))),
+        };
+        assert_eq!(
+            assert_same_result(&statement, &environment)["caught"],
+            number(3)
+        );
+    }
+
+    #[test]
+    fn uncaught_test() {
+        let statement = sequence(throw(number(3)), assign("x", number(1)));
+        let (rest, environment) = run(&statement, &Environment::new());
+        assert_eq!(rest.to_string(), "throw 3");
+        assert!(environment.is_empty());
+        assert_same_result(&statement, &Environment::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "uncaught exception 3")]
+    fn uncaught_evaluate_test() {
+        throw(number(3)).evaluate(&mut Environment::new());
+    }
+
+    #[test]
+    fn to_ruby_test() {
+        assert_eq!(
+            Statement::Break.to_ruby().unwrap(),
+            "-> e { throw :break, e }"
+        );
+        assert_eq!(
+            throw(number(1)).to_ruby().unwrap(),
+            "-> e { throw :throw, [(-> e { 1 }).call(e), e] }"
+        );
+        let parallel = Statement::Parallel {
+            left: Box::new(assign("x", number(1))),
+            right: Box::new(Statement::DoNothing),
+        };
+        assert_eq!(
+            sequence(Statement::Break, parallel).to_ruby(),
+            Err("parallel composition is not supported: { x = 1 } || { do-nothing }".to_string())
+        );
+    }
+
+    #[test]
+    fn assert_test() {
+        let statement = sequence(
+            assign("x", number(1)),
+            Statement::Assert {
+                condition: less_than(variable("x"), number(2)),
+            },
+        );
+        assert_eq!(
+            assert_same_result(&statement, &Environment::new())["x"],
+            number(1)
+        );
+        assert_eq!(statement.to_string(), "x = 1; assert (x < 2)");
+    }
+
+    #[test]
+    #[should_panic(expected = "assertion failed")]
+    fn assert_failure_test() {
+        let statement = Statement::Assert {
+            condition: less_than(number(2), number(1)),
+        };
+        run(&statement, &Environment::new());
+    }
 }
@@ -0,0 +1,281 @@
+use std::fmt;
+
+use crate::expression::Expression;
+use crate::statement::Statement;
+
+// 解析やコンパイラによっては扱えない構文
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Construct {
+    Choose,
+    Parallel,
+    Assert,
+    Break,
+    Continue,
+    Throw,
+    Try,
+    Let,
+    Block,
+    Var,
+    Update,
+    Tuple,
+    Record,
+    Field,
+    Function,
+    Call,
+}
+
+impl fmt::Display for Construct {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Construct::Choose => write!(f, "nondeterministic statement"),
+            Construct::Parallel => write!(f, "parallel composition"),
+            Construct::Assert => write!(f, "assertion"),
+            Construct::Break => write!(f, "break"),
+            Construct::Continue => write!(f, "continue"),
+            Construct::Throw => write!(f, "throw"),
+            Construct::Try => write!(f, "exception handling"),
+            Construct::Let => write!(f, "let binding"),
+            Construct::Block => write!(f, "block scoping"),
+            Construct::Var => write!(f, "var declaration"),
+            Construct::Update => write!(f, "field update"),
+            Construct::Tuple => write!(f, "tuple"),
+            Construct::Record => write!(f, "record"),
+            Construct::Field => write!(f, "field access"),
+            Construct::Function => write!(f, "function"),
+            Construct::Call => write!(f, "function call"),
+        }
+    }
+}
+
+impl Statement {
+    fn construct(&self) -> Option<Construct> {
+        match self {
+            Statement::Choose { .. } => Some(Construct::Choose),
+            Statement::Parallel { .. } => Some(Construct::Parallel),
+            Statement::Assert { .. } => Some(Construct::Assert),
+            Statement::Break => Some(Construct::Break),
+            Statement::Continue => Some(Construct::Continue),
+            Statement::Throw { .. } => Some(Construct::Throw),
+            Statement::Try { .. } => Some(Construct::Try),
+            Statement::Let { .. } => Some(Construct::Let),
+            Statement::Block { .. } => Some(Construct::Block),
+            Statement::Var { .. } => Some(Construct::Var),
+            Statement::Update { .. } => Some(Construct::Update),
+            _ => None,
+        }
+    }
+    // supportedにない構文を使っていたら、最初に見つかったものをエラーにする
+    pub fn check_supported(&self, supported: &[Construct]) -> Result<(), String> {
+        if let Some(construct) = self.construct() {
+            if !supported.contains(&construct) {
+                return Err(format!("{} is not supported: {}", construct, self));
+            }
+        }
+        match self {
+            Statement::Assignment { expression, .. }
+            | Statement::Update { expression, .. }
+            | Statement::Throw { expression }
+            | Statement::Let { expression, .. }
+            | Statement::Var { expression, .. }
+            | Statement::If {
+                condition: expression,
+                ..
+            }
+            | Statement::While {
+                condition: expression,
+                ..
+            }
+            | Statement::Assert {
+                condition: expression,
+            } => expression.check_supported(supported)?,
+            _ => {}
+        }
+        match self {
+            Statement::If {
+                consequence,
+                alternative,
+                ..
+            } => {
+                consequence.check_supported(supported)?;
+                alternative.check_supported(supported)
+            }
+            Statement::Sequence { first, second } | Statement::Choose { first, second } => {
+                first.check_supported(supported)?;
+                second.check_supported(supported)
+            }
+            Statement::Parallel { left, right } => {
+                left.check_supported(supported)?;
+                right.check_supported(supported)
+            }
+            Statement::Try { body, handler, .. } => {
+                body.check_supported(supported)?;
+                handler.check_supported(supported)
+            }
+            Statement::While { body, .. }
+            | Statement::Let { body, .. }
+            | Statement::Block { body } => body.check_supported(supported),
+            Statement::DoNothing
+            | Statement::Assignment { .. }
+            | Statement::Update { .. }
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Throw { .. }
+            | Statement::Var { .. }
+            | Statement::Assert { .. } => Ok(()),
+        }
+    }
+}
+
+impl Expression {
+    fn construct(&self) -> Option<Construct> {
+        match self {
+            Expression::Tuple(_) => Some(Construct::Tuple),
+            Expression::Record(_) => Some(Construct::Record),
+            Expression::Field { .. } => Some(Construct::Field),
+            Expression::Function { .. } | Expression::Closure { .. } => Some(Construct::Function),
+            Expression::Call { .. } => Some(Construct::Call),
+            _ => None,
+        }
+    }
+    pub fn check_supported(&self, supported: &[Construct]) -> Result<(), String> {
+        if let Some(construct) = self.construct() {
+            if !supported.contains(&construct) {
+                return Err(format!("{} is not supported: {}", construct, self));
+            }
+        }
+        match self {
+            Expression::Add { left, right }
+            | Expression::Multiply { left, right }
+            | Expression::LessThan { left, right }
+            | Expression::Equal { left, right } => {
+                left.check_supported(supported)?;
+                right.check_supported(supported)
+            }
+            Expression::Tuple(elements) => elements
+                .iter()
+                .try_for_each(|e| e.check_supported(supported)),
+            Expression::Record(fields) => fields
+                .iter()
+                .try_for_each(|(_, e)| e.check_supported(supported)),
+            Expression::Field { expression, .. } => expression.check_supported(supported),
+            Expression::Function { body, .. } => body.check_supported(supported),
+            Expression::Call {
+                function,
+                arguments,
+            } => {
+                function.check_supported(supported)?;
+                arguments
+                    .iter()
+                    .try_for_each(|e| e.check_supported(supported))
+            }
+            Expression::Number(_)
+            | Expression::Boolean(_)
+            | Expression::Variable(_)
+            | Expression::Closure { .. } => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assign(name: &str, value: u32) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression: Expression::Number(value),
+        }
+    }
+
+    #[test]
+    fn check_supported_test() {
+        let statement = Statement::While {
+            condition: Expression::Boolean(true),
+            body: Box::new(Statement::Sequence {
+                first: Box::new(assign("x", 1)),
+                second: Box::new(Statement::Parallel {
+                    left: Box::new(assign("y", 2)),
+                    right: Box::new(Statement::Assert {
+                        condition: Expression::Boolean(false),
+                    }),
+                }),
+            }),
+        };
+        assert_eq!(
+            statement.check_supported(&[]),
+            Err(
+                "parallel composition is not supported: { y = 2 } || { assert (false) }"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            statement.check_supported(&[Construct::Parallel]),
+            Err("assertion is not supported: assert (false)".to_string())
+        );
+        assert_eq!(
+            statement.check_supported(&[Construct::Parallel, Construct::Assert]),
+            Ok(())
+        );
+        let statement = Statement::Try {
+            body: Box::new(Statement::Break),
+            name: "e".to_string(),
+            handler: Box::new(Statement::DoNothing),
+        };
+        assert_eq!(
+            statement.check_supported(&[]),
+            Err(
+                "exception handling is not supported: try { break } catch (e) { do-nothing }"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            statement.check_supported(&[Construct::Try]),
+            Err("break is not supported: break".to_string())
+        );
+        let statement = Statement::Block {
+            body: Box::new(Statement::Var {
+                name: "x".to_string(),
+                expression: Expression::Number(1),
+            }),
+        };
+        assert_eq!(
+            statement.check_supported(&[Construct::Block]),
+            Err("var declaration is not supported: var x = 1".to_string())
+        );
+        // 数や真偽値の比較は使えるが、フィールドを取り出すところで止まる
+        let statement = Statement::Assignment {
+            name: "b".to_string(),
+            expression: Expression::Equal {
+                left: Box::new(Expression::Number(1)),
+                right: Box::new(Expression::Field {
+                    expression: Box::new(Expression::Variable("p".to_string())),
+                    name: "x".to_string(),
+                }),
+            },
+        };
+        assert_eq!(
+            statement.check_supported(&[]),
+            Err("field access is not supported: p.x".to_string())
+        );
+        assert_eq!(statement.check_supported(&[Construct::Field]), Ok(()));
+        // 関数の中も調べる
+        let statement = Statement::Assignment {
+            name: "f".to_string(),
+            expression: Expression::Function {
+                params: vec!["x".to_string()],
+                body: Box::new(Expression::Tuple(vec![
+                    Expression::Variable("x".to_string()),
+                    Expression::Number(1),
+                ])),
+            },
+        };
+        assert_eq!(
+            statement.check_supported(&[]),
+            Err("function is not supported: function (x) { (x, 1) }".to_string())
+        );
+        assert_eq!(
+            statement.check_supported(&[Construct::Function]),
+            Err("tuple is not supported: (x, 1)".to_string())
+        );
+    }
+}
@@ -0,0 +1,548 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::expression::{Environment, Expression};
+use crate::hoare::{check_validity, Assertion, Verdict};
+use crate::statement::Statement;
+use crate::subset::Construct;
+
+// 記号実行: 入力を未知数のまま式として持ち歩き、分岐ごとに経路条件を集める。
+// 環境の値は入力変数だけを含む式で、計算できる部分は畳み込んでおく
+
+impl Expression {
+    pub fn evaluate_symbolic(&self, environment: &Environment) -> Result<Expression, String> {
+        self.check_supported(&[])?;
+        Ok(self.symbolic(environment))
+    }
+    fn symbolic(&self, environment: &Environment) -> Expression {
+        match self {
+            Expression::Number(_) | Expression::Boolean(_) => self.clone(),
+            Expression::Variable(name) => match environment.get(name) {
+                Some(value) => value.clone(),
+                None => panic!("undefined variable"),
+            },
+            Expression::Add { left, right } => {
+                match (left.symbolic(environment), right.symbolic(environment)) {
+                    (Expression::Number(l), Expression::Number(r))
+                        if l.checked_add(r).is_some() =>
+                    {
+                        Expression::Number(l + r)
+                    }
+                    (Expression::Number(0), e) | (e, Expression::Number(0)) => e,
+                    (l, r) => Expression::Add {
+                        left: Box::new(l),
+                        right: Box::new(r),
+                    },
+                }
+            }
+            Expression::Multiply { left, right } => {
+                match (left.symbolic(environment), right.symbolic(environment)) {
+                    (Expression::Number(l), Expression::Number(r))
+                        if l.checked_mul(r).is_some() =>
+                    {
+                        Expression::Number(l * r)
+                    }
+                    (Expression::Number(0), _) | (_, Expression::Number(0)) => {
+                        Expression::Number(0)
+                    }
+                    (Expression::Number(1), e) | (e, Expression::Number(1)) => e,
+                    (l, r) => Expression::Multiply {
+                        left: Box::new(l),
+                        right: Box::new(r),
+                    },
+                }
+            }
+            Expression::LessThan { left, right } => {
+                match (left.symbolic(environment), right.symbolic(environment)) {
+                    (Expression::Number(l), Expression::Number(r)) => Expression::Boolean(l < r),
+                    (l, r) => Expression::LessThan {
+                        left: Box::new(l),
+                        right: Box::new(r),
+                    },
+                }
+            }
+            Expression::Equal { left, right } => {
+                match (left.symbolic(environment), right.symbolic(environment)) {
+                    (
+                        l @ (Expression::Number(_) | Expression::Boolean(_)),
+                        r @ (Expression::Number(_) | Expression::Boolean(_)),
+                    ) => Expression::Boolean(l == r),
+                    (l, r) => Expression::Equal {
+                        left: Box::new(l),
+                        right: Box::new(r),
+                    },
+                }
+            }
+            Expression::Tuple(_)
+            | Expression::Record(_)
+            | Expression::Field { .. }
+            | Expression::Function { .. }
+            | Expression::Closure { .. }
+            | Expression::Call { .. } => unreachable!(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct State {
+    environment: Environment,
+    condition: Vec<Assertion>,
+}
+
+impl State {
+    fn assume(&self, assertion: Assertion) -> State {
+        let mut condition = self.condition.clone();
+        condition.push(assertion);
+        State {
+            environment: self.environment.clone(),
+            condition,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Path {
+    pub condition: Vec<Assertion>,
+    pub environment: Environment,
+    // falseならループを展開しきれずに打ち切った経路
+    pub is_complete: bool,
+    // この経路を通る具体的な入力。ソルバーで決められなければNone
+    pub input: Option<Environment>,
+}
+
+impl Path {
+    pub fn condition_assertion(&self) -> Assertion {
+        conjunction(&self.condition)
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let environment = self
+            .environment
+            .iter()
+            .collect::<BTreeMap<_, _>>()
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect::<Vec<_>>();
+        write!(
+            f,
+            "{} => {{{}}}",
+            self.condition_assertion(),
+            environment.join(", ")
+        )?;
+        if !self.is_complete {
+            write!(f, " (truncated)")?;
+        }
+        Ok(())
+    }
+}
+
+fn conjunction(assertions: &[Assertion]) -> Assertion {
+    assertions
+        .iter()
+        .cloned()
+        .reduce(Assertion::and)
+        .unwrap_or(Assertion::True)
+}
+
+pub struct SymbolicExecutor {
+    inputs: Vec<String>,
+    max_unrolling: usize,
+}
+
+impl SymbolicExecutor {
+    pub fn new(inputs: Vec<&str>, max_unrolling: usize) -> Self {
+        SymbolicExecutor {
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            max_unrolling,
+        }
+    }
+    pub fn inputs(&self) -> &Vec<String> {
+        &self.inputs
+    }
+    pub fn max_unrolling(&self) -> usize {
+        self.max_unrolling
+    }
+    // environmentの値は具体的な初期値で、inputsに挙げた変数はそれを上書きして未知数になる
+    pub fn explore(
+        &self,
+        statement: &Statement,
+        environment: &Environment,
+    ) -> Result<Vec<Path>, String> {
+        statement.check_supported(&[Construct::Choose])?;
+        let mut environment = environment.clone();
+        for name in &self.inputs {
+            environment.insert(name.clone(), Expression::Variable(name.clone()));
+        }
+        let state = State {
+            environment,
+            condition: vec![],
+        };
+        let mut paths = Vec::new();
+        for (state, is_complete) in self.execute(statement, state) {
+            let input = self.solve(&state.condition);
+            paths.push(Path {
+                condition: state.condition,
+                environment: state.environment,
+                is_complete,
+                input,
+            });
+        }
+        Ok(paths)
+    }
+    // 実行を続けられる状態と、打ち切った状態(false)を返す
+    fn execute(&self, statement: &Statement, state: State) -> Vec<(State, bool)> {
+        match statement {
+            Statement::DoNothing => vec![(state, true)],
+            Statement::Assignment { name, expression } => {
+                let mut state = state;
+                let value = expression.symbolic(&state.environment);
+                state.environment.insert(name.clone(), value);
+                vec![(state, true)]
+            }
+            Statement::Sequence { first, second } => self
+                .execute(first, state)
+                .into_iter()
+                .flat_map(|(state, is_complete)| {
+                    if is_complete {
+                        self.execute(second, state)
+                    } else {
+                        vec![(state, false)]
+                    }
+                })
+                .collect(),
+            Statement::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let (then_state, else_state) = self.branch(condition, &state);
+                let mut results = Vec::new();
+                if let Some(s) = then_state {
+                    results.extend(self.execute(consequence, s));
+                }
+                if let Some(s) = else_state {
+                    results.extend(self.execute(alternative, s));
+                }
+                results
+            }
+            Statement::While { condition, body } => self.unroll(condition, body, state, 0),
+            Statement::Choose { first, second } => {
+                let mut results = self.execute(first, state.clone());
+                results.extend(self.execute(second, state));
+                results
+            }
+            Statement::Parallel { .. }
+            | Statement::Assert { .. }
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Throw { .. }
+            | Statement::Try { .. }
+            | Statement::Let { .. }
+            | Statement::Block { .. }
+            | Statement::Var { .. }
+            | Statement::Update { .. } => unreachable!(),
+        }
+    }
+    fn unroll(
+        &self,
+        condition: &Expression,
+        body: &Statement,
+        state: State,
+        count: usize,
+    ) -> Vec<(State, bool)> {
+        let (loop_state, exit_state) = self.branch(condition, &state);
+        let mut results = Vec::new();
+        if let Some(s) = exit_state {
+            results.push((s, true));
+        }
+        if let Some(s) = loop_state {
+            if count == self.max_unrolling {
+                results.push((s, false));
+            } else {
+                for (s, is_complete) in self.execute(body, s) {
+                    if is_complete {
+                        results.extend(self.unroll(condition, body, s, count + 1));
+                    } else {
+                        results.push((s, false));
+                    }
+                }
+            }
+        }
+        results
+    }
+    // 条件が成り立つ側と成り立たない側の状態。通れない側はNone
+    fn branch(&self, condition: &Expression, state: &State) -> (Option<State>, Option<State>) {
+        match condition.symbolic(&state.environment) {
+            Expression::Boolean(true) => (Some(state.clone()), None),
+            Expression::Boolean(false) => (None, Some(state.clone())),
+            condition => {
+                let assertion = match Assertion::from_condition(&condition) {
+                    Ok(assertion) => assertion,
+                    Err(message) => panic!("{}", message),
+                };
+                let then_state = state.assume(assertion.clone());
+                let else_state = state.assume(Assertion::negate(assertion));
+                (
+                    Some(then_state).filter(|s| self.is_feasible(&s.condition)),
+                    Some(else_state).filter(|s| self.is_feasible(&s.condition)),
+                )
+            }
+        }
+    }
+    // 分からないときは通れるものとして探索を続ける
+    fn is_feasible(&self, condition: &[Assertion]) -> bool {
+        !matches!(
+            check_validity(&Assertion::negate(conjunction(condition))),
+            Ok(Verdict::Valid)
+        )
+    }
+    fn solve(&self, condition: &[Assertion]) -> Option<Environment> {
+        match check_validity(&Assertion::negate(conjunction(condition))) {
+            Ok(Verdict::Invalid(mut input)) => {
+                // 経路条件に出てこない入力は何でもよい
+                for name in &self.inputs {
+                    input.entry(name.clone()).or_insert(Expression::Number(0));
+                }
+                input.retain(|name, _| self.inputs.contains(name));
+                Some(input)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn multiply(left: Expression, right: Expression) -> Expression {
+        Expression::Multiply {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn equal(left: Expression, right: Expression) -> Expression {
+        Expression::Equal {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+    fn if_else(condition: Expression, consequence: Statement, alternative: Statement) -> Statement {
+        Statement::If {
+            condition,
+            consequence: Box::new(consequence),
+            alternative: Box::new(alternative),
+        }
+    }
+
+    // 生成した入力で実際に動かすと、記号実行の結果と一致する
+    fn assert_reproduced(statement: &Statement, environment: &Environment, path: &Path) {
+        let input = path.input.as_ref().unwrap();
+        assert!(path.condition_assertion().evaluate(input));
+        let mut concrete = environment.clone();
+        concrete.extend(input.clone());
+        let result = statement.evaluate(&mut concrete);
+        for (name, value) in &path.environment {
+            assert_eq!(
+                value.evaluate(input).to_string(),
+                result[name].to_string(),
+                "{} on {}",
+                name,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn evaluate_symbolic_test() {
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), variable("x"));
+        environment.insert("y".to_string(), number(3));
+        let expression = add(
+            multiply(variable("y"), number(2)),
+            multiply(variable("x"), number(1)),
+        );
+        assert_eq!(
+            expression
+                .evaluate_symbolic(&environment)
+                .unwrap()
+                .to_string(),
+            "6 + x"
+        );
+        let expression = less_than(variable("y"), number(4));
+        assert_eq!(
+            expression
+                .evaluate_symbolic(&environment)
+                .unwrap()
+                .to_string(),
+            "true"
+        );
+        let expression = equal(variable("y"), number(3));
+        assert_eq!(
+            expression
+                .evaluate_symbolic(&environment)
+                .unwrap()
+                .to_string(),
+            "true"
+        );
+        let expression = equal(variable("x"), add(variable("y"), number(1)));
+        assert_eq!(
+            expression
+                .evaluate_symbolic(&environment)
+                .unwrap()
+                .to_string(),
+            "x == 4"
+        );
+    }
+
+    #[test]
+    fn equal_test() {
+        let statement = if_else(
+            equal(variable("x"), number(7)),
+            assign("y", number(1)),
+            assign("y", number(2)),
+        );
+        let environment = Environment::new();
+        let executor = SymbolicExecutor::new(vec!["x"], 10);
+        let paths = executor.explore(&statement, &environment).unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].to_string(), "x == 7 => {x: x, y: 1}");
+        for path in &paths {
+            assert_reproduced(&statement, &environment, path);
+        }
+    }
+
+    #[test]
+    fn if_test() {
+        let statement = if_else(
+            less_than(variable("x"), number(10)),
+            assign("y", number(1)),
+            if_else(
+                less_than(variable("x"), number(20)),
+                assign("y", add(variable("x"), number(2))),
+                assign("y", number(3)),
+            ),
+        );
+        let environment = Environment::new();
+        let executor = SymbolicExecutor::new(vec!["x"], 10);
+        let paths = executor.explore(&statement, &environment).unwrap();
+        assert_eq!(paths.len(), 3);
+        assert_eq!(
+            paths[1].to_string(),
+            "!(x < 10) && x < 20 => {x: x, y: x + 2}"
+        );
+        for path in &paths {
+            assert!(path.is_complete);
+            assert_reproduced(&statement, &environment, path);
+        }
+    }
+
+    #[test]
+    fn infeasible_path_test() {
+        // 2つ目の分岐の片方は通れない
+        let statement = sequence(
+            if_else(
+                less_than(variable("x"), number(5)),
+                assign("y", number(0)),
+                assign("y", number(1)),
+            ),
+            if_else(
+                less_than(variable("x"), number(3)),
+                assign("z", number(0)),
+                assign("z", number(1)),
+            ),
+        );
+        let executor = SymbolicExecutor::new(vec!["x"], 10);
+        let environment = Environment::new();
+        let paths = executor.explore(&statement, &environment).unwrap();
+        assert_eq!(paths.len(), 3);
+        for path in &paths {
+            assert_reproduced(&statement, &environment, path);
+        }
+    }
+
+    #[test]
+    fn while_test() {
+        // i = 0; s = 0; while (i < n) { s = s + i; i = i + 1 }
+        let statement = sequence(
+            assign("i", number(0)),
+            sequence(
+                assign("s", number(0)),
+                Statement::While {
+                    condition: less_than(variable("i"), variable("n")),
+                    body: Box::new(sequence(
+                        assign("s", add(variable("s"), variable("i"))),
+                        assign("i", add(variable("i"), number(1))),
+                    )),
+                },
+            ),
+        );
+        let executor = SymbolicExecutor::new(vec!["n"], 3);
+        let environment = Environment::new();
+        let paths = executor.explore(&statement, &environment).unwrap();
+        // 0回から3回回って抜ける経路と、打ち切った経路
+        assert_eq!(paths.len(), 5);
+        let complete = paths.iter().filter(|p| p.is_complete).collect::<Vec<_>>();
+        assert_eq!(complete.len(), 4);
+        let mut inputs = complete
+            .iter()
+            .map(|p| p.input.as_ref().unwrap()["n"].to_string())
+            .collect::<Vec<_>>();
+        inputs.sort();
+        assert_eq!(inputs, vec!["0", "1", "2", "3"]);
+        for path in complete {
+            assert_reproduced(&statement, &environment, path);
+        }
+        let truncated = paths.iter().find(|p| !p.is_complete).unwrap();
+        assert!(truncated.to_string().ends_with("(truncated)"));
+    }
+
+    #[test]
+    fn boolean_input_test() {
+        let statement = if_else(
+            variable("b"),
+            assign("x", add(variable("x"), number(1))),
+            assign("x", number(0)),
+        );
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(7));
+        let executor = SymbolicExecutor::new(vec!["b"], 1);
+        let paths = executor.explore(&statement, &environment).unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].environment["x"].to_string(), "8");
+        assert_eq!(paths[0].input.as_ref().unwrap()["b"].to_string(), "true");
+        assert_eq!(paths[1].input.as_ref().unwrap()["b"].to_string(), "false");
+    }
+}
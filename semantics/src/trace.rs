@@ -0,0 +1,448 @@
+use crate::expression::{Environment, Expression};
+use crate::machine::Machine;
+use crate::profile::{json_string, Node, Recorder, Shape};
+use crate::running::Running;
+use crate::statement::Statement;
+
+// 小ステップの実行を1状態ずつ記録して、DOTやJSONに書き出す。
+// 次のステップで書き換わる部分式は、Node::childrenの添字の列で表す
+
+#[derive(Clone, Debug)]
+pub struct Configuration {
+    pub statement: Running,
+    pub environment: Environment,
+    pub redex: Option<Vec<usize>>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    pub configurations: Vec<Configuration>,
+}
+
+enum NodeMut<'a> {
+    Statement(&'a mut Statement),
+    Expression(&'a mut Expression),
+    Running(&'a mut Running),
+}
+
+impl<'a> NodeMut<'a> {
+    // Node::childrenと同じ順番
+    fn into_child(self, index: usize) -> NodeMut<'a> {
+        match self {
+            NodeMut::Statement(statement) => match (statement, index) {
+                (Statement::Assignment { expression, .. }, 0)
+                | (Statement::Update { expression, .. }, 0)
+                | (Statement::Var { expression, .. }, 0)
+                | (Statement::Throw { expression }, 0)
+                | (
+                    Statement::Assert {
+                        condition: expression,
+                    },
+                    0,
+                )
+                | (
+                    Statement::If {
+                        condition: expression,
+                        ..
+                    },
+                    0,
+                )
+                | (
+                    Statement::While {
+                        condition: expression,
+                        ..
+                    },
+                    0,
+                )
+                | (Statement::Let { expression, .. }, 0) => NodeMut::Expression(expression),
+                (Statement::If { consequence, .. }, 1) => NodeMut::Statement(consequence),
+                (Statement::If { alternative, .. }, 2) => NodeMut::Statement(alternative),
+                (Statement::While { body, .. }, 1)
+                | (Statement::Let { body, .. }, 1)
+                | (Statement::Block { body }, 0) => NodeMut::Statement(body),
+                (Statement::Sequence { first, .. }, 0)
+                | (Statement::Choose { first, .. }, 0)
+                | (Statement::Parallel { left: first, .. }, 0)
+                | (Statement::Try { body: first, .. }, 0) => NodeMut::Statement(first),
+                (Statement::Sequence { second, .. }, 1)
+                | (Statement::Choose { second, .. }, 1)
+                | (Statement::Parallel { right: second, .. }, 1)
+                | (
+                    Statement::Try {
+                        handler: second, ..
+                    },
+                    1,
+                ) => NodeMut::Statement(second),
+                _ => panic!("no child {}", index),
+            },
+            NodeMut::Expression(expression) => match (expression, index) {
+                (Expression::Add { left, .. }, 0)
+                | (Expression::Multiply { left, .. }, 0)
+                | (Expression::LessThan { left, .. }, 0)
+                | (Expression::Equal { left, .. }, 0)
+                | (
+                    Expression::Field {
+                        expression: left, ..
+                    },
+                    0,
+                )
+                | (Expression::Function { body: left, .. }, 0)
+                | (Expression::Call { function: left, .. }, 0) => NodeMut::Expression(left),
+                (Expression::Add { right, .. }, 1)
+                | (Expression::Multiply { right, .. }, 1)
+                | (Expression::LessThan { right, .. }, 1)
+                | (Expression::Equal { right, .. }, 1) => NodeMut::Expression(right),
+                (Expression::Tuple(elements), i) if i < elements.len() => {
+                    NodeMut::Expression(&mut elements[i])
+                }
+                (Expression::Record(fields), i) if i < fields.len() => {
+                    NodeMut::Expression(&mut fields[i].1)
+                }
+                (Expression::Call { arguments, .. }, i) if 0 < i && i <= arguments.len() => {
+                    NodeMut::Expression(&mut arguments[i - 1])
+                }
+                _ => panic!("no child {}", index),
+            },
+            NodeMut::Running(running) => match (running, index) {
+                (Running::Statement(statement), _) => {
+                    NodeMut::Statement(statement).into_child(index)
+                }
+                (Running::Sequence { first, .. }, 0)
+                | (Running::Parallel { left: first, .. }, 0)
+                | (Running::Try { body: first, .. }, 0)
+                | (Running::Loop { body: first, .. }, 0)
+                | (Running::Scope { body: first, .. }, 0) => NodeMut::Running(first),
+                (Running::Parallel { right, .. }, 1) => NodeMut::Running(right),
+                (Running::Sequence { second, .. }, 1)
+                | (
+                    Running::Try {
+                        handler: second, ..
+                    },
+                    1,
+                )
+                | (Running::Loop { next: second, .. }, 1) => NodeMut::Statement(second),
+                _ => panic!("no child {}", index),
+            },
+        }
+    }
+}
+
+// 規則が当てはまったところまでの、子の添字の列
+#[derive(Default)]
+struct RedexRecorder {
+    path: Vec<usize>,
+    redex: Vec<usize>,
+}
+
+impl Recorder for RedexRecorder {
+    fn enter(&mut self, index: usize) {
+        self.path.push(index);
+    }
+    fn leave(&mut self) {
+        self.path.pop();
+    }
+    fn execute(&mut self) {}
+    fn rule(&mut self, _: &[(usize, Node)], _: Shape) {
+        self.redex = self.path.clone();
+    }
+}
+
+impl Configuration {
+    // 書き換わる部分の前、その部分、後ろに分けた文
+    pub fn highlight(&self) -> Option<(String, String, String)> {
+        let path = self.redex.as_ref()?;
+        let mut node = Node::Running(&self.statement);
+        for &i in path {
+            node = node.children()[i];
+        }
+        let redex = node.to_string();
+        // 目印に置き換えて表示し、その位置で分ける
+        let mut marked = self.statement.clone();
+        let mut target = NodeMut::Running(&mut marked);
+        for &i in path {
+            target = target.into_child(i);
+        }
+        let marker = Expression::Variable("\u{1}".to_string());
+        let mark = || Statement::Var {
+            name: "\u{1}".to_string(),
+            expression: marker.clone(),
+        };
+        let placeholder = match target {
+            NodeMut::Statement(statement) => {
+                *statement = mark();
+                statement.to_string()
+            }
+            NodeMut::Running(running) => {
+                *running = Running::Statement(mark());
+                running.to_string()
+            }
+            NodeMut::Expression(expression) => {
+                *expression = marker.clone();
+                expression.to_string()
+            }
+        };
+        let text = marked.to_string();
+        let start = text.find(&placeholder).unwrap();
+        Some((
+            text[..start].to_string(),
+            redex,
+            text[start + placeholder.len()..].to_string(),
+        ))
+    }
+}
+
+// 変数名の順に並べた、値の表示
+fn environment_entries(environment: &Environment) -> Vec<(String, String)> {
+    let mut names = environment.keys().collect::<Vec<_>>();
+    names.sort();
+    names
+        .into_iter()
+        .map(|name| (name.clone(), environment[name].to_string()))
+        .collect()
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Trace {
+    // 状態ごとに1ノード。書き換わる部分は赤い太字にする
+    pub fn to_dot(&self) -> String {
+        let mut lines = vec![
+            "digraph trace {".to_string(),
+            "  node [shape=box, fontname=\"monospace\"];".to_string(),
+        ];
+        for (i, configuration) in self.configurations.iter().enumerate() {
+            let statement = match configuration.highlight() {
+                Some((before, redex, after)) => format!(
+                    "{}<font color=\"red\"><b>{}</b></font>{}",
+                    dot_escape(&before),
+                    dot_escape(&redex),
+                    dot_escape(&after)
+                ),
+                None => dot_escape(&configuration.statement.to_string()),
+            };
+            let environment = environment_entries(&configuration.environment)
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect::<Vec<_>>();
+            lines.push(format!(
+                "  s{} [label=<{}<br/>{{{}}}>];",
+                i,
+                statement,
+                dot_escape(&environment.join(", "))
+            ));
+        }
+        for i in 1..self.configurations.len() {
+            lines.push(format!("  s{} -> s{};", i - 1, i));
+        }
+        lines.push("}".to_string());
+        lines.join("\n") + "\n"
+    }
+    pub fn to_json(&self) -> String {
+        let steps = self
+            .configurations
+            .iter()
+            .enumerate()
+            .map(|(i, configuration)| {
+                let environment = environment_entries(&configuration.environment)
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", json_string(name), json_string(value)))
+                    .collect::<Vec<_>>();
+                let redex = match (&configuration.redex, configuration.highlight()) {
+                    (Some(path), Some((before, redex, after))) => format!(
+                        "{{\"path\": [{}], \"before\": {}, \"text\": {}, \"after\": {}}}",
+                        path.iter()
+                            .map(|i| i.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        json_string(&before),
+                        json_string(&redex),
+                        json_string(&after)
+                    ),
+                    _ => "null".to_string(),
+                };
+                format!(
+                    "    {{\"step\": {}, \"statement\": {}, \"environment\": {{{}}}, \"redex\": {}}}",
+                    i,
+                    json_string(&configuration.statement.to_string()),
+                    environment.join(", "),
+                    redex
+                )
+            })
+            .collect::<Vec<_>>();
+        format!("{{\n  \"steps\": [\n{}\n  ]\n}}\n", steps.join(",\n"))
+    }
+}
+
+impl Machine {
+    // runと同じように最後まで進め、途中の状態をすべて返す
+    pub fn run_with_trace(&mut self) -> Trace {
+        let mut trace = Trace::default();
+        let mut running = Running::from(self.statement.clone());
+        while running.is_reducible() {
+            let mut configuration = Configuration {
+                statement: running.clone(),
+                environment: self.environment.clone(),
+                redex: None,
+            };
+            let mut recorder = RedexRecorder::default();
+            let (new_running, environment) =
+                running.reduce_with_recorder(&mut self.environment, &mut recorder);
+            configuration.redex = Some(recorder.redex);
+            trace.configurations.push(configuration);
+            running = new_running;
+            self.environment = environment;
+        }
+        trace.configurations.push(Configuration {
+            statement: running.clone(),
+            environment: self.environment.clone(),
+            redex: None,
+        });
+        self.statement = running.into_statement();
+        trace
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+    fn trace(statement: Statement) -> Trace {
+        Machine {
+            statement,
+            environment: Environment::new(),
+        }
+        .run_with_trace()
+    }
+
+    #[test]
+    fn highlight_test() {
+        // 同じ表示の部分式があっても、書き換わる方を選ぶ
+        let statement = sequence(
+            assign("x", number(1)),
+            assign("y", add(variable("x"), variable("x"))),
+        );
+        let highlights = trace(statement)
+            .configurations
+            .iter()
+            .map(|c| c.highlight())
+            .collect::<Vec<_>>();
+        let split = |before: &str, redex: &str, after: &str| {
+            Some((before.to_string(), redex.to_string(), after.to_string()))
+        };
+        assert_eq!(
+            highlights,
+            vec![
+                split("", "x = 1", "; y = x + x"),
+                split("", "do-nothing; y = x + x", ""),
+                split("y = ", "x", " + x"),
+                split("y = 1 + ", "x", ""),
+                split("y = ", "1 + 1", ""),
+                split("", "y = 2", ""),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn redex_test() {
+        let statement = Statement::While {
+            condition: less_than(variable("x"), number(1)),
+            body: Box::new(assign("x", add(variable("x"), number(1)))),
+        };
+        let mut machine = Machine {
+            statement,
+            environment: Environment::new(),
+        };
+        machine.environment.insert("x".to_string(), number(0));
+        let trace = machine.run_with_trace();
+        let redexes = trace
+            .configurations
+            .iter()
+            .map(|c| c.redex.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(redexes[0], Some(vec![]));
+        // 展開したループ本体のifの条件、if、本体の代入の式
+        assert_eq!(redexes[1], Some(vec![0, 0, 0]));
+        assert_eq!(redexes[3], Some(vec![0]));
+        assert_eq!(redexes[4], Some(vec![0, 0, 0]));
+        assert_eq!(redexes.last(), Some(&None));
+        assert_eq!(
+            trace.configurations[4].highlight().unwrap().0,
+            "loop { x = "
+        );
+    }
+
+    #[test]
+    fn dot_test() {
+        let dot = trace(assign("b", less_than(number(1), number(2)))).to_dot();
+        assert_eq!(
+            dot,
+            [
+                "digraph trace {",
+                "  node [shape=box, fontname=\"monospace\"];",
+                "  s0 [label=<b = <font color=\"red\"><b>1 &lt; 2</b></font><br/>{}>];",
+                "  s1 [label=<<font color=\"red\"><b>b = true</b></font><br/>{}>];",
+                "  s2 [label=<do-nothing<br/>{b: true}>];",
+                "  s0 -> s1;",
+                "  s1 -> s2;",
+                "}",
+                ""
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn json_test() {
+        let json = trace(assign("s", add(number(1), number(2)))).to_json();
+        assert_eq!(
+            json,
+            [
+                "{",
+                "  \"steps\": [",
+                "    {\"step\": 0, \"statement\": \"s = 1 + 2\", \"environment\": {}, \"redex\": {\"path\": [0], \"before\": \"s = \", \"text\": \"1 + 2\", \"after\": \"\"}},",
+                "    {\"step\": 1, \"statement\": \"s = 3\", \"environment\": {}, \"redex\": {\"path\": [], \"before\": \"\", \"text\": \"s = 3\", \"after\": \"\"}},",
+                "    {\"step\": 2, \"statement\": \"do-nothing\", \"environment\": {\"s\": \"3\"}, \"redex\": null}",
+                "  ]",
+                "}",
+                ""
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,1127 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::expression::{Environment, Expression};
+use crate::statement::Statement;
+
+// 変数はすべてi32のグローバル変数にする。真偽値は0と1で表すので、
+// 値の型は変数ごとに静的に決めておき、モジュールの外で戻せるようにする
+
+const RUN: &str = "run";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValueType {
+    Number,
+    Boolean,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub value_type: ValueType,
+    pub initial: u32,
+}
+
+impl Global {
+    pub fn to_expression(&self, value: u32) -> Expression {
+        match self.value_type {
+            ValueType::Number => Expression::Number(value),
+            ValueType::Boolean => Expression::Boolean(value != 0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    Block,
+    Loop,
+    If,
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    I32Const(u32),
+    I32Eqz,
+    I32Eq,
+    I32LtU,
+    I32Add,
+    I32Mul,
+}
+
+impl Instruction {
+    fn to_wat(self, globals: &[Global]) -> String {
+        match self {
+            Instruction::Block => "block".to_string(),
+            Instruction::Loop => "loop".to_string(),
+            Instruction::If => "if".to_string(),
+            Instruction::Else => "else".to_string(),
+            Instruction::End => "end".to_string(),
+            Instruction::Br(depth) => format!("br {}", depth),
+            Instruction::BrIf(depth) => format!("br_if {}", depth),
+            Instruction::GlobalGet(index) => {
+                format!("global.get ${}", globals[index as usize].name)
+            }
+            Instruction::GlobalSet(index) => {
+                format!("global.set ${}", globals[index as usize].name)
+            }
+            Instruction::I32Const(value) => format!("i32.const {}", value as i32),
+            Instruction::I32Eqz => "i32.eqz".to_string(),
+            Instruction::I32Eq => "i32.eq".to_string(),
+            Instruction::I32LtU => "i32.lt_u".to_string(),
+            Instruction::I32Add => "i32.add".to_string(),
+            Instruction::I32Mul => "i32.mul".to_string(),
+        }
+    }
+    fn encode(self, bytes: &mut Vec<u8>) {
+        // ブロックの型は値を返さない0x40だけを使う
+        match self {
+            Instruction::Block => bytes.extend([0x02, 0x40]),
+            Instruction::Loop => bytes.extend([0x03, 0x40]),
+            Instruction::If => bytes.extend([0x04, 0x40]),
+            Instruction::Else => bytes.push(0x05),
+            Instruction::End => bytes.push(0x0b),
+            Instruction::Br(depth) => {
+                bytes.push(0x0c);
+                write_unsigned(depth, bytes);
+            }
+            Instruction::BrIf(depth) => {
+                bytes.push(0x0d);
+                write_unsigned(depth, bytes);
+            }
+            Instruction::GlobalGet(index) => {
+                bytes.push(0x23);
+                write_unsigned(index, bytes);
+            }
+            Instruction::GlobalSet(index) => {
+                bytes.push(0x24);
+                write_unsigned(index, bytes);
+            }
+            Instruction::I32Const(value) => {
+                bytes.push(0x41);
+                write_signed(value as i32, bytes);
+            }
+            Instruction::I32Eqz => bytes.push(0x45),
+            Instruction::I32Eq => bytes.push(0x46),
+            Instruction::I32LtU => bytes.push(0x49),
+            Instruction::I32Add => bytes.push(0x6a),
+            Instruction::I32Mul => bytes.push(0x6c),
+        }
+    }
+}
+
+fn write_unsigned(mut value: u32, bytes: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn write_signed(mut value: i32, bytes: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn write_name(name: &str, bytes: &mut Vec<u8>) {
+    write_unsigned(name.len() as u32, bytes);
+    bytes.extend(name.as_bytes());
+}
+
+fn write_section(id: u8, contents: Vec<u8>, bytes: &mut Vec<u8>) {
+    bytes.push(id);
+    write_unsigned(contents.len() as u32, bytes);
+    bytes.extend(contents);
+}
+
+pub struct WasmModule {
+    pub globals: Vec<Global>,
+    pub code: Vec<Instruction>,
+}
+
+impl WasmModule {
+    pub fn global(&self, name: &str) -> Option<&Global> {
+        self.globals.iter().find(|g| g.name == name)
+    }
+    pub fn to_wat(&self) -> String {
+        let mut lines = vec!["(module".to_string()];
+        for global in &self.globals {
+            lines.push(format!(
+                "  (global ${} (export \"{}\") (mut i32) (i32.const {}))",
+                global.name, global.name, global.initial as i32
+            ));
+        }
+        lines.push(format!("  (func (export \"{}\")", RUN));
+        let mut depth = 2;
+        for instruction in &self.code {
+            if let Instruction::Else | Instruction::End = instruction {
+                depth -= 1;
+            }
+            lines.push(format!(
+                "{}{}",
+                "  ".repeat(depth),
+                instruction.to_wat(&self.globals)
+            ));
+            if let Instruction::Block | Instruction::Loop | Instruction::If | Instruction::Else =
+                instruction
+            {
+                depth += 1;
+            }
+        }
+        lines.push("  )".to_string());
+        lines.push(")".to_string());
+        lines.join("\n") + "\n"
+    }
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut bytes = b"\0asm".to_vec();
+        bytes.extend([1, 0, 0, 0]);
+
+        // 型: () -> ()
+        write_section(1, vec![1, 0x60, 0, 0], &mut bytes);
+        // 関数: runの型は0番
+        write_section(3, vec![1, 0], &mut bytes);
+
+        let mut globals = Vec::new();
+        write_unsigned(self.globals.len() as u32, &mut globals);
+        for global in &self.globals {
+            globals.extend([0x7f, 0x01]);
+            Instruction::I32Const(global.initial).encode(&mut globals);
+            Instruction::End.encode(&mut globals);
+        }
+        write_section(6, globals, &mut bytes);
+
+        let mut exports = Vec::new();
+        write_unsigned(self.globals.len() as u32 + 1, &mut exports);
+        write_name(RUN, &mut exports);
+        exports.extend([0x00, 0x00]);
+        for (index, global) in self.globals.iter().enumerate() {
+            write_name(&global.name, &mut exports);
+            exports.push(0x03);
+            write_unsigned(index as u32, &mut exports);
+        }
+        write_section(7, exports, &mut bytes);
+
+        let mut body = vec![0];
+        for instruction in &self.code {
+            instruction.encode(&mut body);
+        }
+        Instruction::End.encode(&mut body);
+        let mut code = vec![1];
+        write_unsigned(body.len() as u32, &mut code);
+        code.extend(body);
+        write_section(10, code, &mut bytes);
+        bytes
+    }
+}
+
+fn value_type(expression: &Expression, types: &BTreeMap<String, ValueType>) -> Option<ValueType> {
+    match expression {
+        Expression::Number(_) | Expression::Add { .. } | Expression::Multiply { .. } => {
+            Some(ValueType::Number)
+        }
+        Expression::Boolean(_) | Expression::LessThan { .. } | Expression::Equal { .. } => {
+            Some(ValueType::Boolean)
+        }
+        Expression::Variable(name) => types.get(name).cloned(),
+        Expression::Tuple(_)
+        | Expression::Record(_)
+        | Expression::Field { .. }
+        | Expression::Function { .. }
+        | Expression::Closure { .. }
+        | Expression::Call { .. } => unreachable!(),
+    }
+}
+
+fn infer_types(
+    statement: &Statement,
+    types: &mut BTreeMap<String, ValueType>,
+) -> Result<bool, String> {
+    match statement {
+        Statement::DoNothing => Ok(false),
+        Statement::Choose { .. }
+        | Statement::Parallel { .. }
+        | Statement::Assert { .. }
+        | Statement::Break
+        | Statement::Continue
+        | Statement::Throw { .. }
+        | Statement::Try { .. }
+        | Statement::Let { .. }
+        | Statement::Block { .. }
+        | Statement::Var { .. }
+        | Statement::Update { .. } => unreachable!(),
+        Statement::Assignment { name, expression } => match value_type(expression, types) {
+            Some(t) => match types.insert(name.clone(), t) {
+                Some(old) if old != t => Err(format!("variable {} has inconsistent types", name)),
+                Some(_) => Ok(false),
+                None => Ok(true),
+            },
+            None => Ok(false),
+        },
+        Statement::If {
+            consequence,
+            alternative,
+            ..
+        } => Ok(infer_types(consequence, types)? | infer_types(alternative, types)?),
+        Statement::Sequence { first, second } => {
+            Ok(infer_types(first, types)? | infer_types(second, types)?)
+        }
+        Statement::While { body, .. } => infer_types(body, types),
+    }
+}
+
+fn collect_variables(statement: &Statement, names: &mut Vec<String>) {
+    fn expression_variables(expression: &Expression, names: &mut Vec<String>) {
+        match expression {
+            Expression::Variable(name) => {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+            Expression::Add { left, right }
+            | Expression::Multiply { left, right }
+            | Expression::LessThan { left, right }
+            | Expression::Equal { left, right } => {
+                expression_variables(left, names);
+                expression_variables(right, names);
+            }
+            Expression::Number(_) | Expression::Boolean(_) => {}
+            Expression::Tuple(_)
+            | Expression::Record(_)
+            | Expression::Field { .. }
+            | Expression::Function { .. }
+            | Expression::Closure { .. }
+            | Expression::Call { .. } => unreachable!(),
+        }
+    }
+    match statement {
+        Statement::DoNothing => {}
+        Statement::Choose { .. }
+        | Statement::Parallel { .. }
+        | Statement::Assert { .. }
+        | Statement::Break
+        | Statement::Continue
+        | Statement::Throw { .. }
+        | Statement::Try { .. }
+        | Statement::Let { .. }
+        | Statement::Block { .. }
+        | Statement::Var { .. }
+        | Statement::Update { .. } => unreachable!(),
+        Statement::Assignment { name, expression } => {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+            expression_variables(expression, names);
+        }
+        Statement::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            expression_variables(condition, names);
+            collect_variables(consequence, names);
+            collect_variables(alternative, names);
+        }
+        Statement::Sequence { first, second } => {
+            collect_variables(first, names);
+            collect_variables(second, names);
+        }
+        Statement::While { condition, body } => {
+            expression_variables(condition, names);
+            collect_variables(body, names);
+        }
+    }
+}
+
+// グローバル変数は0で始まるので、代入より前に読む変数があれば
+// evaluateと結果が変わらないように変換しない
+fn check_assigned(statement: &Statement, assigned: &mut BTreeSet<String>) -> Result<(), String> {
+    fn check_expression(
+        expression: &Expression,
+        assigned: &BTreeSet<String>,
+    ) -> Result<(), String> {
+        match expression {
+            Expression::Variable(name) if !assigned.contains(name) => Err(format!(
+                "variable {} may be read before it is assigned",
+                name
+            )),
+            Expression::Add { left, right }
+            | Expression::Multiply { left, right }
+            | Expression::LessThan { left, right }
+            | Expression::Equal { left, right } => {
+                check_expression(left, assigned)?;
+                check_expression(right, assigned)
+            }
+            Expression::Variable(_) | Expression::Number(_) | Expression::Boolean(_) => Ok(()),
+            Expression::Tuple(_)
+            | Expression::Record(_)
+            | Expression::Field { .. }
+            | Expression::Function { .. }
+            | Expression::Closure { .. }
+            | Expression::Call { .. } => unreachable!(),
+        }
+    }
+    match statement {
+        Statement::DoNothing => Ok(()),
+        Statement::Choose { .. }
+        | Statement::Parallel { .. }
+        | Statement::Assert { .. }
+        | Statement::Break
+        | Statement::Continue
+        | Statement::Throw { .. }
+        | Statement::Try { .. }
+        | Statement::Let { .. }
+        | Statement::Block { .. }
+        | Statement::Var { .. }
+        | Statement::Update { .. } => unreachable!(),
+        Statement::Assignment { name, expression } => {
+            check_expression(expression, assigned)?;
+            assigned.insert(name.clone());
+            Ok(())
+        }
+        Statement::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            check_expression(condition, assigned)?;
+            let mut then_assigned = assigned.clone();
+            check_assigned(consequence, &mut then_assigned)?;
+            check_assigned(alternative, assigned)?;
+            assigned.retain(|name| then_assigned.contains(name));
+            Ok(())
+        }
+        Statement::Sequence { first, second } => {
+            check_assigned(first, assigned)?;
+            check_assigned(second, assigned)
+        }
+        // 本体は一度も実行されないかもしれない
+        Statement::While { condition, body } => {
+            check_expression(condition, assigned)?;
+            check_assigned(body, &mut assigned.clone())
+        }
+    }
+}
+
+struct Compiler {
+    indices: HashMap<String, u32>,
+    types: BTreeMap<String, ValueType>,
+    code: Vec<Instruction>,
+}
+
+impl Compiler {
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Number(value) => self.code.push(Instruction::I32Const(*value)),
+            Expression::Boolean(value) => self.code.push(Instruction::I32Const(*value as u32)),
+            Expression::Variable(name) => {
+                self.code.push(Instruction::GlobalGet(self.indices[name]))
+            }
+            Expression::Add { left, right } => {
+                self.expression(left);
+                self.expression(right);
+                self.code.push(Instruction::I32Add);
+            }
+            Expression::Multiply { left, right } => {
+                self.expression(left);
+                self.expression(right);
+                self.code.push(Instruction::I32Mul);
+            }
+            Expression::LessThan { left, right } => {
+                self.expression(left);
+                self.expression(right);
+                self.code.push(Instruction::I32LtU);
+            }
+            // 数と真偽値はどちらも整数になるので、型が違えば比べずに偽にする
+            Expression::Equal { left, right } => {
+                match (
+                    value_type(left, &self.types),
+                    value_type(right, &self.types),
+                ) {
+                    (Some(l), Some(r)) if l != r => self.code.push(Instruction::I32Const(0)),
+                    _ => {
+                        self.expression(left);
+                        self.expression(right);
+                        self.code.push(Instruction::I32Eq);
+                    }
+                }
+            }
+            Expression::Tuple(_)
+            | Expression::Record(_)
+            | Expression::Field { .. }
+            | Expression::Function { .. }
+            | Expression::Closure { .. }
+            | Expression::Call { .. } => unreachable!(),
+        }
+    }
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::DoNothing => {}
+            Statement::Choose { .. }
+            | Statement::Parallel { .. }
+            | Statement::Assert { .. }
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Throw { .. }
+            | Statement::Try { .. }
+            | Statement::Let { .. }
+            | Statement::Block { .. }
+            | Statement::Var { .. }
+            | Statement::Update { .. } => unreachable!(),
+            Statement::Assignment { name, expression } => {
+                self.expression(expression);
+                self.code.push(Instruction::GlobalSet(self.indices[name]));
+            }
+            Statement::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.expression(condition);
+                self.code.push(Instruction::If);
+                self.statement(consequence);
+                self.code.push(Instruction::Else);
+                self.statement(alternative);
+                self.code.push(Instruction::End);
+            }
+            Statement::Sequence { first, second } => {
+                self.statement(first);
+                self.statement(second);
+            }
+            // 条件が偽ならblockの外へ、本体の最後でloopの先頭へ
+            Statement::While { condition, body } => {
+                self.code.push(Instruction::Block);
+                self.code.push(Instruction::Loop);
+                self.expression(condition);
+                self.code.push(Instruction::I32Eqz);
+                self.code.push(Instruction::BrIf(1));
+                self.statement(body);
+                self.code.push(Instruction::Br(0));
+                self.code.push(Instruction::End);
+                self.code.push(Instruction::End);
+            }
+        }
+    }
+}
+
+impl Statement {
+    pub fn to_wasm(&self) -> Result<WasmModule, String> {
+        self.to_wasm_with_environment(&Environment::new())
+    }
+    pub fn to_wasm_with_environment(
+        &self,
+        environment: &Environment,
+    ) -> Result<WasmModule, String> {
+        self.check_supported(&[])?;
+        let mut types = BTreeMap::new();
+        let mut initial = BTreeMap::new();
+        for (name, value) in environment {
+            let (value_type, bits) = match value {
+                Expression::Number(n) => (ValueType::Number, *n),
+                Expression::Boolean(b) => (ValueType::Boolean, *b as u32),
+                _ => {
+                    return Err(format!(
+                        "environment value of {} is not number or bool: {}",
+                        name, value
+                    ))
+                }
+            };
+            types.insert(name.clone(), value_type);
+            initial.insert(name.clone(), bits);
+        }
+        while infer_types(self, &mut types)? {}
+        check_assigned(self, &mut environment.keys().cloned().collect())?;
+
+        let mut names = environment.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        collect_variables(self, &mut names);
+        if names.iter().any(|name| name == RUN) {
+            return Err(format!("variable name conflicts with the {} export", RUN));
+        }
+        let globals = names
+            .iter()
+            .map(|name| Global {
+                name: name.clone(),
+                value_type: *types.get(name).unwrap_or(&ValueType::Number),
+                initial: *initial.get(name).unwrap_or(&0),
+            })
+            .collect::<Vec<_>>();
+
+        let mut compiler = Compiler {
+            indices: names
+                .iter()
+                .enumerate()
+                .map(|(index, name)| (name.clone(), index as u32))
+                .collect(),
+            types,
+            code: vec![],
+        };
+        compiler.statement(self);
+        Ok(WasmModule {
+            globals,
+            code: compiler.code,
+        })
+    }
+}
+
+// 上のバックエンドが出力する命令だけを実行できる小さなインタプリタ
+pub struct WasmInstance {
+    globals: Vec<u32>,
+    exports: HashMap<String, (u8, u32)>,
+    code: Vec<Instruction>,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> u8 {
+        let byte = self.bytes[self.position];
+        self.position += 1;
+        byte
+    }
+    fn unsigned(&mut self) -> u32 {
+        let mut result = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte();
+            result |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return result;
+            }
+            shift += 7;
+        }
+    }
+    fn signed(&mut self) -> i32 {
+        let mut result: i64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte();
+            result |= ((byte & 0x7f) as i64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    result |= -1 << shift;
+                }
+                return result as i32;
+            }
+        }
+    }
+    fn name(&mut self) -> String {
+        let length = self.unsigned() as usize;
+        let name =
+            String::from_utf8(self.bytes[self.position..self.position + length].to_vec()).unwrap();
+        self.position += length;
+        name
+    }
+    fn instruction(&mut self) -> Instruction {
+        let block_type = |reader: &mut Reader| {
+            if reader.byte() != 0x40 {
+                panic!("unsupported block type");
+            }
+        };
+        match self.byte() {
+            0x02 => {
+                block_type(self);
+                Instruction::Block
+            }
+            0x03 => {
+                block_type(self);
+                Instruction::Loop
+            }
+            0x04 => {
+                block_type(self);
+                Instruction::If
+            }
+            0x05 => Instruction::Else,
+            0x0b => Instruction::End,
+            0x0c => Instruction::Br(self.unsigned()),
+            0x0d => Instruction::BrIf(self.unsigned()),
+            0x23 => Instruction::GlobalGet(self.unsigned()),
+            0x24 => Instruction::GlobalSet(self.unsigned()),
+            0x41 => Instruction::I32Const(self.signed() as u32),
+            0x45 => Instruction::I32Eqz,
+            0x46 => Instruction::I32Eq,
+            0x49 => Instruction::I32LtU,
+            0x6a => Instruction::I32Add,
+            0x6c => Instruction::I32Mul,
+            opcode => panic!("unsupported opcode {:#x}", opcode),
+        }
+    }
+}
+
+struct Label {
+    // loopならその先頭、blockやifなら対応するendの位置
+    target: usize,
+    is_loop: bool,
+}
+
+impl WasmInstance {
+    pub fn instantiate(bytes: &[u8]) -> Self {
+        if bytes.len() < 8 || &bytes[0..4] != b"\0asm" || bytes[4..8] != [1, 0, 0, 0] {
+            panic!("not a wasm module");
+        }
+        let mut reader = Reader { bytes, position: 8 };
+        let mut instance = WasmInstance {
+            globals: vec![],
+            exports: HashMap::new(),
+            code: vec![],
+        };
+        while reader.position < bytes.len() {
+            let id = reader.byte();
+            let size = reader.unsigned() as usize;
+            let end = reader.position + size;
+            match id {
+                6 => {
+                    for _ in 0..reader.unsigned() {
+                        reader.byte();
+                        reader.byte();
+                        match reader.instruction() {
+                            Instruction::I32Const(value) => instance.globals.push(value),
+                            _ => panic!("unsupported global initializer"),
+                        }
+                        reader.instruction();
+                    }
+                }
+                7 => {
+                    for _ in 0..reader.unsigned() {
+                        let name = reader.name();
+                        let kind = reader.byte();
+                        let index = reader.unsigned();
+                        instance.exports.insert(name, (kind, index));
+                    }
+                }
+                10 => {
+                    if reader.unsigned() != 1 {
+                        panic!("only one function is supported");
+                    }
+                    let body_end = reader.unsigned() as usize + reader.position;
+                    if reader.unsigned() != 0 {
+                        panic!("locals are not supported");
+                    }
+                    while reader.position < body_end {
+                        instance.code.push(reader.instruction());
+                    }
+                }
+                _ => {}
+            }
+            reader.position = end;
+        }
+        instance
+    }
+    pub fn global(&self, name: &str) -> u32 {
+        match self.exports.get(name) {
+            Some((0x03, index)) => self.globals[*index as usize],
+            _ => panic!("no exported global {}", name),
+        }
+    }
+    // 各block/loop/ifに対応するelseとendの位置
+    fn matching(&self) -> HashMap<usize, (Option<usize>, usize)> {
+        let mut result = HashMap::new();
+        let mut stack: Vec<(usize, Option<usize>)> = Vec::new();
+        for (position, instruction) in self.code.iter().enumerate() {
+            match instruction {
+                Instruction::Block | Instruction::Loop | Instruction::If => {
+                    stack.push((position, None))
+                }
+                Instruction::Else => stack.last_mut().unwrap().1 = Some(position),
+                Instruction::End => {
+                    if let Some((start, else_position)) = stack.pop() {
+                        result.insert(start, (else_position, position));
+                        if let Some(e) = else_position {
+                            result.insert(e, (None, position));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        result
+    }
+    pub fn invoke(&mut self, name: &str) {
+        match self.exports.get(name) {
+            Some((0x00, 0)) => {}
+            _ => panic!("no exported function {}", name),
+        }
+        let matching = self.matching();
+        let mut stack: Vec<u32> = Vec::new();
+        let mut labels: Vec<Label> = Vec::new();
+        let mut pc = 0;
+        while pc < self.code.len() {
+            let mut next = pc + 1;
+            match self.code[pc] {
+                Instruction::Block => labels.push(Label {
+                    target: matching[&pc].1,
+                    is_loop: false,
+                }),
+                Instruction::Loop => labels.push(Label {
+                    target: pc,
+                    is_loop: true,
+                }),
+                Instruction::If => {
+                    let (else_position, end) = matching[&pc];
+                    labels.push(Label {
+                        target: end,
+                        is_loop: false,
+                    });
+                    if stack.pop().unwrap() == 0 {
+                        next = else_position.map_or(end, |e| e + 1);
+                    }
+                }
+                Instruction::Else => next = matching[&pc].1,
+                Instruction::End => {
+                    if labels.pop().is_none() {
+                        return;
+                    }
+                }
+                Instruction::Br(depth) => next = Self::branch(&mut labels, depth),
+                Instruction::BrIf(depth) => {
+                    if stack.pop().unwrap() != 0 {
+                        next = Self::branch(&mut labels, depth);
+                    }
+                }
+                Instruction::GlobalGet(index) => stack.push(self.globals[index as usize]),
+                Instruction::GlobalSet(index) => {
+                    self.globals[index as usize] = stack.pop().unwrap()
+                }
+                Instruction::I32Const(value) => stack.push(value),
+                Instruction::I32Eqz => {
+                    let value = stack.pop().unwrap();
+                    stack.push((value == 0) as u32);
+                }
+                Instruction::I32Eq
+                | Instruction::I32LtU
+                | Instruction::I32Add
+                | Instruction::I32Mul => {
+                    let right = stack.pop().unwrap();
+                    let left = stack.pop().unwrap();
+                    stack.push(match self.code[pc] {
+                        Instruction::I32Eq => (left == right) as u32,
+                        Instruction::I32LtU => (left < right) as u32,
+                        // evaluateと同じく桁あふれでは止める
+                        Instruction::I32Add => left.checked_add(right).expect("trap: overflow"),
+                        _ => left.checked_mul(right).expect("trap: overflow"),
+                    });
+                }
+            }
+            pc = next;
+        }
+    }
+    fn branch(labels: &mut Vec<Label>, depth: u32) -> usize {
+        let index = labels.len() - 1 - depth as usize;
+        let Label { target, is_loop } = labels[index];
+        if is_loop {
+            labels.truncate(index + 1);
+            target + 1
+        } else {
+            // endを実行してラベルを外す
+            labels.truncate(index + 1);
+            target
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(value: u32) -> Expression {
+        Expression::Number(value)
+    }
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+    fn add(left: Expression, right: Expression) -> Expression {
+        Expression::Add {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn multiply(left: Expression, right: Expression) -> Expression {
+        Expression::Multiply {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn less_than(left: Expression, right: Expression) -> Expression {
+        Expression::LessThan {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn equal(left: Expression, right: Expression) -> Expression {
+        Expression::Equal {
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+    fn assign(name: &str, expression: Expression) -> Statement {
+        Statement::Assignment {
+            name: name.to_string(),
+            expression,
+        }
+    }
+    fn sequence(first: Statement, second: Statement) -> Statement {
+        Statement::Sequence {
+            first: Box::new(first),
+            second: Box::new(second),
+        }
+    }
+
+    fn assert_same_as_evaluate(statement: &Statement, environment: &Environment) {
+        let module = statement.to_wasm_with_environment(environment).unwrap();
+        let mut instance = WasmInstance::instantiate(&module.to_binary());
+        instance.invoke("run");
+        let expected = statement.evaluate(&mut environment.clone());
+        for name in expected.keys() {
+            assert!(module.global(name).is_some(), "{}", name);
+        }
+        for global in &module.globals {
+            match expected.get(&global.name) {
+                Some(value) => assert_eq!(
+                    &global.to_expression(instance.global(&global.name)),
+                    value,
+                    "{}",
+                    global.name
+                ),
+                // 通らなかった分岐でだけ代入される変数は初期値のまま
+                None => assert_eq!(instance.global(&global.name), 0, "{}", global.name),
+            }
+        }
+    }
+
+    #[test]
+    fn leb128_test() {
+        let mut bytes = Vec::new();
+        write_unsigned(624485, &mut bytes);
+        assert_eq!(bytes, vec![0xe5, 0x8e, 0x26]);
+        let mut bytes = Vec::new();
+        write_signed(-123456, &mut bytes);
+        assert_eq!(bytes, vec![0xc0, 0xbb, 0x78]);
+        let mut reader = Reader {
+            bytes: &bytes,
+            position: 0,
+        };
+        assert_eq!(reader.signed(), -123456);
+    }
+
+    #[test]
+    fn wat_test() {
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(2));
+        let statement = Statement::While {
+            condition: less_than(variable("x"), number(5)),
+            body: Box::new(assign("x", add(variable("x"), number(1)))),
+        };
+        assert_eq!(
+            statement
+                .to_wasm_with_environment(&environment)
+                .unwrap()
+                .to_wat(),
+            r#"(module
+  (global $x (export "x") (mut i32) (i32.const 2))
+  (func (export "run")
+    block
+      loop
+        global.get $x
+        i32.const 5
+        i32.lt_u
+        i32.eqz
+        br_if 1
+        global.get $x
+        i32.const 1
+        i32.add
+        global.set $x
+        br 0
+      end
+    end
+  )
+)
+"#
+        );
+    }
+
+    #[test]
+    fn binary_test() {
+        let module = assign("x", number(300)).to_wasm().unwrap();
+        let bytes = module.to_binary();
+        assert_eq!(&bytes[0..8], b"\0asm\x01\0\0\0");
+        assert!(bytes.ends_with(&[0x41, 0xac, 0x02, 0x24, 0x00, 0x0b]));
+        let mut instance = WasmInstance::instantiate(&bytes);
+        assert_eq!(instance.global("x"), 0);
+        instance.invoke("run");
+        assert_eq!(instance.global("x"), 300);
+    }
+
+    #[test]
+    fn if_test() {
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), Expression::Boolean(true));
+        let statement = Statement::If {
+            condition: variable("x"),
+            consequence: Box::new(assign("y", number(1))),
+            alternative: Box::new(assign("y", number(2))),
+        };
+        assert_same_as_evaluate(&statement, &environment);
+        environment.insert("x".to_string(), Expression::Boolean(false));
+        assert_same_as_evaluate(&statement, &environment);
+    }
+
+    #[test]
+    fn equal_test() {
+        let statement = sequence(
+            assign("b", equal(variable("x"), number(3))),
+            sequence(
+                assign(
+                    "c",
+                    equal(variable("b"), less_than(variable("x"), number(5))),
+                ),
+                sequence(
+                    assign("d", equal(variable("x"), Expression::Boolean(true))),
+                    Statement::If {
+                        condition: equal(variable("x"), number(3)),
+                        consequence: Box::new(assign("y", number(1))),
+                        alternative: Box::new(assign("y", number(2))),
+                    },
+                ),
+            ),
+        );
+        for x in [1, 3, 4] {
+            let mut environment = Environment::new();
+            environment.insert("x".to_string(), number(x));
+            assert_same_as_evaluate(&statement, &environment);
+        }
+    }
+
+    #[test]
+    fn while_test() {
+        // 階乗と、途中で真偽値を入れる変数
+        let statement = sequence(
+            assign("result", number(1)),
+            sequence(
+                assign("i", number(1)),
+                Statement::While {
+                    condition: less_than(variable("i"), add(variable("n"), number(1))),
+                    body: Box::new(sequence(
+                        assign("result", multiply(variable("result"), variable("i"))),
+                        sequence(
+                            assign("i", add(variable("i"), number(1))),
+                            assign("done", less_than(variable("n"), variable("i"))),
+                        ),
+                    )),
+                },
+            ),
+        );
+        for n in [0, 1, 5, 10] {
+            let mut environment = Environment::new();
+            environment.insert("n".to_string(), number(n));
+            assert_same_as_evaluate(&statement, &environment);
+        }
+        let mut environment = Environment::new();
+        environment.insert("n".to_string(), number(3));
+        let module = statement.to_wasm_with_environment(&environment).unwrap();
+        assert_eq!(
+            module.global("done").unwrap().value_type,
+            ValueType::Boolean
+        );
+    }
+
+    #[test]
+    fn nested_while_test() {
+        // 九九の和
+        let statement = sequence(
+            assign("sum", number(0)),
+            sequence(
+                assign("i", number(1)),
+                Statement::While {
+                    condition: less_than(variable("i"), number(10)),
+                    body: Box::new(sequence(
+                        assign("j", number(1)),
+                        sequence(
+                            Statement::While {
+                                condition: less_than(variable("j"), number(10)),
+                                body: Box::new(sequence(
+                                    assign(
+                                        "sum",
+                                        add(
+                                            variable("sum"),
+                                            multiply(variable("i"), variable("j")),
+                                        ),
+                                    ),
+                                    assign("j", add(variable("j"), number(1))),
+                                )),
+                            },
+                            assign("i", add(variable("i"), number(1))),
+                        ),
+                    )),
+                },
+            ),
+        );
+        assert_same_as_evaluate(&statement, &Environment::new());
+    }
+
+    #[test]
+    fn error_test() {
+        let statement = sequence(
+            assign("x", number(1)),
+            assign("x", Expression::Boolean(true)),
+        );
+        assert_eq!(
+            statement.to_wasm().err(),
+            Some("variable x has inconsistent types".to_string())
+        );
+
+        let mut environment = Environment::new();
+        environment.insert(
+            "x".to_string(),
+            Expression::Tuple(vec![number(1), number(2)]),
+        );
+        assert!(assign("y", number(1))
+            .to_wasm_with_environment(&environment)
+            .is_err());
+
+        assert_eq!(
+            assign("run", number(1)).to_wasm().err(),
+            Some("variable name conflicts with the run export".to_string())
+        );
+
+        let statement = Statement::If {
+            condition: variable("b"),
+            consequence: Box::new(assign("x", number(1))),
+            alternative: Box::new(Statement::DoNothing),
+        };
+        let mut environment = Environment::new();
+        environment.insert("b".to_string(), Expression::Boolean(true));
+        assert!(statement.to_wasm_with_environment(&environment).is_ok());
+        assert_eq!(
+            sequence(statement, assign("y", variable("x")))
+                .to_wasm_with_environment(&environment)
+                .err(),
+            Some("variable x may be read before it is assigned".to_string())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "trap: overflow")]
+    fn overflow_test() {
+        let mut environment = Environment::new();
+        environment.insert("x".to_string(), number(u32::MAX));
+        let module = assign("y", add(variable("x"), number(1)))
+            .to_wasm_with_environment(&environment)
+            .unwrap();
+        WasmInstance::instantiate(&module.to_binary()).invoke("run");
+    }
+}